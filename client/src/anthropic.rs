@@ -0,0 +1,904 @@
+//! Anthropic Messages API (`POST /v1/messages`) on top of the chat completion flow.
+//!
+//! Requests are translated into a `ChatCompletionRequest` and sent through the normal
+//! authorize/worker path; the OpenAI-shaped reply is then rewritten into an Anthropic
+//! message, or, when streaming, into Anthropic's `message_start` / `content_block_*` /
+//! `message_delta` / `message_stop` events. Errors keep their status and are re-shaped
+//! into Anthropic's `{"type": "error", ...}` body.
+
+use crate::encoding;
+use crate::proxy::{complete_chat, ProxyState, RequestContext};
+use crate::usage::{self, TokenCounts};
+use axum::body::Body;
+use axum::extract::{rejection::JsonRejection, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+use monkey_troop_shared::{
+    ChatCompletionRequest, ChatMessage, ContentPart, FunctionCall, FunctionDefinition, ImageUrl,
+    MessageContent, Tool, ToolCall,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Largest error body read back from the chat flow when re-shaping it.
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct MessagesRequest {
+    pub model: String,
+    /// Required by the Messages API, unlike `max_tokens` for chat completions
+    pub max_tokens: u32,
+    #[serde(default)]
+    pub system: Option<Content>,
+    pub messages: Vec<Message>,
+    #[serde(default)]
+    pub stop_sequences: Vec<String>,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub top_p: Option<f64>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub tools: Vec<ToolDefinition>,
+    #[serde(default)]
+    pub tool_choice: Option<ToolChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Message {
+    pub role: String,
+    pub content: Content,
+}
+
+/// A plain string, or a list of content blocks.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+    },
+    Image {
+        source: ImageSource,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        #[serde(default)]
+        content: Option<Content>,
+        #[serde(default)]
+        is_error: bool,
+    },
+    /// Documents, thinking blocks and anything newer, which have no chat equivalent
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageSource {
+    Base64 { media_type: String, data: String },
+    Url { url: String },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub input_schema: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolChoice {
+    Auto,
+    Any,
+    None,
+    Tool { name: String },
+}
+
+impl MessagesRequest {
+    /// The equivalent chat completion request, or why there is none.
+    pub fn to_chat_request(&self) -> Result<ChatCompletionRequest, String> {
+        let mut messages = Vec::new();
+        if let Some(system) = &self.system {
+            messages.push(chat_message("system", Some(text_of(system)?.into())));
+        }
+        for message in &self.messages {
+            translate_message(message, &mut messages)?;
+        }
+
+        let tools = self
+            .tools
+            .iter()
+            .map(|tool| Tool {
+                kind: "function".to_string(),
+                function: FunctionDefinition {
+                    name: tool.name.clone(),
+                    description: tool.description.clone(),
+                    parameters: Some(tool.input_schema.clone()),
+                    strict: None,
+                },
+            })
+            .collect::<Vec<_>>();
+        let tool_choice = self.tool_choice.as_ref().map(|choice| match choice {
+            ToolChoice::Auto => json!("auto"),
+            ToolChoice::Any => json!("required"),
+            ToolChoice::None => json!("none"),
+            ToolChoice::Tool { name } => json!({"type": "function", "function": {"name": name}}),
+        });
+
+        Ok(ChatCompletionRequest {
+            model: self.model.clone(),
+            messages,
+            stream: self.stream,
+            tools: (!tools.is_empty()).then_some(tools),
+            tool_choice,
+            max_tokens: Some(self.max_tokens),
+            stop: (!self.stop_sequences.is_empty()).then(|| json!(self.stop_sequences)),
+            temperature: self.temperature,
+            top_p: self.top_p,
+            seed: None,
+            // The final `message_delta` event carries the usage
+            stream_options: self.stream.then(|| json!({"include_usage": true})),
+            extra: Default::default(),
+        })
+    }
+}
+
+fn chat_message(role: &str, content: Option<MessageContent>) -> ChatMessage {
+    ChatMessage {
+        role: role.to_string(),
+        content,
+        tool_calls: None,
+        tool_call_id: None,
+    }
+}
+
+/// Append the chat messages for one Anthropic message. Tool results become `tool`
+/// messages, which the chat format needs right after the assistant's tool calls.
+fn translate_message(message: &Message, out: &mut Vec<ChatMessage>) -> Result<(), String> {
+    if !matches!(message.role.as_str(), "user" | "assistant") {
+        return Err(format!(
+            "messages: role must be 'user' or 'assistant', got '{}'",
+            message.role
+        ));
+    }
+    let blocks = match &message.content {
+        Content::Text(text) => {
+            out.push(chat_message(&message.role, Some(text.as_str().into())));
+            return Ok(());
+        }
+        Content::Blocks(blocks) => blocks,
+    };
+
+    let mut parts = Vec::new();
+    let mut tool_calls = Vec::new();
+    for block in blocks {
+        match block {
+            ContentBlock::Text { text } => parts.push(ContentPart::Text { text: text.clone() }),
+            ContentBlock::Image { source } => parts.push(ContentPart::ImageUrl {
+                image_url: ImageUrl {
+                    url: match source {
+                        ImageSource::Base64 { media_type, data } => {
+                            format!("data:{media_type};base64,{data}")
+                        }
+                        ImageSource::Url { url } => url.clone(),
+                    },
+                    detail: None,
+                },
+            }),
+            ContentBlock::ToolUse { id, name, input } => tool_calls.push(ToolCall {
+                id: id.clone(),
+                kind: "function".to_string(),
+                function: FunctionCall {
+                    name: name.clone(),
+                    arguments: input.to_string(),
+                },
+            }),
+            ContentBlock::ToolResult {
+                tool_use_id,
+                content,
+                is_error,
+            } => {
+                let mut text = content
+                    .as_ref()
+                    .map(text_of)
+                    .transpose()?
+                    .unwrap_or_default();
+                if *is_error {
+                    text = format!("Error: {text}");
+                }
+                out.push(ChatMessage {
+                    tool_call_id: Some(tool_use_id.clone()),
+                    ..chat_message("tool", Some(text.into()))
+                });
+            }
+            ContentBlock::Unsupported => {
+                return Err(
+                    "messages: only text, image, tool_use and tool_result content \
+                            blocks are supported"
+                        .to_string(),
+                )
+            }
+        }
+    }
+
+    // A user turn made only of tool results has nothing left to send
+    if parts.is_empty() && tool_calls.is_empty() {
+        return Ok(());
+    }
+    let content = if parts.is_empty() {
+        None
+    } else if parts
+        .iter()
+        .all(|part| matches!(part, ContentPart::Text { .. }))
+    {
+        Some(MessageContent::Parts(parts).text().into_owned().into())
+    } else {
+        Some(MessageContent::Parts(parts))
+    };
+    out.push(ChatMessage {
+        tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+        ..chat_message(&message.role, content)
+    });
+    Ok(())
+}
+
+/// Text of a system prompt or tool result, whose blocks may only be text.
+fn text_of(content: &Content) -> Result<String, String> {
+    match content {
+        Content::Text(text) => Ok(text.clone()),
+        Content::Blocks(blocks) => blocks
+            .iter()
+            .map(|block| match block {
+                ContentBlock::Text { text } => Ok(text.as_str()),
+                _ => Err("system prompts and tool results may only contain text".to_string()),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|texts| texts.join("\n")),
+    }
+}
+
+/// What the reply translation needs to know about the original request.
+pub struct ReplyContext {
+    /// The model as requested, before aliasing
+    pub model: String,
+    pub stop_sequences: Vec<String>,
+    /// For estimating input tokens when the engine reports no usage
+    pub prompt_chars: usize,
+}
+
+impl ReplyContext {
+    /// `stop_reason` and `stop_sequence` for a chat `finish_reason`. Which stop sequence
+    /// ended generation is only known when the engine names it in the choice's
+    /// `stop_reason`, as vLLM does.
+    fn stop_reason(
+        &self,
+        finish_reason: Option<&str>,
+        engine_stop: Option<&str>,
+        used_tools: bool,
+    ) -> (&'static str, Option<String>) {
+        match finish_reason {
+            Some("length") => ("max_tokens", None),
+            Some("tool_calls") => ("tool_use", None),
+            _ if used_tools => ("tool_use", None),
+            _ => match engine_stop.filter(|stop| self.stop_sequences.iter().any(|s| s == stop)) {
+                Some(stop) => ("stop_sequence", Some(stop.to_string())),
+                None => ("end_turn", None),
+            },
+        }
+    }
+
+    fn usage(&self, usage: &Value, output_chars: usize) -> Value {
+        let tokens = TokenCounts::from_usage(usage)
+            .unwrap_or_else(|| TokenCounts::estimate(self.prompt_chars, output_chars));
+        json!({"input_tokens": tokens.prompt, "output_tokens": tokens.completion})
+    }
+}
+
+fn message_id() -> String {
+    format!("msg_{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Tool call arguments as a JSON object; the Messages API has no room for invalid JSON.
+fn tool_input(arguments: &Value) -> Value {
+    arguments
+        .as_str()
+        .and_then(|args| serde_json::from_str(args).ok())
+        .unwrap_or_else(|| json!({}))
+}
+
+/// An Anthropic message for a buffered chat completion.
+pub fn message_from_completion(completion: &Value, reply: &ReplyContext) -> Value {
+    let choice = &completion["choices"][0];
+    let text = choice["message"]["content"].as_str().unwrap_or_default();
+    let mut content = Vec::new();
+    if !text.is_empty() {
+        content.push(json!({"type": "text", "text": text}));
+    }
+    let tool_calls = choice["message"]["tool_calls"].as_array();
+    for call in tool_calls.into_iter().flatten() {
+        content.push(json!({
+            "type": "tool_use",
+            "id": call["id"],
+            "name": call["function"]["name"],
+            "input": tool_input(&call["function"]["arguments"]),
+        }));
+    }
+    let (stop_reason, stop_sequence) = reply.stop_reason(
+        choice["finish_reason"].as_str(),
+        choice["stop_reason"].as_str(),
+        tool_calls.is_some_and(|calls| !calls.is_empty()),
+    );
+
+    json!({
+        "id": message_id(),
+        "type": "message",
+        "role": "assistant",
+        "model": reply.model,
+        "content": content,
+        "stop_reason": stop_reason,
+        "stop_sequence": stop_sequence,
+        "usage": reply.usage(&completion["usage"], text.chars().count()),
+    })
+}
+
+/// The content block currently open in a translated stream.
+#[derive(PartialEq)]
+enum OpenBlock {
+    Text,
+    /// A tool call, by its index in the chat stream's `tool_calls`
+    ToolUse(u64),
+}
+
+/// Rewrites a chat completion SSE stream into Anthropic stream events, chunk by chunk.
+pub struct StreamTranslator {
+    reply: ReplyContext,
+    pending: Vec<u8>,
+    started: bool,
+    finished: bool,
+    open: Option<OpenBlock>,
+    next_index: usize,
+    output_chars: usize,
+    usage: Value,
+    finish_reason: Option<String>,
+    engine_stop: Option<String>,
+    used_tools: bool,
+}
+
+impl StreamTranslator {
+    pub fn new(reply: ReplyContext) -> Self {
+        Self {
+            reply,
+            pending: Vec::new(),
+            started: false,
+            finished: false,
+            open: None,
+            next_index: 0,
+            output_chars: 0,
+            usage: Value::Null,
+            finish_reason: None,
+            engine_stop: None,
+            used_tools: false,
+        }
+    }
+
+    /// Events for the complete SSE lines of `chunk`; partial lines wait for the next chunk.
+    pub fn translate(&mut self, chunk: &[u8]) -> Bytes {
+        let mut out = String::new();
+        self.pending.extend_from_slice(chunk);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:").map(str::trim) else {
+                continue;
+            };
+            if data == "[DONE]" {
+                self.finish_into(&mut out);
+            } else if let Ok(chunk) = serde_json::from_str::<Value>(data) {
+                self.on_chunk(&chunk, &mut out);
+            }
+        }
+        Bytes::from(out)
+    }
+
+    /// Closing events, for a stream that ended without `[DONE]`; empty if already sent.
+    pub fn finish(&mut self) -> Bytes {
+        let mut out = String::new();
+        self.finish_into(&mut out);
+        Bytes::from(out)
+    }
+
+    fn on_chunk(&mut self, chunk: &Value, out: &mut String) {
+        if self.finished {
+            return;
+        }
+        self.start(out);
+        if chunk["usage"].is_object() {
+            self.usage = chunk["usage"].clone();
+        }
+        let choice = &chunk["choices"][0];
+        let delta = &choice["delta"];
+
+        if let Some(text) = delta["content"].as_str().filter(|t| !t.is_empty()) {
+            if self.open != Some(OpenBlock::Text) {
+                self.open_block(OpenBlock::Text, json!({"type": "text", "text": ""}), out);
+            }
+            self.output_chars += text.chars().count();
+            self.block_delta(json!({"type": "text_delta", "text": text}), out);
+        }
+        for call in delta["tool_calls"].as_array().into_iter().flatten() {
+            let index = call["index"].as_u64().unwrap_or(0);
+            if self.open != Some(OpenBlock::ToolUse(index)) {
+                self.used_tools = true;
+                let id = call["id"].as_str().map_or_else(
+                    || format!("toolu_{}", uuid::Uuid::new_v4().simple()),
+                    str::to_string,
+                );
+                let block = json!({
+                    "type": "tool_use",
+                    "id": id,
+                    "name": call["function"]["name"],
+                    "input": {},
+                });
+                self.open_block(OpenBlock::ToolUse(index), block, out);
+            }
+            if let Some(args) = call["function"]["arguments"]
+                .as_str()
+                .filter(|a| !a.is_empty())
+            {
+                self.block_delta(
+                    json!({"type": "input_json_delta", "partial_json": args}),
+                    out,
+                );
+            }
+        }
+
+        if let Some(reason) = choice["finish_reason"].as_str() {
+            self.finish_reason = Some(reason.to_string());
+        }
+        if let Some(stop) = choice["stop_reason"].as_str() {
+            self.engine_stop = Some(stop.to_string());
+        }
+    }
+
+    fn start(&mut self, out: &mut String) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        push_event(
+            out,
+            json!({
+                "type": "message_start",
+                "message": {
+                    "id": message_id(),
+                    "type": "message",
+                    "role": "assistant",
+                    "model": self.reply.model,
+                    "content": [],
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": self.reply.usage(&Value::Null, 0),
+                },
+            }),
+        );
+    }
+
+    fn open_block(&mut self, block: OpenBlock, content_block: Value, out: &mut String) {
+        self.close_block(out);
+        push_event(
+            out,
+            json!({
+                "type": "content_block_start",
+                "index": self.next_index,
+                "content_block": content_block,
+            }),
+        );
+        self.open = Some(block);
+    }
+
+    fn block_delta(&self, delta: Value, out: &mut String) {
+        push_event(
+            out,
+            json!({"type": "content_block_delta", "index": self.next_index, "delta": delta}),
+        );
+    }
+
+    fn close_block(&mut self, out: &mut String) {
+        if self.open.take().is_some() {
+            push_event(
+                out,
+                json!({"type": "content_block_stop", "index": self.next_index}),
+            );
+            self.next_index += 1;
+        }
+    }
+
+    fn finish_into(&mut self, out: &mut String) {
+        if self.finished {
+            return;
+        }
+        self.start(out);
+        self.finished = true;
+        self.close_block(out);
+        let (stop_reason, stop_sequence) = self.reply.stop_reason(
+            self.finish_reason.as_deref(),
+            self.engine_stop.as_deref(),
+            self.used_tools,
+        );
+        push_event(
+            out,
+            json!({
+                "type": "message_delta",
+                "delta": {"stop_reason": stop_reason, "stop_sequence": stop_sequence},
+                "usage": self.reply.usage(&self.usage, self.output_chars),
+            }),
+        );
+        push_event(out, json!({"type": "message_stop"}));
+    }
+}
+
+fn push_event(out: &mut String, data: Value) {
+    let kind = data["type"].as_str().unwrap_or_default();
+    out.push_str(&format!("event: {kind}\ndata: {data}\n\n"));
+}
+
+/// Anthropic's error type for an HTTP status.
+fn error_type(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        400 | 422 => "invalid_request_error",
+        401 => "authentication_error",
+        402 => "billing_error",
+        403 => "permission_error",
+        404 => "not_found_error",
+        413 => "request_too_large",
+        429 => "rate_limit_error",
+        503 | 529 => "overloaded_error",
+        504 => "timeout_error",
+        _ => "api_error",
+    }
+}
+
+pub fn error_response(status: StatusCode, message: impl Into<String>) -> Response {
+    let body = json!({
+        "type": "error",
+        "error": {"type": error_type(status), "message": message.into()},
+    });
+    (status, Json(body)).into_response()
+}
+
+/// Rewrite the chat flow's `response` for a Messages API caller, keeping its status and
+/// headers such as `Retry-After` and the request id.
+pub async fn translate_response(response: Response, reply: ReplyContext, stream: bool) -> Response {
+    let (mut parts, body) = response.into_parts();
+
+    if !parts.status.is_success() {
+        let body = axum::body::to_bytes(body, MAX_ERROR_BODY)
+            .await
+            .unwrap_or_default();
+        let message = serde_json::from_slice::<Value>(&body)
+            .ok()
+            .and_then(|v| v["error"]["message"].as_str().map(str::to_string))
+            .or_else(|| {
+                Some(String::from_utf8_lossy(&body).trim().to_string()).filter(|m| !m.is_empty())
+            })
+            .unwrap_or_else(|| {
+                parts
+                    .status
+                    .canonical_reason()
+                    .unwrap_or("Request failed")
+                    .to_string()
+            });
+        let mut translated = error_response(parts.status, message);
+        for (name, value) in parts.headers.iter() {
+            if !translated.headers().contains_key(name) && name != header::CONTENT_ENCODING {
+                translated.headers_mut().insert(name, value.clone());
+            }
+        }
+        return translated;
+    }
+
+    if stream {
+        let state = Some((body.into_data_stream(), StreamTranslator::new(reply)));
+        let events = stream::unfold(state, |state| async move {
+            let (mut body, mut translator) = state?;
+            Some(match body.next().await {
+                Some(Ok(chunk)) => (Ok(translator.translate(&chunk)), Some((body, translator))),
+                Some(Err(e)) => (Err(e), Some((body, translator))),
+                None => (Ok(translator.finish()), None),
+            })
+        });
+        return Response::from_parts(parts, Body::from_stream(events));
+    }
+
+    let encoding = parts
+        .headers
+        .remove(header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok().map(str::to_string));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let completion = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => encoding::decode_body(encoding.as_deref(), body)
+            .ok()
+            .and_then(|body| serde_json::from_slice::<Value>(&body).ok()),
+        Err(_) => None,
+    };
+    let Some(completion) = completion else {
+        return error_response(
+            StatusCode::BAD_GATEWAY,
+            "Worker returned an unreadable chat completion",
+        );
+    };
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    let message = message_from_completion(&completion, &reply);
+    Response::from_parts(parts, Body::from(message.to_string()))
+}
+
+/// Anthropic Messages API, served by translating to and from a chat completion.
+pub(crate) async fn messages_handler(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    payload: Result<Json<MessagesRequest>, JsonRejection>,
+) -> Response {
+    let payload = match payload {
+        Ok(Json(payload)) => payload,
+        Err(rejection) => return error_response(StatusCode::BAD_REQUEST, rejection.body_text()),
+    };
+    let chat = match payload.to_chat_request() {
+        Ok(chat) => chat,
+        Err(message) => return error_response(StatusCode::BAD_REQUEST, message),
+    };
+
+    let mut request = RequestContext::from_headers(&headers, &state.config);
+    // The reply is rewritten into Anthropic's format, so it must arrive plain
+    request.accept_encoding = None;
+    let reply = ReplyContext {
+        model: payload.model,
+        stop_sequences: payload.stop_sequences,
+        prompt_chars: usage::prompt_chars(&chat.messages),
+    };
+    let (response, _) = complete_chat(&state, request, chat).await;
+    translate_response(response, reply, payload.stream).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(stop_sequences: &[&str]) -> ReplyContext {
+        ReplyContext {
+            model: "claude-sonnet".to_string(),
+            stop_sequences: stop_sequences.iter().map(|s| s.to_string()).collect(),
+            prompt_chars: 40,
+        }
+    }
+
+    /// The `data` of each event in an Anthropic stream, checking it matches the event name.
+    fn events(stream: &str) -> Vec<Value> {
+        stream
+            .split("\n\n")
+            .filter(|event| !event.is_empty())
+            .map(|event| {
+                let (name, data) = event.split_once('\n').unwrap();
+                let data: Value =
+                    serde_json::from_str(data.strip_prefix("data: ").unwrap()).unwrap();
+                assert_eq!(name.strip_prefix("event: ").unwrap(), data["type"]);
+                data
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_request_translation() {
+        let request: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet",
+            "max_tokens": 512,
+            "system": [{"type": "text", "text": "Be brief."}, {"type": "text", "text": "Be kind."}],
+            "stop_sequences": ["END"],
+            "temperature": 0.5,
+            "tools": [{"name": "get_weather", "input_schema": {"type": "object"}}],
+            "tool_choice": {"type": "any"},
+            "messages": [
+                {"role": "user", "content": [
+                    {"type": "text", "text": "Where is this?"},
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBO"}}
+                ]},
+                {"role": "assistant", "content": [
+                    {"type": "text", "text": "Checking."},
+                    {"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {"city": "Paris"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": [{"type": "text", "text": "18C"}]},
+                    {"type": "text", "text": "And tomorrow?"}
+                ]}
+            ]
+        }))
+        .unwrap();
+
+        let chat = serde_json::to_value(request.to_chat_request().unwrap()).unwrap();
+        assert_eq!(
+            chat,
+            json!({
+                "model": "claude-sonnet",
+                "messages": [
+                    {"role": "system", "content": "Be brief.\nBe kind."},
+                    {"role": "user", "content": [
+                        {"type": "text", "text": "Where is this?"},
+                        {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBO"}}
+                    ]},
+                    {"role": "assistant", "content": "Checking.", "tool_calls": [{
+                        "id": "toolu_1",
+                        "type": "function",
+                        "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}
+                    }]},
+                    {"role": "tool", "content": "18C", "tool_call_id": "toolu_1"},
+                    {"role": "user", "content": "And tomorrow?"}
+                ],
+                "stream": false,
+                "tools": [{
+                    "type": "function",
+                    "function": {"name": "get_weather", "parameters": {"type": "object"}}
+                }],
+                "tool_choice": "required",
+                "max_tokens": 512,
+                "stop": ["END"],
+                "temperature": 0.5
+            })
+        );
+
+        let unsupported: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet",
+            "max_tokens": 512,
+            "messages": [{"role": "user", "content": [{"type": "document", "source": {}}]}]
+        }))
+        .unwrap();
+        assert!(unsupported.to_chat_request().is_err());
+    }
+
+    #[test]
+    fn test_buffered_reply_translation() {
+        let completion = json!({
+            "choices": [{
+                "message": {"role": "assistant", "content": "Hello"},
+                "finish_reason": "stop",
+                "stop_reason": "END"
+            }],
+            "usage": {"prompt_tokens": 12, "completion_tokens": 3}
+        });
+        let message = message_from_completion(&completion, &reply(&["END"]));
+        assert!(message["id"].as_str().unwrap().starts_with("msg_"));
+        assert_eq!(message["model"], "claude-sonnet");
+        assert_eq!(
+            message["content"],
+            json!([{"type": "text", "text": "Hello"}])
+        );
+        assert_eq!(message["stop_reason"], "stop_sequence");
+        assert_eq!(message["stop_sequence"], "END");
+        assert_eq!(
+            message["usage"],
+            json!({"input_tokens": 12, "output_tokens": 3})
+        );
+
+        // Tool calls, and usage estimated when the engine reports none
+        let completion = json!({
+            "choices": [{
+                "message": {"content": null, "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"city\": \"Paris\"}"}
+                }]},
+                "finish_reason": "tool_calls"
+            }]
+        });
+        let message = message_from_completion(&completion, &reply(&[]));
+        assert_eq!(
+            message["content"],
+            json!([{"type": "tool_use", "id": "call_1", "name": "get_weather", "input": {"city": "Paris"}}])
+        );
+        assert_eq!(message["stop_reason"], "tool_use");
+        assert_eq!(message["stop_sequence"], Value::Null);
+        assert_eq!(
+            message["usage"],
+            json!({"input_tokens": 10, "output_tokens": 0})
+        );
+    }
+
+    #[test]
+    fn test_stream_translation() {
+        let sse = concat!(
+            "data: {\"choices\": [{\"delta\": {\"role\": \"assistant\", \"content\": \"\"}}]}\n\n",
+            "data: {\"choices\": [{\"delta\": {\"content\": \"Hel\"}}]}\n\n",
+            "data: {\"choices\": [{\"delta\": {\"content\": \"lo\"}}]}\n\n",
+            "data: {\"choices\": [{\"delta\": {\"tool_calls\": [{\"index\": 0, \"id\": \"call_1\", ",
+            "\"function\": {\"name\": \"get_weather\", \"arguments\": \"{\\\"city\\\"\"}}]}}]}\n\n",
+            "data: {\"choices\": [{\"delta\": {\"tool_calls\": [{\"index\": 0, ",
+            "\"function\": {\"arguments\": \": \\\"Paris\\\"}\"}}]}, \"finish_reason\": \"tool_calls\"}]}\n\n",
+            "data: {\"choices\": [], \"usage\": {\"prompt_tokens\": 12, \"completion_tokens\": 7}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let mut translator = StreamTranslator::new(reply(&[]));
+        // Chunks split mid-event are held back until the line is complete
+        let (head, tail) = sse.as_bytes().split_at(100);
+        let mut out = translator.translate(head).to_vec();
+        out.extend_from_slice(&translator.translate(tail));
+        assert!(translator.finish().is_empty());
+
+        let events = events(std::str::from_utf8(&out).unwrap());
+        let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+        assert_eq!(
+            types,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop"
+            ]
+        );
+        assert_eq!(events[0]["message"]["model"], "claude-sonnet");
+        assert_eq!(
+            events[2]["delta"],
+            json!({"type": "text_delta", "text": "Hel"})
+        );
+        assert_eq!(
+            events[5]["content_block"],
+            json!({"type": "tool_use", "id": "call_1", "name": "get_weather", "input": {}})
+        );
+        assert_eq!(events[5]["index"], 1);
+        assert_eq!(events[7]["delta"]["partial_json"], ": \"Paris\"}");
+        assert_eq!(
+            events[9],
+            json!({
+                "type": "message_delta",
+                "delta": {"stop_reason": "tool_use", "stop_sequence": null},
+                "usage": {"input_tokens": 12, "output_tokens": 7}
+            })
+        );
+    }
+
+    #[test]
+    fn test_stream_without_done_is_closed_on_finish() {
+        let mut translator = StreamTranslator::new(reply(&[]));
+        let out = translator.translate(
+            b"data: {\"choices\": [{\"delta\": {\"content\": \"Hi\"}, \"finish_reason\": \"length\"}]}\n\n",
+        );
+        let mut stream = String::from_utf8(out.to_vec()).unwrap();
+        stream.push_str(std::str::from_utf8(&translator.finish()).unwrap());
+
+        let events = events(&stream);
+        assert_eq!(events.len(), 6);
+        assert_eq!(events[4]["delta"]["stop_reason"], "max_tokens");
+        // Estimated from 40 prompt and 2 output characters
+        assert_eq!(
+            events[4]["usage"],
+            json!({"input_tokens": 10, "output_tokens": 1})
+        );
+        assert_eq!(events[5]["type"], "message_stop");
+    }
+}
@@ -0,0 +1,291 @@
+//! Local request/response audit log written as JSON Lines.
+//!
+//! Records are handed to a background task over a bounded channel so the request
+//! path never waits on disk I/O. The writer re-opens the log file whenever it has
+//! been moved or deleted, which keeps it compatible with `logrotate`-style rotation.
+
+use chrono::{DateTime, Utc};
+use monkey_troop_shared::{ChatMessage, MessageContent};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+/// Maximum number of records buffered before new records are dropped.
+const AUDIT_CHANNEL_CAPACITY: usize = 1024;
+
+/// A single request message as it appears in the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditMessage {
+    pub role: String,
+    pub content_chars: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+impl AuditMessage {
+    pub fn new(role: &str, content: &str, include_content: bool) -> Self {
+        Self {
+            role: role.to_string(),
+            content_chars: content.chars().count(),
+            content: include_content.then(|| content.to_string()),
+        }
+    }
+}
+
+/// One proxied chat completion exchange.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub request_id: String,
+    pub model: String,
+    pub node_ip: Option<String>,
+    pub status: u16,
+    /// Until the reply was complete; for a stream, until its last chunk was relayed
+    pub latency_ms: u64,
+    pub stream: bool,
+    /// As reported by the engine, in its reply or a stream's final chunk
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    pub total_tokens: Option<u64>,
+    pub messages: Vec<AuditMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<AuditMessage>,
+}
+
+/// Handle used by the proxy to submit audit records.
+#[derive(Clone)]
+pub struct AuditLogger {
+    tx: mpsc::Sender<AuditRecord>,
+    include_content: bool,
+}
+
+impl AuditLogger {
+    /// Start the background writer appending to `path`.
+    pub fn spawn(path: PathBuf, include_content: bool) -> Self {
+        let (tx, rx) = mpsc::channel(AUDIT_CHANNEL_CAPACITY);
+        tokio::spawn(run_writer(path, rx));
+        Self {
+            tx,
+            include_content,
+        }
+    }
+
+    /// Whether message content should be recorded verbatim instead of redacted.
+    pub fn include_content(&self) -> bool {
+        self.include_content
+    }
+
+    /// Build the redacted (or verbatim) view of the request messages.
+    pub fn audit_messages(&self, messages: &[ChatMessage]) -> Vec<AuditMessage> {
+        messages
+            .iter()
+            .map(|m| {
+                AuditMessage::new(
+                    &m.role,
+                    &m.content
+                        .as_ref()
+                        .map(MessageContent::text)
+                        .unwrap_or_default(),
+                    self.include_content,
+                )
+            })
+            .collect()
+    }
+
+    /// Queue a record without waiting. Records are dropped if the writer falls behind.
+    pub fn log(&self, record: AuditRecord) {
+        if let Err(e) = self.tx.try_send(record) {
+            warn!("Dropping audit record: {}", e);
+        }
+    }
+}
+
+struct OpenLog {
+    file: File,
+    id: Option<(u64, u64)>,
+}
+
+/// What tells a file apart from another later created at the same path, where the
+/// platform has it.
+#[cfg(unix)]
+pub fn file_id(meta: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+pub fn file_id(_meta: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+async fn open_log(path: &Path) -> std::io::Result<OpenLog> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    let id = file_id(&file.metadata().await?);
+    Ok(OpenLog { file, id })
+}
+
+/// True when the file at `path` is no longer the one we hold open (rotated or removed).
+async fn is_rotated(path: &Path, current: &OpenLog) -> bool {
+    match tokio::fs::metadata(path).await {
+        Ok(meta) => file_id(&meta) != current.id,
+        Err(_) => true,
+    }
+}
+
+async fn run_writer(path: PathBuf, mut rx: mpsc::Receiver<AuditRecord>) {
+    let mut current: Option<OpenLog> = None;
+
+    while let Some(record) = rx.recv().await {
+        let mut line = match serde_json::to_vec(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize audit record: {}", e);
+                continue;
+            }
+        };
+        line.push(b'\n');
+
+        let stale = match current.as_ref() {
+            Some(log) => is_rotated(&path, log).await,
+            None => true,
+        };
+        if stale {
+            current = match open_log(&path).await {
+                Ok(log) => Some(log),
+                Err(e) => {
+                    error!("Failed to open audit log {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+        }
+
+        if let Some(log) = current.as_mut() {
+            if let Err(e) = log.file.write_all(&line).await {
+                error!("Failed to write audit record: {}", e);
+                current = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn temp_log_path(name: &str) -> PathBuf {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        std::env::temp_dir().join(format!(
+            "monkey-troop-audit-{name}-{}-{nanos}.jsonl",
+            std::process::id()
+        ))
+    }
+
+    fn sample_record(logger: &AuditLogger) -> AuditRecord {
+        AuditRecord {
+            timestamp: Utc::now(),
+            request_id: "req-1".to_string(),
+            model: "llama3:8b".to_string(),
+            node_ip: Some("100.64.0.1".to_string()),
+            status: 200,
+            latency_ms: 42,
+            stream: false,
+            prompt_tokens: Some(3),
+            completion_tokens: Some(5),
+            total_tokens: Some(8),
+            messages: logger.audit_messages(&[ChatMessage {
+                role: "user".to_string(),
+                content: Some("secret prompt".into()),
+                tool_calls: None,
+                tool_call_id: None,
+            }]),
+            response: None,
+        }
+    }
+
+    async fn read_lines_eventually(path: &Path, expected: usize) -> Vec<serde_json::Value> {
+        for _ in 0..50 {
+            if let Ok(text) = tokio::fs::read_to_string(path).await {
+                let lines: Vec<_> = text
+                    .lines()
+                    .map(|l| serde_json::from_str(l).unwrap())
+                    .collect();
+                if lines.len() >= expected {
+                    return lines;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!(
+            "audit log {} never reached {expected} lines",
+            path.display()
+        );
+    }
+
+    #[test]
+    fn test_audit_message_redacts_by_default() {
+        let msg = AuditMessage::new("user", "héllo", false);
+        assert_eq!(msg.content_chars, 5);
+        assert!(msg.content.is_none());
+
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(!json.contains("héllo"));
+    }
+
+    #[test]
+    fn test_audit_message_includes_content_when_opted_in() {
+        let msg = AuditMessage::new("user", "hello", true);
+        assert_eq!(msg.content.as_deref(), Some("hello"));
+    }
+
+    #[tokio::test]
+    async fn test_writer_appends_json_lines() {
+        let path = temp_log_path("append");
+        let logger = AuditLogger::spawn(path.clone(), false);
+
+        logger.log(sample_record(&logger));
+        logger.log(sample_record(&logger));
+
+        let lines = read_lines_eventually(&path, 2).await;
+        assert_eq!(lines[0]["model"], "llama3:8b");
+        assert_eq!(lines[0]["total_tokens"], 8);
+        assert_eq!(lines[0]["messages"][0]["content_chars"], 13);
+        assert!(lines[0]["messages"][0].get("content").is_none());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_writer_reopens_after_rotation() {
+        let path = temp_log_path("rotate");
+        let rotated = path.with_extension("jsonl.1");
+        let logger = AuditLogger::spawn(path.clone(), true);
+
+        logger.log(sample_record(&logger));
+        read_lines_eventually(&path, 1).await;
+
+        std::fs::rename(&path, &rotated).unwrap();
+        logger.log(sample_record(&logger));
+
+        let lines = read_lines_eventually(&path, 1).await;
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["messages"][0]["content"], "secret prompt");
+        assert_eq!(
+            std::fs::read_to_string(&rotated).unwrap().lines().count(),
+            1
+        );
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&rotated);
+    }
+}
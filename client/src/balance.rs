@@ -0,0 +1,141 @@
+//! `balance --watch`: polling the credit balance and showing how it moves.
+//!
+//! Each poll is compared with the last successful one, so the change since the previous
+//! check is shown next to the balance. A poll that fails keeps showing the last known
+//! balance, marked stale, instead of ending the watch: the coordinator being briefly
+//! unreachable says nothing about the balance.
+
+use serde::Serialize;
+
+/// Seconds between polls when `--watch` is given without an interval.
+pub const DEFAULT_WATCH_INTERVAL_SECS: &str = "10";
+
+/// What one poll found.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Reading {
+    /// The balance, or the last known one when `stale`
+    pub balance_seconds: Option<i64>,
+    /// Change since the previous successful poll
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<i64>,
+    pub stale: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Reading {
+    /// The fresh balance when it is under `threshold`.
+    pub fn below(&self, threshold: i64) -> Option<i64> {
+        self.balance_seconds
+            .filter(|&balance| !self.stale && balance < threshold)
+    }
+
+    /// The reading as one line of text.
+    pub fn line(&self) -> String {
+        let mut line = match self.balance_seconds {
+            Some(balance) => format!(
+                "Balance: {} seconds ({:.2} hours)",
+                balance,
+                balance as f64 / 3600.0
+            ),
+            None => "Balance: unknown".to_string(),
+        };
+        match (self.change, &self.error) {
+            (_, Some(error)) => line.push_str(&format!("  [stale: {error}]")),
+            (Some(0), None) => line.push_str("  no change since last check"),
+            (Some(change), None) => line.push_str(&format!("  {change:+} since last check")),
+            (None, None) => {}
+        }
+        line
+    }
+
+    /// The reading as tab-separated balance, change and freshness, "-" where unknown.
+    pub fn plain(&self) -> String {
+        let known = |value: Option<i64>| value.map_or("-".to_string(), |v| v.to_string());
+        let freshness = if self.stale { "stale" } else { "fresh" };
+        format!(
+            "{}\t{}\t{freshness}",
+            known(self.balance_seconds),
+            known(self.change)
+        )
+    }
+}
+
+/// The last balance seen, carried from one poll to the next.
+#[derive(Debug, Default)]
+pub struct BalanceWatch {
+    last: Option<i64>,
+}
+
+impl BalanceWatch {
+    pub fn observe(&mut self, fetched: Result<i64, String>) -> Reading {
+        match fetched {
+            Ok(balance) => {
+                let change = self.last.map(|last| balance - last);
+                self.last = Some(balance);
+                Reading {
+                    balance_seconds: Some(balance),
+                    change,
+                    stale: false,
+                    error: None,
+                }
+            }
+            Err(error) => Reading {
+                balance_seconds: self.last,
+                change: None,
+                stale: true,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_change_is_since_last_successful_poll() {
+        let mut watch = BalanceWatch::default();
+
+        let first = watch.observe(Ok(3600));
+        assert_eq!(first.line(), "Balance: 3600 seconds (1.00 hours)");
+
+        let drained = watch.observe(Ok(3558));
+        assert_eq!(drained.change, Some(-42));
+        assert_eq!(
+            drained.line(),
+            "Balance: 3558 seconds (0.99 hours)  -42 since last check"
+        );
+
+        let failed = watch.observe(Err("coordinator unreachable".to_string()));
+        assert_eq!(failed.balance_seconds, Some(3558));
+        assert_eq!(
+            failed.line(),
+            "Balance: 3558 seconds (0.99 hours)  [stale: coordinator unreachable]"
+        );
+        assert_eq!(failed.plain(), "3558\t-\tstale");
+
+        let recovered = watch.observe(Ok(3558));
+        assert_eq!(
+            recovered.line(),
+            "Balance: 3558 seconds (0.99 hours)  no change since last check"
+        );
+        assert_eq!(recovered.plain(), "3558\t0\tfresh");
+    }
+
+    #[test]
+    fn test_failure_before_any_balance() {
+        let reading = BalanceWatch::default().observe(Err("timed out".to_string()));
+        assert_eq!(reading.line(), "Balance: unknown  [stale: timed out]");
+        assert_eq!(reading.below(100), None);
+    }
+
+    #[test]
+    fn test_only_a_fresh_balance_counts_as_below() {
+        let mut watch = BalanceWatch::default();
+        assert_eq!(watch.observe(Ok(100)).below(100), None);
+        assert_eq!(watch.observe(Ok(99)).below(100), Some(99));
+        assert_eq!(watch.observe(Err("down".to_string())).below(100), None);
+    }
+}
@@ -0,0 +1,283 @@
+//! Batches of chat completions for `POST /v1/batch`.
+//!
+//! Each request in a batch goes through the normal chat flow with its own authorization
+//! ticket, so the coordinator spreads the work across nodes. Up to `BATCH_PARALLELISM`
+//! requests run at once. A failed request is reported in its own result and never
+//! aborts the rest of the batch. Results are returned together, ordered by index, or
+//! with `"stream": true` as NDJSON lines in completion order.
+
+use crate::proxy::{complete_chat, resolve_request_id, ProxyError, ProxyState, RequestContext};
+use axum::extract::{rejection::JsonRejection, State};
+use axum::http::{header, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use bytes::Bytes;
+use futures::StreamExt;
+use monkey_troop_shared::{ApiErrorBody, ChatCompletionRequest, TroopError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+#[derive(Debug, Deserialize)]
+pub struct BatchRequest {
+    /// Chat completion requests; each is validated on its own, so one malformed entry
+    /// only fails its own result
+    pub requests: Vec<Value>,
+    /// Send each result as an NDJSON line as soon as it finishes
+    #[serde(default)]
+    pub stream: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchItemResult {
+    /// Position of the request in the batch
+    pub index: usize,
+    /// Node that served the request, if it reached one
+    pub node: Option<String>,
+    pub latency_ms: u64,
+    pub status: u16,
+    /// The chat completion, on success
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<Value>,
+    /// OpenAI-style `{"message", "type", "code"}`, on failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Value>,
+}
+
+/// Parse one entry of a batch. Streaming is refused, as the batch returns whole replies.
+pub fn parse_item(value: Value) -> Result<ChatCompletionRequest, ApiErrorBody> {
+    let request: ChatCompletionRequest = serde_json::from_value(value).map_err(|e| {
+        ApiErrorBody::new(
+            format!("Invalid chat completion request: {e}"),
+            "invalid_request_error",
+            "invalid_request",
+        )
+    })?;
+    if request.stream {
+        return Err(ApiErrorBody::new(
+            "stream is not supported for requests in a batch",
+            "invalid_request_error",
+            "invalid_request",
+        ));
+    }
+    Ok(request)
+}
+
+impl BatchItemResult {
+    /// The result for a request that got `status` and `body` back from the chat flow.
+    pub fn from_reply(
+        index: usize,
+        node: Option<String>,
+        latency: Duration,
+        status: u16,
+        body: &Bytes,
+    ) -> Self {
+        let parsed = serde_json::from_slice::<Value>(body).ok();
+        let (response, error) = if (200..300).contains(&status) {
+            (Some(parsed.unwrap_or(Value::Null)), None)
+        } else {
+            let error = match parsed {
+                Some(Value::Object(mut body))
+                    if body.get("error").is_some_and(Value::is_object) =>
+                {
+                    body.remove("error")
+                }
+                _ => serde_json::to_value(
+                    ApiErrorBody::for_status(status, String::from_utf8_lossy(body)).error,
+                )
+                .ok(),
+            };
+            (None, error)
+        };
+        Self {
+            index,
+            node,
+            latency_ms: latency.as_millis() as u64,
+            status,
+            response,
+            error,
+        }
+    }
+
+    /// The result for a request that was rejected before it was sent.
+    pub fn rejected(index: usize, status: u16, error: ApiErrorBody) -> Self {
+        Self {
+            index,
+            node: None,
+            latency_ms: 0,
+            status,
+            response: None,
+            error: serde_json::to_value(error.error).ok(),
+        }
+    }
+}
+
+/// Run a batch of chat completions, each with its own ticket, at most
+/// `BATCH_PARALLELISM` at a time. See `batch` for the request and result format. With
+/// `MAX_CONCURRENT_REQUESTS` set, each request takes its own slot, queued like any other
+/// request, so a batch never sends more at once than the limit allows.
+pub(crate) async fn batch_handler(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    payload: Result<Json<BatchRequest>, JsonRejection>,
+) -> Response {
+    let batch = match payload {
+        Ok(Json(batch)) if !batch.requests.is_empty() => batch,
+        Ok(_) => {
+            return ProxyError::Troop(TroopError::InvalidRequest(
+                "requests must list at least one chat completion request".to_string(),
+            ))
+            .into_response()
+        }
+        Err(rejection) => {
+            return ProxyError::Troop(TroopError::InvalidRequest(rejection.body_text()))
+                .into_response()
+        }
+    };
+
+    let batch_id = resolve_request_id(&headers);
+    let total = batch.requests.len();
+    let parallelism = match &state.concurrency {
+        Some(limit) => state
+            .config
+            .batch_parallelism
+            .min(limit.status().max_concurrent),
+        None => state.config.batch_parallelism,
+    };
+    info!(
+        "Batch {}: {} request(s), {} at a time",
+        batch_id, total, parallelism
+    );
+    let results = futures::stream::iter(batch.requests.into_iter().enumerate())
+        .map(move |(index, item)| {
+            let state = state.clone();
+            let headers = headers.clone();
+            let id = format!("{batch_id}-{index}");
+            async move {
+                let payload = match parse_item(item) {
+                    Ok(payload) => payload,
+                    Err(error) => return BatchItemResult::rejected(index, 400, error),
+                };
+                // Each request gets its own timeout, counted from when it starts. No session
+                // pinning, so the batch spreads over nodes, and the reply must arrive plain
+                // to be embedded in the result.
+                let mut request = RequestContext::from_headers(&headers, &state.config);
+                request.id = id;
+                request.session = None;
+                request.accept_encoding = None;
+                let started = Instant::now();
+                let permit = match &state.concurrency {
+                    Some(limit) => match limit.acquire().await {
+                        Ok(permit) => Some(permit),
+                        Err(rejection) => {
+                            return item_result(index, None, started, rejection.into_response())
+                                .await
+                        }
+                    },
+                    None => None,
+                };
+                let (response, node) = complete_chat(&state, request, payload).await;
+                let result = item_result(index, node, started, response).await;
+                drop(permit);
+                result
+            }
+        })
+        .buffer_unordered(parallelism);
+
+    let mut done = 0;
+    let results = results.inspect(move |result| {
+        done += 1;
+        info!(
+            "Batch request {} finished with {} ({}/{} done)",
+            result.index, result.status, done, total
+        );
+    });
+
+    if batch.stream {
+        let lines = results.map(|result| {
+            let mut line = serde_json::to_vec(&result).unwrap_or_default();
+            line.push(b'\n');
+            Ok::<_, std::convert::Infallible>(Bytes::from(line))
+        });
+        return (
+            [(header::CONTENT_TYPE, "application/x-ndjson")],
+            axum::body::Body::from_stream(lines),
+        )
+            .into_response();
+    }
+
+    let mut results: Vec<BatchItemResult> = results.collect().await;
+    results.sort_by_key(|result| result.index);
+    Json(serde_json::json!({ "results": results })).into_response()
+}
+
+/// The result for the request at `index`, read from the chat flow's `response`.
+async fn item_result(
+    index: usize,
+    node: Option<String>,
+    started: Instant,
+    response: Response,
+) -> BatchItemResult {
+    let status = response.status().as_u16();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap_or_default();
+    BatchItemResult::from_reply(index, node, started.elapsed(), status, &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_item_rejects_malformed_and_streaming_requests() {
+        let ok = parse_item(json!({"model": "m", "messages": []})).unwrap();
+        assert_eq!(ok.model, "m");
+        let err = parse_item(json!({"messages": []})).unwrap_err();
+        assert_eq!(err.error.code, "invalid_request");
+        let err = parse_item(json!({"model": "m", "messages": [], "stream": true})).unwrap_err();
+        assert!(err.error.message.contains("stream"));
+    }
+
+    #[test]
+    fn test_result_from_reply() {
+        let success = BatchItemResult::from_reply(
+            2,
+            Some("100.64.0.7".to_string()),
+            Duration::from_millis(120),
+            200,
+            &Bytes::from_static(br#"{"choices": []}"#),
+        );
+        assert_eq!(
+            serde_json::to_value(success).unwrap(),
+            json!({
+                "index": 2,
+                "node": "100.64.0.7",
+                "latency_ms": 120,
+                "status": 200,
+                "response": {"choices": []}
+            })
+        );
+
+        let failure = BatchItemResult::from_reply(
+            0,
+            None,
+            Duration::ZERO,
+            403,
+            &Bytes::from_static(
+                br#"{"error": {"message": "no", "type": "permission_error", "code": "model_not_allowed"}}"#,
+            ),
+        );
+        assert_eq!(failure.error.unwrap()["code"], "model_not_allowed");
+        assert!(failure.response.is_none());
+
+        let plain = BatchItemResult::from_reply(1, None, Duration::ZERO, 502, &Bytes::from("oops"));
+        assert_eq!(
+            plain.error.unwrap(),
+            json!({"message": "oops", "type": "api_error", "code": "upstream_error"})
+        );
+    }
+}
@@ -0,0 +1,346 @@
+//! End-to-end speed of a model on troop nodes, for the `bench` command.
+//!
+//! Each run is a streamed chat completion through the proxy's own authorization and
+//! worker calls (`proxy::chat_completion`), timed from this machine. Time to first token
+//! covers authorization, the trip to the node and prompt processing; tokens per second is
+//! the rate the rest of the reply arrived at. Every run sends the same prompt verbatim,
+//! and each node's first `warmup` runs are left out of its summary, so a model still
+//! loading does not skew the numbers.
+//!
+//! Runs share a session, which keeps them on one node. With `all_nodes`, each node that
+//! hosts the model gets its own session pinned to it, so the coordinator is asked for
+//! that node; every run is credited to the node that actually served it.
+
+use crate::chat::{self, Event, EventReader};
+use crate::config::Config;
+use crate::proxy::{self, ProxyState};
+use anyhow::{bail, Result};
+use futures::StreamExt;
+use monkey_troop_shared::{ChatCompletionRequest, NodeStatus, PeersResponse, DISCOVERY_TIMEOUT};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+pub struct Options {
+    pub model: String,
+    pub prompt: String,
+    /// Measured runs per node
+    pub runs: u32,
+    /// Runs per node before the measured ones
+    pub warmup: u32,
+    pub max_tokens: u32,
+    pub all_nodes: bool,
+}
+
+/// One timed completion.
+#[derive(Debug, Clone, Serialize)]
+pub struct Run {
+    /// Address of the node that served it; `None` when none was reached
+    pub node: Option<String>,
+    pub node_id: Option<String>,
+    /// Left out of the summary
+    pub warmup: bool,
+    /// `None` when no token arrived
+    pub ttft_ms: Option<f64>,
+    pub total_ms: f64,
+    pub completion_tokens: u64,
+    /// `None` without at least two tokens to time between
+    pub tokens_per_sec: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// The measured runs credited to one node.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeSummary {
+    /// Address of the node, or "-" for runs that never reached one
+    pub node: String,
+    pub node_id: Option<String>,
+    pub runs: usize,
+    pub failed: usize,
+    pub avg_ttft_ms: Option<f64>,
+    pub avg_tokens_per_sec: Option<f64>,
+    pub best_tokens_per_sec: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BenchReport {
+    pub model: String,
+    pub max_tokens: u32,
+    pub nodes: Vec<NodeSummary>,
+    /// Every run in the order made, warm-up runs included
+    pub runs: Vec<Run>,
+}
+
+/// Run the benchmark described by `options`. Progress goes to stderr.
+pub async fn run(config: Config, options: &Options) -> Result<BenchReport> {
+    let state = ProxyState::new(config);
+    let peers = state
+        .coordinators
+        .get_json_once::<PeersResponse>("peers", DISCOVERY_TIMEOUT)
+        .await;
+    let node_ids: HashMap<String, String> = peers
+        .as_ref()
+        .map(|peers| {
+            peers
+                .nodes
+                .iter()
+                .map(|node| (node.tailscale_ip.clone(), node.node_id.clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // `None` leaves the choice of node to the coordinator
+    let targets: Vec<Option<String>> = if options.all_nodes {
+        let peers = peers?;
+        let targets: Vec<_> = peers
+            .nodes
+            .iter()
+            .filter(|node| !matches!(node.status, NodeStatus::Offline))
+            .filter(|node| node.models.iter().any(|m| m.name == options.model))
+            .map(|node| Some(node.tailscale_ip.clone()))
+            .collect();
+        if targets.is_empty() {
+            bail!("No online node hosts {}", options.model);
+        }
+        targets
+    } else {
+        vec![None]
+    };
+
+    let request = completion_request(options);
+    let mut runs = Vec::new();
+    for target in targets {
+        let session = uuid::Uuid::new_v4().to_string();
+        if let Some(node_ip) = &target {
+            state.sessions.pin(&session, &options.model, node_ip);
+        }
+        for index in 0..options.warmup + options.runs {
+            let mut run = timed_run(&state, request.clone(), session.clone()).await;
+            run.warmup = index < options.warmup;
+            run.node_id = run.node.as_ref().and_then(|ip| node_ids.get(ip).cloned());
+            eprintln!("{}", progress_line(&run, index, options));
+            runs.push(run);
+        }
+    }
+
+    Ok(BenchReport {
+        model: options.model.clone(),
+        max_tokens: options.max_tokens,
+        nodes: summarize(&runs),
+        runs,
+    })
+}
+
+fn completion_request(options: &Options) -> ChatCompletionRequest {
+    ChatCompletionRequest {
+        model: options.model.clone(),
+        messages: vec![chat::message("user", &options.prompt)],
+        stream: true,
+        tools: None,
+        tool_choice: None,
+        max_tokens: Some(options.max_tokens),
+        stop: None,
+        temperature: None,
+        top_p: None,
+        seed: None,
+        stream_options: Some(serde_json::json!({"include_usage": true})),
+        extra: Default::default(),
+    }
+}
+
+/// Send `request` and time its reply. Failures are recorded in the run, not returned.
+async fn timed_run(state: &ProxyState, request: ChatCompletionRequest, session: String) -> Run {
+    let started = Instant::now();
+    let (response, node) = proxy::chat_completion(state, request, Some(session)).await;
+    let mut run = Run {
+        node,
+        node_id: None,
+        warmup: false,
+        ttft_ms: None,
+        total_ms: 0.0,
+        completion_tokens: 0,
+        tokens_per_sec: None,
+        error: None,
+    };
+    if !response.status().is_success() {
+        run.error = Some(chat::error_message(response).await);
+        run.total_ms = millis(started.elapsed());
+        return run;
+    }
+
+    let mut first_token = None;
+    let mut chunks = 0;
+    let mut usage = None;
+    let mut body = response.into_body().into_data_stream();
+    let mut events = EventReader::default();
+    'read: while let Some(chunk) = body.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                run.error = Some(e.to_string());
+                break;
+            }
+        };
+        for event in events.push(&chunk) {
+            match event {
+                Event::Text(_) => {
+                    first_token.get_or_insert_with(|| started.elapsed());
+                    chunks += 1;
+                }
+                Event::Usage(tokens) => usage = Some(tokens),
+                Event::Error(message) => {
+                    run.error = Some(message);
+                    break 'read;
+                }
+                Event::Done => break 'read,
+            }
+        }
+    }
+
+    let total = started.elapsed();
+    // Engines that report no usage stream about a token per chunk
+    run.completion_tokens = usage.unwrap_or(chunks);
+    run.ttft_ms = first_token.map(millis);
+    run.total_ms = millis(total);
+    run.tokens_per_sec =
+        first_token.and_then(|first| tokens_per_sec(run.completion_tokens, first, total));
+    run
+}
+
+/// The rate tokens arrived after the first one; `None` without two tokens to time between.
+fn tokens_per_sec(tokens: u64, first_token: Duration, total: Duration) -> Option<f64> {
+    let generating = total.checked_sub(first_token)?.as_secs_f64();
+    (tokens > 1 && generating > 0.0).then(|| (tokens - 1) as f64 / generating)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn progress_line(run: &Run, index: u32, options: &Options) -> String {
+    let label = if run.warmup {
+        format!("warm-up {}/{}", index + 1, options.warmup)
+    } else {
+        format!("run {}/{}", index - options.warmup + 1, options.runs)
+    };
+    let node = run
+        .node_id
+        .as_deref()
+        .or(run.node.as_deref())
+        .unwrap_or("no node");
+    match (&run.error, run.ttft_ms, run.tokens_per_sec) {
+        (Some(error), _, _) => format!("{label} on {node}: failed: {error}"),
+        (None, Some(ttft), Some(rate)) => {
+            format!("{label} on {node}: first token {ttft:.0} ms, {rate:.1} tokens/s")
+        }
+        (None, _, _) => format!(
+            "{label} on {node}: {} tokens in {:.0} ms",
+            run.completion_tokens, run.total_ms
+        ),
+    }
+}
+
+/// Per node, in address order, over the runs that are not warm-ups. A failed run counts
+/// toward `failed` only.
+fn summarize(runs: &[Run]) -> Vec<NodeSummary> {
+    let mut by_node: BTreeMap<&str, Vec<&Run>> = BTreeMap::new();
+    for run in runs.iter().filter(|run| !run.warmup) {
+        by_node
+            .entry(run.node.as_deref().unwrap_or("-"))
+            .or_default()
+            .push(run);
+    }
+    by_node
+        .into_iter()
+        .map(|(node, runs)| {
+            let succeeded: Vec<&Run> = runs
+                .iter()
+                .copied()
+                .filter(|run| run.error.is_none())
+                .collect();
+            let ttfts: Vec<f64> = succeeded.iter().filter_map(|run| run.ttft_ms).collect();
+            let rates: Vec<f64> = succeeded
+                .iter()
+                .filter_map(|run| run.tokens_per_sec)
+                .collect();
+            NodeSummary {
+                node: node.to_string(),
+                node_id: runs.iter().find_map(|run| run.node_id.clone()),
+                runs: runs.len(),
+                failed: runs.len() - succeeded.len(),
+                avg_ttft_ms: average(&ttfts),
+                avg_tokens_per_sec: average(&rates),
+                best_tokens_per_sec: rates.iter().copied().reduce(f64::max),
+            }
+        })
+        .collect()
+}
+
+fn average(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(node: Option<&str>, warmup: bool, ttft_ms: f64, rate: Option<f64>) -> Run {
+        Run {
+            node: node.map(str::to_string),
+            node_id: node.map(|ip| format!("node-{ip}")),
+            warmup,
+            ttft_ms: Some(ttft_ms),
+            total_ms: 1000.0,
+            completion_tokens: 50,
+            tokens_per_sec: rate,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_tokens_per_sec_times_the_tokens_after_the_first() {
+        let rate = tokens_per_sec(11, Duration::from_millis(500), Duration::from_millis(1500));
+        assert_eq!(rate, Some(10.0));
+        assert_eq!(
+            tokens_per_sec(1, Duration::from_millis(500), Duration::from_secs(1)),
+            None
+        );
+        assert_eq!(
+            tokens_per_sec(5, Duration::from_secs(1), Duration::from_secs(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_summary_leaves_out_warmups_and_failures() {
+        let failed = Run {
+            error: Some("Worker did not respond within 60s".to_string()),
+            ttft_ms: None,
+            tokens_per_sec: None,
+            ..run(Some("100.64.0.2"), false, 0.0, None)
+        };
+        let runs = [
+            run(Some("100.64.0.1"), true, 9000.0, Some(1.0)),
+            run(Some("100.64.0.1"), false, 200.0, Some(40.0)),
+            run(Some("100.64.0.1"), false, 400.0, Some(50.0)),
+            run(Some("100.64.0.2"), false, 300.0, Some(20.0)),
+            failed,
+            Run {
+                node_id: None,
+                ..run(None, false, 0.0, None)
+            },
+        ];
+
+        let summary = summarize(&runs);
+        assert_eq!(summary.len(), 3);
+        assert_eq!(summary[0].node, "-");
+        assert_eq!(summary[1].node_id.as_deref(), Some("node-100.64.0.1"));
+        assert_eq!(summary[1].runs, 2);
+        assert_eq!(summary[1].avg_ttft_ms, Some(300.0));
+        assert_eq!(summary[1].avg_tokens_per_sec, Some(45.0));
+        assert_eq!(summary[1].best_tokens_per_sec, Some(50.0));
+        assert_eq!(summary[2].runs, 2);
+        assert_eq!(summary[2].failed, 1);
+        assert_eq!(summary[2].avg_ttft_ms, Some(300.0));
+    }
+}
@@ -0,0 +1,428 @@
+//! Interactive chat with a model, for the `chat` command.
+//!
+//! Every message is sent as a streamed chat completion through the proxy's own
+//! authorization and worker calls (`proxy::chat_completion`), in this process and under
+//! one session, so the conversation stays on the node that already has it cached. The
+//! reply is printed as it arrives; Ctrl-C cancels it, and end of input (Ctrl-D) or
+//! `/exit` leaves.
+
+use crate::config::Config;
+use crate::proxy::{self, ProxyState};
+use anyhow::{anyhow, bail, Context, Result};
+use axum::response::Response;
+use futures::StreamExt;
+use monkey_troop_shared::{ChatCompletionRequest, ChatMessage};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+const HELP: &str = "\
+/reset             start the conversation over
+/model <name>      talk to another model from the next message on
+/system [prompt]   set the system prompt, or clear it
+/save <file>       write the conversation to a JSON file
+/exit              leave (or Ctrl-D)
+Ctrl-C stops a reply while it is being generated.";
+
+/// One line typed at the prompt.
+#[derive(Debug, PartialEq)]
+enum Input {
+    Empty,
+    Message(String),
+    Reset,
+    Model(String),
+    System(Option<String>),
+    Save(PathBuf),
+    Help,
+    Exit,
+}
+
+/// Read a line as a message, or as a command when it starts with `/`. Unknown commands
+/// and missing arguments are errors, so a typo is not sent to the model.
+fn parse_input(line: &str) -> Result<Input, String> {
+    let line = line.trim();
+    let Some(command) = line.strip_prefix('/') else {
+        return Ok(if line.is_empty() {
+            Input::Empty
+        } else {
+            Input::Message(line.to_string())
+        });
+    };
+    let (name, argument) = command
+        .split_once(char::is_whitespace)
+        .map_or((command, ""), |(name, argument)| (name, argument.trim()));
+    match (name, argument) {
+        ("reset", _) => Ok(Input::Reset),
+        ("model", "") => Err("Usage: /model <name>".to_string()),
+        ("model", model) => Ok(Input::Model(model.to_string())),
+        ("system", "") => Ok(Input::System(None)),
+        ("system", prompt) => Ok(Input::System(Some(prompt.to_string()))),
+        ("save", "") => Err("Usage: /save <file>".to_string()),
+        ("save", path) => Ok(Input::Save(PathBuf::from(path))),
+        ("help", _) => Ok(Input::Help),
+        ("exit" | "quit", _) => Ok(Input::Exit),
+        _ => Err(format!("Unknown command /{name}; /help lists them")),
+    }
+}
+
+/// What has been said so far, and to which model.
+struct Conversation {
+    model: String,
+    system: Option<String>,
+    /// User and assistant messages, oldest first
+    history: Vec<ChatMessage>,
+    /// Keeps the conversation on one node; a new one after `/reset`
+    session: String,
+}
+
+impl Conversation {
+    fn new(model: String) -> Self {
+        Self {
+            model,
+            system: None,
+            history: Vec::new(),
+            session: uuid::Uuid::new_v4().to_string(),
+        }
+    }
+
+    /// The system prompt, if any, followed by the history.
+    fn messages(&self) -> Vec<ChatMessage> {
+        let system = self.system.as_deref().map(|s| message("system", s));
+        system.into_iter().chain(self.history.clone()).collect()
+    }
+
+    /// The streamed request asking the model to answer `prompt`.
+    fn request(&self, prompt: &str) -> ChatCompletionRequest {
+        let mut messages = self.messages();
+        messages.push(message("user", prompt));
+        ChatCompletionRequest {
+            model: self.model.clone(),
+            messages,
+            stream: true,
+            tools: None,
+            tool_choice: None,
+            max_tokens: None,
+            stop: None,
+            temperature: None,
+            top_p: None,
+            seed: None,
+            stream_options: None,
+            extra: Default::default(),
+        }
+    }
+
+    /// Add an answered exchange to the history.
+    fn record(&mut self, prompt: &str, reply: &str) {
+        self.history.push(message("user", prompt));
+        self.history.push(message("assistant", reply));
+    }
+
+    fn reset(&mut self) {
+        self.history.clear();
+        self.session = uuid::Uuid::new_v4().to_string();
+    }
+
+    /// Write the conversation as a chat completion request body, so it can be replayed.
+    fn save(&self, path: &Path) -> Result<()> {
+        let body = serde_json::json!({"model": self.model, "messages": self.messages()});
+        std::fs::write(path, serde_json::to_string_pretty(&body)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+pub fn message(role: &str, text: &str) -> ChatMessage {
+    ChatMessage {
+        role: role.to_string(),
+        content: Some(text.into()),
+        tool_calls: None,
+        tool_call_id: None,
+    }
+}
+
+/// What a streamed reply's `data:` events carry, as far as the terminal and `bench`
+/// care.
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Text(String),
+    /// Completion tokens, from the final chunk when `include_usage` was asked for
+    Usage(u64),
+    Error(String),
+    Done,
+}
+
+/// Pulls events out of a streamed reply, however its chunks are split.
+#[derive(Default)]
+pub struct EventReader {
+    buffer: Vec<u8>,
+}
+
+impl EventReader {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Event> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        // Only whole lines are read, so a character split across chunks stays intact
+        while let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if let Some(data) = line.trim_end().strip_prefix("data:") {
+                events.extend(parse_event(data.trim_start()));
+            }
+        }
+        events
+    }
+}
+
+fn parse_event(data: &str) -> Option<Event> {
+    if data == "[DONE]" {
+        return Some(Event::Done);
+    }
+    let event: serde_json::Value = serde_json::from_str(data).ok()?;
+    if let Some(message) = event["error"]["message"].as_str() {
+        return Some(Event::Error(message.to_string()));
+    }
+    event["choices"][0]["delta"]["content"]
+        .as_str()
+        .filter(|text| !text.is_empty())
+        .map(|text| Event::Text(text.to_string()))
+        .or_else(|| {
+            event["usage"]["completion_tokens"]
+                .as_u64()
+                .map(Event::Usage)
+        })
+}
+
+/// Print the reply to `request` as it arrives, and return all of it.
+async fn stream_reply(
+    state: &ProxyState,
+    request: ChatCompletionRequest,
+    session: String,
+) -> Result<String> {
+    let (response, _) = proxy::chat_completion(state, request, Some(session)).await;
+    if !response.status().is_success() {
+        return Err(anyhow!(error_message(response).await));
+    }
+
+    let mut body = response.into_body().into_data_stream();
+    let mut events = EventReader::default();
+    let mut reply = String::new();
+    let mut stdout = std::io::stdout();
+    while let Some(chunk) = body.next().await {
+        for event in events.push(&chunk?) {
+            match event {
+                Event::Text(text) => {
+                    print!("{text}");
+                    stdout.flush()?;
+                    reply.push_str(&text);
+                }
+                Event::Usage(_) => {}
+                Event::Error(message) => bail!(message),
+                Event::Done => return Ok(reply),
+            }
+        }
+    }
+    Ok(reply)
+}
+
+/// The message of an OpenAI-shaped error reply, or its status.
+pub async fn error_message(response: Response) -> String {
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap_or_default();
+    serde_json::from_slice::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|body| body["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| status.to_string())
+}
+
+/// Chat with `model` until the input ends.
+pub async fn run(config: Config, model: String) -> Result<()> {
+    let state = ProxyState::new(config);
+    let mut conversation = Conversation::new(model);
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    println!(
+        "Chatting with {}; /help lists the commands, Ctrl-D leaves.",
+        conversation.model
+    );
+
+    loop {
+        print!("> ");
+        std::io::stdout().flush()?;
+        // Once Ctrl-C is listened for it no longer ends the process; here it just clears the line
+        let line = tokio::select! {
+            line = lines.next_line() => line.context("Failed to read input")?,
+            _ = tokio::signal::ctrl_c() => {
+                println!();
+                continue;
+            }
+        };
+        let Some(line) = line else {
+            println!();
+            break;
+        };
+
+        match parse_input(&line) {
+            Ok(Input::Empty) => {}
+            Ok(Input::Message(prompt)) => {
+                let request = conversation.request(&prompt);
+                let session = conversation.session.clone();
+                // Dropping the reply closes the worker connection, which stops generating
+                tokio::select! {
+                    reply = stream_reply(&state, request, session) => {
+                        println!();
+                        match reply {
+                            Ok(reply) => conversation.record(&prompt, &reply),
+                            Err(e) => eprintln!("Error: {e:#}"),
+                        }
+                    }
+                    _ = tokio::signal::ctrl_c() => println!("\n[cancelled]"),
+                }
+            }
+            Ok(Input::Reset) => {
+                conversation.reset();
+                println!("Conversation reset");
+            }
+            Ok(Input::Model(model)) => {
+                println!("Now chatting with {model}");
+                conversation.model = model;
+            }
+            Ok(Input::System(prompt)) => {
+                match prompt {
+                    Some(_) => println!("System prompt set"),
+                    None => println!("System prompt cleared"),
+                }
+                conversation.system = prompt;
+            }
+            Ok(Input::Save(path)) => match conversation.save(&path) {
+                Ok(()) => println!("Saved to {}", path.display()),
+                Err(e) => eprintln!("Error: {e:#}"),
+            },
+            Ok(Input::Help) => println!("{HELP}"),
+            Ok(Input::Exit) => break,
+            Err(message) => eprintln!("{message}"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_input() {
+        assert_eq!(parse_input("  "), Ok(Input::Empty));
+        assert_eq!(
+            parse_input(" hello there "),
+            Ok(Input::Message("hello there".to_string()))
+        );
+        assert_eq!(parse_input("/reset"), Ok(Input::Reset));
+        assert_eq!(
+            parse_input("/model  qwen2.5:7b"),
+            Ok(Input::Model("qwen2.5:7b".to_string()))
+        );
+        assert_eq!(
+            parse_input("/system You are terse."),
+            Ok(Input::System(Some("You are terse.".to_string())))
+        );
+        assert_eq!(parse_input("/system"), Ok(Input::System(None)));
+        assert_eq!(
+            parse_input("/save chat.json"),
+            Ok(Input::Save(PathBuf::from("chat.json")))
+        );
+        assert!(parse_input("/model").is_err());
+        assert!(parse_input("/save").is_err());
+        assert!(parse_input("/sytem be brief").is_err());
+    }
+
+    #[test]
+    fn test_request_carries_the_system_prompt_and_history() {
+        let mut conversation = Conversation::new("llama3:8b".to_string());
+        conversation.system = Some("Be brief.".to_string());
+        conversation.record("hi", "Hello!");
+
+        let request = conversation.request("how are you?");
+        assert_eq!(request.model, "llama3:8b");
+        assert!(request.stream);
+        let messages: Vec<(&str, String)> = request
+            .messages
+            .iter()
+            .map(|m| (m.role.as_str(), m.content.as_ref().unwrap().text().into()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                ("system", "Be brief.".to_string()),
+                ("user", "hi".to_string()),
+                ("assistant", "Hello!".to_string()),
+                ("user", "how are you?".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reset_keeps_the_system_prompt_and_starts_a_new_session() {
+        let mut conversation = Conversation::new("llama3:8b".to_string());
+        conversation.system = Some("Be brief.".to_string());
+        conversation.record("hi", "Hello!");
+        let session = conversation.session.clone();
+
+        conversation.reset();
+
+        assert_eq!(conversation.messages().len(), 1);
+        assert_ne!(conversation.session, session);
+    }
+
+    #[test]
+    fn test_saved_conversation_is_a_chat_request() {
+        let mut conversation = Conversation::new("llama3:8b".to_string());
+        conversation.record("hi", "Hello!");
+        let path = std::env::temp_dir().join(format!("troop-chat-{}.json", uuid::Uuid::new_v4()));
+
+        conversation.save(&path).unwrap();
+        let saved: ChatCompletionRequest =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(saved.model, "llama3:8b");
+        assert_eq!(saved.messages.len(), 2);
+        assert_eq!(saved.messages[1].role, "assistant");
+    }
+
+    #[test]
+    fn test_events_split_across_chunks() {
+        let mut events = EventReader::default();
+        let stream = "data: {\"choices\": [{\"delta\": {\"role\": \"assistant\"}}]}\n\n\
+             data: {\"choices\": [{\"delta\": {\"content\": \"Héllo\"}}]}\r\n\r\n\
+             : keep-alive\n\n\
+             data: {\"choices\": [], \"usage\": {\"completion_tokens\": 2}}\n\n\
+             data: [DONE]\n\n"
+            .as_bytes();
+        // Split inside the two-byte "é"
+        let split = stream.iter().position(|&b| b == 0xC3).unwrap() + 1;
+
+        let mut seen = events.push(&stream[..split]);
+        seen.extend(events.push(&stream[split..]));
+
+        assert_eq!(
+            seen,
+            [
+                Event::Text("Héllo".to_string()),
+                Event::Usage(2),
+                Event::Done
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_event_is_read() {
+        let mut events = EventReader::default();
+        let seen = events.push(
+            b"data: {\"error\": {\"message\": \"Worker stream failed: reset\", \"type\": \"api_error\"}}\n\n",
+        );
+        assert_eq!(
+            seen,
+            [Event::Error("Worker stream failed: reset".to_string())]
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! Concurrency limit with a bounded wait queue for the client proxy.
+//!
+//! At most `max_concurrent` requests are forwarded at once. Up to `max_queued` more wait
+//! for a slot for at most `queue_timeout`; anything beyond that is turned away with a 429
+//! so an agent firing dozens of parallel requests backs off instead of burning credits
+//! on requests that would time out anyway.
+
+use axum::{
+    body::Body,
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use http_body::{Frame, SizeHint};
+use monkey_troop_shared::ApiErrorBody;
+use serde::Serialize;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{info, warn};
+
+/// Seconds a caller turned away by a full queue is asked to wait.
+const QUEUE_FULL_RETRY_AFTER_SECS: u64 = 1;
+
+pub struct ConcurrencyLimit {
+    semaphore: Arc<Semaphore>,
+    max_concurrent: usize,
+    max_queued: usize,
+    queued: AtomicUsize,
+    queue_timeout: Duration,
+}
+
+/// Why a request was not admitted.
+#[derive(Debug, PartialEq)]
+pub enum Rejection {
+    QueueFull,
+    QueueTimeout(Duration),
+}
+
+/// Snapshot of the limit, reported by `/health`.
+#[derive(Debug, Serialize)]
+pub struct ConcurrencyStatus {
+    pub in_flight: usize,
+    pub max_concurrent: usize,
+    pub queued: usize,
+    pub max_queued: usize,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(max_concurrent: usize, max_queued: usize, queue_timeout: Duration) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            max_concurrent,
+            max_queued,
+            queued: AtomicUsize::new(0),
+            queue_timeout,
+        }
+    }
+
+    pub fn status(&self) -> ConcurrencyStatus {
+        ConcurrencyStatus {
+            in_flight: self.max_concurrent - self.semaphore.available_permits(),
+            max_concurrent: self.max_concurrent,
+            queued: self.queued.load(Ordering::SeqCst),
+            max_queued: self.max_queued,
+        }
+    }
+
+    /// Take a slot, waiting in the queue when all are busy and the queue has room.
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit, Rejection> {
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        let Ok(depth) = self
+            .queued
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < self.max_queued).then_some(n + 1)
+            })
+        else {
+            warn!(
+                "Rejecting request, queue is full ({} queued)",
+                self.max_queued
+            );
+            return Err(Rejection::QueueFull);
+        };
+        // Leaves the queue however the wait ends, including the caller going away
+        let _slot = QueueSlot(&self.queued);
+        info!(
+            "All {} slots busy, request queued (queue depth {})",
+            self.max_concurrent,
+            depth + 1
+        );
+
+        match tokio::time::timeout(self.queue_timeout, self.semaphore.clone().acquire_owned()).await
+        {
+            Ok(permit) => permit.map_err(|_| Rejection::QueueFull),
+            Err(_) => {
+                warn!(
+                    "Request timed out after {:?} in queue (queue depth {})",
+                    self.queue_timeout,
+                    self.queued.load(Ordering::SeqCst)
+                );
+                Err(Rejection::QueueTimeout(self.queue_timeout))
+            }
+        }
+    }
+}
+
+struct QueueSlot<'a>(&'a AtomicUsize);
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        match self {
+            Rejection::QueueFull => {
+                let body = ApiErrorBody::new(
+                    "Too many concurrent requests, proxy queue is full",
+                    "rate_limit_error",
+                    "queue_full",
+                );
+                let mut response = (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response();
+                response
+                    .headers_mut()
+                    .insert(RETRY_AFTER, HeaderValue::from(QUEUE_FULL_RETRY_AFTER_SECS));
+                response
+            }
+            Rejection::QueueTimeout(waited) => {
+                let body = ApiErrorBody::new(
+                    format!(
+                        "Request waited {}s in the proxy queue without a free slot",
+                        waited.as_secs()
+                    ),
+                    "service_unavailable",
+                    "queue_timeout",
+                );
+                (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response()
+            }
+        }
+    }
+}
+
+/// Keep `permit` until `body` has been fully sent (or dropped).
+pub fn hold_until_sent(permit: OwnedSemaphorePermit, body: Body) -> Body {
+    Body::new(PermitBody {
+        inner: body,
+        _permit: permit,
+    })
+}
+
+struct PermitBody {
+    inner: Body,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl http_body::Body for PermitBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_queued_request_gets_slot_when_released() {
+        let limit = Arc::new(ConcurrencyLimit::new(1, 1, Duration::from_secs(5)));
+        let first = limit.acquire().await.unwrap();
+
+        let waiter = tokio::spawn({
+            let limit = limit.clone();
+            async move { limit.acquire().await.map(drop) }
+        });
+        while limit.status().queued == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(limit.acquire().await.unwrap_err(), Rejection::QueueFull);
+
+        drop(first);
+        assert_eq!(waiter.await.unwrap(), Ok(()));
+        assert_eq!(limit.status().queued, 0);
+    }
+
+    #[tokio::test]
+    async fn test_queued_request_times_out() {
+        tokio::time::pause();
+        let limit = ConcurrencyLimit::new(1, 1, Duration::from_secs(2));
+        let _held = limit.acquire().await.unwrap();
+
+        assert_eq!(
+            limit.acquire().await.unwrap_err(),
+            Rejection::QueueTimeout(Duration::from_secs(2))
+        );
+        assert_eq!(limit.status().queued, 0);
+    }
+
+    #[tokio::test]
+    async fn test_permit_released_after_body_is_sent() {
+        let limit = ConcurrencyLimit::new(1, 0, Duration::from_secs(1));
+        let body = hold_until_sent(limit.acquire().await.unwrap(), Body::from("hi"));
+        assert_eq!(limit.status().in_flight, 1);
+
+        axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(limit.status().in_flight, 0);
+    }
+}
@@ -1,56 +1,399 @@
+use crate::config_file::{self, ConfigFile};
+use crate::fan_out::MAX_FAN_OUT_N;
+use crate::model_filter::ModelFilter;
 use anyhow::{Context, Result};
+use monkey_troop_shared::{TroopError, TroopResult};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::env;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::time::Duration;
 use url::Url;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
-    pub coordinator_url: Url,
+    /// Coordinators in failover order (`COORDINATOR_URL` is a comma-separated list)
+    pub coordinator_urls: Vec<Url>,
+    /// Address the proxy listens on (`PROXY_BIND`); loopback by default
+    pub proxy_bind: IpAddr,
     pub proxy_port: u16,
     pub worker_port: u16,
     pub requester_id: String,
+    /// JSONL file receiving one audit record per proxied request; disabled when unset
+    pub audit_log_path: Option<PathBuf>,
+    /// Record message content verbatim in the audit log instead of just its length
+    pub audit_log_include_content: bool,
+    /// How long in-flight requests may keep running after a shutdown signal
+    pub shutdown_drain_timeout: Duration,
+    /// Bounds applied to per-request `X-Troop-Timeout-Secs` overrides
+    pub min_request_timeout: Duration,
+    pub max_request_timeout: Duration,
+    /// Requests forwarded at once; unlimited when unset
+    pub max_concurrent_requests: Option<usize>,
+    /// Requests allowed to wait for a slot once the concurrency limit is reached
+    pub max_queued_requests: usize,
+    /// How long a queued request waits for a slot before failing
+    pub queue_timeout: Duration,
+    /// JSON file the `/usage` totals are saved to so they survive restarts
+    pub usage_file: Option<PathBuf>,
+    /// Model names rewritten before authorization (`MODEL_ALIASES="gpt-4o=llama3:70b,..."`)
+    pub model_aliases: HashMap<String, String>,
+    /// Models the proxy will serve (`MODEL_ALLOWLIST` / `MODEL_DENYLIST`), checked after aliasing
+    pub model_filter: ModelFilter,
+    /// How long to wait for a worker's first byte before racing a second node
+    /// (`HEDGE_AFTER_MS`); disabled when unset, since the duplicate costs credits
+    pub hedge_after: Option<Duration>,
+    /// Upper bound on `max_tokens` (`MAX_TOKENS_CAP`): injected into requests without one
+    /// and enforced on requests asking for more; no limit when unset
+    pub max_tokens_cap: Option<u32>,
+    /// Requests from one `/v1/batch` call run at once (`BATCH_PARALLELISM`)
+    pub batch_parallelism: usize,
+    /// Largest `n` served by fanning a chat completion out as that many single-choice
+    /// requests (`FAN_OUT_MAX_N`, at most `MAX_FAN_OUT_N`); larger `n` is rejected. Off
+    /// when unset, and `n` is forwarded to the worker as is
+    pub fan_out_max_n: Option<usize>,
+    /// Answer repeated deterministic requests from a local cache (`ENABLE_RESPONSE_CACHE`)
+    pub enable_response_cache: bool,
+    /// How long a cached reply is served (`RESPONSE_CACHE_TTL_SECS`)
+    pub response_cache_ttl: Duration,
+    /// Most replies cached at once (`RESPONSE_CACHE_SIZE`)
+    pub response_cache_size: NonZeroUsize,
+    /// Certificate and key the proxy serves HTTPS with (`PROXY_TLS_CERT` / `PROXY_TLS_KEY`);
+    /// plain HTTP when unset
+    pub proxy_tls: Option<TlsFiles>,
+    /// Also (or only, with `PROXY_TCP=false`) serve the proxy on this unix socket
+    pub proxy_unix_socket: Option<PathBuf>,
+    /// Permissions the socket file is created with (`PROXY_UNIX_SOCKET_MODE`, octal)
+    pub proxy_unix_socket_mode: u32,
+    /// Whether to listen on `proxy_bind:proxy_port` (`PROXY_TCP`)
+    pub proxy_tcp: bool,
+    /// Where `up --daemon` records the background proxy's pid for `down` (`PID_FILE`)
+    pub pid_file: PathBuf,
+    /// File the background proxy's output is appended to (`DAEMON_LOG_FILE`)
+    pub daemon_log_file: PathBuf,
+}
+
+/// Values given on the command line, which take precedence over the environment.
+#[derive(Debug, Default)]
+pub struct Overrides {
+    pub proxy_port: Option<u16>,
+    pub coordinator_url: Option<String>,
+    pub requester_id: Option<String>,
+    pub proxy_bind: Option<IpAddr>,
+}
+
+/// PEM files for serving the proxy over HTTPS.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
 }
 
 impl Config {
-    pub fn from_env() -> Result<Self> {
-        let url_str = env::var("COORDINATOR_URL")
-            .unwrap_or_else(|_| "https://troop.100monkeys.ai".to_string());
+    /// Load the configuration from command-line `overrides`, the environment and the
+    /// config file, and reject unusable values, naming the file line a bad value is on.
+    pub fn load(overrides: &Overrides) -> Result<Self> {
+        let file = match config_file::path() {
+            Some(path) => ConfigFile::read(&path)?,
+            None => None,
+        };
+        Self::load_with(overrides, |name| env::var(name).ok(), file.as_ref())
+    }
 
-        let coordinator_url =
-            Url::parse(&url_str).with_context(|| format!("Invalid COORDINATOR_URL: {url_str}"))?;
+    fn load_with(
+        overrides: &Overrides,
+        env: impl Fn(&str) -> Option<String>,
+        file: Option<&ConfigFile>,
+    ) -> Result<Self> {
+        let blame = |error: anyhow::Error| match file.and_then(|file| file.blame(&error, &env)) {
+            Some(origin) => error.context(origin),
+            None => error,
+        };
+        let config = Self::resolve(overrides, |name| {
+            env(name).or_else(|| file?.get(name).map(str::to_string))
+        })
+        .map_err(blame)?;
+        config
+            .validate()
+            .context("Invalid client configuration")
+            .map_err(blame)?;
+        Ok(config)
+    }
 
-        // Basic SSRF protection: Ensure the URL uses a permitted scheme (http or https)
-        if coordinator_url.scheme() != "http" && coordinator_url.scheme() != "https" {
-            anyhow::bail!("COORDINATOR_URL must use http or https scheme");
-        }
+    /// Build the configuration from command-line `overrides` and the environment, read
+    /// through `var`. A flag takes precedence over its variable, which takes precedence
+    /// over the default.
+    pub fn resolve(overrides: &Overrides, var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let coordinator_urls = match &overrides.coordinator_url {
+            Some(urls_str) => parse_coordinator_urls("--coordinator-url", urls_str)?,
+            None => parse_coordinator_urls(
+                "COORDINATOR_URL",
+                &var("COORDINATOR_URL")
+                    .unwrap_or_else(|| "https://troop.100monkeys.ai".to_string()),
+            )?,
+        };
+        let proxy_bind = match (overrides.proxy_bind, var("PROXY_BIND")) {
+            (Some(addr), _) => addr,
+            (None, Some(s)) if !s.trim().is_empty() => s
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid PROXY_BIND address: {s}"))?,
+            (None, _) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        };
+
+        let secs_from_env = |name: &str, default: u64| {
+            Duration::from_secs(var(name).and_then(|s| s.parse().ok()).unwrap_or(default))
+        };
+        let path_from_env = |name: &str| var(name).filter(|s| !s.is_empty()).map(PathBuf::from);
+        let proxy_tls = match (
+            path_from_env("PROXY_TLS_CERT"),
+            path_from_env("PROXY_TLS_KEY"),
+        ) {
+            (Some(cert), Some(key)) => Some(TlsFiles { cert, key }),
+            (None, None) => None,
+            _ => anyhow::bail!("PROXY_TLS_CERT and PROXY_TLS_KEY must be set together"),
+        };
+        let proxy_unix_socket_mode = match var("PROXY_UNIX_SOCKET_MODE") {
+            Some(s) if !s.trim().is_empty() => {
+                u32::from_str_radix(s.trim(), 8).with_context(|| {
+                    format!("Invalid PROXY_UNIX_SOCKET_MODE: {s}, expected octal like 0600")
+                })?
+            }
+            _ => 0o600,
+        };
+        let min_request_timeout = secs_from_env("REQUEST_TIMEOUT_MIN_SECS", 5);
+        let max_request_timeout =
+            secs_from_env("REQUEST_TIMEOUT_MAX_SECS", 3600).max(min_request_timeout);
 
         Ok(Config {
-            coordinator_url,
-            proxy_port: env::var("PROXY_PORT")
-                .and_then(|s| s.parse().map_err(|_| env::VarError::NotPresent))
+            coordinator_urls,
+            proxy_bind,
+            proxy_port: overrides
+                .proxy_port
+                .or_else(|| var("PROXY_PORT").and_then(|s| s.parse().ok()))
                 .unwrap_or(9000),
-            worker_port: env::var("WORKER_PORT")
-                .and_then(|s| s.parse().map_err(|_| env::VarError::NotPresent))
+            worker_port: var("WORKER_PORT")
+                .and_then(|s| s.parse().ok())
                 .unwrap_or(8080),
-            requester_id: env::var("REQUESTER_ID")
-                .unwrap_or_else(|_| get_tailscale_ip().unwrap_or_else(|_| "unknown".to_string())),
+            requester_id: overrides
+                .requester_id
+                .clone()
+                .or_else(|| var("REQUESTER_ID"))
+                .unwrap_or_else(|| tailscale_ip(false).unwrap_or_else(|_| "unknown".to_string())),
+            audit_log_path: path_from_env("AUDIT_LOG_PATH"),
+            audit_log_include_content: var("AUDIT_LOG_INCLUDE_CONTENT")
+                .map(|s| matches!(s.to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+                .unwrap_or(false),
+            shutdown_drain_timeout: secs_from_env("SHUTDOWN_DRAIN_TIMEOUT_SECS", 30),
+            min_request_timeout,
+            max_request_timeout,
+            max_concurrent_requests: var("MAX_CONCURRENT_REQUESTS")
+                .and_then(|s| s.parse().ok())
+                .filter(|&limit: &usize| limit > 0),
+            max_queued_requests: var("MAX_QUEUED_REQUESTS")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+            queue_timeout: secs_from_env("QUEUE_TIMEOUT_SECS", 30),
+            usage_file: path_from_env("USAGE_FILE"),
+            model_aliases: parse_model_aliases(&var("MODEL_ALIASES").unwrap_or_default())?,
+            model_filter: ModelFilter::new(
+                &var("MODEL_ALLOWLIST").unwrap_or_default(),
+                &var("MODEL_DENYLIST").unwrap_or_default(),
+            ),
+            hedge_after: var("HEDGE_AFTER_MS")
+                .and_then(|s| s.parse().ok())
+                .filter(|&ms| ms > 0)
+                .map(Duration::from_millis),
+            max_tokens_cap: var("MAX_TOKENS_CAP")
+                .and_then(|s| s.parse().ok())
+                .filter(|&cap: &u32| cap > 0),
+            batch_parallelism: var("BATCH_PARALLELISM")
+                .and_then(|s| s.parse().ok())
+                .filter(|&limit: &usize| limit > 0)
+                .unwrap_or(4),
+            fan_out_max_n: var("FAN_OUT_MAX_N")
+                .and_then(|s| s.parse().ok())
+                .filter(|&n: &usize| n > 1)
+                .map(|n| n.min(MAX_FAN_OUT_N)),
+            enable_response_cache: var("ENABLE_RESPONSE_CACHE")
+                .map(|s| matches!(s.to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+                .unwrap_or(false),
+            response_cache_ttl: secs_from_env("RESPONSE_CACHE_TTL_SECS", 300),
+            response_cache_size: var("RESPONSE_CACHE_SIZE")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(NonZeroUsize::new(256).unwrap()),
+            proxy_tls,
+            proxy_unix_socket: path_from_env("PROXY_UNIX_SOCKET"),
+            proxy_unix_socket_mode,
+            proxy_tcp: var("PROXY_TCP")
+                .map(|s| !matches!(s.to_ascii_lowercase().as_str(), "0" | "false" | "no"))
+                .unwrap_or(true),
+            pid_file: path_from_env("PID_FILE")
+                .unwrap_or_else(|| env::temp_dir().join("monkey-troop-client.pid")),
+            daemon_log_file: path_from_env("DAEMON_LOG_FILE")
+                .unwrap_or_else(|| env::temp_dir().join("monkey-troop-client.log")),
+        })
+    }
+
+    /// Reject values that parse but cannot work, so misconfiguration fails at startup
+    /// rather than on the first request.
+    pub fn validate(&self) -> TroopResult<()> {
+        // Each URL was parsed and scheme-checked by `parse_coordinator_urls`
+        if self.coordinator_urls.is_empty() {
+            return Err(TroopError::InvalidRequest(
+                "COORDINATOR_URL must list at least one coordinator URL".to_string(),
+            ));
+        }
+        for (name, port) in [
+            ("PROXY_PORT", self.proxy_port),
+            ("WORKER_PORT", self.worker_port),
+        ] {
+            if port == 0 {
+                return Err(TroopError::InvalidRequest(format!(
+                    "{name} must be between 1 and 65535"
+                )));
+            }
+        }
+        if !self.proxy_tcp && self.proxy_unix_socket.is_none() {
+            return Err(TroopError::InvalidRequest(
+                "PROXY_TCP=false needs PROXY_UNIX_SOCKET, or the proxy has nothing to listen on"
+                    .to_string(),
+            ));
+        }
+        if self.proxy_unix_socket_mode > 0o777 {
+            return Err(TroopError::InvalidRequest(format!(
+                "PROXY_UNIX_SOCKET_MODE must be at most 0777, got {:o}",
+                self.proxy_unix_socket_mode
+            )));
+        }
+        if cfg!(not(unix)) && self.proxy_unix_socket.is_some() {
+            return Err(TroopError::InvalidRequest(
+                "PROXY_UNIX_SOCKET is only supported on Unix".to_string(),
+            ));
+        }
+        for (name, interval) in [
+            ("REQUEST_TIMEOUT_MIN_SECS", self.min_request_timeout),
+            ("QUEUE_TIMEOUT_SECS", self.queue_timeout),
+            ("RESPONSE_CACHE_TTL_SECS", self.response_cache_ttl),
+        ] {
+            if interval.is_zero() {
+                return Err(TroopError::InvalidRequest(format!(
+                    "{name} must be at least 1 second"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Base URL AI tools reach the local proxy at.
+    pub fn proxy_url(&self) -> String {
+        let scheme = if self.proxy_tls.is_some() {
+            "https"
+        } else {
+            "http"
+        };
+        if self.proxy_bind.is_loopback() || self.proxy_bind.is_unspecified() {
+            format!("{scheme}://localhost:{}", self.proxy_port)
+        } else {
+            format!(
+                "{scheme}://{}",
+                SocketAddr::new(self.proxy_bind, self.proxy_port)
+            )
+        }
+    }
+
+    /// The model a request for `model` should be served by: its alias target, if any.
+    pub fn resolve_model<'a>(&'a self, model: &'a str) -> &'a str {
+        self.model_aliases
+            .get(model)
+            .map(String::as_str)
+            .unwrap_or(model)
+    }
+}
+
+fn parse_model_aliases(aliases_str: &str) -> Result<HashMap<String, String>> {
+    aliases_str
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((alias, model)) if !alias.trim().is_empty() && !model.trim().is_empty() => {
+                Ok((alias.trim().to_string(), model.trim().to_string()))
+            }
+            _ => anyhow::bail!("Invalid MODEL_ALIASES entry '{entry}', expected alias=model"),
+        })
+        .collect()
+}
+
+/// `source` names where the list came from, for error messages.
+fn parse_coordinator_urls(source: &str, urls_str: &str) -> Result<Vec<Url>> {
+    let coordinator_urls = urls_str
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|url_str| {
+            let url =
+                Url::parse(url_str).with_context(|| format!("Invalid {source}: {url_str}"))?;
+            // Basic SSRF protection: Ensure the URL uses a permitted scheme (http or https)
+            if url.scheme() != "http" && url.scheme() != "https" {
+                anyhow::bail!("{source} must use http or https scheme: {url_str}");
+            }
+            Ok(url)
         })
+        .collect::<Result<Vec<_>>>()?;
+
+    if coordinator_urls.is_empty() {
+        anyhow::bail!("{source} must list at least one coordinator URL");
+    }
+    Ok(coordinator_urls)
+}
+
+/// Why this machine's Tailscale address could not be found.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TailscaleError {
+    /// No `tailscale` binary in the trusted system paths
+    NotInstalled(String),
+    /// `tailscale ip` could not be run, failed, or printed no address
+    CommandFailed(String),
+}
+
+impl std::fmt::Display for TailscaleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TailscaleError::NotInstalled(reason) => {
+                write!(f, "tailscale is not installed: {reason}")
+            }
+            TailscaleError::CommandFailed(reason) => write!(f, "tailscale ip failed: {reason}"),
+        }
     }
 }
 
-fn get_tailscale_ip() -> Result<String> {
+/// This machine's Tailscale IPv4 address, or its IPv6 one with `ipv6`.
+pub fn tailscale_ip(ipv6: bool) -> Result<String, TailscaleError> {
     use std::process::Command;
 
-    let output = Command::new(monkey_troop_shared::get_secure_binary_path("tailscale")?)
-        .args(["ip", "-4"])
-        .output()?;
+    let binary = monkey_troop_shared::get_secure_binary_path("tailscale")
+        .map_err(|e| TailscaleError::NotInstalled(e.to_string()))?;
+    let output = Command::new(binary)
+        .args(["ip", if ipv6 { "-6" } else { "-4" }])
+        .output()
+        .map_err(|e| TailscaleError::CommandFailed(e.to_string()))?;
 
-    if output.status.success() {
-        let ip = String::from_utf8_lossy(&output.stdout);
-        Ok(ip.trim().to_string())
-    } else {
-        Err(anyhow::anyhow!("Failed to get Tailscale IP"))
+    let ip = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(TailscaleError::CommandFailed(format!(
+            "{}: {}",
+            output.status,
+            stderr.trim()
+        )));
     }
+    if ip.is_empty() {
+        return Err(TailscaleError::CommandFailed("no address".to_string()));
+    }
+    Ok(ip)
 }
 
 #[cfg(test)]
@@ -59,18 +402,127 @@ mod tests {
     use serial_test::serial;
     use std::env;
 
+    #[test]
+    fn test_resolve_precedence() {
+        let env = HashMap::from([
+            ("COORDINATOR_URL", "http://env.example:8000"),
+            ("PROXY_PORT", "1234"),
+            ("REQUESTER_ID", "env-requester"),
+            ("PROXY_BIND", "0.0.0.0"),
+        ]);
+        let var = |name: &str| env.get(name).map(|s| s.to_string());
+
+        // Flag > env
+        let overrides = Overrides {
+            proxy_port: Some(4321),
+            coordinator_url: Some("https://flag.example".to_string()),
+            requester_id: Some("flag-requester".to_string()),
+            proxy_bind: Some("100.64.0.7".parse().unwrap()),
+        };
+        let config = Config::resolve(&overrides, var).unwrap();
+        assert_eq!(config.coordinator_urls[0].as_str(), "https://flag.example/");
+        assert_eq!(config.proxy_port, 4321);
+        assert_eq!(config.requester_id, "flag-requester");
+        assert_eq!(config.proxy_url(), "http://100.64.0.7:4321");
+
+        // Env > default
+        let config = Config::resolve(&Overrides::default(), var).unwrap();
+        assert_eq!(
+            config.coordinator_urls[0].as_str(),
+            "http://env.example:8000/"
+        );
+        assert_eq!(config.proxy_port, 1234);
+        assert_eq!(config.requester_id, "env-requester");
+        assert!(config.proxy_bind.is_unspecified());
+        assert_eq!(config.proxy_url(), "http://localhost:1234");
+
+        // Defaults
+        let config = Config::resolve(
+            &Overrides {
+                requester_id: Some("r".to_string()),
+                ..Overrides::default()
+            },
+            |_| None,
+        )
+        .unwrap();
+        assert_eq!(
+            config.coordinator_urls[0].as_str(),
+            "https://troop.100monkeys.ai/"
+        );
+        assert_eq!(config.proxy_port, 9000);
+        assert!(config.proxy_bind.is_loopback());
+
+        // Bad values are reported against where they came from
+        let overrides = Overrides {
+            coordinator_url: Some("not a url".to_string()),
+            ..Overrides::default()
+        };
+        let err = Config::resolve(&overrides, var).unwrap_err();
+        assert!(err.to_string().contains("Invalid --coordinator-url"));
+        let err = Config::resolve(&Overrides::default(), |name| {
+            (name == "PROXY_BIND").then(|| "localhost".to_string())
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("Invalid PROXY_BIND"));
+    }
+
+    #[test]
+    fn test_load_takes_file_values_below_env() {
+        let file = ConfigFile::parse(
+            std::path::Path::new("client.toml"),
+            "requester_id = \"file\"\nproxy_port = 9001\nworker_port = 8081\n",
+        )
+        .unwrap();
+        let env = |name: &str| (name == "PROXY_PORT").then(|| "1234".to_string());
+        let config = Config::load_with(&Overrides::default(), env, Some(&file)).unwrap();
+        assert_eq!(config.requester_id, "file");
+        assert_eq!(config.proxy_port, 1234);
+        assert_eq!(config.worker_port, 8081);
+
+        // Errors from file values name the line they are on
+        let file = ConfigFile::parse(
+            std::path::Path::new("client.toml"),
+            "requester_id = \"r\"\n\nqueue_timeout_secs = 0\n",
+        )
+        .unwrap();
+        let err = Config::load_with(&Overrides::default(), |_| None, Some(&file)).unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "`queue_timeout_secs` is set at client.toml:3: Invalid client configuration: \
+             Invalid request: QUEUE_TIMEOUT_SECS must be at least 1 second"
+        );
+    }
+
+    fn from_env() -> Result<Config> {
+        Config::resolve(&Overrides::default(), |name| env::var(name).ok())
+    }
+
+    /// Resolve with only `vars` set, leaving the process environment alone.
+    fn resolve(vars: &[(&str, &str)]) -> Result<Config> {
+        Config::resolve(&Overrides::default(), |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
     #[test]
     #[serial]
     fn test_config_from_env() {
         // Since environment variables are global, we run all scenarios in one test
         // to avoid race conditions between scenarios in this module.
         // Note: this does not prevent races with other tests that also modify these vars.
+        // Settings added since are tested on their own below, through `resolve`.
 
         // Save original values to restore them later
-        let orig_url = env::var("COORDINATOR_URL").ok();
-        let orig_port = env::var("PROXY_PORT").ok();
-        let orig_worker_port = env::var("WORKER_PORT").ok();
-        let orig_id = env::var("REQUESTER_ID").ok();
+        let saved: Vec<_> = [
+            "COORDINATOR_URL",
+            "PROXY_PORT",
+            "WORKER_PORT",
+            "REQUESTER_ID",
+        ]
+        .map(|name| (name, env::var(name).ok()))
+        .into();
 
         // Scenario 1: Custom values
         env::set_var("COORDINATOR_URL", "http://localhost:8000");
@@ -78,8 +530,11 @@ mod tests {
         env::set_var("WORKER_PORT", "9090");
         env::set_var("REQUESTER_ID", "test-requester");
 
-        let config = Config::from_env().unwrap();
-        assert_eq!(config.coordinator_url.as_str(), "http://localhost:8000/");
+        let config = from_env().unwrap();
+        assert_eq!(
+            config.coordinator_urls[0].as_str(),
+            "http://localhost:8000/"
+        );
         assert_eq!(config.proxy_port, 1234);
         assert_eq!(config.worker_port, 9090);
         assert_eq!(config.requester_id, "test-requester");
@@ -90,9 +545,10 @@ mod tests {
         env::remove_var("WORKER_PORT");
         env::remove_var("REQUESTER_ID");
 
-        let config = Config::from_env().unwrap();
+        let config = from_env().unwrap();
+        assert_eq!(config.coordinator_urls.len(), 1);
         assert_eq!(
-            config.coordinator_url.as_str(),
+            config.coordinator_urls[0].as_str(),
             "https://troop.100monkeys.ai/"
         );
         assert_eq!(config.proxy_port, 9000);
@@ -103,35 +559,284 @@ mod tests {
         );
 
         // Scenario 3: Invalid port
-        // Ensure environment is explicitly set for this scenario
-        env::remove_var("COORDINATOR_URL");
-        env::remove_var("REQUESTER_ID");
         env::set_var("PROXY_PORT", "not-a-number");
         env::set_var("WORKER_PORT", "not-a-number");
-        let config = Config::from_env().unwrap();
+        let config = from_env().unwrap();
         assert_eq!(config.proxy_port, 9000);
         assert_eq!(config.worker_port, 8080);
 
         // Restore original values
-        if let Some(val) = orig_url {
-            env::set_var("COORDINATOR_URL", val);
-        } else {
-            env::remove_var("COORDINATOR_URL");
-        }
-        if let Some(val) = orig_port {
-            env::set_var("PROXY_PORT", val);
-        } else {
-            env::remove_var("PROXY_PORT");
-        }
-        if let Some(val) = orig_worker_port {
-            env::set_var("WORKER_PORT", val);
-        } else {
-            env::remove_var("WORKER_PORT");
-        }
-        if let Some(val) = orig_id {
-            env::set_var("REQUESTER_ID", val);
-        } else {
-            env::remove_var("REQUESTER_ID");
+        for (name, val) in saved {
+            match val {
+                Some(val) => env::set_var(name, val),
+                None => env::remove_var(name),
+            }
         }
     }
+
+    #[test]
+    fn test_coordinator_urls_are_a_failover_list() {
+        let config = resolve(&[(
+            "COORDINATOR_URL",
+            "https://primary.example, http://backup.example:8000",
+        )])
+        .unwrap();
+        let urls: Vec<_> = config.coordinator_urls.iter().map(Url::as_str).collect();
+        assert_eq!(
+            urls,
+            ["https://primary.example/", "http://backup.example:8000/"]
+        );
+
+        // An empty list or a non-http scheme is rejected
+        let err = resolve(&[("COORDINATOR_URL", " , ")]).unwrap_err();
+        assert!(err.to_string().contains("at least one coordinator"));
+        assert!(resolve(&[("COORDINATOR_URL", "https://ok.example,file:///etc/passwd")]).is_err());
+    }
+
+    #[test]
+    fn test_audit_log_settings() {
+        let config = resolve(&[]).unwrap();
+        assert!(config.audit_log_path.is_none());
+        assert!(!config.audit_log_include_content);
+
+        let config = resolve(&[
+            ("AUDIT_LOG_PATH", "/tmp/troop-audit.jsonl"),
+            ("AUDIT_LOG_INCLUDE_CONTENT", "true"),
+        ])
+        .unwrap();
+        assert_eq!(
+            config.audit_log_path,
+            Some(PathBuf::from("/tmp/troop-audit.jsonl"))
+        );
+        assert!(config.audit_log_include_content);
+    }
+
+    #[test]
+    fn test_shutdown_drain_timeout() {
+        let config = resolve(&[]).unwrap();
+        assert_eq!(config.shutdown_drain_timeout, Duration::from_secs(30));
+        let config = resolve(&[("SHUTDOWN_DRAIN_TIMEOUT_SECS", "5")]).unwrap();
+        assert_eq!(config.shutdown_drain_timeout, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_request_timeout_bounds() {
+        let config = resolve(&[]).unwrap();
+        assert_eq!(config.min_request_timeout, Duration::from_secs(5));
+        assert_eq!(config.max_request_timeout, Duration::from_secs(3600));
+
+        let config = resolve(&[
+            ("REQUEST_TIMEOUT_MIN_SECS", "10"),
+            ("REQUEST_TIMEOUT_MAX_SECS", "120"),
+        ])
+        .unwrap();
+        assert_eq!(config.min_request_timeout, Duration::from_secs(10));
+        assert_eq!(config.max_request_timeout, Duration::from_secs(120));
+
+        // A maximum below the minimum is raised to the minimum
+        let config = resolve(&[
+            ("REQUEST_TIMEOUT_MIN_SECS", "60"),
+            ("REQUEST_TIMEOUT_MAX_SECS", "30"),
+        ])
+        .unwrap();
+        assert_eq!(config.max_request_timeout, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_model_aliases() {
+        assert!(resolve(&[]).unwrap().model_aliases.is_empty());
+
+        let config = resolve(&[(
+            "MODEL_ALIASES",
+            "gpt-4o=llama3:70b, gpt-3.5-turbo = llama3:8b",
+        )])
+        .unwrap();
+        assert_eq!(config.resolve_model("gpt-4o"), "llama3:70b");
+        assert_eq!(config.resolve_model("gpt-3.5-turbo"), "llama3:8b");
+        assert_eq!(config.resolve_model("mistral"), "mistral");
+
+        // Malformed aliases are rejected
+        let err = resolve(&[("MODEL_ALIASES", "gpt-4o")]).unwrap_err();
+        assert!(err.to_string().contains("expected alias=model"));
+        assert!(resolve(&[("MODEL_ALIASES", "gpt-4o=")]).is_err());
+    }
+
+    #[test]
+    fn test_concurrency_limit_and_queue() {
+        let config = resolve(&[]).unwrap();
+        assert_eq!(config.max_concurrent_requests, None);
+        assert_eq!(config.max_queued_requests, 0);
+        assert_eq!(config.queue_timeout, Duration::from_secs(30));
+
+        let config = resolve(&[
+            ("MAX_CONCURRENT_REQUESTS", "4"),
+            ("MAX_QUEUED_REQUESTS", "16"),
+            ("QUEUE_TIMEOUT_SECS", "45"),
+        ])
+        .unwrap();
+        assert_eq!(config.max_concurrent_requests, Some(4));
+        assert_eq!(config.max_queued_requests, 16);
+        assert_eq!(config.queue_timeout, Duration::from_secs(45));
+
+        let err = resolve(&[("QUEUE_TIMEOUT_SECS", "0")])
+            .unwrap()
+            .validate()
+            .unwrap_err();
+        assert!(err.to_string().contains("QUEUE_TIMEOUT_SECS"));
+    }
+
+    #[test]
+    fn test_usage_file() {
+        assert!(resolve(&[]).unwrap().usage_file.is_none());
+        let config = resolve(&[("USAGE_FILE", "/tmp/troop-usage.json")]).unwrap();
+        assert_eq!(
+            config.usage_file,
+            Some(PathBuf::from("/tmp/troop-usage.json"))
+        );
+    }
+
+    #[test]
+    fn test_model_allow_and_deny_lists() {
+        assert!(resolve(&[]).unwrap().model_filter.allows("llama3:70b"));
+
+        let config =
+            resolve(&[("MODEL_ALLOWLIST", "llama3*"), ("MODEL_DENYLIST", "*:70b")]).unwrap();
+        assert!(config.model_filter.allows("llama3:8b"));
+        assert!(!config.model_filter.allows("llama3:70b"));
+        assert!(!config.model_filter.allows("mistral"));
+    }
+
+    #[test]
+    fn test_hedge_after() {
+        assert_eq!(resolve(&[]).unwrap().hedge_after, None);
+        let config = resolve(&[("HEDGE_AFTER_MS", "2000")]).unwrap();
+        assert_eq!(config.hedge_after, Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_max_tokens_cap() {
+        assert_eq!(resolve(&[]).unwrap().max_tokens_cap, None);
+        let config = resolve(&[("MAX_TOKENS_CAP", "4096")]).unwrap();
+        assert_eq!(config.max_tokens_cap, Some(4096));
+    }
+
+    #[test]
+    fn test_batch_parallelism() {
+        assert_eq!(resolve(&[]).unwrap().batch_parallelism, 4);
+        let config = resolve(&[("BATCH_PARALLELISM", "16")]).unwrap();
+        assert_eq!(config.batch_parallelism, 16);
+    }
+
+    #[test]
+    fn test_fan_out_max_n_is_capped() {
+        assert_eq!(resolve(&[]).unwrap().fan_out_max_n, None);
+        let config = resolve(&[("FAN_OUT_MAX_N", "100")]).unwrap();
+        assert_eq!(config.fan_out_max_n, Some(MAX_FAN_OUT_N));
+    }
+
+    #[test]
+    fn test_response_cache_settings() {
+        let config = resolve(&[]).unwrap();
+        assert!(!config.enable_response_cache);
+        assert_eq!(config.response_cache_ttl, Duration::from_secs(300));
+        assert_eq!(config.response_cache_size.get(), 256);
+
+        let config = resolve(&[
+            ("ENABLE_RESPONSE_CACHE", "true"),
+            ("RESPONSE_CACHE_TTL_SECS", "60"),
+            ("RESPONSE_CACHE_SIZE", "32"),
+        ])
+        .unwrap();
+        assert!(config.enable_response_cache);
+        assert_eq!(config.response_cache_ttl, Duration::from_secs(60));
+        assert_eq!(config.response_cache_size.get(), 32);
+    }
+
+    #[test]
+    fn test_proxy_tls_files() {
+        let config = resolve(&[]).unwrap();
+        assert!(config.proxy_tls.is_none());
+        assert_eq!(config.proxy_url(), "http://localhost:9000");
+
+        let config = resolve(&[
+            ("PROXY_TLS_CERT", "/etc/troop/proxy.crt"),
+            ("PROXY_TLS_KEY", "/etc/troop/proxy.key"),
+        ])
+        .unwrap();
+        assert_eq!(
+            config.proxy_tls,
+            Some(TlsFiles {
+                cert: PathBuf::from("/etc/troop/proxy.crt"),
+                key: PathBuf::from("/etc/troop/proxy.key"),
+            })
+        );
+        assert_eq!(config.proxy_url(), "https://localhost:9000");
+
+        // A certificate without its key (or vice versa) is rejected
+        let err = resolve(&[("PROXY_TLS_CERT", "/etc/troop/proxy.crt")]).unwrap_err();
+        assert!(err.to_string().contains("must be set together"));
+        assert!(resolve(&[("PROXY_TLS_KEY", "/etc/troop/proxy.key")]).is_err());
+    }
+
+    #[test]
+    fn test_proxy_unix_socket() {
+        let config = resolve(&[]).unwrap();
+        assert!(config.proxy_unix_socket.is_none());
+        assert_eq!(config.proxy_unix_socket_mode, 0o600);
+        assert!(config.proxy_tcp);
+
+        let config = resolve(&[
+            ("PROXY_UNIX_SOCKET", "/run/troop/proxy.sock"),
+            ("PROXY_UNIX_SOCKET_MODE", "0660"),
+            ("PROXY_TCP", "false"),
+        ])
+        .unwrap();
+        assert_eq!(
+            config.proxy_unix_socket,
+            Some(PathBuf::from("/run/troop/proxy.sock"))
+        );
+        assert_eq!(config.proxy_unix_socket_mode, 0o660);
+        assert!(!config.proxy_tcp);
+
+        // TCP can only be turned off when there is a socket to listen on instead
+        let err = resolve(&[("PROXY_TCP", "no")])
+            .unwrap()
+            .validate()
+            .unwrap_err();
+        assert!(err.to_string().contains("needs PROXY_UNIX_SOCKET"));
+        let err = resolve(&[("PROXY_UNIX_SOCKET_MODE", "rw-------")]).unwrap_err();
+        assert!(err.to_string().contains("PROXY_UNIX_SOCKET_MODE"));
+    }
+
+    #[test]
+    fn test_daemon_files() {
+        let config = resolve(&[]).unwrap();
+        assert_eq!(
+            config.pid_file,
+            env::temp_dir().join("monkey-troop-client.pid")
+        );
+
+        let config = resolve(&[
+            ("PID_FILE", "/run/troop/client.pid"),
+            ("DAEMON_LOG_FILE", "/var/log/troop/client.log"),
+        ])
+        .unwrap();
+        assert_eq!(config.pid_file, PathBuf::from("/run/troop/client.pid"));
+        assert_eq!(
+            config.daemon_log_file,
+            PathBuf::from("/var/log/troop/client.log")
+        );
+    }
+
+    #[test]
+    fn test_validation_rejects_values_that_cannot_work() {
+        assert!(resolve(&[]).unwrap().validate().is_ok());
+        let err = resolve(&[("PROXY_PORT", "0")])
+            .unwrap()
+            .validate()
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("PROXY_PORT must be between 1 and 65535"));
+    }
 }
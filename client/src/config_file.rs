@@ -0,0 +1,673 @@
+//! The client configuration file, managed with `config init`, `config show` and
+//! `config set`.
+//!
+//! A flat TOML file in the platform config directory (`CLIENT_CONFIG_FILE` points
+//! elsewhere) whose keys are the environment variable names in lower case, so
+//! `proxy_port = 9001` is `PROXY_PORT=9001`. Each setting is taken from, in order, its
+//! command-line flag, its environment variable, the file, and its default. Values are
+//! type-checked when the file is read, and both those errors and any the configuration
+//! raises later name the key and line they came from.
+
+use crate::config::Overrides;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable pointing at a config file other than the default one.
+pub const CONFIG_FILE_VAR: &str = "CLIENT_CONFIG_FILE";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    /// A string, or an array of strings for the comma-separated lists
+    Text,
+    /// A non-negative integer
+    Integer,
+    Boolean,
+}
+
+/// One setting the file can hold.
+pub struct Setting {
+    pub key: &'static str,
+    kind: Kind,
+    /// The default as a TOML value, when it is a fixed one
+    default: Option<&'static str>,
+    about: &'static str,
+}
+
+impl Setting {
+    fn env_var(&self) -> String {
+        self.key.to_ascii_uppercase()
+    }
+}
+
+const fn setting(
+    key: &'static str,
+    kind: Kind,
+    default: Option<&'static str>,
+    about: &'static str,
+) -> Setting {
+    Setting {
+        key,
+        kind,
+        default,
+        about,
+    }
+}
+
+/// Every setting, in the order `config init` and `config show` list them.
+pub const SETTINGS: &[Setting] = &[
+    setting(
+        "coordinator_url",
+        Kind::Text,
+        Some("\"https://troop.100monkeys.ai\""),
+        "Coordinator URL, or a comma-separated failover list",
+    ),
+    setting(
+        "requester_id",
+        Kind::Text,
+        None,
+        "Identity requests are made under; this machine's Tailscale IP by default",
+    ),
+    setting(
+        "proxy_bind",
+        Kind::Text,
+        Some("\"127.0.0.1\""),
+        "Address the proxy listens on",
+    ),
+    setting(
+        "proxy_port",
+        Kind::Integer,
+        Some("9000"),
+        "Port the proxy listens on",
+    ),
+    setting(
+        "worker_port",
+        Kind::Integer,
+        Some("8080"),
+        "Port workers serve on, when the coordinator does not say",
+    ),
+    setting(
+        "proxy_tcp",
+        Kind::Boolean,
+        Some("true"),
+        "Listen on proxy_bind:proxy_port; false serves only the unix socket",
+    ),
+    setting(
+        "proxy_unix_socket",
+        Kind::Text,
+        None,
+        "Also serve the proxy on this unix socket",
+    ),
+    setting(
+        "proxy_unix_socket_mode",
+        Kind::Text,
+        Some("\"0600\""),
+        "Permissions the socket file is created with, in octal",
+    ),
+    setting(
+        "proxy_tls_cert",
+        Kind::Text,
+        None,
+        "PEM certificate to serve HTTPS with, together with proxy_tls_key",
+    ),
+    setting(
+        "proxy_tls_key",
+        Kind::Text,
+        None,
+        "PEM private key to serve HTTPS with, together with proxy_tls_cert",
+    ),
+    setting(
+        "request_timeout_min_secs",
+        Kind::Integer,
+        Some("5"),
+        "Lower bound on per-request X-Troop-Timeout-Secs",
+    ),
+    setting(
+        "request_timeout_max_secs",
+        Kind::Integer,
+        Some("3600"),
+        "Upper bound on per-request X-Troop-Timeout-Secs",
+    ),
+    setting(
+        "shutdown_drain_timeout_secs",
+        Kind::Integer,
+        Some("30"),
+        "How long in-flight requests may run after a shutdown signal",
+    ),
+    setting(
+        "max_concurrent_requests",
+        Kind::Integer,
+        None,
+        "Requests forwarded at once; unlimited when unset",
+    ),
+    setting(
+        "max_queued_requests",
+        Kind::Integer,
+        Some("0"),
+        "Requests allowed to wait once max_concurrent_requests is reached",
+    ),
+    setting(
+        "queue_timeout_secs",
+        Kind::Integer,
+        Some("30"),
+        "How long a queued request waits for a slot",
+    ),
+    setting(
+        "model_aliases",
+        Kind::Text,
+        None,
+        "Model names rewritten before authorization, as alias=model pairs",
+    ),
+    setting(
+        "model_allowlist",
+        Kind::Text,
+        None,
+        "Only serve these models",
+    ),
+    setting(
+        "model_denylist",
+        Kind::Text,
+        None,
+        "Never serve these models",
+    ),
+    setting(
+        "hedge_after_ms",
+        Kind::Integer,
+        None,
+        "Race a second node after this long without a first byte; costs extra credits",
+    ),
+    setting(
+        "max_tokens_cap",
+        Kind::Integer,
+        None,
+        "Upper bound on max_tokens, injected into requests without one",
+    ),
+    setting(
+        "batch_parallelism",
+        Kind::Integer,
+        Some("4"),
+        "Requests from one /v1/batch call run at once",
+    ),
+    setting(
+        "fan_out_max_n",
+        Kind::Integer,
+        None,
+        "Largest n served by fanning a chat completion out; off when unset",
+    ),
+    setting(
+        "enable_response_cache",
+        Kind::Boolean,
+        Some("false"),
+        "Answer repeated requests with temperature 0 and a seed from a local cache",
+    ),
+    setting(
+        "response_cache_ttl_secs",
+        Kind::Integer,
+        Some("300"),
+        "How long a cached reply is served",
+    ),
+    setting(
+        "response_cache_size",
+        Kind::Integer,
+        Some("256"),
+        "Most replies cached at once",
+    ),
+    setting(
+        "audit_log_path",
+        Kind::Text,
+        None,
+        "JSONL file receiving one audit record per proxied request",
+    ),
+    setting(
+        "audit_log_include_content",
+        Kind::Boolean,
+        Some("false"),
+        "Record message content in the audit log instead of just its length",
+    ),
+    setting(
+        "usage_file",
+        Kind::Text,
+        None,
+        "JSON file the /usage totals are saved to",
+    ),
+    setting(
+        "pid_file",
+        Kind::Text,
+        None,
+        "Where up --daemon records the background proxy's pid; in the temp dir by default",
+    ),
+    setting(
+        "daemon_log_file",
+        Kind::Text,
+        None,
+        "File the background proxy logs to; in the temp dir by default",
+    ),
+];
+
+fn find_setting(key: &str) -> Result<&'static Setting> {
+    SETTINGS
+        .iter()
+        .find(|setting| setting.key == key)
+        .with_context(|| {
+            format!(
+                "Unknown setting `{key}`; expected one of: {}",
+                SETTINGS
+                    .iter()
+                    .map(|s| s.key)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+}
+
+/// The config file used when `CLIENT_CONFIG_FILE` is not set: `monkey-troop/client.toml`
+/// in the platform config directory, if there is one.
+pub fn default_path() -> Option<PathBuf> {
+    let non_empty = |name| env::var_os(name).filter(|value| !value.is_empty());
+    let dir = if cfg!(windows) {
+        PathBuf::from(non_empty("APPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(non_empty("HOME")?).join("Library/Application Support")
+    } else {
+        match non_empty("XDG_CONFIG_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(non_empty("HOME")?).join(".config"),
+        }
+    };
+    Some(dir.join("monkey-troop").join("client.toml"))
+}
+
+/// The config file in use.
+pub fn path() -> Option<PathBuf> {
+    env::var_os(CONFIG_FILE_VAR)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .or_else(default_path)
+}
+
+/// A parsed config file. Values are kept in the form their environment variable takes.
+#[derive(Debug)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    values: BTreeMap<String, (String, usize)>,
+}
+
+impl ConfigFile {
+    /// Read the file at `path`, or `None` when there is none.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(path, &text).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    pub fn parse(path: &Path, text: &str) -> Result<Self> {
+        let table: BTreeMap<String, toml::Spanned<toml::Value>> = toml::from_str(text)
+            .map_err(|e| anyhow::anyhow!("Invalid config file {}: {e}", path.display()))?;
+
+        let mut values = BTreeMap::new();
+        for (key, value) in table {
+            let line = text[..value.span().start].matches('\n').count() + 1;
+            let at = || format!("{}:{line}", path.display());
+            let setting = find_setting(&key).with_context(at)?;
+            let value = env_value(setting, value.get_ref()).with_context(at)?;
+            values.insert(key, (value, line));
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            values,
+        })
+    }
+
+    /// The value for environment variable `name`, if the file sets it.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .get(&name.to_ascii_lowercase())
+            .map(|(value, _)| value.as_str())
+    }
+
+    fn line(&self, key: &str) -> Option<usize> {
+        self.values.get(key).map(|(_, line)| *line)
+    }
+
+    /// Where the value behind `error` came from, when it was read from this file: the
+    /// first setting named in the error that the environment did not override.
+    pub fn blame(
+        &self,
+        error: &anyhow::Error,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Option<String> {
+        let message = format!("{error:#}");
+        SETTINGS
+            .iter()
+            .filter(|setting| mentions(&message, &setting.env_var()))
+            .filter(|setting| env(&setting.env_var()).is_none())
+            .find_map(|setting| {
+                let line = self.line(setting.key)?;
+                Some(format!(
+                    "`{}` is set at {}:{line}",
+                    setting.key,
+                    self.path.display()
+                ))
+            })
+    }
+}
+
+/// Whether `message` names the variable `name`, rather than one it is a prefix of.
+fn mentions(message: &str, name: &str) -> bool {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    message.match_indices(name).any(|(start, _)| {
+        !message[..start].ends_with(is_name_char)
+            && !message[start + name.len()..].starts_with(is_name_char)
+    })
+}
+
+/// A TOML value as its environment variable would spell it.
+fn env_value(setting: &Setting, value: &toml::Value) -> Result<String> {
+    let key = setting.key;
+    match (setting.kind, value) {
+        (Kind::Text, toml::Value::String(s)) => Ok(s.clone()),
+        (Kind::Text, toml::Value::Array(items)) => items
+            .iter()
+            .map(|item| {
+                item.as_str()
+                    .map(str::to_string)
+                    .with_context(|| format!("`{key}` must be a string or a list of strings"))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|items| items.join(",")),
+        (Kind::Text, _) => anyhow::bail!("`{key}` must be a string or a list of strings"),
+        (Kind::Integer, toml::Value::Integer(n)) if *n >= 0 => Ok(n.to_string()),
+        (Kind::Integer, _) => anyhow::bail!("`{key}` must be a non-negative integer"),
+        (Kind::Boolean, toml::Value::Boolean(b)) => Ok(b.to_string()),
+        (Kind::Boolean, _) => anyhow::bail!("`{key}` must be true or false"),
+    }
+}
+
+/// `value` given on the command line as the TOML literal `config set` writes.
+fn toml_literal(setting: &Setting, value: &str) -> Result<String> {
+    let key = setting.key;
+    match setting.kind {
+        Kind::Text => Ok(toml::Value::String(value.to_string()).to_string()),
+        Kind::Integer => value
+            .parse::<u64>()
+            .map(|n| n.to_string())
+            .with_context(|| format!("`{key}` must be a non-negative integer, got '{value}'")),
+        Kind::Boolean => value
+            .parse::<bool>()
+            .map(|b| b.to_string())
+            .with_context(|| format!("`{key}` must be true or false, got '{value}'")),
+    }
+}
+
+/// The file `config init` writes: every setting commented out at its default.
+pub fn template() -> String {
+    let mut text = String::from(
+        "# Monkey Troop client configuration.\n\
+         #\n\
+         # Each setting can also be given as the environment variable of the same name in\n\
+         # upper case, which takes precedence over this file. Uncomment a line to change it.\n",
+    );
+    for setting in SETTINGS {
+        text.push_str(&format!(
+            "\n# {}\n# {} = {}\n",
+            setting.about,
+            setting.key,
+            setting.default.unwrap_or("")
+        ));
+    }
+    text
+}
+
+/// Write `template()` to `path`, refusing to replace an existing file unless `force`.
+pub fn init(path: &Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        anyhow::bail!(
+            "{} already exists; use --force to overwrite it",
+            path.display()
+        );
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(path, template()).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// `text` with `key` set to `value`: its line replaced, its commented-out line restored,
+/// or a line appended, in that order of preference.
+fn with_setting(text: &str, key: &str, literal: &str) -> String {
+    let assigns = |line: &str| {
+        line.strip_prefix(key)
+            .is_some_and(|rest| rest.trim_start().starts_with('='))
+    };
+    let line = format!("{key} = {literal}");
+    let mut lines: Vec<&str> = text.lines().collect();
+    let position = lines.iter().position(|l| assigns(l.trim())).or_else(|| {
+        lines.iter().position(|l| {
+            l.trim()
+                .strip_prefix('#')
+                .is_some_and(|rest| assigns(rest.trim()))
+        })
+    });
+    match position {
+        Some(index) => lines[index] = &line,
+        None => lines.push(&line),
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Set `key` to `value` in the file at `path`, creating it from the template if needed.
+pub fn set(path: &Path, key: &str, value: &str) -> Result<()> {
+    let setting = find_setting(key)?;
+    let literal = toml_literal(setting, value)?;
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => template(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let text = with_setting(&text, key, &literal);
+    // Refuse to leave behind a file the client would then fail to read
+    ConfigFile::parse(path, &text)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Where a setting's effective value comes from.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    Flag,
+    Env,
+    File { line: usize },
+    Default,
+}
+
+/// A setting's effective value and its source, as `config show` lists them.
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct Effective {
+    pub key: &'static str,
+    /// `None` when unset, or left to a default worked out at startup
+    pub value: Option<String>,
+    pub source: Source,
+}
+
+/// The effective value of every setting, given the command-line `overrides`, the
+/// environment read through `env`, and the config file, if any.
+pub fn effective(
+    overrides: &Overrides,
+    env: impl Fn(&str) -> Option<String>,
+    file: Option<&ConfigFile>,
+) -> Vec<Effective> {
+    SETTINGS
+        .iter()
+        .map(|setting| {
+            let flag = match setting.key {
+                "coordinator_url" => overrides.coordinator_url.clone(),
+                "requester_id" => overrides.requester_id.clone(),
+                "proxy_port" => overrides.proxy_port.map(|port| port.to_string()),
+                "proxy_bind" => overrides.proxy_bind.map(|addr| addr.to_string()),
+                _ => None,
+            };
+            let file_value = file.and_then(|file| {
+                let value = file.get(setting.key)?.to_string();
+                Some((value, file.line(setting.key)?))
+            });
+            let (value, source) = if let Some(value) = flag {
+                (Some(value), Source::Flag)
+            } else if let Some(value) = env(&setting.env_var()) {
+                (Some(value), Source::Env)
+            } else if let Some((value, line)) = file_value {
+                (Some(value), Source::File { line })
+            } else {
+                let default = setting
+                    .default
+                    .map(|literal| literal.trim_matches('"').to_string());
+                (default, Source::Default)
+            };
+            Effective {
+                key: setting.key,
+                value,
+                source,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<ConfigFile> {
+        ConfigFile::parse(Path::new("client.toml"), text)
+    }
+
+    #[test]
+    fn test_values_take_their_environment_form() {
+        let file = parse(
+            "# comment\n\
+             coordinator_url = [\"https://a.example\", \"https://b.example\"]\n\
+             proxy_port = 9001\n\
+             proxy_tcp = false\n",
+        )
+        .unwrap();
+        assert_eq!(
+            file.get("COORDINATOR_URL"),
+            Some("https://a.example,https://b.example")
+        );
+        assert_eq!(file.get("PROXY_PORT"), Some("9001"));
+        assert_eq!(file.get("PROXY_TCP"), Some("false"));
+        assert_eq!(file.get("WORKER_PORT"), None);
+        assert_eq!(file.line("proxy_port"), Some(3));
+    }
+
+    #[test]
+    fn test_errors_point_at_the_line() {
+        let err = parse("proxy_port = 9001\nproxy_prot = 9002\n").unwrap_err();
+        let message = format!("{err:#}");
+        assert!(message.starts_with("client.toml:2: Unknown setting `proxy_prot`"));
+
+        let err = parse("\nproxy_port = \"9001\"\n").unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "client.toml:2: `proxy_port` must be a non-negative integer"
+        );
+
+        let err = parse("proxy_port = 1\nproxy_port = \n").unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn test_template_parses_and_each_default_is_valid() {
+        let template = template();
+        assert_eq!(parse(&template).unwrap().values.len(), 0);
+        for setting in SETTINGS.iter().filter(|s| s.default.is_some()) {
+            let line = format!("# {} = ", setting.key);
+            let uncommented = template.replacen(&line, &line[2..], 1);
+            assert!(parse(&uncommented).unwrap().get(setting.key).is_some());
+        }
+    }
+
+    #[test]
+    fn test_set_replaces_restores_or_appends_a_line() {
+        let text = "# Port\n# proxy_port = 9000\nrequester_id = \"old\"\n";
+        let text = with_setting(text, "requester_id", "\"new\"");
+        let text = with_setting(&text, "proxy_port", "9001");
+        let text = with_setting(&text, "worker_port", "8081");
+        assert_eq!(
+            text,
+            "# Port\nproxy_port = 9001\nrequester_id = \"new\"\nworker_port = 8081\n"
+        );
+        // A key that merely starts with another is left alone
+        let text = with_setting("proxy_port_x = 1\n", "proxy_port", "2");
+        assert_eq!(text, "proxy_port_x = 1\nproxy_port = 2\n");
+    }
+
+    #[test]
+    fn test_set_checks_the_value() {
+        let dir = std::env::temp_dir().join(format!("troop-config-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("client.toml");
+
+        set(&path, "requester_id", "alice \"a\"").unwrap();
+        set(&path, "proxy_port", "9001").unwrap();
+        let file = ConfigFile::read(&path).unwrap().unwrap();
+        assert_eq!(file.get("REQUESTER_ID"), Some("alice \"a\""));
+        assert_eq!(file.get("PROXY_PORT"), Some("9001"));
+
+        assert!(set(&path, "proxy_port", "ninety").is_err());
+        assert!(set(&path, "no_such_key", "1").is_err());
+        assert!(init(&path, false).is_err());
+        init(&path, true).unwrap();
+        assert_eq!(ConfigFile::read(&path).unwrap().unwrap().values.len(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_effective_values_and_sources() {
+        let file =
+            parse("proxy_port = 9001\nworker_port = 8081\nrequester_id = \"file\"\n").unwrap();
+        let overrides = Overrides {
+            requester_id: Some("flag".to_string()),
+            ..Overrides::default()
+        };
+        let env = |name: &str| (name == "WORKER_PORT").then(|| "8082".to_string());
+        let settings = effective(&overrides, env, Some(&file));
+        let find = |key| settings.iter().find(|s| s.key == key).unwrap();
+
+        assert_eq!(find("requester_id").source, Source::Flag);
+        assert_eq!(find("worker_port").value.as_deref(), Some("8082"));
+        assert_eq!(find("worker_port").source, Source::Env);
+        assert_eq!(find("proxy_port").source, Source::File { line: 1 });
+        assert_eq!(
+            find("coordinator_url").value.as_deref(),
+            Some("https://troop.100monkeys.ai")
+        );
+        assert_eq!(find("coordinator_url").source, Source::Default);
+        assert_eq!(find("usage_file").value, None);
+    }
+
+    #[test]
+    fn test_blame_names_the_key_and_line_unless_env_overrides() {
+        let file = parse("\nproxy_bind = \"localhost\"\n").unwrap();
+        let error = anyhow::anyhow!("Invalid PROXY_BIND address: localhost");
+        assert_eq!(
+            file.blame(&error, |_| None).as_deref(),
+            Some("`proxy_bind` is set at client.toml:2")
+        );
+        assert_eq!(file.blame(&error, |_| Some("localhost".to_string())), None);
+
+        let file =
+            parse("proxy_unix_socket = \"/tmp/s\"\nproxy_unix_socket_mode = \"999\"\n").unwrap();
+        let error = anyhow::anyhow!("PROXY_UNIX_SOCKET_MODE must be at most 0777, got 1747");
+        assert_eq!(
+            file.blame(&error, |_| None).as_deref(),
+            Some("`proxy_unix_socket_mode` is set at client.toml:2")
+        );
+    }
+}
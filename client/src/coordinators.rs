@@ -0,0 +1,400 @@
+//! Coordinator failover for the client.
+//!
+//! `COORDINATOR_URL` may list several coordinators. Calls start at the one that last
+//! answered and fall through the list in order, skipping coordinators whose circuit
+//! breaker is open so a dead primary is not re-probed on every request. The time of the
+//! last answer from any coordinator is kept for the readiness check.
+
+use chrono::{DateTime, Utc};
+use monkey_troop_shared::{
+    http_client, retry_with_policy, CircuitBreaker, RetryPolicy, TroopError, TroopResult,
+    AUTH_TIMEOUT, CIRCUIT_BREAKER_THRESHOLD, CIRCUIT_BREAKER_TIMEOUT, MAX_RETRIES, RETRY_DELAYS,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{info, warn};
+use url::Url;
+
+/// Retries for coordinator calls. A 429's `Retry-After` hint sets the delay; without one
+/// the backoff is jittered so rate-limited clients do not come back in lockstep.
+pub const COORDINATOR_RETRY_POLICY: RetryPolicy = RetryPolicy {
+    base_delay: Duration::from_secs(RETRY_DELAYS[0]),
+    max_retries: MAX_RETRIES,
+    jitter: true,
+};
+
+/// How long a readiness probe waits for each coordinator's `/health`.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct Coordinators {
+    urls: Vec<Url>,
+    breakers: Vec<CircuitBreaker>,
+    active: AtomicUsize,
+    http: reqwest::Client,
+    last_contact: Mutex<Option<DateTime<Utc>>>,
+    last_probe: tokio::sync::Mutex<Option<Instant>>,
+}
+
+/// Snapshot of the failover state, reported by `/health`.
+#[derive(Debug, Serialize)]
+pub struct CoordinatorStatus {
+    pub active: String,
+    pub failover_order: Vec<String>,
+}
+
+impl Coordinators {
+    /// `urls` must not be empty; `Config::resolve` guarantees this.
+    pub fn new(urls: Vec<Url>) -> Self {
+        assert!(!urls.is_empty(), "at least one coordinator URL is required");
+        let breakers = urls
+            .iter()
+            .map(|_| CircuitBreaker::new(CIRCUIT_BREAKER_THRESHOLD, CIRCUIT_BREAKER_TIMEOUT))
+            .collect();
+        Self {
+            urls,
+            breakers,
+            active: AtomicUsize::new(0),
+            http: http_client(AUTH_TIMEOUT),
+            last_contact: Mutex::new(None),
+            last_probe: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// HTTP client shared by all calls to the coordinators.
+    pub fn http(&self) -> &reqwest::Client {
+        &self.http
+    }
+
+    pub fn active(&self) -> &Url {
+        &self.urls[self.active.load(Ordering::Relaxed)]
+    }
+
+    pub fn status(&self) -> CoordinatorStatus {
+        CoordinatorStatus {
+            active: self.active().to_string(),
+            failover_order: self.urls.iter().map(Url::to_string).collect(),
+        }
+    }
+
+    /// When a coordinator last answered any call.
+    pub fn last_contact(&self) -> Option<DateTime<Utc>> {
+        *self.last_contact.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether a coordinator answered within the last `age`.
+    pub fn contacted_within(&self, age: Duration) -> bool {
+        self.last_contact().is_some_and(|at| {
+            Utc::now()
+                .signed_duration_since(at)
+                .to_std()
+                .unwrap_or_default()
+                < age
+        })
+    }
+
+    /// Probe `/health` unless a coordinator answered within `max_age`. Concurrent callers
+    /// share one probe, and a failed probe is not repeated until `max_age` has passed, so
+    /// frequent health checks do not turn into coordinator traffic.
+    pub async fn refresh_contact(&self, max_age: Duration) {
+        if self.contacted_within(max_age) {
+            return;
+        }
+        let mut last_probe = self.last_probe.lock().await;
+        if last_probe.is_some_and(|at| at.elapsed() < max_age) {
+            return;
+        }
+        *last_probe = Some(Instant::now());
+
+        let probe = self
+            .call(|base| {
+                let client = self.http.clone();
+                async move {
+                    let url = base
+                        .join("health")
+                        .map_err(|e| TroopError::InvalidRequest(e.to_string()))?;
+                    client.get(url).timeout(PROBE_TIMEOUT).send().await?;
+                    Ok(())
+                }
+            })
+            .await;
+        if let Err(e) = probe {
+            warn!("No coordinator answered the health probe: {}", e);
+        }
+    }
+
+    /// Run `operation` against each coordinator, starting with the active one, until one
+    /// answers. Only an unreachable or failing coordinator triggers failover; any other
+    /// answer (including "no nodes available" or "insufficient credits") is returned as-is.
+    pub async fn call<F, Fut, T>(&self, mut operation: F) -> TroopResult<T>
+    where
+        F: FnMut(Url) -> Fut,
+        Fut: Future<Output = TroopResult<T>>,
+    {
+        let start = self.active.load(Ordering::Relaxed);
+        let mut last_error = TroopError::CircuitBreakerOpen;
+
+        for index in (0..self.urls.len()).map(|i| (start + i) % self.urls.len()) {
+            let url = &self.urls[index];
+            let breaker = &self.breakers[index];
+            if !breaker.allow_request().await {
+                warn!("Skipping coordinator {}, circuit is open", url);
+                continue;
+            }
+
+            match operation(url.clone()).await {
+                Err(e) if is_coordinator_failure(&e) => {
+                    warn!("Coordinator {} failed: {}", url, e);
+                    breaker.record_failure().await;
+                    last_error = match e {
+                        // The message names the coordinator's URL
+                        TroopError::NetworkError(message) => {
+                            TroopError::CoordinatorUnreachable(message)
+                        }
+                        e => e,
+                    };
+                }
+                result => {
+                    breaker.record_success().await;
+                    *self.last_contact.lock().unwrap_or_else(|e| e.into_inner()) = Some(Utc::now());
+                    if self.active.swap(index, Ordering::Relaxed) != index {
+                        info!("Active coordinator is now {}", url);
+                    }
+                    return result;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// GET `path` as JSON with failover but no retries, each coordinator getting `timeout`
+    /// to answer, for status checks that should fail fast rather than back off.
+    pub async fn get_json_once<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        timeout: Duration,
+    ) -> TroopResult<T> {
+        self.call(|base| {
+            let client = self.http.clone();
+            async move {
+                let url = base
+                    .join(path)
+                    .map_err(|e| TroopError::InvalidRequest(e.to_string()))?;
+                let response = client.get(url).timeout(timeout).send().await?;
+                if !response.status().is_success() {
+                    return Err(TroopError::from_response(response).await);
+                }
+                Ok(response.json().await?)
+            }
+        })
+        .await
+    }
+
+    /// POST `body` to `path` and read the reply as JSON, with failover but no retries: a
+    /// request that timed out may still have been acted on.
+    pub async fn post_json<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> TroopResult<T> {
+        self.call(|base| {
+            let client = self.http.clone();
+            async move {
+                let url = base
+                    .join(path)
+                    .map_err(|e| TroopError::InvalidRequest(e.to_string()))?;
+                let response = client.post(url).json(body).send().await?;
+                if !response.status().is_success() {
+                    return Err(TroopError::from_response(response).await);
+                }
+                Ok(response.json().await?)
+            }
+        })
+        .await
+    }
+
+    /// GET `path` (relative to the coordinator URL) as JSON, with failover and retries.
+    pub async fn get_json<T: DeserializeOwned>(&self, path: &str) -> TroopResult<T> {
+        retry_with_policy(path, &COORDINATOR_RETRY_POLICY, || {
+            self.call(|base| {
+                let client = self.http.clone();
+                async move {
+                    let url = base
+                        .join(path)
+                        .map_err(|e| TroopError::InvalidRequest(e.to_string()))?;
+                    let response = client.get(url).send().await?;
+                    if !response.status().is_success() {
+                        return Err(TroopError::from_response(response).await);
+                    }
+                    Ok(response.json().await?)
+                }
+            })
+        })
+        .await
+    }
+}
+
+/// Whether `error` means the coordinator itself is down rather than answering normally.
+fn is_coordinator_failure(error: &TroopError) -> bool {
+    match error {
+        TroopError::NetworkError(_)
+        | TroopError::CoordinatorUnreachable(_)
+        | TroopError::Timeout(_) => true,
+        TroopError::UpstreamError { status, .. } => *status >= 500,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    fn url(server: &MockServer) -> Url {
+        Url::parse(&server.base_url()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_and_remembers_working_coordinator() {
+        let primary = MockServer::start();
+        let backup = MockServer::start();
+        let primary_peers = primary.mock(|when, then| {
+            when.method(GET).path("/peers");
+            then.status(502);
+        });
+        let backup_peers = backup.mock(|when, then| {
+            when.method(GET).path("/peers");
+            then.status(200).json_body(json!({"nodes": []}));
+        });
+
+        let coordinators = Coordinators::new(vec![url(&primary), url(&backup)]);
+        for _ in 0..2 {
+            let peers: serde_json::Value = coordinators.get_json("peers").await.unwrap();
+            assert_eq!(peers, json!({"nodes": []}));
+        }
+
+        assert_eq!(coordinators.active(), &url(&backup));
+        // The second call went straight to the backup
+        primary_peers.assert_calls(1);
+        backup_peers.assert_calls(2);
+    }
+
+    #[tokio::test]
+    async fn test_refused_connections_report_the_coordinator_unreachable() {
+        // Nothing listens on the discard port
+        let coordinators = Coordinators::new(vec![Url::parse("http://127.0.0.1:9").unwrap()]);
+        let error = coordinators
+            .get_json_once::<serde_json::Value>("peers", Duration::from_secs(2))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&error, TroopError::CoordinatorUnreachable(message) if message.contains("127.0.0.1:9")),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_permanent_errors_do_not_fail_over() {
+        let primary = MockServer::start();
+        let backup = MockServer::start();
+        primary.mock(|when, then| {
+            when.method(GET).path("/users/me/balance");
+            then.status(404);
+        });
+        let backup_balance = backup.mock(|when, then| {
+            when.method(GET).path("/users/me/balance");
+            then.status(200).json_body(json!({}));
+        });
+
+        let coordinators = Coordinators::new(vec![url(&primary), url(&backup)]);
+        let result = coordinators
+            .get_json::<serde_json::Value>("users/me/balance")
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(TroopError::UpstreamError { status: 404, .. })
+        ));
+        backup_balance.assert_calls(0);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_calls_wait_for_retry_after() {
+        let server = MockServer::start();
+        let balance = server.mock(|when, then| {
+            when.method(GET).path("/users/me/balance");
+            then.status(429)
+                .header("Retry-After", "1")
+                .json_body(json!({"detail": "Too many requests"}));
+        });
+
+        let coordinators = Coordinators::new(vec![url(&server)]);
+        let started = Instant::now();
+        let error = coordinators
+            .get_json::<serde_json::Value>("users/me/balance")
+            .await
+            .unwrap_err();
+
+        balance.assert_calls(MAX_RETRIES as usize);
+        assert!(started.elapsed() >= Duration::from_secs(u64::from(MAX_RETRIES - 1)));
+        assert_eq!(
+            error.to_string(),
+            "Rate limited: Too many requests, retry after 1s"
+        );
+        // A rate-limited coordinator is alive, so it keeps its place
+        assert!(coordinators.breakers[0].allow_request().await);
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_skips_coordinator() {
+        let primary = MockServer::start();
+        let backup = MockServer::start();
+        let primary_peers = primary.mock(|when, then| {
+            when.method(GET).path("/peers");
+            then.status(200).json_body(json!([]));
+        });
+        backup.mock(|when, then| {
+            when.method(GET).path("/peers");
+            then.status(200).json_body(json!([]));
+        });
+
+        let coordinators = Coordinators::new(vec![url(&primary), url(&backup)]);
+        for _ in 0..CIRCUIT_BREAKER_THRESHOLD {
+            coordinators.breakers[0].record_failure().await;
+        }
+        coordinators
+            .get_json::<serde_json::Value>("peers")
+            .await
+            .unwrap();
+
+        primary_peers.assert_calls(0);
+        assert_eq!(
+            coordinators.status().failover_order,
+            vec![url(&primary).to_string(), url(&backup).to_string()]
+        );
+        assert_eq!(coordinators.status().active, url(&backup).to_string());
+    }
+
+    #[tokio::test]
+    async fn test_health_probe_is_cached() {
+        let server = MockServer::start();
+        let health = server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200).json_body(json!({"status": "healthy"}));
+        });
+
+        let coordinators = Coordinators::new(vec![url(&server)]);
+        assert!(!coordinators.contacted_within(Duration::from_secs(5)));
+        for _ in 0..3 {
+            coordinators.refresh_contact(Duration::from_secs(5)).await;
+        }
+
+        health.assert_calls(1);
+        assert!(coordinators.contacted_within(Duration::from_secs(5)));
+    }
+}
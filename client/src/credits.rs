@@ -0,0 +1,241 @@
+//! Where a user's credits came from and went, and asking for more, for the `credits`
+//! command.
+//!
+//! The breakdown is worked out from the transaction history over a period: credits other
+//! users paid for requests this user's nodes served, credits granted by the coordinator
+//! (transactions with no payer), and credits spent on this user's own requests. A credit
+//! request is posted to the coordinator, which either grants it at once, recording a
+//! transaction, or leaves it pending for an operator.
+
+use crate::coordinators::Coordinators;
+use crate::transactions::{self, Totals};
+use anyhow::Result;
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use monkey_troop_shared::{
+    BalanceResponse, CreditRequest, CreditRequestResponse, CreditRequestStatus, TroopError,
+};
+use serde::Serialize;
+
+/// Days the breakdown covers without `--since`.
+const DEFAULT_PERIOD_DAYS: i64 = 30;
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Breakdown {
+    pub balance_seconds: i64,
+    pub since: NaiveDateTime,
+    /// `None` for up to now
+    pub until: Option<NaiveDateTime>,
+    pub transactions: usize,
+    /// Paid by other users for requests this user's nodes served
+    pub earned_serving: i64,
+    pub granted: i64,
+    pub spent: i64,
+    pub net: i64,
+}
+
+impl Breakdown {
+    fn new(
+        balance_seconds: i64,
+        since: NaiveDateTime,
+        until: Option<NaiveDateTime>,
+        totals: Totals,
+    ) -> Self {
+        Self {
+            balance_seconds,
+            since,
+            until,
+            transactions: totals.transactions,
+            earned_serving: totals.earned - totals.granted,
+            granted: totals.granted,
+            spent: totals.spent,
+            net: totals.net,
+        }
+    }
+
+    /// The breakdown as lines of text.
+    pub fn lines(&self) -> Vec<String> {
+        let until = match self.until {
+            Some(until) => until.format("%Y-%m-%d %H:%M").to_string(),
+            None => "now".to_string(),
+        };
+        vec![
+            format!(
+                "Balance: {} seconds ({:.2} hours)",
+                self.balance_seconds,
+                self.balance_seconds as f64 / 3600.0
+            ),
+            format!(
+                "From {} to {until}, {} transactions:",
+                self.since.format("%Y-%m-%d %H:%M"),
+                self.transactions
+            ),
+            format!("  Earned serving requests: {}", self.earned_serving),
+            format!("  Granted: {}", self.granted),
+            format!("  Spent on requests: {}", self.spent),
+            format!("  Net: {:+}", self.net),
+        ]
+    }
+}
+
+/// `requester_id`'s balance, and its credits earned and spent in `[since, until)`;
+/// `since` defaults to `DEFAULT_PERIOD_DAYS` ago.
+pub async fn breakdown(
+    coordinators: &Coordinators,
+    requester_id: &str,
+    since: Option<NaiveDateTime>,
+    until: Option<NaiveDateTime>,
+) -> Result<Breakdown> {
+    let since =
+        since.unwrap_or_else(|| (Utc::now() - TimeDelta::days(DEFAULT_PERIOD_DAYS)).naive_utc());
+    let balance: BalanceResponse = coordinators
+        .get_json(&format!("users/{requester_id}/balance"))
+        .await?;
+    let totals = transactions::sum(coordinators, requester_id, Some(since), until).await?;
+    Ok(Breakdown::new(
+        balance.balance_seconds,
+        since,
+        until,
+        totals,
+    ))
+}
+
+/// Ask the coordinator to add `amount` credits to `requester_id`'s balance.
+pub async fn request(
+    coordinators: &Coordinators,
+    requester_id: &str,
+    amount: u64,
+    reason: Option<String>,
+) -> Result<CreditRequestResponse, TroopError> {
+    coordinators
+        .post_json(
+            &format!("users/{requester_id}/credit-requests"),
+            &CreditRequest { amount, reason },
+        )
+        .await
+        .map_err(request_error)
+}
+
+/// Tell a refusal and a coordinator without credit requests apart from other failures.
+fn request_error(error: TroopError) -> TroopError {
+    let TroopError::UpstreamError { status, .. } = &error else {
+        return error;
+    };
+    let detail = error.api_error_body().error.message;
+    match status {
+        401 | 403 => {
+            TroopError::PermissionDenied(format!("this account may not request credits ({detail})"))
+        }
+        404 | 405 | 501 => TroopError::Unsupported(
+            "this coordinator does not take credit requests; it needs upgrading".to_string(),
+        ),
+        _ => error,
+    }
+}
+
+/// What became of a request for `amount` credits, as a line of text.
+pub fn outcome(response: &CreditRequestResponse, amount: u64) -> String {
+    let mut line = match response.status {
+        CreditRequestStatus::Completed => match &response.transaction {
+            Some(txn) => format!(
+                "Added {} credits (transaction {})",
+                txn.credits,
+                txn.id.map_or("-".to_string(), |id| id.to_string())
+            ),
+            None => format!("Added {amount} credits"),
+        },
+        CreditRequestStatus::Pending => format!(
+            "Request {} for {amount} credits is waiting for approval",
+            response.request_id.as_deref().unwrap_or("-")
+        ),
+        CreditRequestStatus::Rejected => format!("Request for {amount} credits was rejected"),
+    };
+    if let Some(message) = &response.message {
+        line.push_str(&format!(": {message}"));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use serde_json::json;
+    use url::Url;
+
+    fn coordinators(server: &MockServer) -> Coordinators {
+        Coordinators::new(vec![Url::parse(&server.base_url()).unwrap()])
+    }
+
+    #[test]
+    fn test_breakdown_separates_grants_from_earnings() {
+        let since = transactions::parse_time("2026-10-01").unwrap();
+        let totals = Totals {
+            transactions: 5,
+            earned: 3700,
+            granted: 3600,
+            spent: 40,
+            net: 3660,
+        };
+        let breakdown = Breakdown::new(7200, since, None, totals);
+        assert_eq!(breakdown.earned_serving, 100);
+        assert_eq!(
+            breakdown.lines()[1..],
+            [
+                "From 2026-10-01 00:00 to now, 5 transactions:",
+                "  Earned serving requests: 100",
+                "  Granted: 3600",
+                "  Spent on requests: 40",
+                "  Net: +3660",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_reports_a_pending_request() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST)
+                .path("/users/me/credit-requests")
+                .json_body(json!({"amount": 3600, "reason": "testing"}));
+            then.status(202)
+                .json_body(json!({"request_id": "cr-7", "status": "pending"}));
+        });
+
+        let response = request(&coordinators(&server), "me", 3600, Some("testing".into()))
+            .await
+            .unwrap();
+        assert_eq!(response.status, CreditRequestStatus::Pending);
+        assert_eq!(
+            outcome(&response, 3600),
+            "Request cr-7 for 3600 credits is waiting for approval"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_errors_say_what_went_wrong() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/users/guest/credit-requests");
+            then.status(403)
+                .json_body(json!({"detail": "Only operators may grant credits"}));
+        });
+        let error = request(&coordinators(&server), "guest", 10, None)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&error, TroopError::PermissionDenied(m) if m.contains("Only operators")),
+            "{error}"
+        );
+
+        // Older coordinators have no such route
+        let old = MockServer::start();
+        old.mock(|when, then| {
+            when.method(POST);
+            then.status(404).json_body(json!({"detail": "Not Found"}));
+        });
+        let error = request(&coordinators(&old), "me", 10, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, TroopError::Unsupported(_)), "{error}");
+    }
+}
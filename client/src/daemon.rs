@@ -0,0 +1,206 @@
+//! Background mode for the client proxy (`up --daemon` / `down`).
+//!
+//! `up --daemon` re-runs the same command line without `--daemon` as a detached child
+//! with its output sent to a log file, and records the child's pid in the pid file.
+//! `down` sends that pid SIGTERM, which triggers the proxy's usual graceful drain, and
+//! waits for the process to exit. A pid file whose process is gone is stale and is
+//! removed wherever it is found, so a crashed proxy never blocks the next `up`.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tracing::warn;
+
+/// How long `up --daemon` watches the child before reporting it started, so a proxy
+/// that fails at startup (a port in use, say) is reported instead of a stale pid.
+const STARTUP_CHECK: Duration = Duration::from_millis(500);
+
+/// Poll interval while waiting for a stopped proxy to exit.
+const EXIT_POLL: Duration = Duration::from_millis(100);
+
+/// The pid recorded in `pid_file` if that process is still running. A stale or
+/// unreadable pid file is removed.
+pub fn running_pid(pid_file: &Path) -> Result<Option<u32>> {
+    let contents = match fs::read_to_string(pid_file) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", pid_file.display())),
+    };
+    match contents.trim().parse() {
+        Ok(pid) if process_alive(pid) => Ok(Some(pid)),
+        _ => {
+            warn!("Removing stale pid file {}", pid_file.display());
+            fs::remove_file(pid_file)
+                .with_context(|| format!("Failed to remove {}", pid_file.display()))?;
+            Ok(None)
+        }
+    }
+}
+
+/// Fail if a proxy recorded in `pid_file` is already running.
+pub fn ensure_not_running(pid_file: &Path) -> Result<()> {
+    if let Some(pid) = running_pid(pid_file)? {
+        anyhow::bail!(
+            "monkey-troop-client is already running (pid {pid}, pid file {}); \
+             stop it with `monkey-troop-client down`",
+            pid_file.display()
+        );
+    }
+    Ok(())
+}
+
+/// Start this command line again without `--daemon`, detached, with its output appended
+/// to `log_file`, and record its pid in `pid_file`. Returns the pid.
+pub async fn spawn(pid_file: &Path, log_file: &Path) -> Result<u32> {
+    ensure_not_running(pid_file)?;
+
+    let log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)
+        .with_context(|| format!("Failed to open log file {}", log_file.display()))?;
+    let mut command = tokio::process::Command::new(
+        std::env::current_exe().context("Failed to locate the client binary")?,
+    );
+    command
+        .args(std::env::args_os().skip(1).filter(|arg| arg != "--daemon"))
+        // Plain log lines in the file rather than terminal colour codes
+        .env("NO_COLOR", "1")
+        .stdin(std::process::Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+    detach(&mut command);
+    let mut child = command
+        .spawn()
+        .context("Failed to start the background proxy")?;
+    let pid = child.id().context("Background proxy exited immediately")?;
+
+    if let Ok(status) = tokio::time::timeout(STARTUP_CHECK, child.wait()).await {
+        anyhow::bail!(
+            "Background proxy exited during startup ({}); see {}",
+            status?,
+            log_file.display()
+        );
+    }
+    fs::write(pid_file, format!("{pid}\n"))
+        .with_context(|| format!("Failed to write pid file {}", pid_file.display()))?;
+    Ok(pid)
+}
+
+/// Send SIGTERM to the proxy recorded in `pid_file` and wait up to `timeout` for it to
+/// exit, then remove the pid file. Returns the stopped pid, or `None` if none was running.
+pub async fn stop(pid_file: &Path, timeout: Duration) -> Result<Option<u32>> {
+    let Some(pid) = running_pid(pid_file)? else {
+        return Ok(None);
+    };
+    terminate(pid)?;
+
+    let exited = tokio::time::timeout(timeout, async {
+        while process_alive(pid) {
+            tokio::time::sleep(EXIT_POLL).await;
+        }
+    })
+    .await;
+    if exited.is_err() {
+        anyhow::bail!("monkey-troop-client (pid {pid}) did not exit within {timeout:?}");
+    }
+    fs::remove_file(pid_file)
+        .with_context(|| format!("Failed to remove {}", pid_file.display()))?;
+    Ok(Some(pid))
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 only checks the process exists; EPERM means it exists as another user
+    pid > 0
+        && (unsafe { libc::kill(pid, 0) } == 0
+            || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM))
+}
+
+#[cfg(unix)]
+fn terminate(pid: u32) -> Result<()> {
+    let pid = libc::pid_t::try_from(pid).context("Invalid pid")?;
+    if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to signal pid {pid}"));
+    }
+    Ok(())
+}
+
+/// Put the child in its own process group, so Ctrl-C in the terminal that started it
+/// does not reach it.
+#[cfg(unix)]
+fn detach(command: &mut tokio::process::Command) {
+    command.process_group(0);
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    false
+}
+
+#[cfg(not(unix))]
+fn terminate(_pid: u32) -> Result<()> {
+    anyhow::bail!("Stopping the background proxy is only supported on Unix")
+}
+
+#[cfg(not(unix))]
+fn detach(_command: &mut tokio::process::Command) {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn pid_file(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "troop-{name}-{}.pid",
+            uuid::Uuid::new_v4().simple()
+        ))
+    }
+
+    #[test]
+    fn test_live_pid_file_refuses_and_stale_one_is_removed() {
+        let path = pid_file("live");
+        fs::write(&path, format!("{}\n", std::process::id())).unwrap();
+        let err = ensure_not_running(&path).unwrap_err();
+        assert!(err.to_string().contains("already running"));
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+
+        // A process that has exited and been reaped
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        for contents in [format!("{pid}\n"), "garbage".to_string()] {
+            let path = pid_file("stale");
+            fs::write(&path, contents).unwrap();
+            ensure_not_running(&path).unwrap();
+            assert!(!path.exists());
+        }
+        assert_eq!(running_pid(&pid_file("missing")).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_stop_terminates_and_waits_for_exit() {
+        let mut child = tokio::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let pid = child.id().unwrap();
+        // Reap it as soon as it dies, as init would for a detached proxy
+        let reaper = tokio::spawn(async move { child.wait().await });
+
+        let path = pid_file("stop");
+        fs::write(&path, format!("{pid}\n")).unwrap();
+        let stopped = stop(&path, Duration::from_secs(10)).await.unwrap();
+        assert_eq!(stopped, Some(pid));
+        assert!(!path.exists());
+        assert!(!reaper.await.unwrap().unwrap().success());
+
+        assert_eq!(stop(&path, Duration::from_secs(1)).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,114 @@
+//! `Content-Encoding` handling for worker responses.
+//!
+//! Buffered plaintext replies are relayed byte for byte, so the caller's `Accept-Encoding`
+//! is forwarded to the worker and the worker's `Content-Encoding` is kept. Replies the
+//! proxy has to read or rewrite (streams, which are metered and may be cut off at
+//! shutdown, and E2E-encrypted replies) are requested uncompressed, and are decoded here
+//! should a worker compress them anyway.
+
+use bytes::Bytes;
+use flate2::write::GzDecoder;
+use futures::{Stream, StreamExt};
+use std::io::{self, Read, Write};
+
+/// Incremental decoder for one response body.
+enum Decoder {
+    Identity,
+    Gzip(Box<GzDecoder<Vec<u8>>>),
+}
+
+impl Decoder {
+    /// `encoding` is the response's `Content-Encoding`, if any; only gzip is supported.
+    fn new(encoding: Option<&str>) -> io::Result<Self> {
+        match encoding.map(str::trim) {
+            None | Some("") => Ok(Decoder::Identity),
+            Some(e) if e.eq_ignore_ascii_case("identity") => Ok(Decoder::Identity),
+            Some(e) if e.eq_ignore_ascii_case("gzip") || e.eq_ignore_ascii_case("x-gzip") => {
+                Ok(Decoder::Gzip(Box::new(GzDecoder::new(Vec::new()))))
+            }
+            Some(e) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("unsupported Content-Encoding: {e}"),
+            )),
+        }
+    }
+
+    /// Decoded bytes available after feeding in `chunk`.
+    fn decode(&mut self, chunk: Bytes) -> io::Result<Bytes> {
+        match self {
+            Decoder::Identity => Ok(chunk),
+            Decoder::Gzip(decoder) => {
+                decoder.write_all(&chunk)?;
+                decoder.flush()?;
+                Ok(Bytes::from(std::mem::take(decoder.get_mut())))
+            }
+        }
+    }
+}
+
+/// Decode a whole buffered body.
+pub fn decode_body(encoding: Option<&str>, body: Bytes) -> io::Result<Bytes> {
+    match Decoder::new(encoding)? {
+        Decoder::Identity => Ok(body),
+        Decoder::Gzip(_) => {
+            let mut decoded = Vec::new();
+            flate2::read::GzDecoder::new(body.as_ref()).read_to_end(&mut decoded)?;
+            Ok(Bytes::from(decoded))
+        }
+    }
+}
+
+/// Decode a body stream chunk by chunk, so streamed tokens are not held back.
+pub fn decode_stream<S, E>(
+    encoding: Option<&str>,
+    stream: S,
+) -> io::Result<impl Stream<Item = io::Result<Bytes>>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let mut decoder = Decoder::new(encoding)?;
+    Ok(stream.map(move |chunk| decoder.decode(chunk.map_err(io::Error::other)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+
+    fn gzip(data: &[u8]) -> Bytes {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        Bytes::from(encoder.finish().unwrap())
+    }
+
+    #[test]
+    fn test_decode_body() {
+        let body = br#"{"choices": []}"#;
+        assert_eq!(decode_body(Some("gzip"), gzip(body)).unwrap(), &body[..]);
+        assert_eq!(
+            decode_body(None, Bytes::from_static(body)).unwrap(),
+            &body[..]
+        );
+        assert!(decode_body(Some("br"), Bytes::from_static(body)).is_err());
+        assert!(decode_body(Some("gzip"), Bytes::from_static(body)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_decode_stream_across_chunk_boundaries() {
+        let compressed = gzip(b"data: {\"n\": 1}\n\ndata: [DONE]\n\n");
+        let (head, tail) = compressed.split_at(compressed.len() / 2);
+        let chunks = vec![
+            Ok::<_, io::Error>(Bytes::copy_from_slice(head)),
+            Ok(Bytes::copy_from_slice(tail)),
+        ];
+
+        let decoded: Vec<u8> = decode_stream(Some("GZIP"), futures::stream::iter(chunks))
+            .unwrap()
+            .map(|chunk| chunk.unwrap().to_vec())
+            .concat()
+            .await;
+
+        assert_eq!(decoded, b"data: {\"n\": 1}\n\ndata: [DONE]\n\n");
+    }
+}
@@ -0,0 +1,147 @@
+//! Chat completions asking for `n > 1` choices (`FAN_OUT_MAX_N`).
+//!
+//! Local engines mostly produce one choice per request, so a request for `n` choices is
+//! sent as `n` single-choice requests, each with its own ticket so they can land on
+//! different nodes, and the replies are merged into one completion: choices are indexed
+//! `0..n` in request order and usage is summed over all of them. Every request is billed,
+//! so this multiplies the cost of the call, and it cannot be combined with streaming.
+
+use monkey_troop_shared::{ChatCompletionRequest, TroopError};
+use serde_json::{Map, Value};
+
+/// Upper bound on `FAN_OUT_MAX_N`.
+pub const MAX_FAN_OUT_N: usize = 16;
+
+/// The `n` a request asks for, when it is more than one choice.
+pub fn requested_choices(request: &ChatCompletionRequest) -> Option<usize> {
+    request
+        .extra
+        .get("n")
+        .and_then(Value::as_u64)
+        .and_then(|n| usize::try_from(n).ok())
+        .filter(|&n| n > 1)
+}
+
+/// Refuse a fan-out the proxy will not serve.
+pub fn check(n: usize, max_n: usize, stream: bool) -> Result<(), TroopError> {
+    if stream {
+        return Err(TroopError::InvalidRequest(
+            "stream is not supported with n > 1".to_string(),
+        ));
+    }
+    if n > max_n {
+        return Err(TroopError::InvalidRequest(format!(
+            "n must be at most {max_n}, got {n}"
+        )));
+    }
+    Ok(())
+}
+
+/// Merge single-choice completions into one, taking the other fields from the first.
+pub fn merge(replies: Vec<Value>) -> Value {
+    let mut merged = Map::new();
+    let mut choices = Vec::new();
+    let mut usage = Map::new();
+    for reply in replies {
+        let Value::Object(mut reply) = reply else {
+            continue;
+        };
+        if let Some(Value::Array(reply_choices)) = reply.remove("choices") {
+            choices.extend(reply_choices);
+        }
+        if let Some(Value::Object(reply_usage)) = reply.remove("usage") {
+            for (key, value) in reply_usage {
+                if let Some(count) = value.as_u64() {
+                    let total = usage.get(&key).and_then(Value::as_u64).unwrap_or(0);
+                    usage.insert(key, (total + count).into());
+                }
+            }
+        }
+        if merged.is_empty() {
+            merged = reply;
+        }
+    }
+
+    for (index, choice) in choices.iter_mut().enumerate() {
+        if let Some(choice) = choice.as_object_mut() {
+            choice.insert("index".to_string(), index.into());
+        }
+    }
+    merged.insert("choices".to_string(), Value::Array(choices));
+    if !usage.is_empty() {
+        merged.insert("usage".to_string(), Value::Object(usage));
+    }
+    Value::Object(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(body: Value) -> ChatCompletionRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn test_requested_choices() {
+        let base = json!({"model": "m", "messages": []});
+        assert_eq!(requested_choices(&request(base.clone())), None);
+        for (n, expected) in [(json!(1), None), (json!(3), Some(3)), (json!("3"), None)] {
+            let mut body = base.clone();
+            body["n"] = n;
+            assert_eq!(requested_choices(&request(body)), expected);
+        }
+    }
+
+    #[test]
+    fn test_check_rejects_streams_and_large_n() {
+        assert!(check(4, 4, false).is_ok());
+        let err = check(2, 4, true).unwrap_err();
+        assert!(err.to_string().contains("stream"));
+        let err = check(5, 4, false).unwrap_err();
+        assert_eq!(err.http_status(), 400);
+        assert!(err.to_string().contains("at most 4"));
+    }
+
+    #[test]
+    fn test_merge_reindexes_choices_and_sums_usage() {
+        let reply = |content: &str, completion_tokens: u64| {
+            json!({
+                "id": format!("chatcmpl-{content}"),
+                "object": "chat.completion",
+                "model": "llama3:8b",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": content},
+                    "finish_reason": "stop"
+                }],
+                "usage": {
+                    "prompt_tokens": 10,
+                    "completion_tokens": completion_tokens,
+                    "total_tokens": 10 + completion_tokens
+                }
+            })
+        };
+
+        let merged = merge(vec![reply("a", 2), reply("b", 3), reply("c", 4)]);
+
+        assert_eq!(merged["id"], "chatcmpl-a");
+        assert_eq!(merged["model"], "llama3:8b");
+        let choices = merged["choices"].as_array().unwrap();
+        let indexed: Vec<_> = choices
+            .iter()
+            .map(|c| {
+                (
+                    c["index"].as_u64().unwrap(),
+                    c["message"]["content"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(indexed, [(0, json!("a")), (1, json!("b")), (2, json!("c"))]);
+        assert_eq!(
+            merged["usage"],
+            json!({"prompt_tokens": 30, "completion_tokens": 9, "total_tokens": 39})
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Hedged requests for the client proxy.
+//!
+//! With `HEDGE_AFTER_MS` set, a request whose worker has sent nothing after that delay is
+//! also sent to a second node, and whichever node produces the first body chunk wins; the
+//! slower request is dropped, which cancels it and hands back the circuit breaker probe it
+//! may hold, without counting as a failure. At most one hedge is sent per request.
+
+use crate::proxy::{
+    authorize_healthy_node, send_to_node, ProxyError, ProxyState, RequestContext, WorkerReply,
+};
+use serde::Serialize;
+use std::future::Future;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Which request of a hedged pair answered first.
+pub enum Winner<T> {
+    Primary(T),
+    Hedge(T),
+}
+
+/// Race `primary` against `hedge`, which is only started once `delay` passes without
+/// `primary` completing. The hedge yields `None` when it fails: it can rescue a failing
+/// primary that is still outstanding, but never fails the request itself.
+pub async fn race<T, E>(
+    primary: impl Future<Output = Result<T, E>>,
+    delay: Duration,
+    hedge: impl Future<Output = Option<T>>,
+) -> Result<Winner<T>, E> {
+    tokio::pin!(primary);
+    tokio::select! {
+        result = &mut primary => return result.map(Winner::Primary),
+        _ = tokio::time::sleep(delay) => {}
+    }
+
+    tokio::pin!(hedge);
+    let mut hedge_failed = false;
+    loop {
+        tokio::select! {
+            result = &mut primary => return match result {
+                Ok(value) => Ok(Winner::Primary(value)),
+                Err(e) if hedge_failed => Err(e),
+                Err(e) => hedge.await.map(Winner::Hedge).ok_or(e),
+            },
+            value = &mut hedge, if !hedge_failed => match value {
+                Some(value) => return Ok(Winner::Hedge(value)),
+                None => hedge_failed = true,
+            },
+        }
+    }
+}
+
+/// Await `primary`, the request already sent to `primary_node`, hedging it to a second
+/// node once `HEDGE_AFTER_MS` passes without a reply.
+pub(crate) async fn send_hedged<T: Serialize>(
+    state: &ProxyState,
+    model: &str,
+    path: &str,
+    payload: &T,
+    request: &RequestContext,
+    primary: impl Future<Output = Result<WorkerReply, ProxyError>>,
+    primary_node: &str,
+) -> Result<WorkerReply, ProxyError> {
+    let Some(delay) = state.config.hedge_after else {
+        return primary.await;
+    };
+    let hedge = hedge_request(state, model, path, payload, request, primary_node);
+    race(primary, delay, hedge)
+        .await
+        .map(|winner| match winner {
+            Winner::Primary(reply) => reply,
+            Winner::Hedge(reply) => {
+                info!(
+                    "Hedge won: node {} answered before node {}",
+                    reply.node_ip, primary_node
+                );
+                reply
+            }
+        })
+}
+
+/// Send the request to a second node, other than `primary_node`, as a hedge. Failures are
+/// only logged: the hedge can rescue the request but never fails it.
+async fn hedge_request<T: Serialize>(
+    state: &ProxyState,
+    model: &str,
+    path: &str,
+    payload: &T,
+    request: &RequestContext,
+    primary_node: &str,
+) -> Option<WorkerReply> {
+    info!(
+        "Node {} has not answered yet, hedging to a second node",
+        primary_node
+    );
+    let (auth_response, admission) =
+        authorize_healthy_node(state, model, request, vec![primary_node.to_string()])
+            .await
+            .ok()?;
+
+    info!("Got hedge ticket for node: {}", auth_response.target_ip);
+    match send_to_node(state, &auth_response, admission, path, payload, request).await {
+        Ok(reply) => Some(reply),
+        Err(e) => {
+            if let ProxyError::Troop(e) = e {
+                warn!("Hedge to node {} failed: {}", auth_response.target_ip, e);
+            }
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::pending;
+
+    async fn after<T>(delay_ms: u64, value: T) -> T {
+        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+        value
+    }
+
+    fn winner(result: Result<Winner<&str>, &str>) -> String {
+        match result {
+            Ok(Winner::Primary(v)) => format!("primary:{v}"),
+            Ok(Winner::Hedge(v)) => format!("hedge:{v}"),
+            Err(e) => format!("error:{e}"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fast_primary_never_starts_the_hedge() {
+        let hedge = async { panic!("hedge should not start") };
+        let result = race(after(100, Ok("a")), Duration::from_secs(2), hedge).await;
+        assert_eq!(winner(result), "primary:a");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_first_response_wins_once_hedged() {
+        // Hedge starts at 2s and answers at 2.5s, before the primary at 5s
+        let result = race(
+            after(5000, Ok("a")),
+            Duration::from_secs(2),
+            after(500, Some("b")),
+        )
+        .await;
+        assert_eq!(winner(result), "hedge:b");
+
+        let result = race(
+            after(3000, Ok("a")),
+            Duration::from_secs(2),
+            after(5000, Some("b")),
+        )
+        .await;
+        assert_eq!(winner(result), "primary:a");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failures_fall_back_to_the_other_request() {
+        // A failed hedge leaves the primary to finish
+        let result = race(after(5000, Ok("a")), Duration::from_secs(2), async { None }).await;
+        assert_eq!(winner(result), "primary:a");
+
+        // A primary failing after the hedge started is rescued by the hedge
+        let result = race(
+            after(3000, Err("boom")),
+            Duration::from_secs(2),
+            after(5000, Some("b")),
+        )
+        .await;
+        assert_eq!(winner(result), "hedge:b");
+
+        // Before the delay, a primary failure is final
+        let result = race(
+            after(100, Err("boom")),
+            Duration::from_secs(2),
+            pending::<Option<&str>>(),
+        )
+        .await;
+        assert_eq!(winner(result), "error:boom");
+
+        // When both fail, the primary's error is reported
+        let result = race(after(3000, Err("boom")), Duration::from_secs(2), async {
+            None
+        })
+        .await;
+        assert_eq!(winner(result), "error:boom");
+    }
+}
@@ -0,0 +1,335 @@
+//! Reading the proxy's request log back, for the `logs` command.
+//!
+//! The request log is the audit log (`AUDIT_LOG_PATH`), one JSON record per request. It
+//! is read from the file rather than from the proxy, so it works whether or not the proxy
+//! is running. Following it survives rotation: a log that is truncated is read again from
+//! the start, and one that is moved away is drained before the new file is opened.
+
+use crate::audit::{self, AuditRecord};
+use crate::output::Format;
+use crate::transactions;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration as TimeDelta, Utc};
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// How often a followed log is checked for new records.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Which requests `--status` keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StatusFilter {
+    /// Answered with a 2xx status
+    Ok,
+    /// Answered with any other status
+    Error,
+}
+
+/// Which records are shown.
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub model: Option<String>,
+    pub status: Option<StatusFilter>,
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl Filter {
+    fn matches(&self, record: &AuditRecord) -> bool {
+        let ok = (200..300).contains(&record.status);
+        self.model
+            .as_ref()
+            .is_none_or(|model| record.model == *model)
+            && self
+                .status
+                .is_none_or(|status| ok == (status == StatusFilter::Ok))
+            && self.since.is_none_or(|since| record.timestamp >= since)
+    }
+}
+
+/// Parse `--since`: an age such as `30s`, `10m`, `2h` or `1d`, or a date (midnight UTC)
+/// or RFC 3339 time as `transactions --since` takes.
+pub fn parse_since(value: &str) -> Result<DateTime<Utc>, String> {
+    let age = value.char_indices().last().and_then(|(unit_at, unit)| {
+        let count: i64 = value[..unit_at].parse().ok()?;
+        match unit {
+            's' => TimeDelta::try_seconds(count),
+            'm' => TimeDelta::try_minutes(count),
+            'h' => TimeDelta::try_hours(count),
+            'd' => TimeDelta::try_days(count),
+            _ => None,
+        }
+    });
+    match age {
+        Some(age) => Ok(Utc::now() - age),
+        None => transactions::parse_time(value)
+            .map(|time| time.and_utc())
+            .map_err(|_| {
+                format!(
+                    "expected an age like 10m, 2h or 1d, a date or an RFC 3339 time, got '{value}'"
+                )
+            }),
+    }
+}
+
+/// `line` as it is shown in `format`, or `None` when it is not a record `filter` keeps.
+/// JSON output is the line exactly as logged.
+fn render(line: &str, filter: &Filter, format: Format) -> Option<String> {
+    let record: AuditRecord = serde_json::from_str(line).ok()?;
+    if !filter.matches(&record) {
+        return None;
+    }
+    let node = record.node_ip.as_deref().unwrap_or("-");
+    Some(match format {
+        Format::Json => line.to_string(),
+        Format::Table => format!(
+            "{}  {}  {:>6} ms  {}  {}",
+            record.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            record.status,
+            record.latency_ms,
+            record.model,
+            node
+        ),
+        Format::Plain | Format::Csv => format!(
+            "{}\t{}\t{}\t{}\t{}",
+            record.timestamp.to_rfc3339(),
+            record.status,
+            record.latency_ms,
+            record.model,
+            node
+        ),
+    })
+}
+
+/// Print the last `lines` records of the log at `path` that pass `filter`, then, when
+/// following, every new one as it is logged.
+pub async fn show(
+    path: &Path,
+    filter: &Filter,
+    lines: usize,
+    follow: bool,
+    format: Format,
+) -> Result<()> {
+    let mut tail = Tail::new(path.to_path_buf());
+    let logged = tail
+        .read_new()
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    if !follow && tail.file.is_none() {
+        anyhow::bail!("No requests have been logged to {} yet", path.display());
+    }
+
+    let mut recent = VecDeque::with_capacity(lines);
+    for line in logged.iter().filter_map(|l| render(l, filter, format)) {
+        if recent.len() == lines {
+            recent.pop_front();
+        }
+        if lines > 0 {
+            recent.push_back(line);
+        }
+    }
+    for line in recent {
+        println!("{line}");
+    }
+    if !follow {
+        return Ok(());
+    }
+
+    if tail.file.is_none() {
+        eprintln!("Waiting for requests to be logged to {}", path.display());
+    }
+    let mut interval = tokio::time::interval(FOLLOW_INTERVAL);
+    loop {
+        interval.tick().await;
+        let logged = tail
+            .read_new()
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        for line in logged.iter().filter_map(|l| render(l, filter, format)) {
+            println!("{line}");
+        }
+    }
+}
+
+/// A log file read a line at a time as it grows, across truncation and rotation.
+struct Tail {
+    path: PathBuf,
+    /// `None` until the file exists
+    file: Option<File>,
+    id: Option<(u64, u64)>,
+    /// Bytes of the open file read so far
+    position: u64,
+    /// The end of a line still being written
+    partial: Vec<u8>,
+}
+
+impl Tail {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            file: None,
+            id: None,
+            position: 0,
+            partial: Vec::new(),
+        }
+    }
+
+    /// The complete lines written since the last call.
+    async fn read_new(&mut self) -> std::io::Result<Vec<String>> {
+        let mut lines = self.read_open_file().await?;
+        let meta = match tokio::fs::metadata(&self.path).await {
+            Ok(meta) => meta,
+            // Moved away and not recreated yet; the open file was read to its end
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(lines),
+            Err(e) => return Err(e),
+        };
+
+        if self.file.is_some() && audit::file_id(&meta) == self.id {
+            if meta.len() < self.position {
+                // Truncated in place (`copytruncate`): start over
+                if let Some(file) = self.file.as_mut() {
+                    file.rewind().await?;
+                }
+                self.position = 0;
+                self.partial.clear();
+                lines.extend(self.read_open_file().await?);
+            }
+        } else {
+            // Created, or replaced by a new file after rotation
+            let file = File::open(&self.path).await?;
+            self.id = audit::file_id(&file.metadata().await?);
+            self.file = Some(file);
+            self.position = 0;
+            self.partial.clear();
+            lines.extend(self.read_open_file().await?);
+        }
+        Ok(lines)
+    }
+
+    async fn read_open_file(&mut self) -> std::io::Result<Vec<String>> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(Vec::new());
+        };
+        let read = file.read_to_end(&mut self.partial).await?;
+        self.position += read as u64;
+
+        let Some(end) = self.partial.iter().rposition(|&b| b == b'\n') else {
+            return Ok(Vec::new());
+        };
+        let complete: Vec<u8> = self.partial.drain(..=end).collect();
+        Ok(String::from_utf8_lossy(&complete)
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn record_line(model: &str, status: u16, timestamp: &str) -> String {
+        serde_json::json!({
+            "timestamp": timestamp,
+            "request_id": "req-1",
+            "model": model,
+            "node_ip": "100.64.0.1",
+            "status": status,
+            "latency_ms": 1234,
+            "stream": false,
+            "prompt_tokens": null,
+            "completion_tokens": null,
+            "total_tokens": null,
+            "messages": []
+        })
+        .to_string()
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let ago = |value: &str| Utc::now() - parse_since(value).unwrap();
+        assert!((ago("10m") - TimeDelta::minutes(10)).num_seconds().abs() <= 1);
+        assert!((ago("2h") - TimeDelta::hours(2)).num_seconds().abs() <= 1);
+        assert!((ago("1d") - TimeDelta::days(1)).num_seconds().abs() <= 1);
+        assert_eq!(
+            parse_since("2026-10-01").unwrap().to_rfc3339(),
+            "2026-10-01T00:00:00+00:00"
+        );
+        assert!(parse_since("10x").is_err());
+        assert!(parse_since("m").is_err());
+    }
+
+    #[test]
+    fn test_filter_and_render() {
+        let ok = record_line("llama3:8b", 200, "2026-10-15T12:00:01Z");
+        let failed = record_line("llama3:8b", 502, "2026-10-15T12:00:02Z");
+        let other = record_line("mistral:7b", 200, "2026-10-15T12:00:03Z");
+
+        assert_eq!(
+            render(&ok, &Filter::default(), Format::Table).unwrap(),
+            "2026-10-15 12:00:01  200    1234 ms  llama3:8b  100.64.0.1"
+        );
+        assert_eq!(
+            render(&ok, &Filter::default(), Format::Plain).unwrap(),
+            "2026-10-15T12:00:01+00:00\t200\t1234\tllama3:8b\t100.64.0.1"
+        );
+        assert_eq!(render(&ok, &Filter::default(), Format::Json).unwrap(), ok);
+        assert!(render("not a record", &Filter::default(), Format::Json).is_none());
+
+        let errors = Filter {
+            model: Some("llama3:8b".to_string()),
+            status: Some(StatusFilter::Error),
+            since: None,
+        };
+        let shown: Vec<bool> = [&ok, &failed, &other]
+            .iter()
+            .map(|line| render(line, &errors, Format::Json).is_some())
+            .collect();
+        assert_eq!(shown, [false, true, false]);
+
+        let recent = Filter {
+            since: Some(parse_since("2026-10-15T12:00:02Z").unwrap()),
+            ..Filter::default()
+        };
+        assert!(render(&ok, &recent, Format::Json).is_none());
+        assert!(render(&failed, &recent, Format::Json).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_tail_follows_partial_lines_truncation_and_rotation() {
+        let path = std::env::temp_dir().join(format!("troop-logs-{}.jsonl", uuid::Uuid::new_v4()));
+        let rotated = path.with_extension("jsonl.1");
+        let mut tail = Tail::new(path.clone());
+        assert!(tail.read_new().await.unwrap().is_empty());
+
+        append(&path, "one\ntw");
+        assert_eq!(tail.read_new().await.unwrap(), ["one"]);
+        append(&path, "o\n");
+        assert_eq!(tail.read_new().await.unwrap(), ["two"]);
+
+        std::fs::write(&path, "three\n").unwrap();
+        assert_eq!(tail.read_new().await.unwrap(), ["three"]);
+
+        append(&path, "four\n");
+        std::fs::rename(&path, &rotated).unwrap();
+        append(&path, "five\n");
+        assert_eq!(tail.read_new().await.unwrap(), ["four", "five"]);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&rotated).unwrap();
+    }
+}
@@ -1,103 +1,1199 @@
+mod anthropic;
+mod audit;
+mod balance;
+mod batch;
+mod bench;
+mod chat;
+mod concurrency;
 mod config;
+mod config_file;
+mod coordinators;
+mod credits;
+mod daemon;
 mod e2e_crypto;
+mod encoding;
+mod fan_out;
+mod hedging;
+mod logs;
+mod model_filter;
+mod node_breakers;
+mod nodes;
+mod output;
+mod ping;
 mod proxy;
+mod response_cache;
+mod routing;
+mod sessions;
+mod shutdown;
+mod stats;
+mod status;
+mod streams;
+mod transactions;
+#[cfg(unix)]
+mod unix_socket;
+mod usage;
+mod whoami;
 
-use anyhow::Result;
-use clap::{Parser, Subcommand};
-use monkey_troop_shared::BalanceResponse;
+use anyhow::{Context, Result};
+use clap::{CommandFactory, Parser, Subcommand};
+use coordinators::Coordinators;
+use monkey_troop_shared::{
+    BalanceResponse, CreditRequestStatus, ModelsResponse, NodeStatus, PeersResponse,
+    TransactionsResponse, TroopError, COORDINATOR_HINT,
+};
+use std::process::ExitCode;
+use std::time::Duration;
 use tracing::info;
 
+/// Exit statuses scripts can tell apart; any other failure exits with 1.
+const EXIT_NETWORK: u8 = 2;
+const EXIT_AUTH: u8 = 3;
+const EXIT_NOT_FOUND: u8 = 4;
+const EXIT_SERVER: u8 = 5;
+/// Bad arguments (`EX_USAGE`), kept clear of the codes above
+const EXIT_USAGE: u8 = 64;
+
 #[derive(Parser)]
 #[command(name = "monkey-troop-client")]
 #[command(about = "Monkey Troop Client - Access distributed AI compute", long_about = None)]
 struct Cli {
+    /// How to print results: a table for people, or json or plain (tab-separated) for scripts
+    #[arg(long, global = true, value_enum, default_value_t)]
+    output: output::Format,
+
+    /// Print JSON; the same as `--output json`
+    #[arg(long, global = true, conflicts_with = "output")]
+    json: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+impl Commands {
+    /// Whether the command prints a table, which `--output csv` can hold.
+    fn lists_rows(&self) -> bool {
+        matches!(
+            self,
+            Commands::Nodes { watch: None, .. }
+                | Commands::Transactions { sum: false, .. }
+                | Commands::Models { .. }
+                | Commands::Ping { .. }
+                | Commands::Bench { .. }
+                | Commands::Status
+                | Commands::Config {
+                    command: ConfigCommand::Show { .. }
+                }
+        )
+    }
+}
+
+impl Cli {
+    fn format(&self) -> output::Format {
+        if self.json {
+            output::Format::Json
+        } else {
+            self.output
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Start the local proxy server
-    Up,
+    Up {
+        #[command(flatten)]
+        overrides: OverrideArgs,
+        /// Run in the background, logging to DAEMON_LOG_FILE; stop it with `down`
+        #[arg(long)]
+        daemon: bool,
+    },
+    /// Stop a proxy started with `up --daemon`
+    Down,
     /// Check credit balance
-    Balance,
-    /// List available nodes
-    Nodes,
-    /// List transaction history
-    Transactions,
+    Balance {
+        /// Keep polling every this many seconds, showing the change since the last check
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            num_args = 0..=1,
+            default_missing_value = balance::DEFAULT_WATCH_INTERVAL_SECS,
+            value_parser = clap::value_parser!(u64).range(1..)
+        )]
+        watch: Option<u64>,
+        /// Exit with an error once the balance is under this many seconds
+        #[arg(long, value_name = "SECONDS")]
+        below: Option<i64>,
+    },
+    /// List available nodes, most free VRAM first
+    Nodes {
+        /// Only show nodes hosting this model
+        #[arg(long)]
+        model: Option<String>,
+        /// Only show nodes with this status: idle, busy or offline
+        #[arg(long, value_parser = parse_node_status)]
+        status: Option<NodeStatus>,
+        /// List every model a node serves instead of the first few
+        #[arg(long)]
+        wide: bool,
+        /// Keep refreshing every this many seconds, marking what changed; q or Ctrl-C quits
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            num_args = 0..=1,
+            default_missing_value = nodes::DEFAULT_WATCH_INTERVAL_SECS,
+            value_parser = clap::value_parser!(u64).range(1..)
+        )]
+        watch: Option<u64>,
+    },
+    /// List transaction history, most recent first
+    Transactions {
+        /// Transactions per page [default: 50]
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..=1000))]
+        limit: Option<u32>,
+        /// Page to show, counting from 1; `--output csv` exports the whole range without it
+        /// or --limit
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        page: Option<u64>,
+        /// Only transactions at or after this date (YYYY-MM-DD, UTC) or RFC 3339 time
+        #[arg(long, value_parser = transactions::parse_time)]
+        since: Option<chrono::NaiveDateTime>,
+        /// Only transactions before this date (YYYY-MM-DD, UTC) or RFC 3339 time
+        #[arg(long, value_parser = transactions::parse_time)]
+        until: Option<chrono::NaiveDateTime>,
+        /// Total the credits earned and spent over the whole range instead of listing a page
+        #[arg(long)]
+        sum: bool,
+        /// Write the listing to this file instead of stdout
+        #[arg(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath, conflicts_with = "sum")]
+        output_file: Option<std::path::PathBuf>,
+    },
+    /// Show credits earned, granted and spent over a period, or request more
+    #[command(args_conflicts_with_subcommands = true)]
+    Credits {
+        #[command(subcommand)]
+        command: Option<CreditsCommand>,
+        /// Start of the period (YYYY-MM-DD, UTC, or RFC 3339 time); the last 30 days by default
+        #[arg(long, value_parser = transactions::parse_time)]
+        since: Option<chrono::NaiveDateTime>,
+        /// End of the period (YYYY-MM-DD, UTC, or RFC 3339 time); now by default
+        #[arg(long, value_parser = transactions::parse_time)]
+        until: Option<chrono::NaiveDateTime>,
+    },
+    /// List available models with how many nodes host each, and on what hardware
+    Models {
+        /// List the nodes hosting this model instead
+        #[arg(long)]
+        model: Option<String>,
+    },
+    /// Manage the client configuration file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Measure round-trip latency to the coordinators, and to each available node
+    Ping {
+        /// Requests sent to each target
+        #[arg(
+            long,
+            alias = "samples",
+            default_value_t = 5,
+            value_parser = clap::value_parser!(u32).range(1..)
+        )]
+        count: u32,
+        /// Also ping every node in the peer list
+        #[arg(long)]
+        nodes: bool,
+        /// Stop waiting for answers after this many seconds in total
+        #[arg(long, value_name = "SECONDS", default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+        timeout: u64,
+    },
+    /// Show the proxy's request log (AUDIT_LOG_PATH); --json prints the records as logged
+    Logs {
+        /// Keep printing requests as they are logged
+        #[arg(short, long)]
+        follow: bool,
+        /// Show this many of the latest requests first
+        #[arg(short = 'n', long, default_value_t = 10)]
+        lines: usize,
+        /// Only requests for this model
+        #[arg(long)]
+        model: Option<String>,
+        /// Only requests that succeeded (ok) or failed (error)
+        #[arg(long, value_enum)]
+        status: Option<logs::StatusFilter>,
+        /// Only requests from the last 10m, 2h, 1d..., or since a date (YYYY-MM-DD) or RFC 3339 time
+        #[arg(long, value_parser = logs::parse_since)]
+        since: Option<chrono::DateTime<chrono::Utc>>,
+    },
+    /// Measure time to first token and tokens per second for a model, per node
+    Bench {
+        /// Model to benchmark
+        #[arg(long)]
+        model: String,
+        /// File sent, verbatim, as the prompt of every run
+        #[arg(
+            long,
+            required_unless_present = "prompt",
+            conflicts_with = "prompt",
+            value_hint = clap::ValueHint::FilePath
+        )]
+        prompt_file: Option<std::path::PathBuf>,
+        /// Prompt text, instead of --prompt-file
+        #[arg(long)]
+        prompt: Option<String>,
+        /// Measured runs per node
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+        runs: u32,
+        /// Runs per node before the measured ones, left out of the summary; 0 measures a cold start
+        #[arg(long, default_value_t = 1)]
+        warmup: u32,
+        /// Most tokens generated per run
+        #[arg(long, default_value_t = 256)]
+        max_tokens: u32,
+        /// Benchmark every online node hosting the model, not just the one assigned
+        #[arg(long)]
+        all_nodes: bool,
+    },
+    /// Check that the local proxy is running and healthy; fails when it is not
+    Status,
+    /// Show the requester ID and where it came from, Tailscale detection, and whether the
+    /// coordinator knows the ID
+    Whoami {
+        #[command(flatten)]
+        overrides: OverrideArgs,
+        /// Also show the balance
+        #[arg(long)]
+        balance: bool,
+    },
+    /// Chat with a model interactively, through the same authorization and nodes as the proxy
+    Chat {
+        /// Model to talk to; `/model` switches mid-conversation
+        #[arg(long)]
+        model: String,
+    },
+    /// Print the completion script for a shell
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+}
+
+/// Settings that can be given on the command line, over the environment and config file.
+#[derive(clap::Args)]
+struct OverrideArgs {
+    /// Port the proxy listens on (overrides PROXY_PORT)
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    port: Option<u16>,
+    /// Coordinator URL, or a comma-separated failover list (overrides COORDINATOR_URL)
+    #[arg(long)]
+    coordinator_url: Option<String>,
+    /// Identity requests are made under (overrides REQUESTER_ID)
+    #[arg(long)]
+    requester_id: Option<String>,
+    /// Address the proxy listens on (overrides PROXY_BIND)
+    #[arg(long)]
+    bind: Option<std::net::IpAddr>,
+}
+
+impl From<OverrideArgs> for config::Overrides {
+    fn from(args: OverrideArgs) -> Self {
+        Self {
+            proxy_port: args.port,
+            coordinator_url: args.coordinator_url,
+            requester_id: args.requester_id,
+            proxy_bind: args.bind,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum CreditsCommand {
+    /// Ask the coordinator to add credits to your balance
+    Request {
+        /// Credits (seconds of compute) to ask for
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        amount: u64,
+        /// Why, for the operator approving it
+        #[arg(long)]
+        reason: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Write a config file listing every setting at its default
+    Init {
+        /// Replace an existing config file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show each setting's effective value and where it comes from
+    Show {
+        #[command(flatten)]
+        overrides: OverrideArgs,
+    },
+    /// Set a value in the config file, such as coordinator_url, proxy_port or requester_id
+    Set { key: String, value: String },
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize logging
-    tracing_subscriber::fmt::init();
+async fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            return if e.use_stderr() {
+                ExitCode::from(EXIT_USAGE)
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+    };
+    let format = cli.format();
 
-    let cli = Cli::parse();
+    // Initialize logging; only tables share stdout with it, scripts, chat and bench get it
+    // alone
+    if format != output::Format::Table
+        || matches!(cli.command, Commands::Chat { .. } | Commands::Bench { .. })
+    {
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .init();
+    } else {
+        tracing_subscriber::fmt::init();
+    }
 
-    match cli.command {
-        Commands::Up => {
+    match run(cli.command, format).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            if let Some(TroopError::CoordinatorUnreachable(_)) = e.downcast_ref::<TroopError>() {
+                eprintln!("Hint: {COORDINATOR_HINT}");
+            }
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+/// The exit status for a failed command, by the kind of `TroopError` behind it.
+fn exit_code(error: &anyhow::Error) -> u8 {
+    let Some(error) = error.downcast_ref::<TroopError>() else {
+        return 1;
+    };
+    match error {
+        TroopError::NetworkError(_)
+        | TroopError::CoordinatorUnreachable(_)
+        | TroopError::Timeout(_) => EXIT_NETWORK,
+        _ => match error.http_status() {
+            401 | 403 => EXIT_AUTH,
+            404 => EXIT_NOT_FOUND,
+            500.. => EXIT_SERVER,
+            _ => 1,
+        },
+    }
+}
+
+async fn run(command: Commands, format: output::Format) -> Result<()> {
+    if format == output::Format::Csv && !command.lists_rows() {
+        anyhow::bail!(
+            "--output csv is only for commands that list rows, such as transactions or nodes"
+        );
+    }
+    match command {
+        Commands::Up { overrides, daemon } => {
+            let config = config::Config::load(&overrides.into())?;
+            if daemon {
+                let pid = daemon::spawn(&config.pid_file, &config.daemon_log_file).await?;
+                if format == output::Format::Json {
+                    output::print_json(&serde_json::json!({
+                        "pid": pid,
+                        "pid_file": config.pid_file,
+                        "log_file": config.daemon_log_file,
+                        "proxy_url": format!("{}/v1", config.proxy_url()),
+                    }))?;
+                } else {
+                    println!(
+                        "Proxy running in the background (pid {pid}) at {}/v1",
+                        config.proxy_url()
+                    );
+                    println!("Logs: {}", config.daemon_log_file.display());
+                    println!("Stop it with: monkey-troop-client down");
+                }
+                return Ok(());
+            }
             info!("🐒 Monkey Troop Client starting...");
-            let config = config::Config::from_env()?;
+            if format == output::Format::Json {
+                output::print_json(&serde_json::json!({
+                    "proxy_url": format!("{}/v1", config.proxy_url()),
+                    "coordinators": config.coordinator_urls,
+                    "requester_id": config.requester_id,
+                }))?;
+            }
             proxy::run_proxy_server(config).await?;
         }
-        Commands::Balance => {
+        Commands::Down => {
+            let config = load_config()?;
+            // The proxy gets its drain timeout, plus a little to finish shutting down
+            let timeout = config.shutdown_drain_timeout + Duration::from_secs(5);
+            let stopped = daemon::stop(&config.pid_file, timeout).await?;
+            if format == output::Format::Json {
+                output::print_json(&serde_json::json!({ "stopped_pid": stopped }))?;
+            } else {
+                match stopped {
+                    Some(pid) => println!("Stopped the background proxy (pid {pid})"),
+                    None => println!("No background proxy is running"),
+                }
+            }
+        }
+        Commands::Balance { watch, below } => {
             info!("Checking balance...");
-            let config = config::Config::from_env()?;
-            check_balance(&config).await?;
+            let config = load_config()?;
+            match watch {
+                Some(interval) => {
+                    watch_balance(&config, Duration::from_secs(interval), below, format).await?
+                }
+                None => check_balance(&config, below, format).await?,
+            }
         }
-        Commands::Nodes => {
+        Commands::Nodes {
+            model,
+            status,
+            wide,
+            watch,
+        } => {
             info!("Listing available nodes...");
-            let config = config::Config::from_env()?;
-            list_nodes(&config).await?;
+            let config = load_config()?;
+            match watch {
+                Some(interval) => {
+                    let watch = nodes::NodesWatch::new(model, status);
+                    watch_nodes(&config, Duration::from_secs(interval), watch, wide, format).await?
+                }
+                None => {
+                    list_nodes(&config, model.as_deref(), status.as_ref(), wide, format).await?
+                }
+            }
         }
-        Commands::Transactions => {
+        Commands::Transactions {
+            limit,
+            page,
+            since,
+            until,
+            sum,
+            output_file,
+        } => {
             info!("Fetching transactions...");
-            let config = config::Config::from_env()?;
-            list_transactions(&config).await?;
+            let config = load_config()?;
+            if sum {
+                sum_transactions(&config, since, until, format).await?;
+            } else {
+                // A CSV export is the whole range unless a page is asked for
+                let page = (format != output::Format::Csv || limit.is_some() || page.is_some())
+                    .then(|| {
+                        let limit = limit.unwrap_or(transactions::DEFAULT_LIMIT);
+                        transactions::Query {
+                            limit,
+                            offset: (page.unwrap_or(1) - 1) * u64::from(limit),
+                            since,
+                            until,
+                        }
+                    });
+                list_transactions(&config, page, since, until, output_file, format).await?;
+            }
+        }
+        Commands::Credits {
+            command,
+            since,
+            until,
+        } => {
+            let config = load_config()?;
+            match command {
+                Some(CreditsCommand::Request { amount, reason }) => {
+                    request_credits(&config, amount, reason, format).await?
+                }
+                None => show_credits(&config, since, until, format).await?,
+            }
+        }
+        Commands::Models { model } => {
+            info!("Listing available models...");
+            let config = load_config()?;
+            list_models(&config, model.as_deref(), format).await?;
+        }
+        Commands::Config { command } => run_config_command(command, format)?,
+        Commands::Ping {
+            count,
+            nodes,
+            timeout,
+        } => {
+            info!("Pinging...");
+            let config = load_config()?;
+            let deadline = tokio::time::Instant::now() + Duration::from_secs(timeout);
+            ping(&config, count, nodes, deadline, format).await?;
+        }
+        Commands::Logs {
+            follow,
+            lines,
+            model,
+            status,
+            since,
+        } => {
+            let config = load_config()?;
+            let path = config.audit_log_path.context(
+                "Request logging is not enabled; set AUDIT_LOG_PATH (or audit_log_path in the \
+                 config file) and restart the proxy",
+            )?;
+            let filter = logs::Filter {
+                model,
+                status,
+                since,
+            };
+            logs::show(&path, &filter, lines, follow, format).await?;
+        }
+        Commands::Bench {
+            model,
+            prompt_file,
+            prompt,
+            runs,
+            warmup,
+            max_tokens,
+            all_nodes,
+        } => {
+            let prompt = match (prompt, prompt_file) {
+                (Some(prompt), _) => prompt,
+                (None, Some(path)) => std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?,
+                (None, None) => unreachable!("clap requires one of them"),
+            };
+            let options = bench::Options {
+                model,
+                prompt,
+                runs,
+                warmup,
+                max_tokens,
+                all_nodes,
+            };
+            let report = bench::run(load_config()?, &options).await?;
+            output::print(format, &report, output::bench_table(&report))?;
+            if report.nodes.iter().all(|node| node.runs == node.failed) {
+                anyhow::bail!("Every run failed");
+            }
+        }
+        Commands::Status => {
+            let config = load_config()?;
+            let status = status::check(&config.proxy_url()).await;
+            output::print(format, &status, output::status_table(&status))?;
+            if let Some(problem) = status.problem {
+                anyhow::bail!("The proxy is not running: {problem}");
+            }
+        }
+        Commands::Whoami { overrides, balance } => {
+            show_identity(&overrides.into(), balance, format).await?
+        }
+        Commands::Chat { model } => chat::run(load_config()?, model).await?,
+        Commands::Completions { shell } => {
+            let mut command = Cli::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
         }
     }
 
     Ok(())
 }
 
-async fn list_nodes(config: &config::Config) -> Result<()> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/peers", config.coordinator_url);
+/// Load the client configuration from the environment and config file.
+fn load_config() -> Result<config::Config> {
+    config::Config::load(&config::Overrides::default())
+}
 
-    let response: serde_json::Value = client.get(&url).send().await?.json().await?;
+async fn show_identity(
+    overrides: &config::Overrides,
+    balance: bool,
+    format: output::Format,
+) -> Result<()> {
+    let config = config::Config::load(overrides)?;
+    let file = match config_file::path() {
+        Some(path) => config_file::ConfigFile::read(&path)?,
+        None => None,
+    };
+    let settings =
+        config_file::effective(overrides, |name| std::env::var(name).ok(), file.as_ref());
+    let identity = whoami::check(&config, &settings, balance).await;
 
-    println!("{}", serde_json::to_string_pretty(&response)?);
+    match format {
+        output::Format::Json => output::print_json(&identity)?,
+        output::Format::Table => {
+            for line in identity.lines() {
+                println!("{line}");
+            }
+        }
+        output::Format::Plain | output::Format::Csv => println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            identity.requester_id,
+            serde_json::to_value(&identity.requester_source)?
+                .as_str()
+                .unwrap_or("file"),
+            identity.tailscale_ipv4.ip.as_deref().unwrap_or("-"),
+            identity.tailscale_ipv6.ip.as_deref().unwrap_or("-"),
+            identity.coordinator_url,
+            serde_json::to_value(&identity.coordinator_knows_requester)?
+                .as_str()
+                .unwrap_or("unchecked"),
+        ),
+    }
 
     Ok(())
 }
 
-async fn check_balance(config: &config::Config) -> Result<()> {
-    let client = reqwest::Client::new();
-    let url = format!(
-        "{}/users/{}/balance",
-        config.coordinator_url, config.requester_id
-    );
+fn run_config_command(command: ConfigCommand, format: output::Format) -> Result<()> {
+    let path = config_file::path().with_context(|| {
+        format!(
+            "No config directory found; set {} to the config file to use",
+            config_file::CONFIG_FILE_VAR
+        )
+    })?;
 
-    let response: BalanceResponse = client.get(&url).send().await?.json().await?;
+    match command {
+        ConfigCommand::Init { force } => {
+            config_file::init(&path, force)?;
+            if format == output::Format::Json {
+                output::print_json(&serde_json::json!({ "path": path }))?;
+            } else {
+                println!("Wrote {}", path.display());
+            }
+        }
+        ConfigCommand::Show { overrides } => {
+            let file = config_file::ConfigFile::read(&path)?;
+            let settings = config_file::effective(
+                &overrides.into(),
+                |name| std::env::var(name).ok(),
+                file.as_ref(),
+            );
+            match format {
+                output::Format::Json => output::print_json(&serde_json::json!({
+                    "path": path,
+                    "exists": file.is_some(),
+                    "settings": settings,
+                }))?,
+                output::Format::Table => {
+                    let missing = if file.is_none() { " (not found)" } else { "" };
+                    println!("Config file: {}{missing}", path.display());
+                    println!();
+                    output::print(format, &settings, output::config_table(&settings))?;
+                }
+                output::Format::Plain | output::Format::Csv => {
+                    output::print(format, &settings, output::config_table(&settings))?
+                }
+            }
+        }
+        ConfigCommand::Set { key, value } => {
+            config_file::set(&path, &key, &value)?;
+            if format == output::Format::Json {
+                output::print_json(&serde_json::json!({
+                    "path": path,
+                    "key": key,
+                    "value": value,
+                }))?;
+            } else {
+                println!("Set {key} in {}", path.display());
+            }
+        }
+    }
 
-    println!(
-        "Balance: {} seconds ({} hours)",
-        response.balance_seconds, response.balance_hours
-    );
+    Ok(())
+}
+
+fn parse_node_status(value: &str) -> Result<NodeStatus, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_ascii_uppercase()))
+        .map_err(|_| format!("expected idle, busy or offline, got '{value}'"))
+}
+
+async fn list_nodes(
+    config: &config::Config,
+    model: Option<&str>,
+    status: Option<&NodeStatus>,
+    wide: bool,
+    format: output::Format,
+) -> Result<()> {
+    let coordinators = Coordinators::new(config.coordinator_urls.clone());
+    let peers: PeersResponse = coordinators.get_json("peers").await?;
+    let nodes = output::select_nodes(peers.nodes, model, status);
+
+    let table = output::nodes_table(&nodes, wide);
+    output::print(
+        format,
+        &PeersResponse {
+            count: nodes.len(),
+            nodes,
+        },
+        table,
+    )?;
 
     Ok(())
 }
 
-async fn list_transactions(config: &config::Config) -> Result<()> {
-    let client = reqwest::Client::new();
-    let url = format!(
-        "{}/users/{}/transactions",
-        config.coordinator_url, config.requester_id
-    );
+/// Refresh the nodes every `interval` until Ctrl-C or `q`. On a terminal the table is
+/// redrawn in place; otherwise each refresh follows the last.
+async fn watch_nodes(
+    config: &config::Config,
+    interval: Duration,
+    mut watch: nodes::NodesWatch,
+    wide: bool,
+    format: output::Format,
+) -> Result<()> {
+    use std::io::{IsTerminal, Write};
 
-    let response: serde_json::Value = client.get(&url).send().await?.json().await?;
+    let coordinators = Coordinators::new(config.coordinator_urls.clone());
+    let redraw = format == output::Format::Table && std::io::stdout().is_terminal();
+    let mut ticker = tokio::time::interval(interval);
+    let mut quit = nodes::QuitKey::listen();
+    let stop = shutdown::shutdown_signal();
+    tokio::pin!(stop);
 
-    println!("{}", serde_json::to_string_pretty(&response)?);
+    loop {
+        let poll = async {
+            ticker.tick().await;
+            coordinators
+                .get_json::<PeersResponse>("peers")
+                .await
+                .map(|peers| peers.nodes)
+                .map_err(|e| e.to_string())
+        };
+        let fetched = tokio::select! {
+            _ = &mut stop => break,
+            _ = quit.pressed() => break,
+            fetched = poll => fetched,
+        };
 
+        let snapshot = watch.observe(fetched);
+        match format {
+            output::Format::Json => println!("{}", serde_json::to_string(&snapshot)?),
+            output::Format::Plain | output::Format::Csv => {
+                // Rows only on stdout, so every line parses the same
+                println!("{}", snapshot.table(wide).plain());
+                for note in snapshot.notes() {
+                    eprintln!("{note}");
+                }
+            }
+            output::Format::Table => {
+                if redraw {
+                    // Cursor home and clear the screen, so the table updates in place
+                    print!("\x1b[H\x1b[2J");
+                    println!(
+                        "Every {}s, q to quit  {}\n",
+                        interval.as_secs(),
+                        chrono::Local::now().format("%H:%M:%S")
+                    );
+                }
+                println!("{}", snapshot.table(wide).aligned(output::terminal_width()));
+                for note in snapshot.notes() {
+                    println!("{note}");
+                }
+                if !redraw {
+                    println!();
+                }
+                std::io::stdout().flush()?;
+            }
+        }
+    }
     Ok(())
 }
+
+async fn list_models(
+    config: &config::Config,
+    model: Option<&str>,
+    format: output::Format,
+) -> Result<()> {
+    let coordinators = Coordinators::new(config.coordinator_urls.clone());
+    let models: ModelsResponse = coordinators.get_json("v1/models").await?;
+    let peers: PeersResponse = coordinators.get_json("peers").await?;
+
+    let availability = output::model_availability(&models, &peers);
+    match model {
+        Some(model) => {
+            let Some(model) = availability.into_iter().find(|m| m.model == model) else {
+                anyhow::bail!("Model '{model}' is not available");
+            };
+            output::print(format, &model, output::model_nodes_table(&model))?;
+        }
+        None => output::print(format, &availability, output::models_table(&availability))?,
+    }
+
+    Ok(())
+}
+
+async fn check_balance(
+    config: &config::Config,
+    below: Option<i64>,
+    format: output::Format,
+) -> Result<()> {
+    let coordinators = Coordinators::new(config.coordinator_urls.clone());
+    let response: BalanceResponse = coordinators
+        .get_json(&format!("users/{}/balance", config.requester_id))
+        .await?;
+
+    match format {
+        output::Format::Json => output::print_json(&response)?,
+        output::Format::Table => println!(
+            "Balance: {} seconds ({} hours)",
+            response.balance_seconds, response.balance_hours
+        ),
+        output::Format::Plain | output::Format::Csv => println!("{}", response.balance_seconds),
+    }
+
+    if let Some(threshold) = below.filter(|&threshold| response.balance_seconds < threshold) {
+        anyhow::bail!(
+            "Balance of {} seconds is below {threshold}",
+            response.balance_seconds
+        );
+    }
+
+    Ok(())
+}
+
+/// Poll the balance every `interval` until Ctrl-C, or until it drops under `below`.
+/// On a terminal the balance is redrawn in place; otherwise each poll is a line.
+async fn watch_balance(
+    config: &config::Config,
+    interval: Duration,
+    below: Option<i64>,
+    format: output::Format,
+) -> Result<()> {
+    use std::io::{IsTerminal, Write};
+
+    let coordinators = Coordinators::new(config.coordinator_urls.clone());
+    let path = format!("users/{}/balance", config.requester_id);
+    let redraw = format == output::Format::Table && std::io::stdout().is_terminal();
+    let mut watch = balance::BalanceWatch::default();
+    let mut ticker = tokio::time::interval(interval);
+    let stop = shutdown::shutdown_signal();
+    tokio::pin!(stop);
+
+    loop {
+        let poll = async {
+            ticker.tick().await;
+            coordinators
+                .get_json::<BalanceResponse>(&path)
+                .await
+                .map(|response| response.balance_seconds)
+                .map_err(|e| e.to_string())
+        };
+        let fetched = tokio::select! {
+            _ = &mut stop => break,
+            fetched = poll => fetched,
+        };
+
+        let reading = watch.observe(fetched);
+        match format {
+            output::Format::Json => println!("{}", serde_json::to_string(&reading)?),
+            output::Format::Plain | output::Format::Csv => println!("{}", reading.plain()),
+            output::Format::Table if redraw => {
+                // Carriage return and erase line, so the balance updates in place
+                print!("\r\x1b[2K{}", reading.line());
+                std::io::stdout().flush()?;
+            }
+            output::Format::Table => println!("{}", reading.line()),
+        }
+
+        if let Some((balance, threshold)) =
+            below.and_then(|threshold| Some((reading.below(threshold)?, threshold)))
+        {
+            if redraw {
+                println!();
+            }
+            anyhow::bail!("Balance of {balance} seconds is below {threshold}");
+        }
+    }
+
+    if redraw {
+        println!();
+    }
+    Ok(())
+}
+
+/// List one `page` of transactions, or all of them in `[since, until)` without one, to
+/// stdout or `output_file`.
+async fn list_transactions(
+    config: &config::Config,
+    page: Option<transactions::Query>,
+    since: Option<chrono::NaiveDateTime>,
+    until: Option<chrono::NaiveDateTime>,
+    output_file: Option<std::path::PathBuf>,
+    format: output::Format,
+) -> Result<()> {
+    let coordinators = Coordinators::new(config.coordinator_urls.clone());
+    let me = &config.requester_id;
+    let response = match page {
+        Some(query) => {
+            coordinators
+                .get_json::<TransactionsResponse>(&query.path(me))
+                .await?
+        }
+        None => TransactionsResponse {
+            transactions: transactions::all(&coordinators, me, since, until).await?,
+        },
+    };
+
+    // Typed fields rather than the table's display text
+    let text = if format == output::Format::Csv {
+        output::transactions_csv(&response.transactions, me)
+    } else {
+        let table = output::transactions_table(&response.transactions, me);
+        let width = output_file.is_none().then(output::terminal_width).flatten();
+        output::render(format, &response, table, width)?
+    };
+    match output_file {
+        Some(path) => {
+            let end = if format == output::Format::Csv {
+                "\r\n"
+            } else {
+                "\n"
+            };
+            std::fs::write(&path, text + end)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            eprintln!(
+                "Wrote {} transactions to {}",
+                response.transactions.len(),
+                path.display()
+            );
+        }
+        None => println!("{text}"),
+    }
+    Ok(())
+}
+
+async fn sum_transactions(
+    config: &config::Config,
+    since: Option<chrono::NaiveDateTime>,
+    until: Option<chrono::NaiveDateTime>,
+    format: output::Format,
+) -> Result<()> {
+    let coordinators = Coordinators::new(config.coordinator_urls.clone());
+    let totals = transactions::sum(&coordinators, &config.requester_id, since, until).await?;
+
+    match format {
+        output::Format::Json => output::print_json(&totals)?,
+        output::Format::Table => {
+            println!("Transactions: {}", totals.transactions);
+            println!("Earned: {} credits", totals.earned);
+            println!("Spent: {} credits", totals.spent);
+            println!("Net: {:+} credits", totals.net);
+        }
+        output::Format::Plain | output::Format::Csv => println!(
+            "{}\t{}\t{}\t{}",
+            totals.transactions, totals.earned, totals.spent, totals.net
+        ),
+    }
+
+    Ok(())
+}
+
+async fn show_credits(
+    config: &config::Config,
+    since: Option<chrono::NaiveDateTime>,
+    until: Option<chrono::NaiveDateTime>,
+    format: output::Format,
+) -> Result<()> {
+    let coordinators = Coordinators::new(config.coordinator_urls.clone());
+    let breakdown = credits::breakdown(&coordinators, &config.requester_id, since, until).await?;
+
+    match format {
+        output::Format::Json => output::print_json(&breakdown)?,
+        output::Format::Table => {
+            for line in breakdown.lines() {
+                println!("{line}");
+            }
+        }
+        output::Format::Plain | output::Format::Csv => println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            breakdown.balance_seconds,
+            breakdown.transactions,
+            breakdown.earned_serving,
+            breakdown.granted,
+            breakdown.spent,
+            breakdown.net
+        ),
+    }
+
+    Ok(())
+}
+
+/// Request `amount` credits; a rejected request fails the command.
+async fn request_credits(
+    config: &config::Config,
+    amount: u64,
+    reason: Option<String>,
+    format: output::Format,
+) -> Result<()> {
+    let coordinators = Coordinators::new(config.coordinator_urls.clone());
+    let response = credits::request(&coordinators, &config.requester_id, amount, reason).await?;
+    let outcome = credits::outcome(&response, amount);
+
+    match format {
+        output::Format::Json => output::print_json(&response)?,
+        output::Format::Table => println!("{outcome}"),
+        output::Format::Plain | output::Format::Csv => println!(
+            "{}\t{}\t{}",
+            serde_json::to_value(response.status)?
+                .as_str()
+                .unwrap_or_default(),
+            response.request_id.as_deref().unwrap_or("-"),
+            response
+                .transaction
+                .as_ref()
+                .and_then(|txn| txn.id)
+                .map_or("-".to_string(), |id| id.to_string())
+        ),
+    }
+
+    if response.status == CreditRequestStatus::Rejected {
+        anyhow::bail!("{outcome}");
+    }
+    Ok(())
+}
+
+/// Ping the coordinators and, with `nodes`, every node they list, until `deadline`.
+/// A peer list that cannot be fetched fails the command after the coordinator results
+/// are printed.
+async fn ping(
+    config: &config::Config,
+    count: u32,
+    nodes: bool,
+    deadline: tokio::time::Instant,
+    format: output::Format,
+) -> Result<()> {
+    let coordinators = Coordinators::new(config.coordinator_urls.clone());
+    let ping_nodes = async {
+        if !nodes {
+            return Ok(None);
+        }
+        let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+        let peers: PeersResponse = coordinators.get_json_once("peers", remaining).await?;
+        anyhow::Ok(Some(
+            ping::ping_nodes(&peers.nodes, config.worker_port, count, deadline).await,
+        ))
+    };
+    let (coordinator_results, node_results) = tokio::join!(
+        ping::ping_coordinators(&config.coordinator_urls, count, deadline),
+        ping_nodes
+    );
+
+    let (nodes, peers_error) = match node_results {
+        Ok(nodes) => (nodes, None),
+        Err(e) => (None, Some(e)),
+    };
+    let report = ping::PingReport {
+        coordinators: coordinator_results,
+        nodes,
+    };
+    output::print(format, &report, output::ping_table(&report))?;
+
+    match peers_error {
+        Some(e) => Err(e.context("Could not fetch the peer list to ping nodes")),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use url::Url;
+
+    /// The exit status for fetching the balance from a coordinator answering `status`.
+    async fn balance_exit_code(status: u16) -> u8 {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/users/me/balance");
+            then.status(status)
+                .json_body(serde_json::json!({"detail": "nope"}));
+        });
+        let coordinators = Coordinators::new(vec![Url::parse(&server.base_url()).unwrap()]);
+        let error = coordinators
+            .get_json_once::<BalanceResponse>("users/me/balance", Duration::from_secs(5))
+            .await
+            .map_err(anyhow::Error::from)
+            .context("Failed to get balance")
+            .unwrap_err();
+        exit_code(&error)
+    }
+
+    #[test]
+    fn test_csv_is_only_offered_for_listings() {
+        let lists_rows = |args: &[&str]| {
+            let cli =
+                Cli::try_parse_from([&["monkey-troop-client", "--output", "csv"], args].concat())
+                    .unwrap();
+            assert_eq!(cli.format(), output::Format::Csv);
+            cli.command.lists_rows()
+        };
+        assert!(lists_rows(&[
+            "transactions",
+            "--since",
+            "2026-09-01",
+            "--until",
+            "2026-10-01"
+        ]));
+        assert!(lists_rows(&["nodes", "--model", "llama3:8b"]));
+        assert!(lists_rows(&["config", "show"]));
+        assert!(!lists_rows(&["transactions", "--sum"]));
+        assert!(!lists_rows(&["nodes", "--watch"]));
+        assert!(!lists_rows(&["balance"]));
+
+        // The file takes the listing, so there is nothing to write with --sum
+        assert!(Cli::try_parse_from([
+            "monkey-troop-client",
+            "transactions",
+            "--sum",
+            "--output-file",
+            "sum.csv"
+        ])
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_exit_codes_tell_failure_classes_apart() {
+        assert_eq!(balance_exit_code(401).await, EXIT_AUTH);
+        assert_eq!(balance_exit_code(403).await, EXIT_AUTH);
+        assert_eq!(balance_exit_code(404).await, EXIT_NOT_FOUND);
+        assert_eq!(balance_exit_code(500).await, EXIT_SERVER);
+        assert_eq!(balance_exit_code(422).await, 1);
+
+        // Nothing listens on a port once its listener is dropped
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        drop(listener);
+        let error = Coordinators::new(vec![url])
+            .get_json_once::<BalanceResponse>("users/me/balance", Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        assert_eq!(exit_code(&error.into()), EXIT_NETWORK);
+        assert_eq!(exit_code(&anyhow::anyhow!("The proxy is not running")), 1);
+    }
+
+    #[test]
+    fn test_completions_cover_every_subcommand_and_flag() {
+        let mut command = Cli::command();
+        command.build();
+        let mut script = Vec::new();
+        clap_complete::generate(
+            clap_complete::Shell::Bash,
+            &mut command.clone(),
+            "monkey-troop-client",
+            &mut script,
+        );
+        let script = String::from_utf8(script).unwrap();
+
+        for subcommand in command.get_subcommands() {
+            assert!(
+                script.contains(subcommand.get_name()),
+                "{}",
+                subcommand.get_name()
+            );
+            for arg in subcommand.get_arguments() {
+                if let Some(long) = arg.get_long() {
+                    assert!(script.contains(&format!("--{long}")), "--{long}");
+                }
+            }
+        }
+        assert!(script.contains("--output"));
+    }
+}
@@ -0,0 +1,126 @@
+//! Model allowlist/denylist for the client proxy.
+//!
+//! `MODEL_ALLOWLIST` and `MODEL_DENYLIST` are comma-separated model names or glob
+//! patterns (`*` matches any run of characters, `?` a single one). The denylist takes
+//! precedence: a model is served only if it matches no denylist entry and, when an
+//! allowlist is set, at least one allowlist entry.
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ModelFilter {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl ModelFilter {
+    pub fn new(allowlist: &str, denylist: &str) -> Self {
+        Self {
+            allow: parse_patterns(allowlist),
+            deny: parse_patterns(denylist),
+        }
+    }
+
+    pub fn allows(&self, model: &str) -> bool {
+        self.check(model).is_ok()
+    }
+
+    /// `Err` carries a message explaining which list rejected `model`.
+    pub fn check(&self, model: &str) -> Result<(), String> {
+        if let Some(pattern) = self.deny.iter().find(|p| glob_match(p, model)) {
+            return Err(format!(
+                "Model '{model}' is blocked by MODEL_DENYLIST entry '{pattern}' \
+                 (the denylist takes precedence over MODEL_ALLOWLIST)"
+            ));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|p| glob_match(p, model)) {
+            return Err(format!(
+                "Model '{model}' is not in MODEL_ALLOWLIST \
+                 (the denylist takes precedence over MODEL_ALLOWLIST)"
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn parse_patterns(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether `text` matches `pattern`, where `*` matches any run of characters (including
+/// none) and `?` matches exactly one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` seen and the text position it is currently matched up to
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` swallow one more character and retry
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    t = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("llama3:8b", "llama3:8b"));
+        assert!(!glob_match("llama3:8b", "llama3:70b"));
+        assert!(glob_match("*:70b", "llama3:70b"));
+        assert!(glob_match("llama3*", "llama3"));
+        assert!(glob_match("qwen2.5:?b", "qwen2.5:7b"));
+        assert!(!glob_match("qwen2.5:?b", "qwen2.5:14b"));
+        assert!(glob_match("*70b*", "llama3.1:70b-instruct"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("llama*:8b", "llama3:70b"));
+    }
+
+    #[test]
+    fn test_denylist_takes_precedence_over_allowlist() {
+        let filter = ModelFilter::new("llama3*, qwen2.5:7b", "*:70b");
+
+        assert!(filter.allows("llama3:8b"));
+        assert!(filter.allows("qwen2.5:7b"));
+        let err = filter.check("llama3:70b").unwrap_err();
+        assert!(err.contains("MODEL_DENYLIST entry '*:70b'"));
+        assert!(err.contains("denylist takes precedence"));
+        let err = filter.check("mistral:7b").unwrap_err();
+        assert!(err.contains("not in MODEL_ALLOWLIST"));
+    }
+
+    #[test]
+    fn test_empty_lists_allow_everything() {
+        let filter = ModelFilter::new("", " , ");
+        assert!(filter.allows("llama3:70b"));
+
+        let deny_only = ModelFilter::new("", "*:70b");
+        assert!(deny_only.allows("mistral:7b"));
+        assert!(!deny_only.allows("llama3:70b"));
+    }
+}
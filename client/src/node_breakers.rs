@@ -0,0 +1,111 @@
+//! Per-worker-node circuit breakers for the client proxy.
+//!
+//! Each node IP gets its own `CircuitBreaker` so a single flapping worker is skipped
+//! quickly instead of costing every request a full retry cycle. Entries that have not
+//! been used for `NODE_BREAKER_TTL` are evicted, which drops nodes that have left the troop.
+
+use monkey_troop_shared::{CircuitBreaker, CircuitState};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How long an unused breaker is kept before it is evicted.
+pub const NODE_BREAKER_TTL: Duration = Duration::from_secs(600);
+
+struct NodeEntry {
+    breaker: Arc<CircuitBreaker>,
+    last_used: Instant,
+}
+
+pub struct NodeBreakers {
+    entries: Mutex<HashMap<String, NodeEntry>>,
+    threshold: u32,
+    timeout: Duration,
+    ttl: Duration,
+}
+
+impl NodeBreakers {
+    pub fn new(threshold: u32, timeout: Duration, ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            threshold,
+            timeout,
+            ttl,
+        }
+    }
+
+    /// Get (or create) the breaker guarding `node_ip`, evicting stale entries on the way.
+    pub fn breaker_for(&self, node_ip: &str) -> Arc<CircuitBreaker> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, entry| now.duration_since(entry.last_used) < self.ttl);
+
+        let entry = entries
+            .entry(node_ip.to_string())
+            .or_insert_with(|| NodeEntry {
+                breaker: Arc::new(CircuitBreaker::new(self.threshold, self.timeout)),
+                last_used: now,
+            });
+        entry.last_used = now;
+        entry.breaker.clone()
+    }
+
+    /// The state of every node's breaker that has not been evicted yet.
+    pub async fn states(&self) -> BTreeMap<String, CircuitState> {
+        let breakers: Vec<(String, Arc<CircuitBreaker>)> = self
+            .entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(node_ip, entry)| (node_ip.clone(), entry.breaker.clone()))
+            .collect();
+        let mut states = BTreeMap::new();
+        for (node_ip, breaker) in breakers {
+            states.insert(node_ip, breaker.state().await);
+        }
+        states
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_breaker_is_shared_per_node() {
+        let breakers = NodeBreakers::new(1, Duration::from_secs(60), NODE_BREAKER_TTL);
+
+        breakers.breaker_for("100.64.0.1").record_failure().await;
+
+        assert_eq!(
+            breakers.breaker_for("100.64.0.1").state().await,
+            CircuitState::Open
+        );
+        assert_eq!(
+            breakers.breaker_for("100.64.0.2").state().await,
+            CircuitState::Closed
+        );
+        assert_eq!(
+            breakers.states().await,
+            BTreeMap::from([
+                ("100.64.0.1".to_string(), CircuitState::Open),
+                ("100.64.0.2".to_string(), CircuitState::Closed),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_idle_breakers_are_evicted() {
+        tokio::time::pause();
+        let breakers = NodeBreakers::new(1, Duration::from_secs(600), Duration::from_secs(30));
+
+        breakers.breaker_for("100.64.0.1").record_failure().await;
+        tokio::time::advance(Duration::from_secs(31)).await;
+
+        assert_eq!(
+            breakers.breaker_for("100.64.0.1").state().await,
+            CircuitState::Closed
+        );
+    }
+}
@@ -0,0 +1,358 @@
+//! `nodes --watch`: following the troop as nodes come and go.
+//!
+//! Each refresh is compared with the previous successful one, and nodes that joined,
+//! went offline or had their free VRAM move are marked, while nodes the coordinator no
+//! longer lists are named under the table. The `--model` and `--status` filters and the
+//! most-free-VRAM-first order apply to every refresh. A refresh that fails keeps the last
+//! nodes on screen, marked stale with how long ago they were fetched, instead of ending
+//! the watch.
+
+use crate::output::{self, Table};
+use monkey_troop_shared::{NodeHeartbeat, NodeStatus};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{IsTerminal, Read};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+/// Seconds between refreshes when `--watch` is given without an interval.
+pub const DEFAULT_WATCH_INTERVAL_SECS: &str = "5";
+
+/// Smallest change in free VRAM, in MB, that is marked; readings jitter by a few MB.
+const VRAM_CHANGE_MB: i64 = 256;
+
+/// How a node differs from the previous refresh.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    /// Not listed last time
+    New,
+    /// Idle or busy last time
+    WentOffline,
+    /// Free VRAM moved by this many MB
+    Vram { delta_mb: i64 },
+}
+
+impl Change {
+    fn text(&self) -> String {
+        match self {
+            Change::New => "new".to_string(),
+            Change::WentOffline => "offline".to_string(),
+            Change::Vram { delta_mb } => format!("vram {:+.1} GB", *delta_mb as f64 / 1024.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchedNode {
+    #[serde(flatten)]
+    pub node: NodeHeartbeat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<Change>,
+}
+
+/// What one refresh found.
+#[derive(Debug, Serialize)]
+pub struct Snapshot {
+    /// The nodes passing the filters, or the last ones fetched when `stale`
+    pub nodes: Vec<WatchedNode>,
+    /// IDs of nodes shown last time that the coordinator no longer lists
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub gone: Vec<String>,
+    pub stale: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Seconds since the nodes shown were fetched, when `stale`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fetched_secs_ago: Option<u64>,
+}
+
+impl Snapshot {
+    /// One row per node, with what changed on the left.
+    pub fn table(&self, wide: bool) -> Table {
+        let nodes: Vec<NodeHeartbeat> = self.nodes.iter().map(|n| n.node.clone()).collect();
+        let changes = self
+            .nodes
+            .iter()
+            .map(|n| n.change.as_ref().map_or("-".to_string(), Change::text))
+            .collect();
+        output::nodes_table(&nodes, wide).with_column("CHANGE", changes)
+    }
+
+    /// Lines for under the table: the nodes that left, and why the table is stale.
+    pub fn notes(&self) -> Vec<String> {
+        let mut notes = Vec::new();
+        if !self.gone.is_empty() {
+            notes.push(format!("Gone: {}", self.gone.join(", ")));
+        }
+        if let Some(error) = &self.error {
+            let age = self
+                .fetched_secs_ago
+                .map_or("never fetched".to_string(), |secs| {
+                    format!("last fetched {secs}s ago")
+                });
+            notes.push(format!("[stale, {age}: {error}]"));
+        }
+        notes
+    }
+}
+
+/// The filters to apply, and what the last successful refresh found.
+pub struct NodesWatch {
+    model: Option<String>,
+    status: Option<NodeStatus>,
+    /// Every node listed last time, by ID, filtered or not
+    last: Option<HashMap<String, NodeHeartbeat>>,
+    shown: Vec<WatchedNode>,
+    fetched: Option<Instant>,
+}
+
+impl NodesWatch {
+    pub fn new(model: Option<String>, status: Option<NodeStatus>) -> Self {
+        Self {
+            model,
+            status,
+            last: None,
+            shown: Vec::new(),
+            fetched: None,
+        }
+    }
+
+    pub fn observe(&mut self, fetched: Result<Vec<NodeHeartbeat>, String>) -> Snapshot {
+        let nodes = match fetched {
+            Ok(nodes) => nodes,
+            Err(error) => {
+                return Snapshot {
+                    nodes: self
+                        .shown
+                        .iter()
+                        .map(|n| WatchedNode {
+                            node: n.node.clone(),
+                            change: None,
+                        })
+                        .collect(),
+                    gone: Vec::new(),
+                    stale: true,
+                    error: Some(error),
+                    fetched_secs_ago: self.fetched.map(|at| at.elapsed().as_secs()),
+                }
+            }
+        };
+
+        let gone = match &self.last {
+            Some(_) => self
+                .shown
+                .iter()
+                .map(|n| &n.node.node_id)
+                .filter(|id| !nodes.iter().any(|node| &node.node_id == *id))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        let shown: Vec<WatchedNode> =
+            output::select_nodes(nodes.clone(), self.model.as_deref(), self.status.as_ref())
+                .into_iter()
+                .map(|node| WatchedNode {
+                    change: self.last.as_ref().and_then(|last| change(last, &node)),
+                    node,
+                })
+                .collect();
+
+        self.last = Some(
+            nodes
+                .into_iter()
+                .map(|node| (node.node_id.clone(), node))
+                .collect(),
+        );
+        self.shown = shown.clone();
+        self.fetched = Some(Instant::now());
+        Snapshot {
+            nodes: shown,
+            gone,
+            stale: false,
+            error: None,
+            fetched_secs_ago: None,
+        }
+    }
+}
+
+/// `q` typed on the terminal, to end the watch. While listening, keys reach the client as
+/// they are typed and are not echoed (on Unix; elsewhere `q` needs Enter after it), and
+/// the terminal is put back when this is dropped. Nothing is read unless stdin is a
+/// terminal.
+pub struct QuitKey {
+    keys: mpsc::UnboundedReceiver<()>,
+    #[cfg(unix)]
+    saved: Option<libc::termios>,
+}
+
+impl QuitKey {
+    pub fn listen() -> Self {
+        let (tx, keys) = mpsc::unbounded_channel();
+        let terminal = std::io::stdin().is_terminal();
+        if terminal {
+            // A plain thread, as it stays blocked on stdin for as long as the process runs
+            std::thread::spawn(move || {
+                for byte in std::io::stdin().lock().bytes() {
+                    match byte {
+                        Ok(b'q' | b'Q') => {
+                            let _ = tx.send(());
+                            break;
+                        }
+                        Ok(_) => {}
+                        Err(_) => break,
+                    }
+                }
+            });
+        }
+        Self {
+            keys,
+            #[cfg(unix)]
+            saved: terminal.then(unbuffer_stdin).flatten(),
+        }
+    }
+
+    /// Resolves once `q` is typed; never, when stdin is not a terminal.
+    pub async fn pressed(&mut self) {
+        if self.keys.recv().await.is_none() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for QuitKey {
+    fn drop(&mut self) {
+        if let Some(saved) = &self.saved {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved) };
+        }
+    }
+}
+
+/// Hand each key over as it is typed, without echo; returns the settings to restore.
+#[cfg(unix)]
+fn unbuffer_stdin() -> Option<libc::termios> {
+    let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+        return None;
+    }
+    let mut unbuffered = saved;
+    unbuffered.c_lflag &= !(libc::ICANON | libc::ECHO);
+    unbuffered.c_cc[libc::VMIN] = 1;
+    unbuffered.c_cc[libc::VTIME] = 0;
+    let ok = unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &unbuffered) } == 0;
+    ok.then_some(saved)
+}
+
+/// How `node` differs from its entry in `last`.
+fn change(last: &HashMap<String, NodeHeartbeat>, node: &NodeHeartbeat) -> Option<Change> {
+    let Some(before) = last.get(&node.node_id) else {
+        return Some(Change::New);
+    };
+    if node.status == NodeStatus::Offline && before.status != NodeStatus::Offline {
+        return Some(Change::WentOffline);
+    }
+    let delta_mb = node.hardware.vram_free as i64 - before.hardware.vram_free as i64;
+    (delta_mb.abs() >= VRAM_CHANGE_MB).then_some(Change::Vram { delta_mb })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn node(id: &str, status: &str, vram_free: u64) -> NodeHeartbeat {
+        serde_json::from_value(json!({
+            "node_id": id,
+            "tailscale_ip": "100.64.0.1",
+            "status": status,
+            "models": [{"name": "llama3:8b", "content_hash": "sha256:abc", "size_bytes": 1}],
+            "hardware": {"gpu": "RTX 4090", "vram_free": vram_free},
+            "engines": []
+        }))
+        .unwrap()
+    }
+
+    fn changes(snapshot: &Snapshot) -> Vec<(&str, Option<Change>)> {
+        snapshot
+            .nodes
+            .iter()
+            .map(|n| (n.node.node_id.as_str(), n.change.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_changes_are_since_the_previous_refresh() {
+        let mut watch = NodesWatch::new(None, None);
+        let first = watch.observe(Ok(vec![
+            node("a", "IDLE", 24576),
+            node("b", "BUSY", 8192),
+            node("c", "IDLE", 4096),
+        ]));
+        assert!(first.nodes.iter().all(|n| n.change.is_none()));
+
+        let second = watch.observe(Ok(vec![
+            node("a", "BUSY", 20480),
+            node("b", "OFFLINE", 8192),
+            node("d", "IDLE", 49152),
+        ]));
+        assert_eq!(
+            changes(&second),
+            [
+                ("d", Some(Change::New)),
+                ("a", Some(Change::Vram { delta_mb: -4096 })),
+                ("b", Some(Change::WentOffline)),
+            ]
+        );
+        assert_eq!(second.gone, ["c"]);
+        assert_eq!(second.notes(), ["Gone: c"]);
+        assert!(second
+            .table(false)
+            .plain()
+            .starts_with("new\td\tIDLE\tRTX 4090\t48.0 GB"));
+
+        // Small VRAM jitter is not a change
+        let third = watch.observe(Ok(vec![node("d", "IDLE", 49000)]));
+        assert_eq!(changes(&third), [("d", None)]);
+    }
+
+    #[test]
+    fn test_filters_apply_to_every_refresh() {
+        let mut watch = NodesWatch::new(None, Some(NodeStatus::Idle));
+        watch.observe(Ok(vec![node("a", "IDLE", 1024), node("b", "IDLE", 2048)]));
+        let snapshot = watch.observe(Ok(vec![
+            node("a", "BUSY", 1024),
+            node("b", "IDLE", 2048),
+            node("c", "IDLE", 4096),
+        ]));
+        // A node leaving the filter is not gone from the troop
+        assert_eq!(changes(&snapshot), [("c", Some(Change::New)), ("b", None)]);
+        assert!(snapshot.gone.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_refresh_keeps_the_last_nodes_marked_stale() {
+        let mut watch = NodesWatch::new(None, None);
+        let failed = watch.observe(Err("coordinator unreachable".to_string()));
+        assert!(failed.nodes.is_empty());
+        assert_eq!(
+            failed.notes(),
+            ["[stale, never fetched: coordinator unreachable]"]
+        );
+
+        watch.observe(Ok(vec![node("a", "IDLE", 1024)]));
+        tokio::time::advance(Duration::from_secs(12)).await;
+        let stale = watch.observe(Err("coordinator unreachable".to_string()));
+        assert_eq!(changes(&stale), [("a", None)]);
+        assert_eq!(stale.fetched_secs_ago, Some(12));
+        assert_eq!(
+            stale.notes(),
+            ["[stale, last fetched 12s ago: coordinator unreachable]"]
+        );
+
+        // Changes are still against the last successful refresh
+        let recovered = watch.observe(Ok(vec![node("a", "IDLE", 1024), node("b", "IDLE", 0)]));
+        assert_eq!(changes(&recovered), [("a", None), ("b", Some(Change::New))]);
+    }
+}
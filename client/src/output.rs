@@ -0,0 +1,933 @@
+//! Output formatting for the CLI commands.
+//!
+//! Every command prints its result in the `--output` format: stable JSON serialized from
+//! the typed models, so new coordinator fields never change what scripts see; a table of
+//! space-aligned columns for people, fitted to the terminal; plain tab-separated rows
+//! without headers for `cut` and `awk`; or RFC 4180 CSV with a header row for
+//! spreadsheets, offered by the commands that list rows.
+
+use crate::bench::BenchReport;
+use crate::config_file::{Effective, Source};
+use crate::ping::PingReport;
+use crate::status::{ProxyStatus, State};
+use crate::transactions;
+use anyhow::Result;
+use monkey_troop_shared::{ModelsResponse, NodeHeartbeat, NodeStatus, PeersResponse, Transaction};
+use serde::Serialize;
+use serde_json::Value;
+use std::io::IsTerminal;
+
+/// How a command prints its result.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// JSON, for scripts
+    Json,
+    /// Aligned columns with headers, fitted to the terminal
+    #[default]
+    Table,
+    /// Tab-separated rows without headers
+    Plain,
+    /// Comma-separated rows with a header row, for spreadsheets
+    Csv,
+}
+
+pub fn print_json(value: &impl Serialize) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Print a result as JSON, or as `table` in the other formats.
+pub fn print(format: Format, value: &impl Serialize, table: Table) -> Result<()> {
+    println!("{}", render(format, value, table, terminal_width())?);
+    Ok(())
+}
+
+/// A result as text: JSON, or `table`, fitted to `max_width` in the table format.
+pub fn render(
+    format: Format,
+    value: &impl Serialize,
+    table: Table,
+    max_width: Option<usize>,
+) -> Result<String> {
+    Ok(match format {
+        Format::Json => serde_json::to_string_pretty(value)?,
+        Format::Table => table.aligned(max_width),
+        Format::Plain => table.plain(),
+        Format::Csv => table.csv(),
+    })
+}
+
+/// Model names listed per node by `nodes_table` unless `wide`.
+const MODELS_SHOWN: usize = 2;
+
+/// The nodes hosting `model` with `status`, when given, most free VRAM first.
+pub fn select_nodes(
+    nodes: Vec<NodeHeartbeat>,
+    model: Option<&str>,
+    status: Option<&NodeStatus>,
+) -> Vec<NodeHeartbeat> {
+    let mut nodes: Vec<NodeHeartbeat> = nodes
+        .into_iter()
+        .filter(|node| model.is_none_or(|model| node.models.iter().any(|m| m.name == model)))
+        .filter(|node| status.is_none_or(|status| node.status == *status))
+        .collect();
+    nodes.sort_by_key(|node| std::cmp::Reverse(node.hardware.vram_free));
+    nodes
+}
+
+/// One row per node. Model lists longer than `MODELS_SHOWN` are cut short unless `wide`.
+pub fn nodes_table(nodes: &[NodeHeartbeat], wide: bool) -> Table {
+    let rows = nodes
+        .iter()
+        .map(|node| {
+            let mut names: Vec<&str> = node.models.iter().map(|m| m.name.as_str()).collect();
+            if !wide && names.len() > MODELS_SHOWN {
+                names.truncate(MODELS_SHOWN);
+                names.push("...");
+            }
+            vec![
+                node.node_id.clone(),
+                status_text(&node.status),
+                node.hardware.gpu.clone(),
+                gigabytes(node.hardware.vram_free),
+                node.tailscale_ip.clone(),
+                node.models.len().to_string(),
+                if names.is_empty() {
+                    "-".to_string()
+                } else {
+                    names.join(", ")
+                },
+            ]
+        })
+        .collect();
+    Table::new(
+        &[
+            "NODE ID",
+            "STATUS",
+            "GPU",
+            "VRAM FREE",
+            "TAILSCALE IP",
+            "MODELS",
+            "SERVING",
+        ],
+        rows,
+    )
+}
+
+/// Transactions from `me`'s point of view: credits are signed by their effect on the
+/// balance, and the counterparty is the other side of each transaction.
+pub fn transactions_table(txns: &[Transaction], me: &str) -> Table {
+    let rows = txns
+        .iter()
+        .map(|txn| {
+            let credits = transactions::signed_credits(txn, me);
+            vec![
+                txn.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+                txn.kind.clone(),
+                if credits > 0 {
+                    format!("+{credits}")
+                } else {
+                    credits.to_string()
+                },
+                transactions::counterparty(txn, me)
+                    .unwrap_or("-")
+                    .to_string(),
+                txn.model.clone().unwrap_or_else(|| "-".to_string()),
+            ]
+        })
+        .collect();
+    Table::new(
+        &["TIMESTAMP", "TYPE", "CREDITS", "COUNTERPARTY", "MODEL"],
+        rows,
+    )
+}
+
+/// Every field of each transaction as CSV, with UTC times in RFC 3339 and `balance_change`
+/// the change to `me`'s balance.
+pub fn transactions_csv(txns: &[Transaction], me: &str) -> String {
+    let header = [
+        "id",
+        "timestamp",
+        "type",
+        "credits",
+        "balance_change",
+        "requester",
+        "worker",
+        "model",
+    ]
+    .map(str::to_string);
+    let rows = txns.iter().map(|txn| {
+        [
+            txn.id.map(|id| id.to_string()).unwrap_or_default(),
+            txn.timestamp
+                .and_utc()
+                .to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true),
+            txn.kind.clone(),
+            txn.credits.to_string(),
+            transactions::signed_credits(txn, me).to_string(),
+            txn.requester.clone().unwrap_or_default(),
+            txn.worker.clone().unwrap_or_default(),
+            txn.model.clone().unwrap_or_default(),
+        ]
+    });
+    std::iter::once(header)
+        .chain(rows)
+        .map(|row| csv_record(&row))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+/// Each setting's effective value and where it comes from.
+pub fn config_table(settings: &[Effective]) -> Table {
+    let rows = settings
+        .iter()
+        .map(|setting| {
+            let source = match setting.source {
+                Source::Flag => "flag".to_string(),
+                Source::Env => "env".to_string(),
+                Source::File { line } => format!("file, line {line}"),
+                Source::Default => "default".to_string(),
+            };
+            vec![
+                setting.key.to_string(),
+                setting.value.clone().unwrap_or_else(|| "-".to_string()),
+                source,
+            ]
+        })
+        .collect();
+    Table::new(&["KEY", "VALUE", "SOURCE"], rows)
+}
+
+/// A model and the nodes hosting it.
+#[derive(Debug, Serialize)]
+pub struct ModelAvailability {
+    pub model: String,
+    /// Hosting nodes that are idle or busy
+    pub online_nodes: usize,
+    /// Most free VRAM on an online hosting node, in MB
+    pub best_vram_free_mb: Option<u64>,
+    /// Engines running on the hosting nodes
+    pub engines: Vec<String>,
+    pub nodes: Vec<ServingNode>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServingNode {
+    pub node_id: String,
+    pub status: NodeStatus,
+    pub gpu: String,
+    pub vram_free_mb: u64,
+    pub engines: Vec<String>,
+}
+
+/// Join `/v1/models` with `/peers`. Each model's nodes are listed idle first, then busy,
+/// then offline, and by free VRAM within each.
+pub fn model_availability(
+    models: &ModelsResponse,
+    peers: &PeersResponse,
+) -> Vec<ModelAvailability> {
+    models
+        .data
+        .iter()
+        .map(|model| {
+            let mut nodes: Vec<ServingNode> = peers
+                .nodes
+                .iter()
+                .filter(|node| node.models.iter().any(|m| m.name == model.id))
+                .map(|node| ServingNode {
+                    node_id: node.node_id.clone(),
+                    status: node.status.clone(),
+                    gpu: node.hardware.gpu.clone(),
+                    vram_free_mb: node.hardware.vram_free,
+                    engines: node
+                        .engines
+                        .iter()
+                        .map(|engine| engine.engine_type.clone())
+                        .collect(),
+                })
+                .collect();
+            nodes.sort_by_key(|node| {
+                let rank = match node.status {
+                    NodeStatus::Idle => 0,
+                    NodeStatus::Busy => 1,
+                    NodeStatus::Offline => 2,
+                };
+                (rank, std::cmp::Reverse(node.vram_free_mb))
+            });
+            let online = || {
+                nodes
+                    .iter()
+                    .filter(|node| !matches!(node.status, NodeStatus::Offline))
+            };
+            let mut engines: Vec<String> = nodes
+                .iter()
+                .flat_map(|node| node.engines.iter().cloned())
+                .collect();
+            engines.sort();
+            engines.dedup();
+            ModelAvailability {
+                model: model.id.clone(),
+                online_nodes: online().count(),
+                best_vram_free_mb: online().map(|node| node.vram_free_mb).max(),
+                engines,
+                nodes,
+            }
+        })
+        .collect()
+}
+
+/// One row per model: its online and idle nodes, the most free VRAM among them, and the
+/// engines involved. Models no online node hosts are flagged.
+pub fn models_table(models: &[ModelAvailability]) -> Table {
+    let rows = models
+        .iter()
+        .map(|model| {
+            let idle = model
+                .nodes
+                .iter()
+                .filter(|node| matches!(node.status, NodeStatus::Idle))
+                .count();
+            vec![
+                model.model.clone(),
+                if model.online_nodes == 0 {
+                    "NONE ONLINE".to_string()
+                } else {
+                    model.online_nodes.to_string()
+                },
+                idle.to_string(),
+                model
+                    .best_vram_free_mb
+                    .map_or_else(|| "-".to_string(), gigabytes),
+                list_text(&model.engines),
+            ]
+        })
+        .collect();
+    Table::new(
+        &["MODEL", "NODES", "IDLE", "BEST VRAM FREE", "ENGINES"],
+        rows,
+    )
+}
+
+/// One row per node hosting `model`, for `models --model`.
+pub fn model_nodes_table(model: &ModelAvailability) -> Table {
+    let rows = model
+        .nodes
+        .iter()
+        .map(|node| {
+            vec![
+                node.node_id.clone(),
+                status_text(&node.status),
+                node.gpu.clone(),
+                gigabytes(node.vram_free_mb),
+                list_text(&node.engines),
+            ]
+        })
+        .collect();
+    Table::new(&["NODE ID", "STATUS", "GPU", "VRAM FREE", "ENGINES"], rows)
+}
+
+fn gigabytes(mb: u64) -> String {
+    format!("{:.1} GB", mb as f64 / 1024.0)
+}
+
+fn list_text(items: &[String]) -> String {
+    if items.is_empty() {
+        "-".to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+fn status_text(status: &NodeStatus) -> String {
+    serde_json::to_value(status).map_or_else(|_| "-".to_string(), |v| text(&v))
+}
+
+/// Latency and lost samples per coordinator and node; targets that never answered are
+/// flagged.
+pub fn ping_table(report: &PingReport) -> Table {
+    let rows = report
+        .results()
+        .map(|result| {
+            let latencies = match (result.min_ms, result.avg_ms, result.max_ms) {
+                (Some(min), Some(avg), Some(max)) => {
+                    [min, avg, max].map(|ms| format!("{ms:.1} ms")).to_vec()
+                }
+                _ => vec!["UNREACHABLE".to_string(), "-".to_string(), "-".to_string()],
+            };
+            [result.target.clone(), result.address.clone()]
+                .into_iter()
+                .chain(latencies)
+                .chain([format!("{}/{}", result.failures, result.samples)])
+                .collect()
+        })
+        .collect();
+    Table::new(&["TARGET", "ADDRESS", "MIN", "AVG", "MAX", "LOST"], rows)
+}
+
+/// One row per node benchmarked, with the averages over its measured runs.
+pub fn bench_table(report: &BenchReport) -> Table {
+    let number = |value: Option<f64>, unit: &str| {
+        value.map_or("-".to_string(), |value| format!("{value:.1}{unit}"))
+    };
+    let rows = report
+        .nodes
+        .iter()
+        .map(|node| {
+            vec![
+                node.node_id.clone().unwrap_or_else(|| "-".to_string()),
+                node.node.clone(),
+                node.runs.to_string(),
+                node.failed.to_string(),
+                number(node.avg_ttft_ms, " ms"),
+                number(node.avg_tokens_per_sec, ""),
+                number(node.best_tokens_per_sec, ""),
+            ]
+        })
+        .collect();
+    Table::new(
+        &[
+            "NODE",
+            "ADDRESS",
+            "RUNS",
+            "FAILED",
+            "FIRST TOKEN",
+            "TOKENS/S",
+            "BEST TOKENS/S",
+        ],
+        rows,
+    )
+}
+
+/// The proxy in one row; what it did not report, or cannot while down, shows as "-".
+pub fn status_table(status: &ProxyStatus) -> Table {
+    let state = match status.state {
+        State::Running => "RUNNING",
+        State::NotRunning => "NOT RUNNING",
+        State::OtherService => "OTHER SERVICE",
+        State::NotResponding => "NOT RESPONDING",
+    };
+    let coordinator = match status.coordinator_reachable {
+        Some(true) => "reachable",
+        Some(false) => "UNREACHABLE",
+        None => "-",
+    };
+    let last_error = status.last_error.as_ref().map_or("-".to_string(), |error| {
+        format!(
+            "{} {} {}: {}",
+            error.at.format("%Y-%m-%d %H:%M:%S"),
+            error.status,
+            error.model,
+            error.message
+        )
+    });
+    let row = vec![
+        state.to_string(),
+        status.url.clone(),
+        status.uptime_secs.map_or("-".to_string(), duration_text),
+        coordinator.to_string(),
+        status
+            .available_models
+            .map_or("-".to_string(), |models| models.to_string()),
+        last_error,
+    ];
+    Table::new(
+        &[
+            "PROXY",
+            "URL",
+            "UPTIME",
+            "COORDINATOR",
+            "MODELS",
+            "LAST ERROR",
+        ],
+        vec![row],
+    )
+}
+
+/// `secs` in its two largest units, such as "3d 4h" or "5m 12s".
+fn duration_text(secs: u64) -> String {
+    let units = [(86_400, "d"), (3_600, "h"), (60, "m"), (1, "s")];
+    let Some(first) = units.iter().position(|&(size, _)| secs >= size) else {
+        return "0s".to_string();
+    };
+    units[first..]
+        .iter()
+        .take(2)
+        .scan(secs, |left, &(size, unit)| {
+            let count = *left / size;
+            *left %= size;
+            Some(format!("{count}{unit}"))
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Narrowest a column is cut to when fitting a table to the terminal, unless its header
+/// is wider.
+const MIN_COLUMN_WIDTH: usize = 8;
+
+/// Rows of cells under column headers.
+#[derive(Debug)]
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&'static str], rows: Vec<Vec<String>>) -> Self {
+        Self {
+            headers: headers.to_vec(),
+            rows,
+        }
+    }
+
+    /// The table with a column of `cells`, one per row, added on the left.
+    pub fn with_column(mut self, header: &'static str, cells: Vec<String>) -> Self {
+        self.headers.insert(0, header);
+        for (row, cell) in self.rows.iter_mut().zip(cells) {
+            row.insert(0, cell);
+        }
+        self
+    }
+
+    /// Left-aligned columns separated by two spaces, each as wide as its widest cell.
+    /// Past `max_width`, the widest columns are narrowed until the table fits, and cells
+    /// that no longer fit are cut short with "…".
+    pub fn aligned(&self, max_width: Option<usize>) -> String {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        if let Some(max_width) = max_width {
+            self.narrow(&mut widths, max_width);
+        }
+
+        let header = self.headers.iter().map(|h| h.to_string()).collect();
+        std::iter::once(&header)
+            .chain(&self.rows)
+            .map(|row| {
+                let line = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, &width)| format!("{:<width$}", truncate(cell, width)))
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                line.trim_end().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Take a character at a time from the widest column that can still give one up.
+    fn narrow(&self, widths: &mut [usize], max_width: usize) {
+        let separators = 2 * widths.len().saturating_sub(1);
+        while widths.iter().sum::<usize>() + separators > max_width {
+            let widest = widths
+                .iter()
+                .zip(&self.headers)
+                .enumerate()
+                .filter(|(_, (&width, header))| {
+                    width > MIN_COLUMN_WIDTH.max(header.chars().count())
+                })
+                .max_by_key(|(_, (&width, _))| width)
+                .map(|(i, _)| i);
+            match widest {
+                Some(i) => widths[i] -= 1,
+                None => break,
+            }
+        }
+    }
+
+    /// One tab-separated line per row, without the headers.
+    pub fn plain(&self) -> String {
+        self.rows
+            .iter()
+            .map(|row| row.join("\t"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The headers and rows as CSV records.
+    pub fn csv(&self) -> String {
+        let header: Vec<String> = self.headers.iter().map(|h| h.to_string()).collect();
+        std::iter::once(&header)
+            .chain(&self.rows)
+            .map(|row| csv_record(row))
+            .collect::<Vec<_>>()
+            .join("\r\n")
+    }
+}
+
+/// One CSV record, per RFC 4180: fields holding a comma, quote or line break are quoted,
+/// with their quotes doubled.
+fn csv_record(fields: &[String]) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn truncate(cell: &str, width: usize) -> String {
+    if cell.chars().count() <= width {
+        return cell.to_string();
+    }
+    let mut cut: String = cell.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+/// Columns of the terminal stdout is writing to, or `None` when it is not a terminal:
+/// output piped elsewhere is never cut.
+pub fn terminal_width() -> Option<usize> {
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .or_else(window_columns)
+}
+
+#[cfg(unix)]
+fn window_columns() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    (ok && size.ws_col > 0).then_some(usize::from(size.ws_col))
+}
+
+#[cfg(not(unix))]
+fn window_columns() -> Option<usize> {
+    None
+}
+
+/// A JSON scalar as table text; strings lose their quotes and missing values show as "-".
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ping::PingResult;
+    use monkey_troop_shared::{EngineInfo, TransactionsResponse};
+    use serde_json::json;
+
+    fn node(id: &str, status: &str, vram_free: u64, models: &[&str]) -> NodeHeartbeat {
+        serde_json::from_value(json!({
+            "node_id": id,
+            "tailscale_ip": "100.64.0.1",
+            "status": status,
+            "models": models
+                .iter()
+                .map(|m| json!({"name": m, "content_hash": "sha256:abc", "size_bytes": 1}))
+                .collect::<Vec<_>>(),
+            "hardware": {"gpu": "RTX 4090", "vram_free": vram_free},
+            "engines": []
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_nodes_table_truncates_model_lists_unless_wide() {
+        let nodes = [
+            node(
+                "node-1",
+                "IDLE",
+                24576,
+                &["llama3:8b", "qwen2.5:14b", "mistral:7b"],
+            ),
+            node("gpu-box-long-name", "BUSY", 0, &[]),
+        ];
+
+        assert_eq!(
+            nodes_table(&nodes, false).aligned(None),
+            "NODE ID            STATUS  GPU       VRAM FREE  TAILSCALE IP  MODELS  SERVING\n\
+             node-1             IDLE    RTX 4090  24.0 GB    100.64.0.1    3       llama3:8b, qwen2.5:14b, ...\n\
+             gpu-box-long-name  BUSY    RTX 4090  0.0 GB     100.64.0.1    0       -"
+        );
+        assert!(nodes_table(&nodes, true)
+            .aligned(None)
+            .contains("llama3:8b, qwen2.5:14b, mistral:7b"));
+    }
+
+    #[test]
+    fn test_select_nodes_filters_and_sorts_by_free_vram() {
+        let nodes = vec![
+            node("small", "IDLE", 8192, &["llama3:8b"]),
+            node("busy", "BUSY", 81920, &["llama3:8b"]),
+            node("big", "IDLE", 49152, &["llama3:8b", "llama3:70b"]),
+        ];
+        let ids = |nodes: Vec<NodeHeartbeat>| {
+            nodes
+                .into_iter()
+                .map(|node| node.node_id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            ids(select_nodes(nodes.clone(), None, None)),
+            ["busy", "big", "small"]
+        );
+        assert_eq!(
+            ids(select_nodes(nodes.clone(), Some("llama3:70b"), None)),
+            ["big"]
+        );
+        assert_eq!(
+            ids(select_nodes(
+                nodes,
+                Some("llama3:8b"),
+                Some(&NodeStatus::Idle)
+            )),
+            ["big", "small"]
+        );
+    }
+
+    #[test]
+    fn test_ping_table_flags_unreachable_targets() {
+        let result = |target: &str, address: &str, ms: Option<[f64; 3]>, failures| PingResult {
+            target: target.to_string(),
+            address: address.to_string(),
+            min_ms: ms.map(|ms| ms[0]),
+            avg_ms: ms.map(|ms| ms[1]),
+            max_ms: ms.map(|ms| ms[2]),
+            samples: 5,
+            failures,
+        };
+        let report = PingReport {
+            coordinators: vec![result(
+                "coordinator",
+                "https://troop.example/",
+                Some([20.0, 25.5, 31.04]),
+                0,
+            )],
+            nodes: Some(vec![
+                result("node-1", "100.64.0.1:8080", Some([9.0, 12.34, 20.0]), 1),
+                result("node-2", "100.64.0.2:8080", None, 5),
+            ]),
+        };
+
+        assert_eq!(
+            ping_table(&report).aligned(None),
+            "TARGET       ADDRESS                 MIN          AVG      MAX      LOST\n\
+             coordinator  https://troop.example/  20.0 ms      25.5 ms  31.0 ms  0/5\n\
+             node-1       100.64.0.1:8080         9.0 ms       12.3 ms  20.0 ms  1/5\n\
+             node-2       100.64.0.2:8080         UNREACHABLE  -        -        5/5"
+        );
+    }
+
+    #[test]
+    fn test_status_table_shows_what_the_proxy_reported() {
+        let mut status = ProxyStatus {
+            url: "http://localhost:9000".to_string(),
+            state: State::Running,
+            problem: None,
+            uptime_secs: Some(3725),
+            coordinator_reachable: Some(true),
+            available_models: Some(4),
+            last_error: Some(crate::stats::LastError {
+                at: "2026-10-15T12:00:00Z".parse().unwrap(),
+                model: "llama3:8b".to_string(),
+                status: 503,
+                message: "No nodes available".to_string(),
+            }),
+        };
+        assert_eq!(
+            status_table(&status).plain(),
+            "RUNNING\thttp://localhost:9000\t1h 2m\treachable\t4\t\
+             2026-10-15 12:00:00 503 llama3:8b: No nodes available"
+        );
+
+        status.state = State::NotRunning;
+        status.uptime_secs = None;
+        status.coordinator_reachable = None;
+        status.available_models = None;
+        status.last_error = None;
+        assert_eq!(
+            status_table(&status).plain(),
+            "NOT RUNNING\thttp://localhost:9000\t-\t-\t-\t-"
+        );
+
+        assert_eq!(duration_text(0), "0s");
+        assert_eq!(duration_text(59), "59s");
+        assert_eq!(duration_text(3 * 86_400 + 4 * 3_600 + 5), "3d 4h");
+    }
+
+    #[test]
+    fn test_models_table_summarizes_hosting_nodes() {
+        let data = ["llama3:8b", "llama3:70b", "mistral:7b"].map(|id| {
+            json!({
+                "id": id,
+                "object": "model",
+                "owned_by": "monkey-troop",
+                "content_hash": "sha256:abc",
+                "size_bytes": 1
+            })
+        });
+        let models: ModelsResponse =
+            serde_json::from_value(json!({"object": "list", "data": data})).unwrap();
+        let engine = |kind: &str| EngineInfo {
+            engine_type: kind.to_string(),
+            version: "1.0".to_string(),
+            port: 11434,
+            healthy: true,
+        };
+        let mut busy = node("node-1", "BUSY", 49152, &["llama3:8b"]);
+        busy.engines = vec![engine("vllm"), engine("ollama")];
+        let mut idle = node("node-2", "IDLE", 8192, &["llama3:8b"]);
+        idle.engines = vec![engine("ollama")];
+        let peers = PeersResponse {
+            count: 3,
+            nodes: vec![
+                busy,
+                idle,
+                node("node-3", "OFFLINE", 81920, &["llama3:70b"]),
+            ],
+        };
+
+        let availability = model_availability(&models, &peers);
+        assert_eq!(
+            models_table(&availability).aligned(None),
+            "MODEL       NODES        IDLE  BEST VRAM FREE  ENGINES\n\
+             llama3:8b   2            1     48.0 GB         ollama, vllm\n\
+             llama3:70b  NONE ONLINE  0     -               -\n\
+             mistral:7b  NONE ONLINE  0     -               -"
+        );
+        assert_eq!(
+            model_nodes_table(&availability[0]).aligned(None),
+            "NODE ID  STATUS  GPU       VRAM FREE  ENGINES\n\
+             node-2   IDLE    RTX 4090  8.0 GB     ollama\n\
+             node-1   BUSY    RTX 4090  48.0 GB    vllm, ollama"
+        );
+    }
+
+    #[test]
+    fn test_config_table() {
+        let settings = [
+            Effective {
+                key: "proxy_port",
+                value: Some("9001".to_string()),
+                source: Source::File { line: 3 },
+            },
+            Effective {
+                key: "usage_file",
+                value: None,
+                source: Source::Default,
+            },
+        ];
+        assert_eq!(
+            config_table(&settings).aligned(None),
+            "KEY         VALUE  SOURCE\n\
+             proxy_port  9001   file, line 3\n\
+             usage_file  -      default"
+        );
+    }
+
+    #[test]
+    fn test_empty_table_has_only_headers() {
+        assert_eq!(
+            transactions_table(&[], "me").aligned(None),
+            "TIMESTAMP  TYPE  CREDITS  COUNTERPARTY  MODEL"
+        );
+    }
+
+    #[test]
+    fn test_transactions_table_is_from_my_point_of_view() {
+        let response: TransactionsResponse = serde_json::from_value(json!({
+            "transactions": [
+                {"id": 2, "requester": "me", "worker": "worker-pk", "credits": 120,
+                 "timestamp": "2026-01-02T09:30:00.123456", "type": "job_completion",
+                 "model": "llama3:8b"},
+                {"id": 1, "requester": null, "worker": "me", "credits": 3600,
+                 "timestamp": "2026-01-01T00:00:00", "type": "starter_grant"}
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            transactions_table(&response.transactions, "me").aligned(None),
+            "TIMESTAMP            TYPE            CREDITS  COUNTERPARTY  MODEL\n\
+             2026-01-02 09:30:00  job_completion  -120     worker-pk     llama3:8b\n\
+             2026-01-01 00:00:00  starter_grant   +3600    -             -"
+        );
+    }
+
+    #[test]
+    fn test_transactions_csv_has_every_field_escaped() {
+        let response: TransactionsResponse = serde_json::from_value(json!({
+            "transactions": [
+                {"id": 2, "requester": "me", "worker": "worker-pk", "credits": 120,
+                 "timestamp": "2026-01-02T09:30:00.123456", "type": "job_completion",
+                 "model": "org/model, \"tuned\""},
+                {"id": null, "requester": null, "worker": "me", "credits": 3600,
+                 "timestamp": "2026-01-01T00:00:00", "type": "starter_grant"}
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            transactions_csv(&response.transactions, "me"),
+            "id,timestamp,type,credits,balance_change,requester,worker,model\r\n\
+             2,2026-01-02T09:30:00.123456Z,job_completion,120,-120,me,worker-pk,\
+             \"org/model, \"\"tuned\"\"\"\r\n\
+             ,2026-01-01T00:00:00Z,starter_grant,3600,3600,,me,"
+        );
+    }
+
+    #[test]
+    fn test_table_csv_keeps_the_headers() {
+        let table = Table::new(
+            &["NODE ID", "SERVING"],
+            vec![vec![
+                "node-1".to_string(),
+                "llama3:8b, qwen2.5:14b".to_string(),
+            ]],
+        );
+        assert_eq!(
+            table.csv(),
+            "NODE ID,SERVING\r\nnode-1,\"llama3:8b, qwen2.5:14b\""
+        );
+    }
+
+    #[test]
+    fn test_narrow_terminal_cuts_the_widest_columns() {
+        let table = Table::new(
+            &["NODE ID", "STATUS", "SERVING"],
+            vec![
+                vec![
+                    "gpu-box-long-name".to_string(),
+                    "IDLE".to_string(),
+                    "llama3:8b, qwen2.5:14b".to_string(),
+                ],
+                vec!["node-1".to_string(), "BUSY".to_string(), "-".to_string()],
+            ],
+        );
+
+        assert_eq!(
+            table.aligned(Some(40)),
+            "NODE ID          STATUS  SERVING\n\
+             gpu-box-long-n…  IDLE    llama3:8b, qwe…\n\
+             node-1           BUSY    -"
+        );
+        // Columns never get narrower than their header, or MIN_COLUMN_WIDTH
+        assert!(table
+            .aligned(Some(10))
+            .starts_with("NODE ID   STATUS  SERVING\n"));
+        assert_eq!(table.aligned(Some(80)), table.aligned(None));
+        assert_eq!(
+            table.plain(),
+            "gpu-box-long-name\tIDLE\tllama3:8b, qwen2.5:14b\nnode-1\tBUSY\t-"
+        );
+    }
+}
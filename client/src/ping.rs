@@ -0,0 +1,195 @@
+//! Round-trip latency to the coordinators and worker nodes, for the `ping` command.
+//!
+//! Each target's `/health` is requested a few times in a row and the fastest, average and
+//! slowest answers are reported. Targets are pinged concurrently against one deadline, so
+//! a target that never answers is reported as unreachable instead of holding up the rest.
+
+use futures::future::join_all;
+use monkey_troop_shared::{http_client, NodeHeartbeat, DISCOVERY_TIMEOUT};
+use serde::Serialize;
+use std::time::Duration;
+use tokio::time::Instant;
+use url::Url;
+
+/// What to call the coordinators in the `target` column.
+pub const COORDINATOR: &str = "coordinator";
+
+#[derive(Debug, Serialize)]
+pub struct PingResult {
+    /// `COORDINATOR`, or the node's ID
+    pub target: String,
+    pub address: String,
+    /// Latency over the answered samples; all `None` when the target never answered
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    pub samples: u32,
+    /// Samples that failed, or were never sent because the deadline passed
+    pub failures: u32,
+}
+
+/// Everything `ping` measured; `nodes` only when they were asked for.
+#[derive(Debug, Serialize)]
+pub struct PingReport {
+    pub coordinators: Vec<PingResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nodes: Option<Vec<PingResult>>,
+}
+
+impl PingReport {
+    /// Coordinators first, then nodes.
+    pub fn results(&self) -> impl Iterator<Item = &PingResult> {
+        self.coordinators.iter().chain(self.nodes.iter().flatten())
+    }
+}
+
+/// Ping each coordinator's `/health`, `samples` times, until `deadline`.
+pub async fn ping_coordinators(urls: &[Url], samples: u32, deadline: Instant) -> Vec<PingResult> {
+    let client = http_client(DISCOVERY_TIMEOUT);
+    join_all(urls.iter().map(|url| {
+        let health = url.join("health").map(String::from).unwrap_or_default();
+        ping(
+            &client,
+            COORDINATOR.to_string(),
+            url.to_string(),
+            health,
+            samples,
+            deadline,
+        )
+    }))
+    .await
+}
+
+/// Ping each node's `/health` on `worker_port`, `samples` times, until `deadline`.
+pub async fn ping_nodes(
+    nodes: &[NodeHeartbeat],
+    worker_port: u16,
+    samples: u32,
+    deadline: Instant,
+) -> Vec<PingResult> {
+    let client = http_client(DISCOVERY_TIMEOUT);
+    join_all(nodes.iter().map(|node| {
+        let address = format!("{}:{}", node.tailscale_ip, worker_port);
+        let health = format!("http://{address}/health");
+        ping(
+            &client,
+            node.node_id.clone(),
+            address,
+            health,
+            samples,
+            deadline,
+        )
+    }))
+    .await
+}
+
+async fn ping(
+    client: &reqwest::Client,
+    target: String,
+    address: String,
+    url: String,
+    samples: u32,
+    deadline: Instant,
+) -> PingResult {
+    let mut latencies = Vec::new();
+    for _ in 0..samples {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        let started = Instant::now();
+        let answered = client
+            .get(&url)
+            .timeout(remaining.min(DISCOVERY_TIMEOUT))
+            .send()
+            .await
+            .is_ok_and(|r| r.status().is_success());
+        if answered {
+            latencies.push(started.elapsed());
+        }
+    }
+
+    let ms = |latency: Duration| latency.as_secs_f64() * 1000.0;
+    let total: Duration = latencies.iter().sum();
+    PingResult {
+        target,
+        address,
+        min_ms: latencies.iter().min().copied().map(ms),
+        avg_ms: u32::try_from(latencies.len())
+            .ok()
+            .filter(|&answered| answered > 0)
+            .map(|answered| ms(total / answered)),
+        max_ms: latencies.iter().max().copied().map(ms),
+        samples,
+        failures: samples - latencies.len() as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    fn node(id: &str, ip: &str) -> NodeHeartbeat {
+        serde_json::from_value(json!({
+            "node_id": id,
+            "tailscale_ip": ip,
+            "status": "IDLE",
+            "models": [],
+            "hardware": {"gpu": "RTX 4090", "vram_free": 24576},
+            "engines": []
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_node_has_no_latency() {
+        let worker = MockServer::start();
+        let health = worker.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200).json_body(json!({"status": "healthy"}));
+        });
+        let nodes = [
+            node("up", "127.0.0.1"),
+            // The mock server only listens on 127.0.0.1
+            node("down", "127.0.0.2"),
+        ];
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let results = ping_nodes(&nodes, worker.port(), 3, deadline).await;
+
+        health.assert_calls(3);
+        assert_eq!(results[0].target, "up");
+        let (min, avg, max) = (
+            results[0].min_ms.unwrap(),
+            results[0].avg_ms.unwrap(),
+            results[0].max_ms.unwrap(),
+        );
+        assert!(min <= avg && avg <= max);
+        assert_eq!(results[0].failures, 0);
+        assert_eq!(results[1].min_ms, None);
+        assert_eq!(results[1].avg_ms, None);
+        assert_eq!(results[1].failures, 3);
+    }
+
+    #[tokio::test]
+    async fn test_deadline_cuts_a_slow_target_short() {
+        let coordinator = MockServer::start();
+        let health = coordinator.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200).delay(Duration::from_secs(5));
+        });
+        let urls = [Url::parse(&coordinator.base_url()).unwrap()];
+
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(300);
+        let results = ping_coordinators(&urls, 5, deadline).await;
+
+        assert!(started.elapsed() < Duration::from_secs(2));
+        health.assert_calls(1);
+        assert_eq!(results[0].target, COORDINATOR);
+        assert_eq!(results[0].max_ms, None);
+        assert_eq!(results[0].failures, 5);
+    }
+}
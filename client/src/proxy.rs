@@ -1,24 +1,76 @@
+use crate::anthropic;
+use crate::audit::{AuditLogger, AuditMessage, AuditRecord};
+use crate::batch;
+use crate::concurrency::{self, ConcurrencyLimit};
 use crate::config::Config;
-use anyhow::Result;
+use crate::coordinators::{Coordinators, COORDINATOR_RETRY_POLICY};
+use crate::encoding;
+use crate::fan_out;
+use crate::hedging;
+use crate::node_breakers::{NodeBreakers, NODE_BREAKER_TTL};
+use crate::response_cache::{ResponseCache, CACHE_HEADER};
+use crate::routing;
+use crate::sessions::{SessionAffinity, MAX_SESSIONS, SESSION_HEADER, SESSION_TTL};
+use crate::shutdown::{shutdown_signal, Shutdown};
+use crate::stats::{StatsReport, StatsTracker};
+use crate::streams::{self, FIRST_CHUNK_WAIT};
+use crate::usage::{self, StreamMeter, TokenCounts, UsageReport, UsageTracker};
+use anyhow::{Context, Result};
 
-use axum::http::HeaderName;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
 use axum::{
     extract::State,
     http::StatusCode,
+    middleware::{self, Next},
     response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
+use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
 use futures::StreamExt;
 use monkey_troop_shared::{
-    retry_with_backoff, AuthorizeRequest, AuthorizeResponse, ChatCompletionRequest, ModelsResponse,
-    TroopError, TroopResult, AUTH_TIMEOUT, INFERENCE_TIMEOUT,
+    passthrough_http_client, retry_with_deadline, retry_with_policy_until, Admission, ApiErrorBody,
+    AuthorizeRequest, AuthorizeResponse, ChatCompletionRequest, CircuitState, EmbeddingsRequest,
+    ModelInfo, ModelsResponse, NodeStatus, PeersResponse, TroopError, TroopResult, AUTH_TIMEOUT,
+    CIRCUIT_BREAKER_THRESHOLD, CIRCUIT_BREAKER_TIMEOUT, COORDINATOR_HINT, DEADLINE_HEADER,
+    INFERENCE_TIMEOUT, REQUEST_ID_HEADER,
 };
+use serde::Serialize;
 use std::collections::HashSet;
+use std::future::{Future, IntoFuture};
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tracing::{error, info};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{error, info, info_span, warn, Instrument};
 use url::Url;
 
+/// Longest caller-supplied request ID we are willing to propagate.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Header callers use to override the worker timeout for a single request.
+const TIMEOUT_HEADER: &str = "x-troop-timeout-secs";
+
+/// How many nodes to try authorizing before giving up because all their circuits are open.
+const MAX_NODE_ATTEMPTS: usize = 3;
+
+/// How long a readiness result is reused before the coordinator is probed again.
+const READINESS_CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// How long `/health` reuses the node counts from the coordinator's `/peers`.
+const PEER_COUNTS_TTL: Duration = Duration::from_secs(5);
+
+/// What `/health` names the proxy as, so `status` can tell it from other services.
+pub const SERVICE_NAME: &str = "monkey-troop-client";
+
+/// How long `/health` waits for each coordinator's `/peers`.
+const PEER_COUNTS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long without any coordinator answer before the proxy reports itself not ready.
+const COORDINATOR_UNREACHABLE_AFTER: Duration = Duration::from_secs(30);
+
+/// How long terminated streams get to flush their final event before connections are dropped.
+const STREAM_TERMINATION_GRACE: Duration = Duration::from_secs(1);
+
 // Standard HTTP hop-by-hop headers that must not be forwarded by a proxy (RFC 7230).
 const HOP_BY_HOP: &[&str] = &[
     "connection",
@@ -64,89 +116,1105 @@ fn copy_end_to_end_headers(src: &axum::http::HeaderMap, dst: &mut axum::http::He
     }
 }
 
+pub struct ProxyState {
+    pub config: Config,
+    pub coordinators: Coordinators,
+    /// HTTP client shared by all requests to workers
+    pub http: reqwest::Client,
+    /// Present only when `MAX_CONCURRENT_REQUESTS` is configured
+    pub concurrency: Option<ConcurrencyLimit>,
+    pub audit: Option<AuditLogger>,
+    /// Present only when `ENABLE_RESPONSE_CACHE` is set
+    pub response_cache: Option<ResponseCache>,
+    pub node_breakers: NodeBreakers,
+    pub sessions: SessionAffinity,
+    pub shutdown: Shutdown,
+    pub usage: Arc<UsageTracker>,
+    pub stats: StatsTracker,
+    /// When `/health` last asked for `/peers`, and the counts it got (`None` on failure)
+    peer_counts: tokio::sync::Mutex<Option<(Instant, Option<PeerCounts>)>>,
+}
+
+/// Nodes in the swarm as the coordinator's `/peers` reports them, for `/health`.
+#[derive(Debug, Clone, Copy, Serialize)]
+struct PeerCounts {
+    /// Nodes that are online, idle or busy
+    available_nodes: usize,
+    idle_nodes: usize,
+    /// Distinct models served by the available nodes
+    available_models: usize,
+}
+
+impl ProxyState {
+    pub fn new(config: Config) -> Self {
+        let audit = config.audit_log_path.clone().map(|path| {
+            info!("Audit log enabled: {}", path.display());
+            AuditLogger::spawn(path, config.audit_log_include_content)
+        });
+        Self {
+            coordinators: Coordinators::new(config.coordinator_urls.clone()),
+            http: passthrough_http_client(INFERENCE_TIMEOUT),
+            concurrency: config.max_concurrent_requests.map(|limit| {
+                info!(
+                    "Concurrency limit: {} requests, {} queued",
+                    limit, config.max_queued_requests
+                );
+                ConcurrencyLimit::new(limit, config.max_queued_requests, config.queue_timeout)
+            }),
+            usage: UsageTracker::open(config.usage_file.clone()),
+            response_cache: config.enable_response_cache.then(|| {
+                info!(
+                    "Response cache: {} replies for {}s",
+                    config.response_cache_size,
+                    config.response_cache_ttl.as_secs()
+                );
+                ResponseCache::new(config.response_cache_ttl, config.response_cache_size)
+            }),
+            config,
+            audit,
+            node_breakers: NodeBreakers::new(
+                CIRCUIT_BREAKER_THRESHOLD,
+                CIRCUIT_BREAKER_TIMEOUT,
+                NODE_BREAKER_TTL,
+            ),
+            sessions: SessionAffinity::new(SESSION_TTL, MAX_SESSIONS),
+            shutdown: Shutdown::default(),
+            stats: StatsTracker::default(),
+            peer_counts: tokio::sync::Mutex::new(None),
+        }
+    }
+}
+
 pub async fn run_proxy_server(config: Config) -> Result<()> {
-    let addr = format!("127.0.0.1:{}", config.proxy_port);
-    info!("Starting OpenAI-compatible proxy on {}", addr);
+    let tls = match &config.proxy_tls {
+        Some(files) => Some(
+            RustlsConfig::from_pem_file(&files.cert, &files.key)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to load TLS certificate {} and key {}",
+                        files.cert.display(),
+                        files.key.display()
+                    )
+                })?,
+        ),
+        None => None,
+    };
+
+    let addr = SocketAddr::new(config.proxy_bind, config.proxy_port);
+    if config.proxy_tcp {
+        info!("Starting OpenAI-compatible proxy on {}", addr);
+        info!("   Point your AI tools to: {}/v1", config.proxy_url());
+    }
+
+    let state = Arc::new(ProxyState::new(config));
     info!(
-        "   Point your AI tools to: http://localhost:{}/v1",
-        config.proxy_port
+        "Coordinator failover order: {:?}",
+        state.coordinators.status().failover_order
     );
+    let mut listeners = Vec::new();
+    if state.config.proxy_tcp {
+        listeners.push(ProxyListener::Tcp(
+            tokio::net::TcpListener::bind(&addr).await?,
+        ));
+        info!("Proxy ready at {}", state.config.proxy_url());
+    }
+    #[cfg(unix)]
+    if let Some(path) = &state.config.proxy_unix_socket {
+        listeners.push(ProxyListener::Unix(
+            crate::unix_socket::bind(path, state.config.proxy_unix_socket_mode).await?,
+        ));
+        info!("Proxy ready on unix socket {}", path.display());
+    }
+
+    let result = serve_with_drain(listeners, tls, state.clone(), shutdown_signal()).await;
+    #[cfg(unix)]
+    if let Some(path) = &state.config.proxy_unix_socket {
+        crate::unix_socket::remove(path);
+    }
+    result
+}
 
-    let shared_config = Arc::new(config);
+/// A socket the proxy accepts connections on.
+enum ProxyListener {
+    Tcp(tokio::net::TcpListener),
+    /// Always plain HTTP: the socket is local, and its file permissions restrict access
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
+}
 
-    let app = Router::new()
-        .route("/v1/chat/completions", post(chat_completions_handler))
-        .route("/v1/models", get(list_models_handler))
-        .route("/health", get(health_handler))
-        .with_state(shared_config.clone());
+/// Serve on every listener until `signal` resolves, over TLS on TCP when `tls` is set,
+/// then stop accepting connections and let in-flight requests finish within the
+/// configured drain timeout. Returns an error if requests were still running when the
+/// timeout elapsed.
+async fn serve_with_drain(
+    listeners: Vec<ProxyListener>,
+    tls: Option<RustlsConfig>,
+    state: Arc<ProxyState>,
+    signal: impl Future<Output = ()>,
+) -> Result<()> {
+    let drain_timeout = state.config.shutdown_drain_timeout;
+    let (stop_tx, _) = tokio::sync::watch::channel(false);
+    let router = create_proxy_router(state.clone());
+    let mut servers = tokio::task::JoinSet::new();
+    for listener in listeners {
+        let mut stop_rx = stop_tx.subscribe();
+        let stopped = async move {
+            let _ = stop_rx.wait_for(|stop| *stop).await;
+        };
+        match (listener, &tls) {
+            (ProxyListener::Tcp(listener), None) => servers.spawn(
+                axum::serve(listener, router.clone())
+                    .with_graceful_shutdown(stopped)
+                    .into_future(),
+            ),
+            (ProxyListener::Tcp(listener), Some(tls)) => {
+                let handle = axum_server::Handle::<SocketAddr>::new();
+                tokio::spawn({
+                    let handle = handle.clone();
+                    async move {
+                        stopped.await;
+                        handle.graceful_shutdown(None);
+                    }
+                });
+                servers.spawn(
+                    axum_server::Server::from_listener(listener)
+                        .acceptor(RustlsAcceptor::new(tls.clone()))
+                        .handle(handle)
+                        .serve(router.clone().into_make_service()),
+                )
+            }
+            #[cfg(unix)]
+            (ProxyListener::Unix(listener), _) => servers.spawn(
+                axum::serve(listener, router.clone())
+                    .with_graceful_shutdown(stopped)
+                    .into_future(),
+            ),
+        };
+    }
+
+    tokio::select! {
+        res = all_stopped(&mut servers) => return res,
+        _ = signal => {}
+    }
 
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
     info!(
-        "Proxy ready at http://localhost:{}",
-        shared_config.proxy_port
+        "Shutting down: draining {} in-flight request(s) (timeout {:?})",
+        state.shutdown.in_flight(),
+        drain_timeout
     );
+    stop_tx.send_replace(true);
+    if let Ok(res) = tokio::time::timeout(drain_timeout, all_stopped(&mut servers)).await {
+        res?;
+        info!("All requests drained, proxy stopped");
+        return Ok(());
+    }
 
-    axum::serve(listener, app).await?;
+    let remaining = state.shutdown.in_flight();
+    warn!(
+        "Drain timeout elapsed with {} request(s) in flight, terminating open streams",
+        remaining
+    );
+    state.shutdown.expire_drain();
+    if tokio::time::timeout(STREAM_TERMINATION_GRACE, all_stopped(&mut servers))
+        .await
+        .is_err()
+    {
+        servers.abort_all();
+    }
+    anyhow::bail!("Proxy stopped with {remaining} request(s) still in flight")
+}
 
+/// Wait for every server to stop, failing as soon as one fails.
+async fn all_stopped(servers: &mut tokio::task::JoinSet<std::io::Result<()>>) -> Result<()> {
+    while let Some(res) = servers.join_next().await {
+        res??;
+    }
     Ok(())
 }
 
-async fn health_handler() -> impl IntoResponse {
+pub fn create_proxy_router(state: Arc<ProxyState>) -> Router {
+    Router::new()
+        .route("/v1/chat/completions", post(chat_completions_handler))
+        .route("/v1/embeddings", post(embeddings_handler))
+        .route("/v1/messages", post(anthropic::messages_handler))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            limit_concurrency,
+        ))
+        // Limited per request it runs rather than as a whole; see `batch_handler`
+        .route("/v1/batch", post(batch::batch_handler))
+        .route("/v1/models", get(list_models_handler))
+        .route("/v1/route", get(routing::route_handler))
+        .route("/health", get(health_handler))
+        .route("/health/ready", get(readiness_handler))
+        .route("/usage", get(usage_handler).delete(reset_usage_handler))
+        .route("/stats", get(stats_handler))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            track_in_flight,
+        ))
+        .with_state(state)
+}
+
+/// Count each request as in flight until its response body has been fully sent.
+async fn track_in_flight(
+    State(state): State<Arc<ProxyState>>,
+    request: axum::extract::Request,
+    next: Next,
+) -> Response {
+    let guard = state.shutdown.begin_request();
+    let (parts, body) = next.run(request).await.into_parts();
+    Response::from_parts(parts, guard.hold_until_sent(body))
+}
+
+/// Hold a concurrency slot for the whole exchange, when a limit is configured.
+async fn limit_concurrency(
+    State(state): State<Arc<ProxyState>>,
+    request: axum::extract::Request,
+    next: Next,
+) -> Response {
+    let Some(limit) = &state.concurrency else {
+        return next.run(request).await;
+    };
+    let permit = match limit.acquire().await {
+        Ok(permit) => permit,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let (parts, body) = next.run(request).await.into_parts();
+    Response::from_parts(parts, concurrency::hold_until_sent(permit, body))
+}
+
+/// Per-request settings taken from the caller's headers.
+#[derive(Clone)]
+pub(crate) struct RequestContext {
+    pub(crate) id: String,
+    timeout: Duration,
+    /// When the request runs out of `timeout`, counted from its arrival; retries and
+    /// hedges share it, and workers are told it so they stop when the proxy does
+    deadline: tokio::time::Instant,
+    /// `X-Troop-Session`, when the caller wants the conversation kept on one node
+    pub(crate) session: Option<String>,
+    /// The caller's `Accept-Encoding`, forwarded to workers whose reply is relayed as is
+    pub(crate) accept_encoding: Option<String>,
+    /// The reply is streamed, so it is held back until the worker's first chunk and a
+    /// worker that fails before sending one is retried
+    stream: bool,
+}
+
+impl RequestContext {
+    pub(crate) fn from_headers(headers: &HeaderMap, config: &Config) -> Self {
+        let timeout = resolve_timeout(headers, config);
+        Self {
+            id: resolve_request_id(headers),
+            timeout,
+            deadline: tokio::time::Instant::now() + timeout,
+            session: header_token(headers, SESSION_HEADER),
+            accept_encoding: headers
+                .get(header::ACCEPT_ENCODING)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+            stream: false,
+        }
+    }
+}
+
+/// `deadline` as unix milliseconds, the form `X-Troop-Deadline` carries it in.
+fn deadline_millis(deadline: tokio::time::Instant) -> u128 {
+    let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+    (SystemTime::now() + remaining)
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+/// Value of `name` when it is a sane token: non-empty and at most `MAX_REQUEST_ID_LEN` long.
+fn header_token(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|token| !token.is_empty() && token.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_string)
+}
+
+/// Reuse the caller's `X-Request-Id` when it is a sane token, otherwise mint a fresh UUID.
+pub(crate) fn resolve_request_id(headers: &HeaderMap) -> String {
+    header_token(headers, REQUEST_ID_HEADER).unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Worker timeout for this request: `X-Troop-Timeout-Secs` clamped to the configured
+/// bounds, or `INFERENCE_TIMEOUT` when the header is missing or not a positive integer.
+fn resolve_timeout(headers: &HeaderMap, config: &Config) -> Duration {
+    headers
+        .get(TIMEOUT_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .filter(|&secs| secs > 0)
+        .map(|secs| {
+            Duration::from_secs(secs)
+                .max(config.min_request_timeout)
+                .min(config.max_request_timeout)
+        })
+        .unwrap_or(INFERENCE_TIMEOUT)
+}
+
+async fn health_handler(State(state): State<Arc<ProxyState>>) -> impl IntoResponse {
+    let peers = peer_counts(&state).await;
     Json(serde_json::json!({
         "status": "healthy",
-        "service": "monkey-troop-client"
+        "service": SERVICE_NAME,
+        "uptime_secs": state.stats.uptime().as_secs(),
+        "coordinator_reachable": peers.is_some(),
+        "available_nodes": peers.map(|p| p.available_nodes),
+        "idle_nodes": peers.map(|p| p.idle_nodes),
+        "available_models": peers.map(|p| p.available_models),
+        "coordinators": state.coordinators.status(),
+        "concurrency": state.concurrency.as_ref().map(ConcurrencyLimit::status),
+        "sessions": state.sessions.count(),
     }))
 }
 
+/// Node counts from the coordinator's `/peers`, reused for `PEER_COUNTS_TTL` so frequent
+/// health checks do not turn into coordinator traffic. Concurrent callers share one
+/// fetch, and a failure is remembered for the same time. `None` when no coordinator
+/// answered.
+async fn peer_counts(state: &ProxyState) -> Option<PeerCounts> {
+    let mut cached = state.peer_counts.lock().await;
+    if let Some((fetched_at, counts)) = *cached {
+        if fetched_at.elapsed() < PEER_COUNTS_TTL {
+            return counts;
+        }
+    }
+
+    let counts = match state
+        .coordinators
+        .get_json_once::<PeersResponse>("peers", PEER_COUNTS_TIMEOUT)
+        .await
+    {
+        Ok(peers) => {
+            let available: Vec<_> = peers
+                .nodes
+                .iter()
+                .filter(|node| !matches!(node.status, NodeStatus::Offline))
+                .collect();
+            Some(PeerCounts {
+                available_nodes: available.len(),
+                idle_nodes: available
+                    .iter()
+                    .filter(|node| matches!(node.status, NodeStatus::Idle))
+                    .count(),
+                available_models: available
+                    .iter()
+                    .flat_map(|node| &node.models)
+                    .map(|model| model.name.as_str())
+                    .collect::<HashSet<_>>()
+                    .len(),
+            })
+        }
+        Err(e) => {
+            warn!("Health check could not list peers: {}", e);
+            None
+        }
+    };
+    *cached = Some((Instant::now(), counts));
+    counts
+}
+
+/// Readiness: 503 once no coordinator has answered for `COORDINATOR_UNREACHABLE_AFTER`.
+async fn readiness_handler(State(state): State<Arc<ProxyState>>) -> Response {
+    state
+        .coordinators
+        .refresh_contact(READINESS_CACHE_TTL)
+        .await;
+    let last_contact = state.coordinators.last_contact();
+    let ready = state
+        .coordinators
+        .contacted_within(COORDINATOR_UNREACHABLE_AFTER);
+    if !ready {
+        warn!(
+            "Not ready: no coordinator has answered since {:?}",
+            last_contact
+        );
+    }
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = Json(serde_json::json!({
+        "status": if ready { "ready" } else { "coordinator_unreachable" },
+        "coordinator": state.coordinators.active().as_str(),
+        "last_coordinator_contact": last_contact,
+        "proxy_port": state.config.proxy_port,
+        "requester_id": state.config.requester_id,
+    }));
+    (status, body).into_response()
+}
+
+async fn usage_handler(State(state): State<Arc<ProxyState>>) -> Json<UsageReport> {
+    Json(state.usage.report())
+}
+
+async fn reset_usage_handler(State(state): State<Arc<ProxyState>>) -> StatusCode {
+    info!("Resetting usage totals");
+    state.usage.reset();
+    StatusCode::NO_CONTENT
+}
+
+async fn stats_handler(State(state): State<Arc<ProxyState>>) -> Json<StatsReport> {
+    let circuits = state.node_breakers.states().await;
+    Json(state.stats.report(&circuits))
+}
+
 async fn list_models_handler(
-    State(config): State<Arc<Config>>,
-) -> Result<Json<ModelsResponse>, StatusCode> {
+    State(state): State<Arc<ProxyState>>,
+) -> Result<Json<ModelsResponse>, ProxyError> {
     info!("Fetching available models from coordinator");
 
-    let client = reqwest::Client::new();
-    let url = config.coordinator_url.join("v1/models").map_err(|e| {
-        error!(
-            "Failed to construct models URL from base '{}' and path 'v1/models': {}",
-            config.coordinator_url, e
-        );
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    let mut models: ModelsResponse =
+        state
+            .coordinators
+            .get_json("v1/models")
+            .await
+            .inspect_err(|e| {
+                error!("Failed to fetch models from coordinator: {}", e);
+            })?;
 
-    let url_str = url.to_string();
-    let response = client.get(url).send().await.map_err(|e| {
-        error!("Failed to fetch models from '{}': {}", url_str, e);
-        StatusCode::BAD_GATEWAY
-    })?;
+    // Hide models this proxy refuses to serve; their aliases go with them
+    models
+        .data
+        .retain(|m| state.config.model_filter.allows(&m.id));
 
-    let models: ModelsResponse = response.json().await.map_err(|e| {
-        error!("Failed to deserialize models response: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    // List each alias whose target is available as a model of its own
+    let aliased: Vec<ModelInfo> = state
+        .config
+        .model_aliases
+        .iter()
+        .filter_map(|(alias, target)| {
+            let model = models.data.iter().find(|m| &m.id == target)?;
+            Some(ModelInfo {
+                id: alias.clone(),
+                ..model.clone()
+            })
+        })
+        .collect();
+    models.data.extend(aliased);
 
     Ok(Json(models))
 }
 
+/// Refuse a model excluded by the allow/deny lists before anything is spent on it.
+pub(crate) fn check_model_allowed(config: &Config, model: &str) -> Result<(), ProxyError> {
+    config.model_filter.check(model).map_err(|message| {
+        warn!("{}", message);
+        ProxyError::ModelNotAllowed(message)
+    })
+}
+
+/// Rewrite an aliased model name to its target, logging both for debugging.
+pub(crate) fn apply_model_alias(config: &Config, model: &mut String) {
+    let resolved = config.resolve_model(model);
+    if resolved != model.as_str() {
+        let resolved = resolved.to_string();
+        info!("Model alias {} resolved to {}", model, resolved);
+        *model = resolved;
+    }
+}
+
+/// Hold `max_tokens` to `MAX_TOKENS_CAP`: a request without one gets the cap, and one
+/// asking for more is clamped to it. Requests within the cap are left alone.
+fn apply_max_tokens_cap(config: &Config, max_tokens: &mut Option<u32>) {
+    let Some(cap) = config.max_tokens_cap else {
+        return;
+    };
+    match *max_tokens {
+        None => {
+            info!("Request has no max_tokens, applying cap of {}", cap);
+            *max_tokens = Some(cap);
+        }
+        Some(requested) if requested > cap => {
+            info!("Clamping max_tokens {} to cap of {}", requested, cap);
+            *max_tokens = Some(cap);
+        }
+        Some(_) => {}
+    }
+}
+
+/// A failed proxy exchange, rendered as an OpenAI-style error response.
+pub(crate) enum ProxyError {
+    Status(StatusCode),
+    /// The model is excluded by `MODEL_ALLOWLIST` / `MODEL_DENYLIST`
+    ModelNotAllowed(String),
+    /// No node in the troop serves the model
+    ModelNotFound(String),
+    /// The worker did not respond within the request's timeout
+    Timeout(Duration),
+    Troop(TroopError),
+}
+
+impl From<StatusCode> for ProxyError {
+    fn from(status: StatusCode) -> Self {
+        ProxyError::Status(status)
+    }
+}
+
+impl From<TroopError> for ProxyError {
+    fn from(error: TroopError) -> Self {
+        ProxyError::Troop(error)
+    }
+}
+
+impl std::fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProxyError::Status(status) => write!(f, "{status}"),
+            ProxyError::ModelNotAllowed(message) | ProxyError::ModelNotFound(message) => {
+                write!(f, "{message}")
+            }
+            ProxyError::Timeout(timeout) => {
+                write!(f, "Worker did not respond within {}s", timeout.as_secs())
+            }
+            ProxyError::Troop(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+fn is_openai_error(body: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(body).is_ok_and(|v| v["error"].is_object())
+}
+
+impl IntoResponse for ProxyError {
+    fn into_response(self) -> Response {
+        let (status, body) = match self {
+            ProxyError::Status(status) => (
+                status,
+                ApiErrorBody::for_status(
+                    status.as_u16(),
+                    status.canonical_reason().unwrap_or("Request failed"),
+                ),
+            ),
+            ProxyError::ModelNotAllowed(message) => (
+                StatusCode::FORBIDDEN,
+                ApiErrorBody::new(message, "permission_error", "model_not_allowed"),
+            ),
+            ProxyError::ModelNotFound(message) => (
+                StatusCode::NOT_FOUND,
+                ApiErrorBody::new(message, "invalid_request_error", "model_not_found"),
+            ),
+            ProxyError::Timeout(timeout) => (
+                StatusCode::GATEWAY_TIMEOUT,
+                ApiErrorBody::new(
+                    format!("Worker did not respond within {}s", timeout.as_secs()),
+                    "timeout",
+                    "request_timeout",
+                ),
+            ),
+            // Upstream bodies that are already OpenAI-shaped are relayed untouched
+            ProxyError::Troop(TroopError::UpstreamError { status, body })
+                if is_openai_error(&body) =>
+            {
+                let status = StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY);
+                return (status, [(header::CONTENT_TYPE, "application/json")], body)
+                    .into_response();
+            }
+            ProxyError::Troop(error) => {
+                let mut response =
+                    (error.status_code(), Json(error.api_error_body())).into_response();
+                if let Some(delay) = error.retry_after() {
+                    response
+                        .headers_mut()
+                        .insert(header::RETRY_AFTER, HeaderValue::from(delay.as_secs()));
+                }
+                return response;
+            }
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+/// What the audit log needs to know about an exchange beyond the request itself.
+#[derive(Default)]
+struct ExchangeOutcome {
+    node_ip: Option<String>,
+    response_json: Option<serde_json::Value>,
+}
+
 async fn chat_completions_handler(
-    State(config): State<Arc<Config>>,
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
     Json(payload): Json<ChatCompletionRequest>,
-) -> Result<Response, StatusCode> {
-    info!(
-        "Received chat completion request for model: {}",
-        payload.model
+) -> Response {
+    let mut request = RequestContext::from_headers(&headers, &state.config);
+    if let (Some(max_n), Some(n)) = (
+        state.config.fan_out_max_n,
+        fan_out::requested_choices(&payload),
+    ) {
+        return fan_out_chat(&state, request, payload, n, max_n).await;
+    }
+    if payload.stream {
+        // Streams are metered and rewritten on the way through, so they must arrive plain
+        request.accept_encoding = None;
+    }
+    complete_chat(&state, request, payload).await.0
+}
+
+/// Run a chat completion from inside the client, as `/v1/chat/completions` would serve
+/// one arriving with no headers but `X-Troop-Session: session`. Also returns the node that
+/// served it, if the request got that far. Used by `chat` and `bench`.
+pub async fn chat_completion(
+    state: &ProxyState,
+    payload: ChatCompletionRequest,
+    session: Option<String>,
+) -> (Response, Option<String>) {
+    let mut request = RequestContext::from_headers(&HeaderMap::new(), &state.config);
+    request.session = session;
+    complete_chat(state, request, payload).await
+}
+
+/// Serve a request for `n` choices as `n` single-choice chat completions, all sent at
+/// once and merged into one reply. See `fan_out`.
+async fn fan_out_chat(
+    state: &ProxyState,
+    request: RequestContext,
+    mut payload: ChatCompletionRequest,
+    n: usize,
+    max_n: usize,
+) -> Response {
+    if let Err(e) = fan_out::check(n, max_n, payload.stream) {
+        return ProxyError::Troop(e).into_response();
+    }
+    payload.extra.remove("n");
+    info!("Request {}: fanning out {} choices", request.id, n);
+
+    let replies = futures::future::join_all((0..n).map(|index| {
+        // The replies are merged here, so they must arrive plain
+        let mut choice_request = request.clone();
+        choice_request.id = format!("{}-{index}", request.id);
+        choice_request.accept_encoding = None;
+        complete_chat(state, choice_request, payload.clone())
+    }))
+    .await;
+
+    let mut bodies = Vec::with_capacity(n);
+    let mut failed = None;
+    for (response, _) in replies {
+        if !response.status().is_success() {
+            // The first failure in choice order fails the whole request
+            failed = Some(response);
+            break;
+        }
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap_or_default();
+        match serde_json::from_slice(&body) {
+            Ok(body) => bodies.push(body),
+            Err(e) => {
+                error!("Worker reply is not a chat completion: {}", e);
+                failed = Some(ProxyError::from(StatusCode::BAD_GATEWAY).into_response());
+                break;
+            }
+        }
+    }
+
+    let mut response = failed.unwrap_or_else(|| Json(fan_out::merge(bodies)).into_response());
+    if let Ok(value) = HeaderValue::from_str(&request.id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Run a chat completion through authorization and a worker, recording usage and audit.
+/// Also returns the node that served it, if the request got that far.
+pub(crate) async fn complete_chat(
+    state: &ProxyState,
+    mut request: RequestContext,
+    mut payload: ChatCompletionRequest,
+) -> (Response, Option<String>) {
+    request.stream = payload.stream;
+    let span = info_span!("chat_completion", request_id = %request.id);
+
+    let started = Instant::now();
+    let mut outcome = ExchangeOutcome::default();
+    let result: Result<Response, ProxyError> = async {
+        info!(
+            "Received chat completion request for model: {}",
+            payload.model
+        );
+        apply_model_alias(&state.config, &mut payload.model);
+        check_model_allowed(&state.config, &payload.model)?;
+        apply_max_tokens_cap(&state.config, &mut payload.max_tokens);
+
+        let cache = state
+            .response_cache
+            .as_ref()
+            .zip(ResponseCache::key(&payload));
+        if let Some(body) = cache.and_then(|(cache, key)| cache.get(key)) {
+            info!("Serving a cached reply");
+            outcome.response_json = Some(body.clone());
+            let mut response = Json(body).into_response();
+            response
+                .headers_mut()
+                .insert(CACHE_HEADER, HeaderValue::from_static("hit"));
+            return Ok(response);
+        }
+        let mut response =
+            forward_chat_completion(state, &payload, &request, started, &mut outcome).await?;
+        if let Some((cache, key)) = cache {
+            if let (true, Some(body)) = (response.status().is_success(), &outcome.response_json) {
+                cache.insert(key, body.clone());
+            }
+            response
+                .headers_mut()
+                .insert(CACHE_HEADER, HeaderValue::from_static("miss"));
+        }
+        Ok(response)
+    }
+    .instrument(span)
+    .await;
+
+    let error = result.as_ref().err().map(ToString::to_string);
+    let mut response = result.unwrap_or_else(IntoResponse::into_response);
+
+    // Successful streams are metered and audited as they are relayed
+    if !(payload.stream && response.status().is_success()) {
+        record_usage(
+            &state.usage,
+            &payload.model,
+            usage::prompt_chars(&payload.messages),
+            &outcome,
+            response.status(),
+            started,
+        );
+        if let Some(ref audit) = state.audit {
+            audit.log(build_audit_record(
+                audit,
+                &payload,
+                &request.id,
+                &outcome,
+                response.status(),
+                started,
+            ));
+        }
+    }
+
+    let node_ip = outcome.node_ip.clone();
+    state.stats.record(
+        &payload.model,
+        node_ip.as_deref(),
+        response.status().is_success(),
+        started.elapsed(),
     );
+    if !response.status().is_success() {
+        // A node's own error reply is relayed as it came, so only its status is known here
+        state.stats.record_error(
+            &payload.model,
+            response.status().as_u16(),
+            error.unwrap_or_else(|| format!("Node answered {}", response.status())),
+        );
+    }
+    if let Ok(value) = HeaderValue::from_str(&request.id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    (response, node_ip)
+}
 
-    // Step 1: Discovery & Authorization (with retry)
-    let auth_response = match get_authorization(&config, &payload.model).await {
-        Ok(resp) => resp,
-        Err(e) => {
-            error!("Authorization failed: {}", e);
-            return Err(StatusCode::BAD_GATEWAY);
+async fn embeddings_handler(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(mut payload): Json<EmbeddingsRequest>,
+) -> Response {
+    let request = RequestContext::from_headers(&headers, &state.config);
+    let span = info_span!("embeddings", request_id = %request.id);
+
+    let started = Instant::now();
+    let mut outcome = ExchangeOutcome::default();
+    let result = async {
+        info!("Received embeddings request for model: {}", payload.model);
+        apply_model_alias(&state.config, &mut payload.model);
+        check_model_allowed(&state.config, &payload.model)?;
+        let (response, e2e_session) = dispatch_to_worker(
+            &state,
+            &payload.model,
+            "v1/embeddings",
+            &payload,
+            &request,
+            &mut outcome,
+        )
+        .await?;
+        Ok::<_, ProxyError>(
+            relay_json_response(response, e2e_session.as_ref(), &mut outcome).await?,
+        )
+    }
+    .instrument(span)
+    .await;
+
+    let mut response = result.unwrap_or_else(IntoResponse::into_response);
+    let input_chars = payload
+        .input
+        .clone()
+        .into_vec()
+        .iter()
+        .map(|text| text.chars().count())
+        .sum();
+    record_usage(
+        &state.usage,
+        &payload.model,
+        input_chars,
+        &outcome,
+        response.status(),
+        started,
+    );
+
+    if let Ok(value) = HeaderValue::from_str(&request.id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Tally a buffered exchange, preferring the engine's reported usage over an estimate.
+/// Failed requests are counted without tokens.
+fn record_usage(
+    tracker: &UsageTracker,
+    model: &str,
+    prompt_chars: usize,
+    outcome: &ExchangeOutcome,
+    status: StatusCode,
+    started: Instant,
+) {
+    let tokens = match &outcome.response_json {
+        _ if !status.is_success() => TokenCounts::default(),
+        Some(body) => TokenCounts::from_usage(&body["usage"]).unwrap_or_else(|| {
+            let completion_chars = body["choices"][0]["message"]["content"]
+                .as_str()
+                .map_or(0, |content| content.chars().count());
+            TokenCounts::estimate(prompt_chars, completion_chars)
+        }),
+        None => TokenCounts::estimate(prompt_chars, 0),
+    };
+    tracker.record(model, status.is_success(), tokens, started.elapsed());
+}
+
+/// The audit record for an exchange as far as it has got. A stream's is completed by its
+/// `StreamMeter` when the stream ends.
+fn build_audit_record(
+    audit: &AuditLogger,
+    payload: &ChatCompletionRequest,
+    request_id: &str,
+    outcome: &ExchangeOutcome,
+    status: StatusCode,
+    started: Instant,
+) -> AuditRecord {
+    let usage = outcome.response_json.as_ref().map(|v| &v["usage"]);
+    let token_count = |field: &str| usage.and_then(|u| u[field].as_u64());
+    let response = outcome
+        .response_json
+        .as_ref()
+        .and_then(|v| v["choices"][0]["message"]["content"].as_str())
+        .map(|content| AuditMessage::new("assistant", content, audit.include_content()));
+
+    AuditRecord {
+        timestamp: chrono::Utc::now(),
+        request_id: request_id.to_string(),
+        model: payload.model.clone(),
+        node_ip: outcome.node_ip.clone(),
+        status: status.as_u16(),
+        latency_ms: started.elapsed().as_millis() as u64,
+        stream: payload.stream,
+        prompt_tokens: token_count("prompt_tokens"),
+        completion_tokens: token_count("completion_tokens"),
+        total_tokens: token_count("total_tokens"),
+        messages: audit.audit_messages(&payload.messages),
+        response,
+    }
+}
+
+/// Obtain a ticket for a node whose circuit breaker admits the request, taking the
+/// coordinator's candidates in its order of preference and asking it for other nodes
+/// when every one offered is known to be failing. Nodes in `excluded` are never used. A
+/// session's previous node is preferred while its circuit is closed. The admission must
+/// be settled with the request's outcome, or dropped if it never reaches the node.
+pub(crate) async fn authorize_healthy_node(
+    state: &ProxyState,
+    model: &str,
+    request: &RequestContext,
+    mut excluded: Vec<String>,
+) -> Result<(AuthorizeResponse, Admission), TroopError> {
+    let mut preferred = preferred_node(state, model, request)
+        .await
+        .filter(|node_ip| !excluded.contains(node_ip));
+
+    for _ in 0..MAX_NODE_ATTEMPTS {
+        let auth_response = get_authorization(state, model, request, &excluded, preferred.take())
+            .await
+            .inspect_err(|e| match e {
+                TroopError::CoordinatorUnreachable(_) => {
+                    error!("Authorization failed: {}; {}", e, COORDINATOR_HINT)
+                }
+                _ => error!("Authorization failed: {}", e),
+            })?;
+
+        for candidate in auth_response.candidates() {
+            if excluded.contains(&candidate.target_ip) {
+                continue;
+            }
+            let breaker = state.node_breakers.breaker_for(&candidate.target_ip);
+            if let Some(admission) = breaker.admit().await {
+                return Ok((AuthorizeResponse::for_candidate(candidate), admission));
+            }
+            warn!(
+                "Circuit open for node {}, trying the next candidate",
+                candidate.target_ip
+            );
+            excluded.push(candidate.target_ip);
+        }
+        info!("No candidate node usable, requesting others");
+    }
+
+    error!(
+        "No node with a closed circuit available for model {}",
+        model
+    );
+    Err(TroopError::CircuitBreakerOpen)
+}
+
+/// The node `request`'s session last used for `model`, unless its circuit is open. Only
+/// the state is looked at: admission happens when the coordinator offers the node.
+async fn preferred_node(
+    state: &ProxyState,
+    model: &str,
+    request: &RequestContext,
+) -> Option<String> {
+    let session = request.session.as_deref()?;
+    let node_ip = state.sessions.node_for(session, model)?;
+    if state.node_breakers.breaker_for(&node_ip).state().await != CircuitState::Open {
+        Some(node_ip)
+    } else {
+        info!(
+            "Session {} affinity broken: circuit open for node {}",
+            session, node_ip
+        );
+        None
+    }
+}
+
+/// Record the node assigned to `session`, logging whether affinity held.
+fn pin_session(state: &ProxyState, session: &str, model: &str, node_ip: &str) {
+    match state.sessions.node_for(session, model) {
+        Some(previous) if previous == node_ip => {
+            info!("Session {} affinity honored: node {}", session, node_ip)
+        }
+        Some(previous) => info!(
+            "Session {} affinity broken: moved from node {} to {}",
+            session, previous, node_ip
+        ),
+        None => info!("Session {} pinned to node {}", session, node_ip),
+    }
+    state.sessions.pin(session, model, node_ip);
+}
+
+/// A worker that accepted a request, with the E2E session (if any) needed to read its reply.
+pub(crate) struct WorkerReply {
+    response: reqwest::Response,
+    e2e_session: Option<crate::e2e_crypto::E2ESession>,
+    pub(crate) node_ip: String,
+}
+
+/// Authorize a node for `model` and send `payload` to its `path`. Returns the worker
+/// response and the E2E session (if any) needed to decrypt it. A ticket the worker rejects
+/// with 401/403 is replaced with a fresh one and the call retried once. With hedging
+/// enabled, a node that is slow to answer is raced against a second one.
+async fn dispatch_to_worker<T: Serialize>(
+    state: &ProxyState,
+    model: &str,
+    path: &str,
+    payload: &T,
+    request: &RequestContext,
+    outcome: &mut ExchangeOutcome,
+) -> Result<(reqwest::Response, Option<crate::e2e_crypto::E2ESession>), ProxyError> {
+    let timeout = request.timeout;
+    let mut ticket_refreshed = false;
+    let mut hedged = false;
+    let mut circuits_opened = Vec::new();
+
+    let reply = loop {
+        // Step 1: Discovery & Authorization (with retry), skipping nodes with open circuits
+        let (auth_response, admission) =
+            authorize_healthy_node(state, model, request, circuits_opened.clone()).await?;
+
+        info!("Got ticket for node: {}", auth_response.target_ip);
+        outcome.node_ip = Some(auth_response.target_ip.clone());
+
+        // Steps 2-3: Send to the worker, hedging to a second node at most once per request
+        let primary = send_to_node(state, &auth_response, admission, path, payload, request);
+        let sent = if state.config.hedge_after.is_some() && !hedged {
+            hedged = true;
+            let primary_node = &auth_response.target_ip;
+            hedging::send_hedged(state, model, path, payload, request, primary, primary_node).await
+        } else {
+            primary.await
+        };
+
+        match sent {
+            Ok(reply) => break reply,
+            // The node failed until its circuit opened; another one may do better
+            Err(ProxyError::Troop(TroopError::CircuitBreakerOpen))
+                if circuits_opened.len() + 1 < MAX_NODE_ATTEMPTS =>
+            {
+                warn!(
+                    "Circuit opened for node {}, trying another node",
+                    auth_response.target_ip
+                );
+                circuits_opened.push(auth_response.target_ip.clone());
+            }
+            Err(ProxyError::Troop(TroopError::Timeout(e))) => {
+                error!("Worker request timed out: {}", e);
+                return Err(ProxyError::Timeout(timeout));
+            }
+            // The ticket expired or the worker rotated its key; a fresh ticket may work
+            Err(ProxyError::Troop(TroopError::UpstreamError {
+                status: status @ (401 | 403),
+                ..
+            })) if !ticket_refreshed => {
+                warn!(
+                    "Worker {} rejected the ticket with {}, ticket refresh retry",
+                    auth_response.target_ip, status
+                );
+                ticket_refreshed = true;
+            }
+            Err(ProxyError::Troop(e @ TroopError::UpstreamError { .. })) => {
+                warn!("{}", e);
+                return Err(e.into());
+            }
+            // Each error keeps its own status and body, e.g. a 429 its `Retry-After`
+            Err(ProxyError::Troop(e)) => {
+                error!("Worker request failed: {}", e);
+                return Err(e.into());
+            }
+            Err(e) => return Err(e),
         }
     };
 
-    info!("Got ticket for node: {}", auth_response.target_ip);
+    outcome.node_ip = Some(reply.node_ip.clone());
+    if let Some(session) = &request.session {
+        pin_session(state, session, model, &reply.node_ip);
+    }
+    Ok((reply.response, reply.e2e_session))
+}
 
-    // Step 2: Establish E2E session if worker supports encryption
+/// Send `payload` to `path` on the node `auth_response` names, encrypting it end to end when
+/// the worker advertises a key. With hedging enabled, the reply only counts once its first
+/// body chunk has arrived. Failing here before the node was contacted, or being cancelled,
+/// drops `admission` unsettled, which frees the node for another probe.
+pub(crate) async fn send_to_node<T: Serialize>(
+    state: &ProxyState,
+    auth_response: &AuthorizeResponse,
+    admission: Admission,
+    path: &str,
+    payload: &T,
+    request: &RequestContext,
+) -> Result<WorkerReply, ProxyError> {
     let e2e_session = if let Some(ref worker_pub_key) = auth_response.encryption_public_key {
         match crate::e2e_crypto::establish_session(worker_pub_key) {
             Ok(session) => {
@@ -155,55 +1223,116 @@ async fn chat_completions_handler(
             }
             Err(e) => {
                 error!("E2E session establishment failed: {}", e);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
             }
         }
     } else {
         None
     };
 
-    // Step 3: Send to worker (encrypted or plaintext)
-    let is_stream = payload.stream;
-    let response = match send_to_worker(
-        &auth_response,
-        &payload,
-        config.worker_port,
-        e2e_session.as_ref(),
-    )
-    .await
-    {
-        Ok(resp) => resp,
-        Err(e) => {
-            error!("Worker request failed: {}", e);
-            return Err(StatusCode::BAD_GATEWAY);
+    let worker_url = Url::parse(&format!(
+        "http://{}:{}/{}",
+        auth_response.target_ip,
+        auth_response
+            .target_port
+            .unwrap_or(state.config.worker_port),
+        path
+    ))
+    .map_err(|e| {
+        error!("Invalid worker address {}: {}", auth_response.target_ip, e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    let breaker = admission.breaker().clone();
+    let exchange = async {
+        let response = send_to_worker(
+            state,
+            auth_response,
+            &worker_url,
+            payload,
+            e2e_session.as_ref(),
+            request,
+            admission,
+        )
+        .await?;
+        if state.config.hedge_after.is_some() {
+            streams::first_chunk(response, None).await
+        } else {
+            Ok::<_, TroopError>(response)
         }
     };
+    // The timeout bounds the whole exchange, retries included, so a caller asking to fail
+    // fast actually does
+    let sent = tokio::time::timeout_at(request.deadline, exchange).await;
+
+    match sent {
+        Ok(Ok(response)) => Ok(WorkerReply {
+            response,
+            e2e_session,
+            node_ip: auth_response.target_ip.clone(),
+        }),
+        Ok(Err(e)) => Err(e.into()),
+        Err(_) => {
+            // The in-flight attempt was cancelled before it could report back
+            breaker.record_failure().await;
+            Err(TroopError::Timeout(format!(
+                "Worker did not respond within {:?}",
+                request.timeout
+            ))
+            .into())
+        }
+    }
+}
+
+async fn forward_chat_completion(
+    state: &ProxyState,
+    payload: &ChatCompletionRequest,
+    request: &RequestContext,
+    started: Instant,
+    outcome: &mut ExchangeOutcome,
+) -> Result<Response, ProxyError> {
+    let (response, e2e_session) = dispatch_to_worker(
+        state,
+        &payload.model,
+        "v1/chat/completions",
+        payload,
+        request,
+        outcome,
+    )
+    .await?;
 
-    let status_code = response.status();
-    let status_u16 = status_code.as_u16();
+    let status = response.status();
+    let status_u16 = status.as_u16();
 
     // Step 4: Handle response (decrypt if E2E)
-    if is_stream {
-        if !status_code.is_success() {
-            // For error responses, forward worker headers without forcing SSE content-type.
-            let worker_headers = response.headers().clone();
-            let mut builder = Response::builder().status(status_u16);
-            if let Some(builder_headers) = builder.headers_mut() {
-                copy_end_to_end_headers(&worker_headers, builder_headers);
-            }
-            return builder
-                .body(axum::body::Body::from_stream(response.bytes_stream()))
-                .map_err(|e| {
-                    error!("Failed to build streaming error response: {}", e);
-                    StatusCode::INTERNAL_SERVER_ERROR
-                });
+    if payload.stream {
+        // Not relayed as is, so a compressed stream is decoded before it is read
+        let content_encoding = response
+            .headers()
+            .get(header::CONTENT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let byte_stream =
+            encoding::decode_stream(content_encoding.as_deref(), response.bytes_stream()).map_err(
+                |e| {
+                    error!("Cannot decode streaming response: {}", e);
+                    StatusCode::BAD_GATEWAY
+                },
+            )?;
+        let mut meter = StreamMeter::new(
+            state.usage.clone(),
+            &payload.model,
+            usage::prompt_chars(&payload.messages),
+            started,
+        );
+        if let Some(ref audit) = state.audit {
+            let record = build_audit_record(audit, payload, &request.id, outcome, status, started);
+            meter = meter.with_audit(audit.clone(), record);
         }
-
         if let Some(ref session) = e2e_session {
             // Decrypt each SSE chunk and re-emit as plaintext
             info!("Decrypting streaming response");
             let session_key = session.session_key;
-            let byte_stream = response.bytes_stream();
 
             let decrypted_stream = byte_stream.map(move |chunk_result| {
                 match chunk_result {
@@ -238,7 +1367,11 @@ async fn chat_completions_handler(
                 .status(status_u16)
                 .header("content-type", "text/event-stream")
                 .header("cache-control", "no-cache")
-                .body(axum::body::Body::from_stream(decrypted_stream))
+                .body(axum::body::Body::from_stream(
+                    state.shutdown.terminate_on_drain(
+                        meter.meter(streams::end_with_error_event(decrypted_stream)),
+                    ),
+                ))
                 .map_err(|e| {
                     error!("Failed to build streaming response: {}", e);
                     StatusCode::INTERNAL_SERVER_ERROR
@@ -250,99 +1383,138 @@ async fn chat_completions_handler(
                 .status(status_u16)
                 .header("content-type", "text/event-stream")
                 .header("cache-control", "no-cache")
-                .body(axum::body::Body::from_stream(response.bytes_stream()))
+                .body(axum::body::Body::from_stream(
+                    state.shutdown.terminate_on_drain(
+                        meter.meter(streams::end_with_error_event(byte_stream)),
+                    ),
+                ))
                 .map_err(|e| {
                     error!("Failed to build streaming response: {}", e);
                     StatusCode::INTERNAL_SERVER_ERROR
                 })?)
         }
     } else {
-        let worker_headers = response.headers().clone();
-        let body = response.bytes().await.map_err(|e| {
-            error!("Failed to read response body: {}", e);
+        Ok(relay_json_response(response, e2e_session.as_ref(), outcome).await?)
+    }
+}
+
+/// Buffer a non-streaming worker response, decrypting it if the exchange was E2E encrypted.
+async fn relay_json_response(
+    response: reqwest::Response,
+    e2e_session: Option<&crate::e2e_crypto::E2ESession>,
+    outcome: &mut ExchangeOutcome,
+) -> Result<Response, StatusCode> {
+    let status_u16 = response.status().as_u16();
+    let worker_headers = response.headers().clone();
+    let content_encoding = worker_headers
+        .get(header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok());
+    let body = response.bytes().await.map_err(|e| {
+        error!("Failed to read response body: {}", e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    if let Some(session) = e2e_session {
+        let body = encoding::decode_body(content_encoding, body).map_err(|e| {
+            error!("Failed to decode encrypted response: {}", e);
             StatusCode::BAD_GATEWAY
         })?;
-
-        if status_code.is_success() {
-            if let Some(ref session) = e2e_session {
-                // Decrypt the response
-                let decrypted = crate::e2e_crypto::decrypt_response(&session.session_key, &body)
-                    .map_err(|e| {
-                        error!("Failed to decrypt response: {}", e);
-                        StatusCode::INTERNAL_SERVER_ERROR
-                    })?;
-                info!("Response decrypted, forwarding to client");
-                Ok(Response::builder()
-                    .status(status_u16)
-                    .header("content-type", "application/json")
-                    .body(axum::body::Body::from(decrypted))
-                    .map_err(|e| {
-                        error!("Failed to build response: {}", e);
-                        StatusCode::INTERNAL_SERVER_ERROR
-                    })?)
-            } else {
-                // Forward with worker headers (minus hop-by-hop)
-                info!("Response received, forwarding to client");
-                let mut builder = Response::builder().status(status_u16);
-                if let Some(builder_headers) = builder.headers_mut() {
-                    copy_end_to_end_headers(&worker_headers, builder_headers);
-                }
-                Ok(builder.body(axum::body::Body::from(body)).map_err(|e| {
-                    error!("Failed to build response: {}", e);
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-            }
-        } else {
-            // Error response: forward worker headers (minus hop-by-hop) without modification
-            let mut builder = Response::builder().status(status_u16);
-            if let Some(builder_headers) = builder.headers_mut() {
-                copy_end_to_end_headers(&worker_headers, builder_headers);
-            }
-            Ok(builder.body(axum::body::Body::from(body)).map_err(|e| {
+        // Decrypt the response
+        let decrypted =
+            crate::e2e_crypto::decrypt_response(&session.session_key, &body).map_err(|e| {
+                error!("Failed to decrypt response: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        outcome.response_json = serde_json::from_slice(&decrypted).ok();
+        info!("Response decrypted, forwarding to client");
+        Ok(Response::builder()
+            .status(status_u16)
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from(decrypted))
+            .map_err(|e| {
                 error!("Failed to build response: {}", e);
                 StatusCode::INTERNAL_SERVER_ERROR
             })?)
+    } else {
+        // Forward the bytes untouched, with worker headers (minus hop-by-hop) so any
+        // Content-Encoding and Content-Length still describe them
+        info!("Response received, forwarding to client");
+        outcome.response_json = encoding::decode_body(content_encoding, body.clone())
+            .ok()
+            .and_then(|decoded| serde_json::from_slice(&decoded).ok());
+        let mut builder = Response::builder().status(status_u16);
+        if let Some(builder_headers) = builder.headers_mut() {
+            copy_end_to_end_headers(&worker_headers, builder_headers);
         }
+        Ok(builder.body(axum::body::Body::from(body)).map_err(|e| {
+            error!("Failed to build response: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?)
     }
 }
 
-async fn get_authorization(config: &Config, model: &str) -> TroopResult<AuthorizeResponse> {
-    retry_with_backoff("Authorization", || {
-        let config = config.clone();
-        let model = model.to_string();
-        async move {
-            let client = reqwest::Client::new();
-            let auth_url = config
-                .coordinator_url
-                .join("authorize")
-                .map_err(anyhow::Error::from)?;
-
-            let auth_request = AuthorizeRequest {
-                model,
-                requester: config.requester_id.clone(),
-            };
+async fn get_authorization(
+    state: &ProxyState,
+    model: &str,
+    request: &RequestContext,
+    exclude_nodes: &[String],
+    preferred_node: Option<String>,
+) -> TroopResult<AuthorizeResponse> {
+    let auth_request = AuthorizeRequest {
+        model: model.to_string(),
+        requester: state.config.requester_id.clone(),
+        exclude_nodes: exclude_nodes.to_vec(),
+        preferred_node,
+    };
+    let auth_request = &auth_request;
 
-            info!("Requesting authorization ticket...");
+    let request_id = request.id.as_str();
+    retry_with_policy_until(
+        "Authorization",
+        &COORDINATOR_RETRY_POLICY,
+        request.deadline,
+        |_, _| state.stats.record_retry("authorization"),
+        || {
+            state.coordinators.call(|coordinator_url| async move {
+                let client = state.coordinators.http();
+                let auth_url = coordinator_url
+                    .join("authorize")
+                    .map_err(anyhow::Error::from)?;
 
-            let response = client
-                .post(auth_url)
-                .json(&auth_request)
-                .timeout(AUTH_TIMEOUT)
-                .send()
-                .await?;
+                info!("Requesting authorization ticket...");
 
-            let auth_response: AuthorizeResponse = response.json().await?;
-            Ok(auth_response)
-        }
-    })
+                let response = client
+                    .post(auth_url)
+                    .header(REQUEST_ID_HEADER, request_id)
+                    .json(&auth_request)
+                    .timeout(AUTH_TIMEOUT)
+                    .send()
+                    .await?;
+
+                let status = response.status();
+                if status == StatusCode::SERVICE_UNAVAILABLE {
+                    return Err(TroopError::NoNodesAvailable);
+                }
+                if !status.is_success() {
+                    return Err(TroopError::from_response(response).await);
+                }
+
+                let auth_response: AuthorizeResponse = response.json().await?;
+                Ok(auth_response)
+            })
+        },
+    )
     .await
 }
 
-async fn send_to_worker(
+async fn send_to_worker<T: Serialize>(
+    state: &ProxyState,
     auth: &AuthorizeResponse,
-    payload: &ChatCompletionRequest,
-    worker_port: u16,
+    worker_url: &Url,
+    payload: &T,
     e2e_session: Option<&crate::e2e_crypto::E2ESession>,
+    request: &RequestContext,
+    admission: Admission,
 ) -> TroopResult<reqwest::Response> {
     // Pre-compute request body (encrypted or plaintext) before the retry loop
     // so we avoid borrow issues with the session reference inside the closure.
@@ -355,29 +1527,2484 @@ async fn send_to_worker(
         serde_json::to_value(payload).map_err(|e| TroopError::InternalError(e.to_string()))?
     };
 
-    retry_with_backoff("Worker request", || {
+    // The first attempt was admitted with the ticket; each retry has to be admitted again,
+    // and once the node's circuit opens it is given up on (`Ok(None)`) rather than retried
+    let breaker = admission.breaker().clone();
+    let mut admission = Some(admission);
+    let on_retry = |_, _: &TroopError| state.stats.record_retry("worker_request");
+    let sent = retry_with_deadline("Worker request", request.deadline, on_retry, || {
         let auth = auth.clone();
+        let worker_url = worker_url.clone();
         let body = request_body.clone();
+        let admitted = admission.take();
+        let breaker = &breaker;
         async move {
-            let client = reqwest::Client::new();
-            let worker_url_str = format!(
-                "http://{}:{}/v1/chat/completions",
-                auth.target_ip, worker_port
-            );
-            let worker_url = Url::parse(&worker_url_str).map_err(anyhow::Error::from)?;
-
+            let admission = match admitted {
+                Some(admission) => admission,
+                None => match breaker.admit().await {
+                    Some(admission) => admission,
+                    None => return Ok(None),
+                },
+            };
             info!("Connecting P2P to worker: {}", worker_url);
 
-            let response = client
+            let mut builder = state
+                .http
                 .post(worker_url)
                 .header("Authorization", format!("Bearer {}", auth.token))
+                .header(REQUEST_ID_HEADER, &request.id)
+                .header(
+                    DEADLINE_HEADER,
+                    deadline_millis(request.deadline).to_string(),
+                );
+            // An encrypted reply has to be decrypted here, so it is only useful uncompressed
+            if let (Some(accept_encoding), None) = (&request.accept_encoding, e2e_session) {
+                builder = builder.header(header::ACCEPT_ENCODING, accept_encoding);
+            }
+            let result = builder
                 .json(&body)
-                .timeout(INFERENCE_TIMEOUT)
+                .timeout(request.timeout)
                 .send()
-                .await?;
+                .await
+                .map_err(TroopError::from);
+            // Nothing has been relayed before the first chunk, so a stream that dies
+            // without one fails this attempt and is retried
+            let result = match result {
+                Ok(response) if request.stream && response.status().is_success() => {
+                    streams::first_chunk(response, Some(FIRST_CHUNK_WAIT)).await
+                }
+                other => other,
+            };
+
+            match &result {
+                Ok(response) if !response.status().is_server_error() => {
+                    admission.record_success().await
+                }
+                _ => admission.record_failure().await,
+            }
 
-            Ok(response)
+            let result = match result {
+                Ok(response) if !response.status().is_success() => {
+                    let status = response.status().as_u16();
+                    let body = response.text().await.unwrap_or_default();
+                    Err(TroopError::UpstreamError { status, body })
+                }
+                other => other,
+            };
+            match result {
+                Err(e) if e.is_retryable() && breaker.state().await == CircuitState::Open => {
+                    warn!("{}; circuit open for worker {}", e, auth.target_ip);
+                    Ok(None)
+                }
+                result => result.map(Some),
+            }
         }
     })
-    .await
+    .await?;
+    sent.ok_or(TroopError::CircuitBreakerOpen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_filter::ModelFilter;
+    use axum::body::Body;
+    use axum::http::Request;
+    use httpmock::prelude::*;
+    use monkey_troop_shared::MAX_RETRIES;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use tower::ServiceExt;
+
+    fn test_config(coordinator: &MockServer, worker_port: u16) -> Config {
+        Config {
+            coordinator_urls: vec![Url::parse(&coordinator.base_url()).unwrap()],
+            proxy_bind: std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST),
+            proxy_port: 0,
+            worker_port,
+            requester_id: "test-requester".to_string(),
+            audit_log_path: None,
+            audit_log_include_content: false,
+            shutdown_drain_timeout: Duration::from_secs(5),
+            min_request_timeout: Duration::from_secs(1),
+            max_request_timeout: Duration::from_secs(600),
+            usage_file: None,
+            max_concurrent_requests: None,
+            max_queued_requests: 0,
+            queue_timeout: Duration::from_secs(30),
+            model_aliases: HashMap::new(),
+            model_filter: ModelFilter::default(),
+            hedge_after: None,
+            max_tokens_cap: None,
+            batch_parallelism: 4,
+            fan_out_max_n: None,
+            enable_response_cache: false,
+            response_cache_ttl: Duration::from_secs(300),
+            response_cache_size: std::num::NonZeroUsize::new(256).unwrap(),
+            proxy_tls: None,
+            proxy_unix_socket: None,
+            proxy_unix_socket_mode: 0o600,
+            proxy_tcp: true,
+            pid_file: std::path::PathBuf::from("/tmp/troop-test.pid"),
+            daemon_log_file: std::path::PathBuf::from("/tmp/troop-test.log"),
+        }
+    }
+
+    fn chat_request() -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header("Content-Type", "application/json")
+            .header(REQUEST_ID_HEADER, "req-abc-123")
+            .body(Body::from(
+                json!({
+                    "model": "llama3:8b",
+                    "messages": [{"role": "user", "content": "hi"}]
+                })
+                .to_string(),
+            ))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_resolve_request_id_reuses_incoming_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("req-1"));
+        assert_eq!(resolve_request_id(&headers), "req-1");
+    }
+
+    #[test]
+    fn test_resolve_request_id_generates_when_missing_or_oversized() {
+        let generated = resolve_request_id(&HeaderMap::new());
+        assert!(uuid::Uuid::parse_str(&generated).is_ok());
+
+        let mut headers = HeaderMap::new();
+        let long = "x".repeat(MAX_REQUEST_ID_LEN + 1);
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_str(&long).unwrap());
+        assert_ne!(resolve_request_id(&headers), long);
+    }
+
+    #[test]
+    fn test_resolve_timeout_clamps_and_falls_back() {
+        let coordinator = MockServer::start();
+        let config = test_config(&coordinator, 0);
+        let with_header = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(TIMEOUT_HEADER, HeaderValue::from_str(value).unwrap());
+            resolve_timeout(&headers, &config)
+        };
+
+        assert_eq!(with_header("45"), Duration::from_secs(45));
+        assert_eq!(with_header("100000"), config.max_request_timeout);
+        assert_eq!(with_header("0"), INFERENCE_TIMEOUT);
+        assert_eq!(with_header("soon"), INFERENCE_TIMEOUT);
+        assert_eq!(
+            resolve_timeout(&HeaderMap::new(), &config),
+            INFERENCE_TIMEOUT
+        );
+    }
+
+    #[tokio::test]
+    async fn test_worker_timeout_maps_to_gateway_timeout() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        authorize_locally(&coordinator);
+        slow_worker(&worker, Duration::from_secs(3));
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header("Content-Type", "application/json")
+            .header(TIMEOUT_HEADER, "1")
+            .body(Body::from(
+                json!({"model": "llama3:8b", "messages": []}).to_string(),
+            ))
+            .unwrap();
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker.port())));
+        let started = Instant::now();
+        let response = create_proxy_router(state).oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert!(started.elapsed() < Duration::from_secs(3));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["type"], "timeout");
+        assert_eq!(body["error"]["message"], "Worker did not respond within 1s");
+    }
+
+    #[tokio::test]
+    async fn test_worker_is_told_the_request_deadline() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        authorize_locally(&coordinator);
+        let sent_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        // X-Troop-Timeout-Secs: 60, minus whatever the proxy spent before forwarding
+        let completion = worker.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .is_true(move |req| {
+                    let headers = req.headers();
+                    let Some(deadline) = headers
+                        .get(DEADLINE_HEADER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.parse::<u128>().ok())
+                    else {
+                        return false;
+                    };
+                    let expected = (sent_at + Duration::from_secs(60)).as_millis();
+                    (expected - 5_000..=expected + 1_000).contains(&deadline)
+                });
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({"choices": []}));
+        });
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header("Content-Type", "application/json")
+            .header(TIMEOUT_HEADER, "60")
+            .body(Body::from(
+                json!({"model": "llama3:8b", "messages": []}).to_string(),
+            ))
+            .unwrap();
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker.port())));
+        let response = create_proxy_router(state).oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        completion.assert();
+    }
+
+    #[tokio::test]
+    async fn test_request_id_propagates_to_coordinator_and_worker() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+
+        let authorize = coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .header(REQUEST_ID_HEADER, "req-abc-123");
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        let completion = worker.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .header(REQUEST_ID_HEADER, "req-abc-123");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({"choices": []}));
+        });
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker.port())));
+        let response = create_proxy_router(state)
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(REQUEST_ID_HEADER).unwrap(),
+            "req-abc-123"
+        );
+        authorize.assert();
+        completion.assert();
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_are_forwarded_to_worker() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+
+        let authorize = coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .json_body_includes(r#"{"model": "nomic-embed-text"}"#);
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        let embeddings = worker.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/embeddings")
+                .header("Authorization", "Bearer ticket")
+                .json_body(json!({"model": "nomic-embed-text", "input": "hello"}));
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({"object": "list", "data": []}));
+        });
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/embeddings")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                json!({"model": "nomic-embed-text", "input": "hello"}).to_string(),
+            ))
+            .unwrap();
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker.port())));
+        let response = create_proxy_router(state).oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(REQUEST_ID_HEADER));
+        authorize.assert();
+        embeddings.assert();
+    }
+
+    #[tokio::test]
+    async fn test_worker_port_from_authorization_overrides_config() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+
+        coordinator.mock(|when, then| {
+            when.method(POST).path("/authorize");
+            then.status(200).json_body(json!({
+                "target_ip": "127.0.0.1",
+                "target_port": worker.port(),
+                "token": "ticket"
+            }));
+        });
+        let completion = worker.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({"choices": []}));
+        });
+
+        // The configured fallback port points nowhere; only the advertised port works.
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, 1)));
+        let response = create_proxy_router(state)
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        completion.assert();
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_reroutes_to_another_node() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+
+        let first = coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .body_excludes("exclude_nodes");
+            then.status(200)
+                .json_body(json!({"target_ip": "10.255.255.1", "token": "ticket"}));
+        });
+        let rerouted = coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .body_includes("10.255.255.1");
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        let completion = worker.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(200).json_body(json!({"choices": []}));
+        });
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker.port())));
+        let flapping = state.node_breakers.breaker_for("10.255.255.1");
+        for _ in 0..CIRCUIT_BREAKER_THRESHOLD {
+            flapping.record_failure().await;
+        }
+
+        let response = create_proxy_router(state)
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        first.assert();
+        rerouted.assert();
+        completion.assert();
+    }
+
+    #[tokio::test]
+    async fn test_candidates_are_tried_in_order_without_asking_again() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+
+        let authorize = coordinator.mock(|when, then| {
+            when.method(POST).path("/authorize");
+            then.status(200).json_body(json!({
+                "target_ip": "10.255.255.1",
+                "token": "ticket-1",
+                "candidates": [
+                    {"target_ip": "10.255.255.1", "token": "ticket-1", "estimated_load": 0.2},
+                    {"target_ip": "127.0.0.1", "token": "ticket-2", "estimated_load": 0.5}
+                ]
+            }));
+        });
+        let completion = worker.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .header("authorization", "Bearer ticket-2");
+            then.status(200).json_body(json!({"choices": []}));
+        });
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker.port())));
+        let flapping = state.node_breakers.breaker_for("10.255.255.1");
+        for _ in 0..CIRCUIT_BREAKER_THRESHOLD {
+            flapping.record_failure().await;
+        }
+
+        let response = create_proxy_router(state)
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        authorize.assert_calls(1);
+        completion.assert();
+    }
+
+    #[tokio::test]
+    async fn test_readiness_reports_coordinator_contact() {
+        let coordinator = MockServer::start();
+        coordinator.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200).json_body(json!({"status": "healthy"}));
+        });
+        let app = create_proxy_router(Arc::new(ProxyState::new(test_config(&coordinator, 0))));
+        let ready = || Request::get("/health/ready").body(Body::empty()).unwrap();
+
+        let response = app.oneshot(ready()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!(body["status"], "ready");
+        assert_eq!(body["requester_id"], "test-requester");
+        assert_eq!(body["coordinator"], format!("{}/", coordinator.base_url()));
+        assert!(body["last_coordinator_contact"].is_string());
+
+        // Nothing listens on the discard port
+        let mut config = test_config(&coordinator, 0);
+        config.coordinator_urls = vec![Url::parse("http://127.0.0.1:9").unwrap()];
+        let app = create_proxy_router(Arc::new(ProxyState::new(config)));
+        let response = app.oneshot(ready()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            json_body(response).await["status"],
+            "coordinator_unreachable"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_swarm_from_cached_peers() {
+        let coordinator = MockServer::start();
+        let peers = coordinator.mock(|when, then| {
+            when.method(GET).path("/peers");
+            then.status(200).json_body(json!({
+                "count": 3,
+                "nodes": [
+                    peer("idle", "IDLE"),
+                    peer("busy", "BUSY"),
+                    peer("gone", "OFFLINE"),
+                ]
+            }));
+        });
+        let app = create_proxy_router(Arc::new(ProxyState::new(test_config(&coordinator, 0))));
+        let health = || Request::get("/health").body(Body::empty()).unwrap();
+
+        for _ in 0..2 {
+            let body = json_body(app.clone().oneshot(health()).await.unwrap()).await;
+            assert_eq!(body["status"], "healthy");
+            assert_eq!(body["coordinator_reachable"], true);
+            assert_eq!(body["available_nodes"], 2);
+            assert_eq!(body["idle_nodes"], 1);
+            assert_eq!(body["available_models"], 1);
+            assert!(body["uptime_secs"].is_u64());
+        }
+        peers.assert_calls(1);
+
+        // Nothing listens on the discard port; the proxy itself is still healthy
+        let mut config = test_config(&coordinator, 0);
+        config.coordinator_urls = vec![Url::parse("http://127.0.0.1:9").unwrap()];
+        let app = create_proxy_router(Arc::new(ProxyState::new(config)));
+        let response = app.oneshot(health()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!(body["coordinator_reachable"], false);
+        assert!(body["available_nodes"].is_null());
+        assert!(body["idle_nodes"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_session_prefers_its_previous_node() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        let fresh = coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .body_excludes("preferred_node");
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        let pinned = coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .json_body_includes(r#"{"preferred_node": "127.0.0.1"}"#);
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        worker.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(200).json_body(json!({"choices": []}));
+        });
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker.port())));
+        let app = create_proxy_router(state.clone());
+        for _ in 0..2 {
+            let mut request = chat_request();
+            request
+                .headers_mut()
+                .insert(SESSION_HEADER, HeaderValue::from_static("chat-1"));
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        app.oneshot(chat_request()).await.unwrap();
+
+        fresh.assert_calls(2);
+        pinned.assert_calls(1);
+        assert_eq!(state.sessions.count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_session_returns_to_its_node_once_the_circuit_recovers() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        let fresh = coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .body_excludes("preferred_node");
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        let pinned = coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .json_body_includes(r#"{"preferred_node": "127.0.0.1"}"#);
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        let completions = worker.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(200).json_body(json!({"choices": []}));
+        });
+
+        let mut state = ProxyState::new(test_config(&coordinator, worker.port()));
+        state.node_breakers = NodeBreakers::new(1, Duration::from_millis(50), NODE_BREAKER_TTL);
+        let state = Arc::new(state);
+        let app = create_proxy_router(state.clone());
+        let session_request = || {
+            let mut request = chat_request();
+            request
+                .headers_mut()
+                .insert(SESSION_HEADER, HeaderValue::from_static("chat-1"));
+            request
+        };
+
+        let response = app.clone().oneshot(session_request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let breaker = state.node_breakers.breaker_for("127.0.0.1");
+        breaker.record_failure().await;
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        // Not preferred while open, but probed and used again once offered
+        let response = app.clone().oneshot(session_request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(breaker.state().await, CircuitState::Closed);
+
+        let response = app.oneshot(session_request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        fresh.assert_calls(2);
+        pinned.assert_calls(1);
+        completions.assert_calls(3);
+    }
+
+    #[tokio::test]
+    async fn test_in_process_chat_completion_keeps_its_session() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        let pinned = coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .json_body_includes(r#"{"preferred_node": "127.0.0.1"}"#);
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .body_excludes("preferred_node");
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        let completions = worker.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .header("Authorization", "Bearer ticket");
+            then.status(200).json_body(json!({"choices": []}));
+        });
+
+        let state = ProxyState::new(test_config(&coordinator, worker.port()));
+        let payload: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "llama3:8b",
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+        for _ in 0..2 {
+            let (response, node) =
+                chat_completion(&state, payload.clone(), Some("repl-1".to_string())).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(node.as_deref(), Some("127.0.0.1"));
+        }
+
+        completions.assert_calls(2);
+        pinned.assert_calls(1);
+        assert_eq!(state.sessions.count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_all_circuits_open_returns_service_unavailable() {
+        let coordinator = MockServer::start();
+        let authorize = coordinator.mock(|when, then| {
+            when.method(POST).path("/authorize");
+            then.status(200)
+                .json_body(json!({"target_ip": "10.255.255.1", "token": "ticket"}));
+        });
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, 1)));
+        let flapping = state.node_breakers.breaker_for("10.255.255.1");
+        for _ in 0..CIRCUIT_BREAKER_THRESHOLD {
+            flapping.record_failure().await;
+        }
+
+        let response = create_proxy_router(state)
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = json_body(response).await;
+        assert_eq!(body["error"]["type"], "service_unavailable");
+        assert_eq!(body["error"]["code"], "circuit_open");
+        authorize.assert_calls(MAX_NODE_ATTEMPTS);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_opening_on_the_last_node_attempt_returns_service_unavailable() {
+        let coordinator = MockServer::start();
+        // Nothing listens on these nodes, so each attempt fails and opens the node's circuit
+        let node =
+            |target_ip: &str| json!({"target_ip": target_ip, "target_port": 1, "token": "ticket"});
+        coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .body_excludes("exclude_nodes");
+            then.status(200).json_body(node("127.0.0.2"));
+        });
+        coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .body_includes(r#""exclude_nodes":["127.0.0.2"]"#);
+            then.status(200).json_body(node("127.0.0.3"));
+        });
+        let last = coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .body_includes(r#""exclude_nodes":["127.0.0.2","127.0.0.3"]"#);
+            then.status(200).json_body(node("127.0.0.4"));
+        });
+
+        let mut state = ProxyState::new(test_config(&coordinator, 1));
+        state.node_breakers = NodeBreakers::new(1, Duration::from_secs(60), NODE_BREAKER_TTL);
+        let response = create_proxy_router(Arc::new(state))
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(json_body(response).await["error"]["code"], "circuit_open");
+        last.assert();
+    }
+
+    #[tokio::test]
+    async fn test_request_failing_before_the_node_frees_its_probe() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .header(REQUEST_ID_HEADER, "req-bad-key");
+            then.status(200).json_body(json!({
+                "target_ip": "127.0.0.1",
+                "token": "ticket",
+                "encryption_public_key": "not a key"
+            }));
+        });
+        authorize_locally(&coordinator);
+        worker.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(200).json_body(json!({"choices": []}));
+        });
+
+        let mut state = ProxyState::new(test_config(&coordinator, worker.port()));
+        state.node_breakers = NodeBreakers::new(1, Duration::from_millis(50), NODE_BREAKER_TTL);
+        let state = Arc::new(state);
+        let breaker = state.node_breakers.breaker_for("127.0.0.1");
+        breaker.record_failure().await;
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        // The probe slot taken by a request that never reached the node is given back
+        let mut request = chat_request();
+        request
+            .headers_mut()
+            .insert(REQUEST_ID_HEADER, HeaderValue::from_static("req-bad-key"));
+        let app = create_proxy_router(state);
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(breaker.state().await, CircuitState::HalfOpen);
+
+        let response = app.oneshot(chat_request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(breaker.state().await, CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_node_is_not_retried_once_its_circuit_opens() {
+        let coordinator = MockServer::start();
+        let failing = MockServer::start();
+        let failures = failing.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(500).body("engine crashed");
+        });
+        let healthy = axum::Router::new().route(
+            "/v1/chat/completions",
+            post(|| async { Json(json!({"choices": [], "node": "healthy"})) }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.2:0").await.unwrap();
+        let healthy_port = listener.local_addr().unwrap().port();
+        tokio::spawn(axum::serve(listener, healthy).into_future());
+        let (first, rerouted) =
+            authorize_with_hedge_node(&coordinator, (failing.port(), healthy_port));
+
+        let mut state = ProxyState::new(test_config(&coordinator, failing.port()));
+        state.node_breakers = NodeBreakers::new(1, Duration::from_secs(60), NODE_BREAKER_TTL);
+        let response = create_proxy_router(Arc::new(state))
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["node"], "healthy");
+        failures.assert_calls(1);
+        first.assert_calls(1);
+        rerouted.assert_calls(1);
+    }
+
+    #[tokio::test]
+    async fn test_authorization_fails_over_to_backup_coordinator() {
+        let primary = MockServer::start();
+        let backup = MockServer::start();
+        let worker = MockServer::start();
+        let primary_authorize = primary.mock(|when, then| {
+            when.method(POST).path("/authorize");
+            then.status(502);
+        });
+        authorize_locally(&backup);
+        worker.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({"choices": []}));
+        });
+
+        let mut config = test_config(&primary, worker.port());
+        config
+            .coordinator_urls
+            .push(Url::parse(&backup.base_url()).unwrap());
+        let router = create_proxy_router(Arc::new(ProxyState::new(config)));
+        let response = router.clone().oneshot(chat_request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        primary_authorize.assert_calls(1);
+
+        let health = router
+            .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = json_body(health).await;
+        assert_eq!(
+            body["coordinators"]["active"],
+            format!("{}/", backup.base_url())
+        );
+        assert_eq!(
+            body["coordinators"]["failover_order"],
+            json!([
+                format!("{}/", primary.base_url()),
+                format!("{}/", backup.base_url())
+            ])
+        );
+    }
+
+    fn with_alias(mut config: Config) -> Config {
+        config
+            .model_aliases
+            .insert("gpt-4o".to_string(), "llama3:8b".to_string());
+        config
+    }
+
+    #[tokio::test]
+    async fn test_model_alias_is_resolved_before_authorization() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        let authorize = coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .json_body_includes(json!({"model": "llama3:8b"}).to_string());
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        let completion = worker.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .json_body_includes(json!({"model": "llama3:8b"}).to_string());
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({"choices": []}));
+        });
+
+        let config = with_alias(test_config(&coordinator, worker.port()));
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                json!({"model": "gpt-4o", "messages": []}).to_string(),
+            ))
+            .unwrap();
+        let response = create_proxy_router(Arc::new(ProxyState::new(config)))
+            .oneshot(request)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        authorize.assert();
+        completion.assert();
+    }
+
+    #[tokio::test]
+    async fn test_max_tokens_cap_is_applied_before_forwarding() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        coordinator.mock(|when, then| {
+            when.method(POST).path("/authorize");
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        let capped = worker.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .json_body_includes(json!({"max_tokens": 256}).to_string());
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({"choices": []}));
+        });
+        let untouched = worker.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .json_body_includes(json!({"max_tokens": 100}).to_string());
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({"choices": []}));
+        });
+
+        let mut config = test_config(&coordinator, worker.port());
+        config.max_tokens_cap = Some(256);
+        let app = create_proxy_router(Arc::new(ProxyState::new(config)));
+        // No max_tokens, too many, and within the cap
+        for max_tokens in [None, Some(30_000), Some(100)] {
+            let mut body = json!({
+                "model": "llama3:8b",
+                "messages": [{"role": "user", "content": "hi"}]
+            });
+            if let Some(max_tokens) = max_tokens {
+                body["max_tokens"] = json!(max_tokens);
+            }
+            let request = Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        capped.assert_calls(2);
+        untouched.assert_calls(1);
+    }
+
+    #[tokio::test]
+    async fn test_deterministic_replies_are_cached_and_streams_bypass_the_cache() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        let authorize = coordinator.mock(|when, then| {
+            when.method(POST).path("/authorize");
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        let completion = worker.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .json_body_includes(json!({"stream": false}).to_string());
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({"choices": [{"message": {"content": "hi"}}]}));
+        });
+        let stream = worker.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .json_body_includes(json!({"stream": true}).to_string());
+            then.status(200)
+                .header("content-type", "text/event-stream")
+                .body(
+                    "data: {\"choices\": [{\"delta\": {\"content\": \"hi\"}}]}\n\ndata: [DONE]\n\n",
+                );
+        });
+
+        let mut config = test_config(&coordinator, worker.port());
+        config.enable_response_cache = true;
+        let state = Arc::new(ProxyState::new(config));
+        let send = |stream: bool, temperature: f64| {
+            let request = Request::post("/v1/chat/completions")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    json!({
+                        "model": "llama3:8b",
+                        "messages": [{"role": "user", "content": "hi"}],
+                        "stream": stream,
+                        "temperature": temperature,
+                        "seed": 7
+                    })
+                    .to_string(),
+                ))
+                .unwrap();
+            create_proxy_router(state.clone()).oneshot(request)
+        };
+
+        let miss = send(false, 0.0).await.unwrap();
+        assert_eq!(miss.headers()[CACHE_HEADER], "miss");
+        let hit = send(false, 0.0).await.unwrap();
+        assert_eq!(hit.status(), StatusCode::OK);
+        assert_eq!(hit.headers()[CACHE_HEADER], "hit");
+        assert_eq!(
+            json_body(hit).await["choices"][0]["message"]["content"],
+            "hi"
+        );
+        completion.assert_calls(1);
+        authorize.assert_calls(1);
+
+        // Sampled replies differ between runs, and streams are never stored
+        let sampled = send(false, 0.7).await.unwrap();
+        assert!(sampled.headers().get(CACHE_HEADER).is_none());
+        for _ in 0..2 {
+            let response = send(true, 0.0).await.unwrap();
+            assert!(response.headers().get(CACHE_HEADER).is_none());
+            axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+        }
+        completion.assert_calls(2);
+        stream.assert_calls(2);
+        authorize.assert_calls(4);
+    }
+
+    #[tokio::test]
+    async fn test_n_choices_are_fanned_out_and_merged() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        authorize_locally(&coordinator);
+        let single_choice = worker.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .body_excludes("\"n\"");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({
+                    "id": "chatcmpl-1",
+                    "object": "chat.completion",
+                    "choices": [{
+                        "index": 0,
+                        "message": {"role": "assistant", "content": "hi"},
+                        "finish_reason": "stop"
+                    }],
+                    "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7}
+                }));
+        });
+
+        let mut config = test_config(&coordinator, worker.port());
+        config.fan_out_max_n = Some(4);
+        let app = create_proxy_router(Arc::new(ProxyState::new(config)));
+        let request = |extra: serde_json::Value| {
+            let mut body = json!({
+                "model": "llama3:8b",
+                "messages": [{"role": "user", "content": "hi"}]
+            });
+            body.as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            Request::post("/v1/chat/completions")
+                .header("Content-Type", "application/json")
+                .header(REQUEST_ID_HEADER, "req-fan")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request(json!({"n": 3}))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "req-fan");
+        let body = json_body(response).await;
+        let indices: Vec<_> = body["choices"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["index"].as_u64().unwrap())
+            .collect();
+        assert_eq!(indices, [0, 1, 2]);
+        assert_eq!(
+            body["usage"],
+            json!({"prompt_tokens": 15, "completion_tokens": 6, "total_tokens": 21})
+        );
+        single_choice.assert_calls(3);
+
+        // Streaming and more choices than allowed are refused before anything is sent
+        for extra in [json!({"n": 2, "stream": true}), json!({"n": 5})] {
+            let response = app.clone().oneshot(request(extra)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        single_choice.assert_calls(3);
+    }
+
+    #[tokio::test]
+    async fn test_models_list_includes_available_aliases() {
+        let coordinator = MockServer::start();
+        coordinator.mock(|when, then| {
+            when.method(GET).path("/v1/models");
+            then.status(200).json_body(json!({
+                "object": "list",
+                "data": [{
+                    "id": "llama3:8b",
+                    "object": "model",
+                    "owned_by": "monkey-troop",
+                    "content_hash": "sha256:abc",
+                    "size_bytes": 1
+                }]
+            }));
+        });
+
+        let mut config = with_alias(test_config(&coordinator, 1));
+        config
+            .model_aliases
+            .insert("gpt-5".to_string(), "not-served".to_string());
+        let response = create_proxy_router(Arc::new(ProxyState::new(config)))
+            .oneshot(Request::get("/v1/models").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        let ids: Vec<_> = body["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["llama3:8b", "gpt-4o"]);
+        assert_eq!(body["data"][1]["content_hash"], "sha256:abc");
+    }
+
+    #[tokio::test]
+    async fn test_models_list_reports_an_unreachable_coordinator() {
+        let coordinator = MockServer::start();
+        // Nothing listens on the discard port
+        let mut config = test_config(&coordinator, 1);
+        config.coordinator_urls = vec![Url::parse("http://127.0.0.1:9").unwrap()];
+        let response = create_proxy_router(Arc::new(ProxyState::new(config)))
+            .oneshot(Request::get("/v1/models").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = json_body(response).await;
+        assert_eq!(body["error"]["type"], "service_unavailable");
+        assert_eq!(body["error"]["code"], "coordinator_unreachable");
+        assert!(body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .ends_with(COORDINATOR_HINT));
+    }
+
+    #[tokio::test]
+    async fn test_denied_model_is_rejected_without_authorization() {
+        let coordinator = MockServer::start();
+        let authorize = coordinator.mock(|when, then| {
+            when.method(POST).path("/authorize");
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+
+        // gpt-4o is an alias for llama3:70b, which the denylist blocks despite the allowlist
+        let mut config = test_config(&coordinator, 1);
+        config
+            .model_aliases
+            .insert("gpt-4o".to_string(), "llama3:70b".to_string());
+        config.model_filter = ModelFilter::new("llama3*", "*:70b");
+        let mut request = chat_request();
+        *request.body_mut() = Body::from(
+            json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "hi"}]}).to_string(),
+        );
+        let response = create_proxy_router(Arc::new(ProxyState::new(config)))
+            .oneshot(request)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = json_body(response).await;
+        assert_eq!(body["error"]["code"], "model_not_allowed");
+        let message = body["error"]["message"].as_str().unwrap();
+        assert!(message.contains("'llama3:70b'"));
+        assert!(message.contains("denylist takes precedence"));
+        authorize.assert_calls(0);
+    }
+
+    fn peer(node_id: &str, status: &str) -> serde_json::Value {
+        json!({
+            "node_id": node_id,
+            "tailscale_ip": "100.64.0.7",
+            "status": status,
+            "models": [{"name": "llama3:8b", "content_hash": "sha256:abc", "size_bytes": 1}],
+            "hardware": {"gpu": "RTX 4090", "vram_free": 24576},
+            "engines": [],
+            "load": {"in_flight": 1, "max_concurrent": 4}
+        })
+    }
+
+    async fn route(config: Config, uri: &str) -> Response {
+        create_proxy_router(Arc::new(ProxyState::new(config)))
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_route_previews_the_node_without_a_ticket() {
+        let coordinator = MockServer::start();
+        let authorize = coordinator.mock(|when, then| {
+            when.method(POST).path("/authorize");
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        let peers = coordinator.mock(|when, then| {
+            when.method(GET)
+                .path("/peers")
+                .query_param("model", "llama3:8b");
+            then.status(200).json_body(json!({
+                "count": 2,
+                "nodes": [peer("node-busy", "BUSY"), peer("node-idle", "IDLE")]
+            }));
+        });
+
+        // gpt-4o is an alias for llama3:8b
+        let config = with_alias(test_config(&coordinator, 1));
+        let response = route(config, "/v1/route?model=gpt-4o").await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!(body["advisory"], true);
+        assert_eq!(body["requested_model"], "gpt-4o");
+        assert_eq!(body["model"], "llama3:8b");
+        assert_eq!(body["target"]["node_id"], "node-idle");
+        assert_eq!(body["target"]["target_ip"], "100.64.0.7");
+        assert_eq!(body["target"]["hardware"]["gpu"], "RTX 4090");
+        assert_eq!(
+            body["queue"],
+            json!({"idle_nodes": 1, "busy_nodes": 1, "in_flight": 2, "max_concurrent": 8})
+        );
+        peers.assert();
+        authorize.assert_calls(0);
+    }
+
+    #[tokio::test]
+    async fn test_route_errors_match_real_requests() {
+        let coordinator = MockServer::start();
+        coordinator.mock(|when, then| {
+            when.method(GET)
+                .path("/peers")
+                .query_param("model", "ghost");
+            then.status(200).json_body(json!({"count": 0, "nodes": []}));
+        });
+        coordinator.mock(|when, then| {
+            when.method(GET)
+                .path("/peers")
+                .query_param("model", "llama3:8b");
+            then.status(200)
+                .json_body(json!({"count": 1, "nodes": [peer("node-busy", "BUSY")]}));
+        });
+        let config = || {
+            let mut config = test_config(&coordinator, 1);
+            config.model_filter = ModelFilter::new("", "*:70b");
+            config
+        };
+
+        let response = route(config(), "/v1/route?model=ghost").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            json_body(response).await["error"]["code"],
+            "model_not_found"
+        );
+
+        let response = route(config(), "/v1/route?model=llama3:8b").await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            json_body(response).await["error"]["code"],
+            TroopError::NoNodesAvailable.api_error_body().error.code
+        );
+
+        let response = route(config(), "/v1/route?model=llama3:70b").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            json_body(response).await["error"]["code"],
+            "model_not_allowed"
+        );
+
+        let response = route(config(), "/v1/route").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            json_body(response).await["error"]["code"],
+            "invalid_request"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_models_list_hides_filtered_models() {
+        let coordinator = MockServer::start();
+        let data = ["llama3:8b", "llama3:70b", "mistral:7b"].map(|id| {
+            json!({
+                "id": id,
+                "object": "model",
+                "owned_by": "monkey-troop",
+                "content_hash": "sha256:abc",
+                "size_bytes": 1
+            })
+        });
+        coordinator.mock(|when, then| {
+            when.method(GET).path("/v1/models");
+            then.status(200)
+                .json_body(json!({"object": "list", "data": data}));
+        });
+
+        let mut config = test_config(&coordinator, 1);
+        config
+            .model_aliases
+            .insert("gpt-4o".to_string(), "llama3:70b".to_string());
+        config.model_filter = ModelFilter::new("llama3*", "*:70b");
+        let response = create_proxy_router(Arc::new(ProxyState::new(config)))
+            .oneshot(Request::get("/v1/models").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let body = json_body(response).await;
+        let ids: Vec<_> = body["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["id"].as_str().unwrap())
+            .collect();
+        // gpt-4o points at the hidden llama3:70b, so it is hidden too
+        assert_eq!(ids, ["llama3:8b"]);
+    }
+
+    #[tokio::test]
+    async fn test_requests_over_the_queue_limit_get_too_many_requests() {
+        let coordinator = MockServer::start();
+        let authorize = coordinator.mock(|when, then| {
+            when.method(POST).path("/authorize");
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+
+        let mut config = test_config(&coordinator, 1);
+        config.max_concurrent_requests = Some(1);
+        let state = Arc::new(ProxyState::new(config));
+        let _busy = state.concurrency.as_ref().unwrap().acquire().await.unwrap();
+
+        let response = create_proxy_router(state)
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "1");
+        let body = json_body(response).await;
+        assert_eq!(body["error"]["code"], "queue_full");
+        authorize.assert_calls(0);
+    }
+
+    #[tokio::test]
+    async fn test_usage_is_tallied_and_can_be_reset() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        authorize_locally(&coordinator);
+        worker.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({
+                    "choices": [{"message": {"role": "assistant", "content": "hello"}}],
+                    "usage": {"prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15}
+                }));
+        });
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker.port())));
+        let router = create_proxy_router(state);
+        let response = router.clone().oneshot(chat_request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let usage = router
+            .clone()
+            .oneshot(Request::get("/usage").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let usage = json_body(usage).await;
+        let model = &usage["models"]["llama3:8b"];
+        assert_eq!(model["requests"], 1);
+        assert_eq!(model["prompt_tokens"], 12);
+        assert_eq!(model["completion_tokens"], 3);
+        assert_eq!(model["estimated_requests"], 0);
+
+        let reset = router
+            .clone()
+            .oneshot(Request::delete("/usage").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(reset.status(), StatusCode::NO_CONTENT);
+        let usage = router
+            .oneshot(Request::get("/usage").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(json_body(usage).await["models"], json!({}));
+    }
+
+    /// Worker that gzips its `body` whatever the request asked for, recording the
+    /// `Accept-Encoding` it was sent in `accepted`.
+    async fn gzip_worker(
+        content_type: &'static str,
+        body: &'static [u8],
+        accepted: Arc<std::sync::Mutex<Option<String>>>,
+    ) -> (u16, Vec<u8>) {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let worker = axum::Router::new().route(
+            "/v1/chat/completions",
+            post({
+                let compressed = compressed.clone();
+                move |headers: HeaderMap| async move {
+                    *accepted.lock().unwrap() = headers
+                        .get(header::ACCEPT_ENCODING)
+                        .map(|v| v.to_str().unwrap().to_string());
+                    (
+                        [
+                            (header::CONTENT_TYPE, content_type),
+                            (header::CONTENT_ENCODING, "gzip"),
+                        ],
+                        compressed,
+                    )
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(axum::serve(listener, worker).into_future());
+        (port, compressed)
+    }
+
+    #[tokio::test]
+    async fn test_compressed_reply_is_relayed_untouched() {
+        let coordinator = MockServer::start();
+        authorize_locally(&coordinator);
+        let accepted = Arc::new(std::sync::Mutex::new(None));
+        let (worker_port, compressed) = gzip_worker(
+            "application/json",
+            br#"{"choices": [], "usage": {"prompt_tokens": 12, "completion_tokens": 3}}"#,
+            accepted.clone(),
+        )
+        .await;
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker_port)));
+        let router = create_proxy_router(state);
+        let mut request = chat_request();
+        request
+            .headers_mut()
+            .insert(header::ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
+        let response = router.clone().oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(accepted.lock().unwrap().as_deref(), Some("gzip"));
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(
+            response.headers()[header::CONTENT_LENGTH],
+            compressed.len().to_string()
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, compressed);
+
+        // Usage is still read from the compressed reply
+        let usage = router
+            .oneshot(Request::get("/usage").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            json_body(usage).await["models"]["llama3:8b"]["prompt_tokens"],
+            12
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compressed_stream_is_decoded() {
+        let coordinator = MockServer::start();
+        authorize_locally(&coordinator);
+        let accepted = Arc::new(std::sync::Mutex::new(None));
+        let sse = b"data: {\"choices\": [{\"delta\": {\"content\": \"hi\"}}]}\n\ndata: [DONE]\n\n";
+        let (worker_port, _) = gzip_worker("text/event-stream", sse, accepted.clone()).await;
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker_port)));
+        let request = Request::post("/v1/chat/completions")
+            .header("Content-Type", "application/json")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::from(
+                json!({
+                    "model": "llama3:8b",
+                    "messages": [{"role": "user", "content": "hi"}],
+                    "stream": true
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        // Kept alive, as the relayed stream ends once the proxy state is dropped
+        let response = create_proxy_router(state.clone())
+            .oneshot(request)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        // The stream is metered here, so it is requested uncompressed and relayed decoded
+        assert_eq!(accepted.lock().unwrap().as_deref(), None);
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, &sse[..]);
+    }
+
+    async fn json_body(response: Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_insufficient_credits_returns_payment_required() {
+        let coordinator = MockServer::start();
+        let authorize = coordinator.mock(|when, then| {
+            when.method(POST).path("/authorize");
+            then.status(402)
+                .json_body(json!({"detail": "Insufficient credits"}));
+        });
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, 1)));
+        let response = create_proxy_router(state)
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+        let body = json_body(response).await;
+        assert_eq!(body["error"]["message"], "Insufficient credits");
+        assert_eq!(body["error"]["type"], "insufficient_quota");
+        assert_eq!(body["error"]["code"], "insufficient_credits");
+        authorize.assert_calls(1);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_model_on_worker_returns_not_found() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        authorize_locally(&coordinator);
+        worker.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(404);
+        });
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker.port())));
+        let response = create_proxy_router(state)
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = json_body(response).await;
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+        assert_eq!(body["error"]["code"], "model_not_found");
+    }
+
+    async fn start_draining_proxy(
+        config: Config,
+        tls: Option<RustlsConfig>,
+    ) -> (
+        Arc<ProxyState>,
+        std::net::SocketAddr,
+        tokio::sync::oneshot::Sender<()>,
+        tokio::task::JoinHandle<Result<()>>,
+    ) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(ProxyState::new(config));
+        let (signal_tx, signal_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve_with_drain(
+            vec![ProxyListener::Tcp(listener)],
+            tls,
+            state.clone(),
+            async {
+                let _ = signal_rx.await;
+            },
+        ));
+        (state, addr, signal_tx, server)
+    }
+
+    async fn wait_for_in_flight(state: &ProxyState, expected: usize) {
+        for _ in 0..200 {
+            if state.shutdown.in_flight() == expected {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("in-flight count never reached {expected}");
+    }
+
+    fn slow_worker(worker: &MockServer, delay: Duration) {
+        worker.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(200)
+                .delay(delay)
+                .header("content-type", "application/json")
+                .json_body(json!({"choices": []}));
+        });
+    }
+
+    fn authorize_locally(coordinator: &MockServer) {
+        coordinator.mock(|when, then| {
+            when.method(POST).path("/authorize");
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_authorization_surfaces_the_coordinator_reason() {
+        let coordinator = MockServer::start();
+        let authorize = coordinator.mock(|when, then| {
+            when.method(POST).path("/authorize");
+            then.status(429)
+                .header("Retry-After", "0")
+                .json_body(json!({"detail": "Requester over quota"}));
+        });
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, 8080)));
+        let response = create_proxy_router(state)
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "0");
+        let body = json_body(response).await;
+        assert_eq!(body["error"]["code"], "rate_limited");
+        assert!(body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("Requester over quota"));
+        authorize.assert_calls(MAX_RETRIES as usize);
+    }
+
+    #[tokio::test]
+    async fn test_worker_client_error_is_relayed_with_body() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        authorize_locally(&coordinator);
+        let error_body = json!({
+            "error": {
+                "message": "context length exceeded",
+                "type": "invalid_request_error"
+            }
+        });
+        let completions = worker.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(400)
+                .header("content-type", "application/json")
+                .json_body(error_body.clone());
+        });
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker.port())));
+        let response = create_proxy_router(state)
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()["content-type"], "application/json");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            error_body
+        );
+        // Client errors are not retried
+        completions.assert_calls(1);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_ticket_is_refreshed_once() {
+        let coordinator = MockServer::start();
+        let authorize = coordinator.mock(|when, then| {
+            when.method(POST).path("/authorize");
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        // Serves the first attempt a 401, as if the ticket had expired in the meantime
+        let attempts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let worker = axum::Router::new().route(
+            "/v1/chat/completions",
+            post({
+                let attempts = attempts.clone();
+                move || async move {
+                    match attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                        0 => (StatusCode::UNAUTHORIZED, Json(json!({}))),
+                        _ => (StatusCode::OK, Json(json!({"choices": []}))),
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let worker_port = listener.local_addr().unwrap().port();
+        tokio::spawn(axum::serve(listener, worker).into_future());
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker_port)));
+        let response = create_proxy_router(state)
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 2);
+        authorize.assert_calls(2);
+    }
+
+    #[tokio::test]
+    async fn test_ticket_refresh_is_not_repeated() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        let authorize = coordinator.mock(|when, then| {
+            when.method(POST).path("/authorize");
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        let completions = worker.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(401);
+        });
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker.port())));
+        let response = create_proxy_router(state)
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        authorize.assert_calls(2);
+        completions.assert_calls(2);
+    }
+
+    /// Coordinator that assigns 127.0.0.1 first and, once that node is excluded, 127.0.0.2.
+    fn authorize_with_hedge_node(
+        coordinator: &MockServer,
+        ports: (u16, u16),
+    ) -> (httpmock::Mock<'_>, httpmock::Mock<'_>) {
+        let primary = coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .body_excludes("exclude_nodes");
+            then.status(200).json_body(
+                json!({"target_ip": "127.0.0.1", "target_port": ports.0, "token": "ticket"}),
+            );
+        });
+        let hedge = coordinator.mock(|when, then| {
+            when.method(POST)
+                .path("/authorize")
+                .json_body_includes(r#"{"exclude_nodes": ["127.0.0.1"]}"#);
+            then.status(200).json_body(
+                json!({"target_ip": "127.0.0.2", "target_port": ports.1, "token": "ticket"}),
+            );
+        });
+        (primary, hedge)
+    }
+
+    /// Worker on `ip` streaming `chunk` after `delay`, with its headers sent straight away.
+    async fn streaming_worker(ip: &str, delay: Duration, chunk: &'static str) -> u16 {
+        let worker = axum::Router::new().route(
+            "/v1/chat/completions",
+            post(move || async move {
+                let body = futures::stream::once(async move {
+                    tokio::time::sleep(delay).await;
+                    Ok::<_, std::convert::Infallible>(chunk)
+                });
+                Response::builder()
+                    .header("content-type", "text/event-stream")
+                    .body(Body::from_stream(body))
+                    .unwrap()
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind((ip, 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(axum::serve(listener, worker).into_future());
+        port
+    }
+
+    #[tokio::test]
+    async fn test_slow_worker_is_hedged_to_a_second_node() {
+        let coordinator = MockServer::start();
+        let slow = MockServer::start();
+        slow_worker(&slow, Duration::from_secs(10));
+        let fast = axum::Router::new().route(
+            "/v1/chat/completions",
+            post(|| async { Json(json!({"choices": [], "node": "hedge"})) }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.2:0").await.unwrap();
+        let fast_port = listener.local_addr().unwrap().port();
+        tokio::spawn(axum::serve(listener, fast).into_future());
+        let (primary, hedge) = authorize_with_hedge_node(&coordinator, (slow.port(), fast_port));
+
+        let mut config = test_config(&coordinator, slow.port());
+        config.hedge_after = Some(Duration::from_millis(200));
+        let state = Arc::new(ProxyState::new(config));
+        let response = create_proxy_router(state)
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["node"], "hedge");
+        primary.assert_calls(1);
+        hedge.assert_calls(1);
+    }
+
+    #[tokio::test]
+    async fn test_probing_primary_that_loses_the_hedge_frees_its_probe() {
+        let coordinator = MockServer::start();
+        let slow = MockServer::start();
+        slow_worker(&slow, Duration::from_secs(10));
+        let fast = axum::Router::new().route(
+            "/v1/chat/completions",
+            post(|| async { Json(json!({"choices": [], "node": "hedge"})) }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.2:0").await.unwrap();
+        let fast_port = listener.local_addr().unwrap().port();
+        tokio::spawn(axum::serve(listener, fast).into_future());
+        authorize_with_hedge_node(&coordinator, (slow.port(), fast_port));
+
+        let mut config = test_config(&coordinator, slow.port());
+        config.hedge_after = Some(Duration::from_millis(200));
+        let mut state = ProxyState::new(config);
+        state.node_breakers = NodeBreakers::new(1, Duration::from_millis(50), NODE_BREAKER_TTL);
+        let state = Arc::new(state);
+        let primary = state.node_breakers.breaker_for("127.0.0.1");
+        primary.record_failure().await;
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        let response = create_proxy_router(state)
+            .oneshot(chat_request())
+            .await
+            .unwrap();
+        assert_eq!(json_body(response).await["node"], "hedge");
+
+        // The cancelled probe counted neither way, and the next request may probe again
+        assert_eq!(primary.state().await, CircuitState::HalfOpen);
+        assert!(primary.admit().await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_first_stream_chunk_wins_the_hedge() {
+        let coordinator = MockServer::start();
+        let slow_port =
+            streaming_worker("127.0.0.1", Duration::from_secs(10), "data: slow\n\n").await;
+        let fast_port = streaming_worker("127.0.0.2", Duration::ZERO, "data: fast\n\n").await;
+        authorize_with_hedge_node(&coordinator, (slow_port, fast_port));
+
+        let mut config = test_config(&coordinator, slow_port);
+        config.hedge_after = Some(Duration::from_millis(200));
+        let state = Arc::new(ProxyState::new(config));
+        let request = Request::post("/v1/chat/completions")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                json!({
+                    "model": "llama3:8b",
+                    "messages": [{"role": "user", "content": "hi"}],
+                    "stream": true
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        // Kept alive, as the relayed stream ends once the proxy state is dropped
+        let response = create_proxy_router(state.clone())
+            .oneshot(request)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "data: fast\n\n");
+    }
+
+    const SSE_HEADERS: &str = "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ntransfer-encoding: chunked\r\nconnection: close\r\n\r\n";
+
+    /// Worker speaking raw HTTP: its `n`th connection gets `replies[n]` written verbatim
+    /// once the request has been read, and is then closed. An empty reply closes the
+    /// connection as soon as it is accepted.
+    async fn scripted_worker(replies: Vec<String>) -> u16 {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            for reply in replies {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                if reply.is_empty() {
+                    continue;
+                }
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let content_length = text[..end]
+                            .lines()
+                            .find_map(|line| {
+                                let (name, value) = line.split_once(':')?;
+                                name.eq_ignore_ascii_case("content-length")
+                                    .then(|| value.trim().parse::<usize>().unwrap())
+                            })
+                            .unwrap_or(0);
+                        if request.len() >= end + 4 + content_length {
+                            break;
+                        }
+                    }
+                }
+                socket.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        port
+    }
+
+    fn stream_chat_request() -> Request<Body> {
+        Request::post("/v1/chat/completions")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                json!({
+                    "model": "llama3:8b",
+                    "messages": [{"role": "user", "content": "hi"}],
+                    "stream": true
+                })
+                .to_string(),
+            ))
+            .unwrap()
+    }
+
+    fn sse_chunk(data: &str) -> String {
+        format!("{:x}\r\n{data}\r\n", data.len())
+    }
+
+    #[tokio::test]
+    async fn test_stream_closed_before_first_chunk_is_retried() {
+        let coordinator = MockServer::start();
+        authorize_locally(&coordinator);
+        let sse = "data: {\"choices\": [{\"delta\": {\"content\": \"hi\"}}]}\n\ndata: [DONE]\n\n";
+        let worker_port = scripted_worker(vec![
+            // Accepts the connection and drops it straight away
+            String::new(),
+            // Starts the stream but dies before the first chunk
+            SSE_HEADERS.to_string(),
+            format!("{SSE_HEADERS}{}0\r\n\r\n", sse_chunk(sse)),
+        ])
+        .await;
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker_port)));
+        // Kept alive, as the relayed stream ends once the proxy state is dropped
+        let response = create_proxy_router(state.clone())
+            .oneshot(stream_chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, sse);
+        let stats = state.stats.report(&std::collections::BTreeMap::new());
+        assert_eq!(
+            stats.retries,
+            std::collections::BTreeMap::from([("worker_request".to_string(), 2)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_is_audited_when_it_ends() {
+        let coordinator = MockServer::start();
+        authorize_locally(&coordinator);
+        let worker_port = scripted_worker(vec![format!(
+            "{SSE_HEADERS}{}{}0\r\n\r\n",
+            sse_chunk("data: {\"choices\": [{\"delta\": {\"content\": \"Hello \"}}]}\n\n"),
+            sse_chunk(concat!(
+                "data: {\"choices\": [{\"delta\": {\"content\": \"there\"}}],",
+                " \"usage\": {\"prompt_tokens\": 9, \"completion_tokens\": 2}}\n\n",
+                "data: [DONE]\n\n"
+            ))
+        )])
+        .await;
+        let audit_log =
+            std::env::temp_dir().join(format!("troop-audit-{}.jsonl", uuid::Uuid::new_v4()));
+
+        let mut config = test_config(&coordinator, worker_port);
+        config.audit_log_path = Some(audit_log.clone());
+        config.audit_log_include_content = true;
+        let state = Arc::new(ProxyState::new(config));
+        let response = create_proxy_router(state.clone())
+            .oneshot(stream_chat_request())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let mut record = None;
+        for _ in 0..50 {
+            if let Ok(text) = tokio::fs::read_to_string(&audit_log).await {
+                if let Some(line) = text.lines().next() {
+                    record = Some(serde_json::from_str::<serde_json::Value>(line).unwrap());
+                    break;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let record = record.expect("stream was never audited");
+        assert_eq!(record["stream"], true);
+        assert_eq!(record["node_ip"], "127.0.0.1");
+        assert_eq!(record["prompt_tokens"], 9);
+        assert_eq!(record["completion_tokens"], 2);
+        assert_eq!(record["total_tokens"], 11);
+        assert_eq!(record["response"]["content"], "Hello there");
+        let _ = std::fs::remove_file(&audit_log);
+    }
+
+    #[tokio::test]
+    async fn test_stream_failing_after_first_chunk_ends_with_error_event() {
+        let coordinator = MockServer::start();
+        authorize_locally(&coordinator);
+        let worker_port = scripted_worker(vec![format!(
+            "{SSE_HEADERS}{}",
+            sse_chunk("data: {\"choices\": [{\"delta\": {\"content\": \"hi\"}}]}\n\n")
+        )])
+        .await;
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker_port)));
+        let response = create_proxy_router(state.clone())
+            .oneshot(stream_chat_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.starts_with("data: {\"choices\": [{\"delta\": {\"content\": \"hi\"}}]}\n\n"));
+        assert!(body.contains("\"code\":\"stream_interrupted\""));
+        assert!(body.ends_with("data: [DONE]\n\n"));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_in_flight_request() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        authorize_locally(&coordinator);
+        slow_worker(&worker, Duration::from_millis(300));
+
+        let (state, addr, signal_tx, server) =
+            start_draining_proxy(test_config(&coordinator, worker.port()), None).await;
+
+        let request = tokio::spawn(
+            reqwest::Client::new()
+                .post(format!("http://{addr}/v1/chat/completions"))
+                .json(&json!({"model": "llama3:8b", "messages": []}))
+                .send(),
+        );
+        wait_for_in_flight(&state, 1).await;
+        signal_tx.send(()).unwrap();
+
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert!(server.await.unwrap().is_ok());
+        assert_eq!(state.shutdown.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_fails_when_drain_timeout_elapses() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        authorize_locally(&coordinator);
+        slow_worker(&worker, Duration::from_secs(5));
+
+        let mut config = test_config(&coordinator, worker.port());
+        config.shutdown_drain_timeout = Duration::from_millis(100);
+        let (state, addr, signal_tx, server) = start_draining_proxy(config, None).await;
+
+        let request = tokio::spawn(
+            reqwest::Client::new()
+                .post(format!("http://{addr}/v1/chat/completions"))
+                .json(&json!({"model": "llama3:8b", "messages": []}))
+                .send(),
+        );
+        wait_for_in_flight(&state, 1).await;
+        signal_tx.send(()).unwrap();
+
+        assert!(server.await.unwrap().is_err());
+        request.abort();
+    }
+
+    #[tokio::test]
+    async fn test_health_is_served_over_tls() {
+        let coordinator = MockServer::start();
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let tls = RustlsConfig::from_pem(
+            certified.cert.pem().into_bytes(),
+            certified.signing_key.serialize_pem().into_bytes(),
+        )
+        .await
+        .unwrap();
+
+        let (_state, addr, signal_tx, server) =
+            start_draining_proxy(test_config(&coordinator, 8080), Some(tls)).await;
+
+        let client = reqwest::Client::builder()
+            .tls_certs_only([
+                reqwest::Certificate::from_pem(certified.cert.pem().as_bytes()).unwrap(),
+            ])
+            .resolve("localhost", addr)
+            .build()
+            .unwrap();
+        let response = client
+            .get(format!("https://localhost:{}/health", addr.port()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+        // Plain HTTP is not answered on a TLS listener
+        assert!(reqwest::Client::new()
+            .get(format!("http://{addr}/health"))
+            .send()
+            .await
+            .is_err());
+
+        signal_tx.send(()).unwrap();
+        assert!(server.await.unwrap().is_ok());
+    }
+
+    fn messages_request(body: serde_json::Value) -> Request<Body> {
+        Request::post("/v1/messages")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_messages_are_translated_to_and_from_a_chat_completion() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        authorize_locally(&coordinator);
+        let completion = worker.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .json_body_includes(
+                    json!({
+                        "model": "llama3:8b",
+                        "messages": [
+                            {"role": "system", "content": "Be brief."},
+                            {"role": "user", "content": "hi"}
+                        ],
+                        "max_tokens": 64,
+                        "stop": ["END"]
+                    })
+                    .to_string(),
+                );
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({
+                    "choices": [{"message": {"content": "Hello"}, "finish_reason": "length"}],
+                    "usage": {"prompt_tokens": 9, "completion_tokens": 64}
+                }));
+        });
+
+        let config = with_alias(test_config(&coordinator, worker.port()));
+        let router = create_proxy_router(Arc::new(ProxyState::new(config)));
+        let response = router
+            .clone()
+            .oneshot(messages_request(json!({
+                "model": "gpt-4o",
+                "max_tokens": 64,
+                "system": "Be brief.",
+                "stop_sequences": ["END"],
+                "messages": [{"role": "user", "content": [{"type": "text", "text": "hi"}]}]
+            })))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        completion.assert();
+        let body = json_body(response).await;
+        assert_eq!(body["type"], "message");
+        assert_eq!(body["model"], "gpt-4o");
+        assert_eq!(body["content"], json!([{"type": "text", "text": "Hello"}]));
+        assert_eq!(body["stop_reason"], "max_tokens");
+        assert_eq!(
+            body["usage"],
+            json!({"input_tokens": 9, "output_tokens": 64})
+        );
+
+        // max_tokens is required, and errors take Anthropic's shape
+        let response = router
+            .oneshot(messages_request(json!({
+                "model": "gpt-4o",
+                "messages": [{"role": "user", "content": "hi"}]
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = json_body(response).await;
+        assert_eq!(body["type"], "error");
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+        assert!(body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("max_tokens"));
+    }
+
+    #[tokio::test]
+    async fn test_message_errors_keep_their_status_in_anthropic_shape() {
+        let coordinator = MockServer::start();
+        let mut config = test_config(&coordinator, 8080);
+        config.model_filter = ModelFilter::new("llama3*", "");
+        let response = create_proxy_router(Arc::new(ProxyState::new(config)))
+            .oneshot(messages_request(json!({
+                "model": "mistral:7b",
+                "max_tokens": 64,
+                "messages": [{"role": "user", "content": "hi"}]
+            })))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(response.headers().contains_key(REQUEST_ID_HEADER));
+        let body = json_body(response).await;
+        assert_eq!(body["error"]["type"], "permission_error");
+        assert!(body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("mistral:7b"));
+    }
+
+    #[tokio::test]
+    async fn test_message_stream_is_translated_to_anthropic_events() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        authorize_locally(&coordinator);
+        let completion = worker.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .json_body_includes(r#"{"stream_options": {"include_usage": true}}"#);
+            then.status(200)
+                .header("content-type", "text/event-stream")
+                .body(concat!(
+                    "data: {\"choices\": [{\"delta\": {\"content\": \"Hi\"}, \"finish_reason\": \"stop\"}]}\n\n",
+                    "data: {\"choices\": [], \"usage\": {\"prompt_tokens\": 5, \"completion_tokens\": 1}}\n\n",
+                    "data: [DONE]\n\n"
+                ));
+        });
+
+        let state = Arc::new(ProxyState::new(test_config(&coordinator, worker.port())));
+        // Kept alive, as the relayed stream ends once the proxy state is dropped
+        let response = create_proxy_router(state.clone())
+            .oneshot(messages_request(json!({
+                "model": "llama3:8b",
+                "max_tokens": 64,
+                "stream": true,
+                "messages": [{"role": "user", "content": "hi"}]
+            })))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        completion.assert();
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let events: Vec<&str> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("event: "))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop"
+            ]
+        );
+        let message_delta: serde_json::Value = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(|data| serde_json::from_str(data).unwrap())
+            .find(|event: &serde_json::Value| event["type"] == "message_delta")
+            .unwrap();
+        assert_eq!(message_delta["delta"]["stop_reason"], "end_turn");
+        assert_eq!(
+            message_delta["usage"],
+            json!({"input_tokens": 5, "output_tokens": 1})
+        );
+
+        // Usage is metered from the stream as for chat completions
+        let usage = create_proxy_router(state)
+            .oneshot(Request::get("/usage").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            json_body(usage).await["models"]["llama3:8b"]["completion_tokens"],
+            1
+        );
+    }
+
+    fn batch_request(body: serde_json::Value) -> Request<Body> {
+        Request::post("/v1/batch")
+            .header("Content-Type", "application/json")
+            .header(REQUEST_ID_HEADER, "batch-1")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_batch_reports_each_request_without_aborting() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        let authorize = coordinator.mock(|when, then| {
+            when.method(POST).path("/authorize");
+            then.status(200)
+                .json_body(json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+        });
+        let completion = worker.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .header(REQUEST_ID_HEADER, "batch-1-0");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({"choices": [{"message": {"content": "Hello"}}]}));
+        });
+
+        let mut config = test_config(&coordinator, worker.port());
+        config.model_filter = ModelFilter::new("llama3*", "");
+        let router = create_proxy_router(Arc::new(ProxyState::new(config)));
+        let requests = json!([
+            {"model": "llama3:8b", "messages": [{"role": "user", "content": "hi"}]},
+            {"model": "mistral", "messages": [{"role": "user", "content": "hi"}]},
+            {"messages": [{"role": "user", "content": "hi"}]}
+        ]);
+        let response = router
+            .clone()
+            .oneshot(batch_request(json!({"requests": requests})))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let results = json_body(response).await["results"].clone();
+        assert_eq!(results[0]["index"], 0);
+        assert_eq!(results[0]["status"], 200);
+        assert_eq!(results[0]["node"], "127.0.0.1");
+        assert_eq!(
+            results[0]["response"]["choices"][0]["message"]["content"],
+            "Hello"
+        );
+        assert_eq!(results[1]["status"], 403);
+        assert_eq!(results[1]["error"]["code"], "model_not_allowed");
+        assert!(results[1]["node"].is_null());
+        assert_eq!(results[2]["status"], 400);
+        assert_eq!(results[2]["error"]["code"], "invalid_request");
+        completion.assert();
+        authorize.assert_calls(1);
+
+        // Streamed, one NDJSON line per request
+        let response = router
+            .clone()
+            .oneshot(batch_request(json!({"requests": requests, "stream": true})))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-ndjson"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let mut statuses: Vec<(u64, u64)> = String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .map(|result| {
+                (
+                    result["index"].as_u64().unwrap(),
+                    result["status"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        statuses.sort();
+        assert_eq!(statuses, [(0, 200), (1, 403), (2, 400)]);
+
+        let response = router
+            .oneshot(batch_request(json!({"requests": []})))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_batch_stays_within_the_concurrency_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // A worker that records the most requests it ever had in flight at once
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let worker = Router::new().route(
+            "/v1/chat/completions",
+            post({
+                let (in_flight, most) = (in_flight.clone(), most.clone());
+                move || async move {
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    most.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    Json(json!({"choices": [{"message": {"content": "Hello"}}]}))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(axum::serve(listener, worker).into_future());
+
+        let coordinator = MockServer::start();
+        authorize_locally(&coordinator);
+        let mut config = test_config(&coordinator, port);
+        config.max_concurrent_requests = Some(2);
+        config.max_queued_requests = 16;
+        config.batch_parallelism = 8;
+        let router = create_proxy_router(Arc::new(ProxyState::new(config)));
+        let request =
+            json!({"model": "llama3:8b", "messages": [{"role": "user", "content": "hi"}]});
+        let response = router
+            .oneshot(batch_request(json!({"requests": vec![request; 6]})))
+            .await
+            .unwrap();
+
+        let results = json_body(response).await["results"].clone();
+        let statuses: Vec<_> = results
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| result["status"].as_u64().unwrap())
+            .collect();
+        assert_eq!(statuses, [200; 6]);
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stats_report_requests_by_model_and_node() {
+        let coordinator = MockServer::start();
+        let worker = MockServer::start();
+        authorize_locally(&coordinator);
+        worker.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({"choices": []}));
+        });
+
+        let mut config = test_config(&coordinator, worker.port());
+        config.model_filter = ModelFilter::new("llama3*", "");
+        let router = create_proxy_router(Arc::new(ProxyState::new(config)));
+        for model in ["llama3:8b", "llama3:8b", "mistral"] {
+            let mut request = chat_request();
+            *request.body_mut() = Body::from(
+                json!({"model": model, "messages": [{"role": "user", "content": "hi"}]})
+                    .to_string(),
+            );
+            router.clone().oneshot(request).await.unwrap();
+        }
+
+        let response = router
+            .oneshot(Request::get("/stats").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let stats = json_body(response).await;
+        let llama = &stats["models"]["llama3:8b"];
+        assert_eq!(llama["succeeded"], 2);
+        assert_eq!(llama["nodes"]["127.0.0.1"]["requests"], 2);
+        assert_eq!(llama["latency"]["samples"], 2);
+        assert_eq!(stats["models"]["mistral"]["failed"], 1);
+        assert_eq!(stats["nodes"]["127.0.0.1"]["requests"], 2);
+        assert_eq!(stats["nodes"]["127.0.0.1"]["circuit"], "closed");
+        assert_eq!(stats["last_error"]["model"], "mistral");
+        assert_eq!(stats["last_error"]["status"], 403);
+    }
 }
@@ -0,0 +1,131 @@
+//! Replies to deterministic chat completions, kept so repeats are not billed again.
+//!
+//! With `ENABLE_RESPONSE_CACHE` set, a non-streaming request with `temperature` 0 and a
+//! fixed `seed` is looked up by the SHA-256 of the request as it would be sent to a
+//! worker. A hit is answered from here without asking the coordinator for a ticket. Only
+//! successful replies are stored, each for `RESPONSE_CACHE_TTL_SECS`, and once
+//! `RESPONSE_CACHE_SIZE` are held the least recently used one makes room for a new reply.
+
+use lru::LruCache;
+use monkey_troop_shared::ChatCompletionRequest;
+use sha2::{Digest, Sha256};
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Header telling whether a cacheable reply came from the cache (`hit`) or a worker (`miss`).
+pub const CACHE_HEADER: &str = "x-troop-cache";
+
+/// SHA-256 of a serialized request: unlike a 64-bit hash, two requests never share one in
+/// practice, so a hit is always the reply to the same request.
+pub type CacheKey = [u8; 32];
+
+struct CachedReply {
+    body: serde_json::Value,
+    stored: Instant,
+}
+
+pub struct ResponseCache {
+    entries: Mutex<LruCache<CacheKey, CachedReply>>,
+    ttl: Duration,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration, capacity: NonZeroUsize) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+            ttl,
+        }
+    }
+
+    /// The cache key for `request`, or `None` when its reply may differ between runs or
+    /// arrives as a stream.
+    pub fn key(request: &ChatCompletionRequest) -> Option<CacheKey> {
+        if request.stream || request.temperature != Some(0.0) || request.seed.is_none() {
+            return None;
+        }
+        // Through `Value`, whose maps are sorted, so `extra` hashes the same every time
+        let serialized = serde_json::to_value(request).ok()?.to_string();
+        Some(Sha256::digest(serialized.as_bytes()).into())
+    }
+
+    /// The reply stored under `key`, unless it has expired.
+    pub fn get(&self, key: CacheKey) -> Option<serde_json::Value> {
+        let mut entries = self.lock();
+        match entries.get(&key) {
+            Some(reply) if reply.stored.elapsed() < self.ttl => Some(reply.body.clone()),
+            Some(_) => {
+                entries.pop(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, key: CacheKey, body: serde_json::Value) {
+        self.lock().put(
+            key,
+            CachedReply {
+                body,
+                stored: Instant::now(),
+            },
+        );
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruCache<CacheKey, CachedReply>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(extra: serde_json::Value) -> ChatCompletionRequest {
+        let mut request = json!({
+            "model": "llama3:8b",
+            "messages": [{"role": "user", "content": "Say hi"}],
+            "temperature": 0.0,
+            "seed": 42
+        });
+        request
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(request).unwrap()
+    }
+
+    #[test]
+    fn test_only_deterministic_non_streaming_requests_have_keys() {
+        let key = ResponseCache::key(&request(json!({"user": "a", "logit_bias": {}})));
+        assert!(key.is_some());
+        assert_eq!(
+            key,
+            ResponseCache::key(&request(json!({"logit_bias": {}, "user": "a"})))
+        );
+        assert_ne!(key, ResponseCache::key(&request(json!({"user": "b"}))));
+
+        assert_eq!(ResponseCache::key(&request(json!({"stream": true}))), None);
+        assert_eq!(
+            ResponseCache::key(&request(json!({"temperature": 0.7}))),
+            None
+        );
+        assert_eq!(ResponseCache::key(&request(json!({"seed": null}))), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_replies_expire_and_the_least_recently_used_is_evicted() {
+        let cache = ResponseCache::new(Duration::from_secs(60), NonZeroUsize::new(2).unwrap());
+        cache.insert([1; 32], json!("one"));
+        cache.insert([2; 32], json!("two"));
+        assert_eq!(cache.get([1; 32]), Some(json!("one")));
+        cache.insert([3; 32], json!("three"));
+        assert_eq!(cache.get([2; 32]), None);
+        assert_eq!(cache.get([1; 32]), Some(json!("one")));
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert_eq!(cache.get([3; 32]), None);
+    }
+}
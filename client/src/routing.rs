@@ -0,0 +1,178 @@
+//! Dry-run routing for `GET /v1/route`.
+//!
+//! Works out where a request for a model would likely land from the coordinator's `/peers`
+//! list, without asking for a ticket, so it costs no credits. The coordinator picks among
+//! idle nodes at random, weighted by reputation, so the answer is advisory: the preview
+//! names the best-reputed idle node, which is the most likely pick.
+
+use crate::proxy::{apply_model_alias, check_model_allowed, ProxyError, ProxyState};
+use axum::extract::{rejection::QueryRejection, Query, State};
+use axum::Json;
+use monkey_troop_shared::{
+    CircuitState, HardwareInfo, NodeHeartbeat, NodeLoad, NodeStatus, PeersResponse, TroopError,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+#[derive(Debug, Serialize)]
+pub struct RoutePreview {
+    /// Always true: nothing was reserved, and the coordinator may pick another node
+    pub advisory: bool,
+    /// The model as asked for, and as served after aliasing
+    pub requested_model: String,
+    pub model: String,
+    pub target: RouteTarget,
+    pub queue: QueueEstimate,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RouteTarget {
+    pub node_id: String,
+    pub target_ip: String,
+    pub hardware: HardwareInfo,
+    pub load: NodeLoad,
+}
+
+/// How busy the nodes serving the model are, summed over all of them.
+#[derive(Debug, Default, Serialize)]
+pub struct QueueEstimate {
+    pub idle_nodes: usize,
+    pub busy_nodes: usize,
+    pub in_flight: u32,
+    pub max_concurrent: u32,
+}
+
+/// Preview the route for `model` over `nodes`, the `/peers` answer for it, which the
+/// coordinator sorts by reputation. Nodes in `skip` (open circuits) are not picked, just
+/// as a real request would pass them over. `None` when no node could take the request.
+pub fn preview(
+    requested_model: &str,
+    model: &str,
+    nodes: &[NodeHeartbeat],
+    skip: &HashSet<String>,
+) -> Option<RoutePreview> {
+    let mut queue = QueueEstimate::default();
+    for node in nodes {
+        match node.status {
+            NodeStatus::Idle => queue.idle_nodes += 1,
+            NodeStatus::Busy => queue.busy_nodes += 1,
+            NodeStatus::Offline => continue,
+        }
+        queue.in_flight += node.load.in_flight;
+        queue.max_concurrent += node.load.max_concurrent;
+    }
+
+    let target = nodes.iter().find(|node| {
+        matches!(node.status, NodeStatus::Idle) && !skip.contains(&node.tailscale_ip)
+    })?;
+
+    Some(RoutePreview {
+        advisory: true,
+        requested_model: requested_model.to_string(),
+        model: model.to_string(),
+        target: RouteTarget {
+            node_id: target.node_id.clone(),
+            target_ip: target.tailscale_ip.clone(),
+            hardware: target.hardware.clone(),
+            load: target.load,
+        },
+        queue,
+    })
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RouteQuery {
+    model: String,
+}
+
+/// Dry run of a request for `model`: the node it would likely be sent to, without
+/// taking a ticket. Aliases and the allow/deny lists apply as for a real request, and
+/// failures take the same shapes.
+pub(crate) async fn route_handler(
+    State(state): State<Arc<ProxyState>>,
+    query: Result<Query<RouteQuery>, QueryRejection>,
+) -> Result<Json<RoutePreview>, ProxyError> {
+    let Query(RouteQuery {
+        model: requested_model,
+    }) = query.map_err(|rejection| TroopError::InvalidRequest(rejection.body_text()))?;
+    let mut model = requested_model.clone();
+    apply_model_alias(&state.config, &mut model);
+    check_model_allowed(&state.config, &model)?;
+
+    let encoded: String = url::form_urlencoded::byte_serialize(model.as_bytes()).collect();
+    let peers: PeersResponse = state
+        .coordinators
+        .get_json(&format!("peers?model={encoded}"))
+        .await?;
+    if peers.nodes.is_empty() {
+        return Err(ProxyError::ModelNotFound(format!(
+            "Model '{model}' is not served by any node"
+        )));
+    }
+
+    let mut open_circuits = HashSet::new();
+    for node in &peers.nodes {
+        let breaker = state.node_breakers.breaker_for(&node.tailscale_ip);
+        if breaker.state().await == CircuitState::Open {
+            open_circuits.insert(node.tailscale_ip.clone());
+        }
+    }
+
+    preview(&requested_model, &model, &peers.nodes, &open_circuits)
+        .map(Json)
+        .ok_or_else(|| TroopError::NoNodesAvailable.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn node(id: &str, ip: &str, status: &str, in_flight: u32) -> NodeHeartbeat {
+        serde_json::from_value(json!({
+            "node_id": id,
+            "tailscale_ip": ip,
+            "status": status,
+            "models": [],
+            "hardware": {"gpu": "RTX 4090", "vram_free": 24576},
+            "engines": [],
+            "load": {"in_flight": in_flight, "max_concurrent": 4}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_preview_picks_best_reputed_usable_idle_node() {
+        let nodes = [
+            node("busy", "100.64.0.1", "BUSY", 4),
+            node("flapping", "100.64.0.2", "IDLE", 0),
+            node("steady", "100.64.0.3", "IDLE", 1),
+            node("gone", "100.64.0.4", "OFFLINE", 0),
+        ];
+        let skip = HashSet::from(["100.64.0.2".to_string()]);
+
+        let route = preview("big", "llama3:70b", &nodes, &skip).unwrap();
+        assert!(route.advisory);
+        assert_eq!(route.requested_model, "big");
+        assert_eq!(route.model, "llama3:70b");
+        assert_eq!(route.target.node_id, "steady");
+        assert_eq!(route.target.target_ip, "100.64.0.3");
+        assert_eq!(route.target.hardware.gpu, "RTX 4090");
+        assert_eq!(route.queue.idle_nodes, 2);
+        assert_eq!(route.queue.busy_nodes, 1);
+        assert_eq!(route.queue.in_flight, 5);
+        assert_eq!(route.queue.max_concurrent, 12);
+    }
+
+    #[test]
+    fn test_preview_without_usable_idle_node() {
+        let nodes = [
+            node("busy", "100.64.0.1", "BUSY", 4),
+            node("flapping", "100.64.0.2", "IDLE", 0),
+        ];
+        let skip = HashSet::from(["100.64.0.2".to_string()]);
+        assert!(preview("m", "m", &nodes, &skip).is_none());
+        assert!(preview("m", "m", &[], &HashSet::new()).is_none());
+    }
+}
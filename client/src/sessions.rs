@@ -0,0 +1,140 @@
+//! Session affinity for the client proxy.
+//!
+//! Requests carrying `X-Troop-Session` are steered back to the node that last served the
+//! session, so the worker can reuse its KV cache for the conversation. Mappings that have
+//! not been used for `SESSION_TTL` are evicted, and once `MAX_SESSIONS` are tracked the
+//! least recently used one makes room for a new session.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Header callers use to pin a conversation to one node.
+pub const SESSION_HEADER: &str = "x-troop-session";
+
+/// How long an unused session mapping is kept before it is evicted.
+pub const SESSION_TTL: Duration = Duration::from_secs(1800);
+
+/// Most sessions tracked at once.
+pub const MAX_SESSIONS: usize = 10_000;
+
+struct SessionEntry {
+    model: String,
+    node_ip: String,
+    last_used: Instant,
+}
+
+pub struct SessionAffinity {
+    entries: Mutex<HashMap<String, SessionEntry>>,
+    ttl: Duration,
+    capacity: usize,
+}
+
+impl SessionAffinity {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            ttl,
+            capacity,
+        }
+    }
+
+    /// Node that last served `session` for `model`. A session that switched models has
+    /// no preference, since its node may not serve the new one.
+    pub fn node_for(&self, session: &str, model: &str) -> Option<String> {
+        self.live_entries()
+            .get(session)
+            .filter(|entry| entry.model == model)
+            .map(|entry| entry.node_ip.clone())
+    }
+
+    /// Remember that `node_ip` served `session`.
+    pub fn pin(&self, session: &str, model: &str, node_ip: &str) {
+        let mut entries = self.live_entries();
+        if !entries.contains_key(session) && entries.len() >= self.capacity {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(session, _)| session.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            session.to_string(),
+            SessionEntry {
+                model: model.to_string(),
+                node_ip: node_ip.to_string(),
+                last_used: Instant::now(),
+            },
+        );
+    }
+
+    /// Sessions currently tracked, reported by `/health`.
+    pub fn count(&self) -> usize {
+        self.live_entries().len()
+    }
+
+    /// Lock the map after evicting expired entries.
+    fn live_entries(&self) -> std::sync::MutexGuard<'_, HashMap<String, SessionEntry>> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, entry| now.duration_since(entry.last_used) < self.ttl);
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_is_pinned_per_model() {
+        let sessions = SessionAffinity::new(SESSION_TTL, MAX_SESSIONS);
+        assert_eq!(sessions.node_for("chat-1", "llama3:8b"), None);
+
+        sessions.pin("chat-1", "llama3:8b", "100.64.0.1");
+        assert_eq!(
+            sessions.node_for("chat-1", "llama3:8b").as_deref(),
+            Some("100.64.0.1")
+        );
+        assert_eq!(sessions.node_for("chat-1", "qwen2.5:14b"), None);
+
+        sessions.pin("chat-1", "llama3:8b", "100.64.0.2");
+        assert_eq!(
+            sessions.node_for("chat-1", "llama3:8b").as_deref(),
+            Some("100.64.0.2")
+        );
+        assert_eq!(sessions.count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_idle_sessions_expire() {
+        tokio::time::pause();
+        let sessions = SessionAffinity::new(Duration::from_secs(30), MAX_SESSIONS);
+        sessions.pin("chat-1", "llama3:8b", "100.64.0.1");
+
+        tokio::time::advance(Duration::from_secs(31)).await;
+
+        assert_eq!(sessions.node_for("chat-1", "llama3:8b"), None);
+        assert_eq!(sessions.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used_session_is_evicted_at_capacity() {
+        tokio::time::pause();
+        let sessions = SessionAffinity::new(SESSION_TTL, 2);
+        sessions.pin("chat-1", "llama3:8b", "100.64.0.1");
+        tokio::time::advance(Duration::from_secs(1)).await;
+        sessions.pin("chat-2", "llama3:8b", "100.64.0.2");
+        tokio::time::advance(Duration::from_secs(1)).await;
+        sessions.pin("chat-1", "llama3:8b", "100.64.0.1");
+
+        sessions.pin("chat-3", "llama3:8b", "100.64.0.3");
+
+        assert_eq!(sessions.count(), 2);
+        assert_eq!(sessions.node_for("chat-2", "llama3:8b"), None);
+        assert!(sessions.node_for("chat-1", "llama3:8b").is_some());
+    }
+}
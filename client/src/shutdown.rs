@@ -0,0 +1,188 @@
+//! Graceful shutdown support for the client proxy.
+//!
+//! On SIGINT/SIGTERM the proxy stops accepting connections and waits for in-flight
+//! requests to finish. If the drain timeout elapses first, open SSE streams are ended
+//! with a final error event so clients see a clean termination instead of a reset.
+
+use axum::body::Body;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use http_body::{Frame, SizeHint};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::watch;
+use tracing::info;
+
+/// SSE event emitted to streams that are cut off because the drain timeout elapsed.
+const DRAIN_TERMINATION_EVENT: &str =
+    "data: {\"error\":{\"message\":\"proxy shutting down\",\"type\":\"shutdown\"}}\n\ndata: [DONE]\n\n";
+
+/// Shared shutdown state: in-flight request count and the "drain deadline reached" flag.
+pub struct Shutdown {
+    in_flight: Arc<AtomicUsize>,
+    drain_expired: watch::Sender<bool>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            drain_expired: watch::Sender::new(false),
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Count a request as in flight until the returned guard is dropped.
+    pub fn begin_request(&self) -> InFlightGuard {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard(self.in_flight.clone())
+    }
+
+    /// Signal open streams that the drain timeout has elapsed.
+    pub fn expire_drain(&self) {
+        self.drain_expired.send_replace(true);
+    }
+
+    /// End `stream` with a termination event once the drain timeout elapses.
+    pub fn terminate_on_drain<S, E>(&self, stream: S) -> impl Stream<Item = Result<Bytes, E>>
+    where
+        S: Stream<Item = Result<Bytes, E>>,
+    {
+        let mut expired = self.drain_expired.subscribe();
+        let cut_off = expired.clone();
+        stream
+            .take_until(async move {
+                let _ = expired.wait_for(|expired| *expired).await;
+            })
+            .chain(
+                futures::stream::once(async move {
+                    (*cut_off.borrow())
+                        .then(|| Ok(Bytes::from_static(DRAIN_TERMINATION_EVENT.as_bytes())))
+                })
+                .filter_map(futures::future::ready),
+            )
+    }
+}
+
+pub struct InFlightGuard(Arc<AtomicUsize>);
+
+impl InFlightGuard {
+    /// Keep the request counted until `body` has been fully sent (or dropped).
+    pub fn hold_until_sent(self, body: Body) -> Body {
+        Body::new(TrackedBody {
+            inner: body,
+            _guard: self,
+        })
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+struct TrackedBody {
+    inner: Body,
+    _guard: InFlightGuard,
+}
+
+impl http_body::Body for TrackedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Resolves on the first SIGINT (Ctrl-C) or, on Unix, SIGTERM.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received Ctrl-C"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tracked_body_counts_until_dropped() {
+        let shutdown = Shutdown::default();
+        let body = shutdown
+            .begin_request()
+            .hold_until_sent(Body::from("hello"));
+        assert_eq!(shutdown.in_flight(), 1);
+
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(&bytes[..], b"hello");
+        assert_eq!(shutdown.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_stream_terminated_with_final_event_on_drain_expiry() {
+        let shutdown = Shutdown::default();
+        let upstream = futures::stream::iter([Ok::<_, std::io::Error>(Bytes::from("data: a\n\n"))])
+            .chain(futures::stream::pending());
+        let mut stream = Box::pin(shutdown.terminate_on_drain(upstream));
+
+        assert_eq!(stream.next().await.unwrap().unwrap(), "data: a\n\n");
+        shutdown.expire_drain();
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            DRAIN_TERMINATION_EVENT
+        );
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_completed_stream_gets_no_termination_event() {
+        let shutdown = Shutdown::default();
+        let upstream = futures::stream::iter([Ok::<_, std::io::Error>(Bytes::from("data: a\n\n"))]);
+        let chunks: Vec<_> = shutdown.terminate_on_drain(upstream).collect().await;
+        assert_eq!(chunks.len(), 1);
+    }
+}
@@ -0,0 +1,338 @@
+//! Rolling request statistics for `GET /stats`.
+//!
+//! Counts chat completions per model, per node, and per model on each node since startup,
+//! and keeps the latencies of the last `LATENCY_WINDOW` requests of each in a fixed-size
+//! ring buffer, so memory stays bounded however long the proxy runs. Percentiles are
+//! worked out from those samples when `/stats` is read. A streamed reply's latency is the
+//! time until it started streaming.
+
+use chrono::{DateTime, Utc};
+use monkey_troop_shared::CircuitState;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Latency samples kept per series.
+pub const LATENCY_WINDOW: usize = 256;
+
+/// The most recent latencies, in milliseconds, overwriting the oldest once full.
+#[derive(Debug, Default)]
+struct LatencyWindow {
+    samples: Vec<u32>,
+    next: usize,
+}
+
+impl LatencyWindow {
+    fn push(&mut self, latency: Duration) {
+        let ms = u32::try_from(latency.as_millis()).unwrap_or(u32::MAX);
+        if self.samples.len() < LATENCY_WINDOW {
+            self.samples.push(ms);
+        } else {
+            self.samples[self.next] = ms;
+        }
+        self.next = (self.next + 1) % LATENCY_WINDOW;
+    }
+
+    fn percentiles(&self) -> Option<Percentiles> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        // Nearest rank: the smallest sample with at least p% of samples at or below it
+        let rank = |p: usize| sorted[(p * sorted.len()).div_ceil(100).max(1) - 1];
+        Some(Percentiles {
+            samples: sorted.len(),
+            p50_ms: rank(50),
+            p95_ms: rank(95),
+            p99_ms: rank(99),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Percentiles {
+    /// How many recent requests the percentiles are taken over
+    pub samples: usize,
+    pub p50_ms: u32,
+    pub p95_ms: u32,
+    pub p99_ms: u32,
+}
+
+/// Counts and latencies for one model, node, or model on a node.
+#[derive(Debug, Default)]
+struct Series {
+    requests: u64,
+    failed: u64,
+    latency: LatencyWindow,
+}
+
+impl Series {
+    fn record(&mut self, succeeded: bool, latency: Duration) {
+        self.requests += 1;
+        if !succeeded {
+            self.failed += 1;
+        }
+        self.latency.push(latency);
+    }
+
+    fn report(&self) -> SeriesStats {
+        SeriesStats {
+            requests: self.requests,
+            succeeded: self.requests - self.failed,
+            failed: self.failed,
+            latency: self.latency.percentiles(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SeriesStats {
+    pub requests: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+    /// `None` until a request has finished
+    pub latency: Option<Percentiles>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModelStats {
+    #[serde(flatten)]
+    pub totals: SeriesStats,
+    /// The same, split by the node that served the request
+    pub nodes: BTreeMap<String, SeriesStats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeStats {
+    #[serde(flatten)]
+    pub totals: SeriesStats,
+    /// `None` once the node's breaker has been evicted as unused, which reads as closed
+    pub circuit: Option<CircuitState>,
+}
+
+/// The most recent request that failed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LastError {
+    pub at: DateTime<Utc>,
+    pub model: String,
+    pub status: u16,
+    pub message: String,
+}
+
+/// Everything served by `GET /stats`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatsReport {
+    pub since: DateTime<Utc>,
+    pub latency_window: usize,
+    pub models: BTreeMap<String, ModelStats>,
+    pub nodes: BTreeMap<String, NodeStats>,
+    /// Retries made by each retried operation, such as authorization
+    pub retries: BTreeMap<String, u64>,
+    /// `None` until a request fails
+    pub last_error: Option<LastError>,
+}
+
+#[derive(Default)]
+struct ModelSeries {
+    totals: Series,
+    nodes: BTreeMap<String, Series>,
+}
+
+#[derive(Default)]
+struct Tallies {
+    models: BTreeMap<String, ModelSeries>,
+    nodes: BTreeMap<String, Series>,
+    retries: BTreeMap<String, u64>,
+    last_error: Option<LastError>,
+}
+
+pub struct StatsTracker {
+    since: DateTime<Utc>,
+    tallies: Mutex<Tallies>,
+}
+
+impl Default for StatsTracker {
+    fn default() -> Self {
+        Self {
+            since: Utc::now(),
+            tallies: Mutex::new(Tallies::default()),
+        }
+    }
+}
+
+impl StatsTracker {
+    /// Count a finished request for `model`, and for `node` when it reached one.
+    pub fn record(&self, model: &str, node: Option<&str>, succeeded: bool, latency: Duration) {
+        let mut tallies = self.tallies.lock().unwrap_or_else(|e| e.into_inner());
+        let model_series = tallies.models.entry(model.to_string()).or_default();
+        model_series.totals.record(succeeded, latency);
+        if let Some(node) = node {
+            model_series
+                .nodes
+                .entry(node.to_string())
+                .or_default()
+                .record(succeeded, latency);
+            tallies
+                .nodes
+                .entry(node.to_string())
+                .or_default()
+                .record(succeeded, latency);
+        }
+    }
+
+    /// Remember a failed request for `model`, replacing the last one.
+    pub fn record_error(&self, model: &str, status: u16, message: String) {
+        let mut tallies = self.tallies.lock().unwrap_or_else(|e| e.into_inner());
+        tallies.last_error = Some(LastError {
+            at: Utc::now(),
+            model: model.to_string(),
+            status,
+            message,
+        });
+    }
+
+    /// How long the proxy has been counting, which is how long it has been up.
+    pub fn uptime(&self) -> Duration {
+        (Utc::now() - self.since).to_std().unwrap_or_default()
+    }
+
+    /// Count a retry of `operation`.
+    pub fn record_retry(&self, operation: &str) {
+        let mut tallies = self.tallies.lock().unwrap_or_else(|e| e.into_inner());
+        *tallies.retries.entry(operation.to_string()).or_default() += 1;
+    }
+
+    /// The current statistics, with node breakers in `circuits`.
+    pub fn report(&self, circuits: &BTreeMap<String, CircuitState>) -> StatsReport {
+        let tallies = self.tallies.lock().unwrap_or_else(|e| e.into_inner());
+        let models = tallies
+            .models
+            .iter()
+            .map(|(model, series)| {
+                let stats = ModelStats {
+                    totals: series.totals.report(),
+                    nodes: series
+                        .nodes
+                        .iter()
+                        .map(|(node, series)| (node.clone(), series.report()))
+                        .collect(),
+                };
+                (model.clone(), stats)
+            })
+            .collect();
+        let nodes = tallies
+            .nodes
+            .iter()
+            .map(|(node, series)| {
+                let stats = NodeStats {
+                    totals: series.report(),
+                    circuit: circuits.get(node).copied(),
+                };
+                (node.clone(), stats)
+            })
+            .collect();
+        StatsReport {
+            since: self.since,
+            latency_window: LATENCY_WINDOW,
+            models,
+            nodes,
+            retries: tallies.retries.clone(),
+            last_error: tallies.last_error.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_percentiles_use_nearest_rank_over_the_window() {
+        let mut window = LatencyWindow::default();
+        assert_eq!(window.percentiles(), None);
+
+        for ms in 1..=100 {
+            window.push(Duration::from_millis(ms));
+        }
+        assert_eq!(
+            window.percentiles(),
+            Some(Percentiles {
+                samples: 100,
+                p50_ms: 50,
+                p95_ms: 95,
+                p99_ms: 99,
+            })
+        );
+
+        // Old samples are overwritten once the window is full
+        for _ in 0..LATENCY_WINDOW {
+            window.push(Duration::from_millis(7));
+        }
+        let percentiles = window.percentiles().unwrap();
+        assert_eq!(percentiles.samples, LATENCY_WINDOW);
+        assert_eq!(percentiles.p99_ms, 7);
+    }
+
+    #[test]
+    fn test_report_splits_by_model_and_node() {
+        let stats = StatsTracker::default();
+        stats.record(
+            "llama3:8b",
+            Some("100.64.0.1"),
+            true,
+            Duration::from_millis(100),
+        );
+        stats.record(
+            "llama3:8b",
+            Some("100.64.0.2"),
+            true,
+            Duration::from_millis(300),
+        );
+        stats.record(
+            "llama3:8b",
+            Some("100.64.0.2"),
+            false,
+            Duration::from_millis(50),
+        );
+        stats.record("mistral", None, false, Duration::ZERO);
+        stats.record_retry("worker_request");
+        stats.record_retry("worker_request");
+        stats.record_retry("authorization");
+        stats.record_error("mistral", 503, "No nodes available".to_string());
+
+        let circuits = BTreeMap::from([("100.64.0.2".to_string(), CircuitState::Open)]);
+        let report = serde_json::to_value(stats.report(&circuits)).unwrap();
+
+        let llama = &report["models"]["llama3:8b"];
+        assert_eq!(llama["requests"], 3);
+        assert_eq!(llama["succeeded"], 2);
+        assert_eq!(llama["failed"], 1);
+        assert_eq!(llama["latency"]["p50_ms"], 100);
+        assert_eq!(llama["nodes"]["100.64.0.1"]["latency"]["p99_ms"], 100);
+        assert_eq!(llama["nodes"]["100.64.0.2"]["latency"]["p99_ms"], 300);
+        assert_eq!(
+            report["models"]["mistral"],
+            json!({
+                "requests": 1,
+                "succeeded": 0,
+                "failed": 1,
+                "latency": {"samples": 1, "p50_ms": 0, "p95_ms": 0, "p99_ms": 0},
+                "nodes": {}
+            })
+        );
+        assert_eq!(report["nodes"]["100.64.0.2"]["requests"], 2);
+        assert_eq!(report["nodes"]["100.64.0.2"]["circuit"], "open");
+        assert!(report["nodes"]["100.64.0.1"]["circuit"].is_null());
+        assert_eq!(report["latency_window"], LATENCY_WINDOW);
+        assert_eq!(
+            report["retries"],
+            json!({"authorization": 1, "worker_request": 2})
+        );
+        assert_eq!(report["last_error"]["model"], "mistral");
+        assert_eq!(report["last_error"]["status"], 503);
+        assert_eq!(report["last_error"]["message"], "No nodes available");
+    }
+}
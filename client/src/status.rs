@@ -0,0 +1,178 @@
+//! Whether the local proxy is up and working, for the `status` command.
+//!
+//! The proxy's `/health` says how long it has been running, whether it reaches a
+//! coordinator and how many models the swarm offers; `/stats` adds the last request that
+//! failed. A port nothing listens on means the proxy is not running, while an answer
+//! that does not name the proxy as its `service` means something else holds the port.
+
+use crate::proxy::SERVICE_NAME;
+use crate::stats::LastError;
+use monkey_troop_shared::http_client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How long to wait for the proxy. `/health` may itself wait on the coordinators.
+const STATUS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What is at the proxy's address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+    Running,
+    /// Nothing accepts connections on the port
+    NotRunning,
+    /// Something answers that is not the proxy
+    OtherService,
+    /// Connections are accepted but not answered in time
+    NotResponding,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProxyStatus {
+    pub url: String,
+    pub state: State,
+    /// Why the proxy is not running, when it is not
+    pub problem: Option<String>,
+    /// The fields below are `None` when the proxy is not running or does not report them
+    pub uptime_secs: Option<u64>,
+    pub coordinator_reachable: Option<bool>,
+    pub available_models: Option<usize>,
+    pub last_error: Option<LastError>,
+}
+
+impl ProxyStatus {
+    fn down(url: &str, state: State, problem: String) -> Self {
+        Self {
+            url: url.to_string(),
+            state,
+            problem: Some(problem),
+            uptime_secs: None,
+            coordinator_reachable: None,
+            available_models: None,
+            last_error: None,
+        }
+    }
+}
+
+/// The parts of `/health` that `status` shows.
+#[derive(Deserialize)]
+struct Health {
+    service: String,
+    uptime_secs: Option<u64>,
+    coordinator_reachable: Option<bool>,
+    available_models: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct Stats {
+    last_error: Option<LastError>,
+}
+
+/// Ask the proxy at `url` how it is doing.
+pub async fn check(url: &str) -> ProxyStatus {
+    let client = http_client(STATUS_TIMEOUT);
+    let response = match client.get(format!("{url}/health")).send().await {
+        Ok(response) => response,
+        Err(e) if e.is_connect() => {
+            return ProxyStatus::down(
+                url,
+                State::NotRunning,
+                format!("nothing is listening at {url}"),
+            )
+        }
+        Err(e) => {
+            return ProxyStatus::down(
+                url,
+                State::NotResponding,
+                format!("{url} did not answer: {e}"),
+            )
+        }
+    };
+    let status = response.status();
+    let health = match response.json::<Health>().await {
+        Ok(health) if status.is_success() && health.service == SERVICE_NAME => health,
+        _ => {
+            return ProxyStatus::down(
+                url,
+                State::OtherService,
+                format!("something other than the proxy is listening at {url} (HTTP {status})"),
+            )
+        }
+    };
+
+    // Proxies from before `/stats` still report everything else
+    let last_error = match client.get(format!("{url}/stats")).send().await {
+        Ok(response) => response
+            .json::<Stats>()
+            .await
+            .ok()
+            .and_then(|stats| stats.last_error),
+        Err(_) => None,
+    };
+    ProxyStatus {
+        url: url.to_string(),
+        state: State::Running,
+        problem: None,
+        uptime_secs: health.uptime_secs,
+        coordinator_reachable: health.coordinator_reachable,
+        available_models: health.available_models,
+        last_error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_check_reports_a_running_proxy() {
+        let proxy = MockServer::start();
+        proxy.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200).json_body(json!({
+                "status": "healthy",
+                "service": SERVICE_NAME,
+                "uptime_secs": 3725,
+                "coordinator_reachable": true,
+                "available_models": 4
+            }));
+        });
+        proxy.mock(|when, then| {
+            when.method(GET).path("/stats");
+            then.status(200).json_body(json!({
+                "last_error": {
+                    "at": "2026-10-15T12:00:00Z",
+                    "model": "llama3:8b",
+                    "status": 503,
+                    "message": "No nodes available"
+                }
+            }));
+        });
+
+        let status = check(&proxy.base_url()).await;
+        assert_eq!(status.state, State::Running);
+        assert_eq!(status.uptime_secs, Some(3725));
+        assert_eq!(status.coordinator_reachable, Some(true));
+        assert_eq!(status.available_models, Some(4));
+        assert_eq!(status.last_error.unwrap().status, 503);
+    }
+
+    #[tokio::test]
+    async fn test_check_tells_a_closed_port_from_another_service() {
+        // Nothing listens on the discard port
+        let status = check("http://127.0.0.1:9").await;
+        assert_eq!(status.state, State::NotRunning);
+
+        let other = MockServer::start();
+        other.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200)
+                .json_body(json!({"status": "ok", "service": "grafana"}));
+        });
+        let status = check(&other.base_url()).await;
+        assert_eq!(status.state, State::OtherService);
+        assert!(status.uptime_secs.is_none());
+    }
+}
@@ -0,0 +1,160 @@
+//! Guarding streamed worker replies in the client proxy.
+//!
+//! Once a reply's headers have been relayed it can no longer be retried, so a streamed
+//! reply is held back until its worker produces the first chunk: a worker that accepts
+//! the request and then drops the connection fails the attempt instead, and the request
+//! goes through the usual retries. A stream that fails after it was relayed is ended
+//! with an SSE error event rather than being cut off mid-response.
+
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+use monkey_troop_shared::{TroopError, TroopResult};
+use std::fmt::Display;
+use std::time::Duration;
+use tracing::warn;
+
+/// How long a streamed reply is held back waiting for its first chunk. A worker slower
+/// than this to start (one loading the model, say) is relayed anyway rather than retried.
+pub const FIRST_CHUNK_WAIT: Duration = Duration::from_secs(10);
+
+/// Wait until `response` yields its first body chunk, for at most `wait` when given, and
+/// put it back in front of the rest of the body. A body that fails or ends before
+/// producing anything is an error.
+pub async fn first_chunk(
+    response: reqwest::Response,
+    wait: Option<Duration>,
+) -> TroopResult<reqwest::Response> {
+    let status = response.status();
+    let headers = response.headers().clone();
+    let mut body = response.bytes_stream();
+
+    // `next` is cancel safe, so a chunk still on its way when the wait ends is kept
+    let first = match wait {
+        Some(wait) => tokio::time::timeout(wait, body.next()).await.ok(),
+        None => Some(body.next().await),
+    };
+    let first = match first {
+        Some(Some(chunk)) => Some(chunk?),
+        Some(None) => {
+            return Err(TroopError::NetworkError(
+                "Worker closed the connection before sending any data".to_string(),
+            ))
+        }
+        None => None,
+    };
+
+    let body = stream::iter(first.map(Ok)).chain(body);
+    let mut rebuilt = axum::http::Response::new(reqwest::Body::wrap_stream(body));
+    *rebuilt.status_mut() = status;
+    *rebuilt.headers_mut() = headers;
+    Ok(rebuilt.into())
+}
+
+/// Pass `stream` through until it fails, then end it with an SSE error event followed by
+/// `[DONE]`, so the caller sees why the completion stopped short.
+pub fn end_with_error_event<S, E>(stream: S) -> impl futures::Stream<Item = Result<Bytes, E>>
+where
+    S: futures::Stream<Item = Result<Bytes, E>>,
+    E: Display,
+{
+    stream.scan(false, |failed, chunk| {
+        let item = match chunk {
+            _ if *failed => None,
+            Ok(bytes) => Some(Ok(bytes)),
+            Err(e) => {
+                warn!("Worker stream failed after it was relayed: {}", e);
+                *failed = true;
+                Some(Ok(error_event(&e)))
+            }
+        };
+        futures::future::ready(item)
+    })
+}
+
+fn error_event(error: &impl Display) -> Bytes {
+    let event = serde_json::json!({
+        "error": {
+            "message": format!("Worker stream failed: {error}"),
+            "type": "api_error",
+            "code": "stream_interrupted",
+        }
+    });
+    Bytes::from(format!("data: {event}\n\ndata: [DONE]\n\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    fn response(
+        chunks: impl futures::Stream<Item = io::Result<&'static str>> + Send + 'static,
+    ) -> reqwest::Response {
+        let body = reqwest::Body::wrap_stream(chunks.map(|chunk| chunk.map(Bytes::from)));
+        axum::http::Response::new(body).into()
+    }
+
+    #[tokio::test]
+    async fn test_first_chunk_is_put_back() {
+        let chunks = stream::iter([Ok("data: a\n\n"), Ok("data: b\n\n")]);
+        let response = first_chunk(response(chunks), None).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "data: a\n\ndata: b\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_body_failing_before_any_chunk_is_an_error() {
+        let err = first_chunk(response(stream::empty()), None)
+            .await
+            .unwrap_err();
+        assert!(err.is_retryable());
+        assert!(err.to_string().contains("before sending any data"));
+
+        let failing = stream::iter([Err(io::Error::other("reset"))]);
+        let err = first_chunk(response(failing), None).await.unwrap_err();
+        assert!(err.is_retryable());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_first_chunk_is_relayed_after_the_wait() {
+        let slow = stream::once(async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok("data: late\n\n")
+        });
+        let response = first_chunk(response(slow), Some(Duration::from_secs(1)))
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "data: late\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_failed_stream_ends_with_error_event() {
+        let chunks = stream::iter([
+            Ok(Bytes::from("data: a\n\n")),
+            Err("connection reset"),
+            Ok(Bytes::from("data: never\n\n")),
+        ]);
+        let relayed: Vec<Bytes> = end_with_error_event(chunks)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(relayed.len(), 2);
+        assert_eq!(relayed[0], "data: a\n\n");
+        let event = String::from_utf8(relayed[1].to_vec()).unwrap();
+        assert!(event.ends_with("\n\ndata: [DONE]\n\n"));
+        let error: serde_json::Value = serde_json::from_str(
+            event
+                .lines()
+                .next()
+                .unwrap()
+                .strip_prefix("data: ")
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(error["error"]["code"], "stream_interrupted");
+        assert!(error["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("connection reset"));
+    }
+}
@@ -0,0 +1,231 @@
+//! Paging through a user's transaction history for the `transactions` command.
+//!
+//! The coordinator lists transactions most recent first, `limit` at a time from `offset`,
+//! optionally bounded to timestamps in `[since, until)`. A single page is fetched for
+//! display; `--sum` and CSV exports walk every page of the range.
+
+use crate::coordinators::Coordinators;
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use monkey_troop_shared::{Transaction, TransactionsResponse};
+use serde::Serialize;
+
+/// Transactions per page when `--limit` is not given.
+pub const DEFAULT_LIMIT: u32 = 50;
+
+/// Transactions fetched per request while walking a range.
+const RANGE_PAGE_SIZE: u32 = 500;
+
+/// Timestamps are sent to the coordinator as naive UTC, the way it stores them.
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// One page of a user's transaction history.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub limit: u32,
+    pub offset: u64,
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+}
+
+impl Query {
+    /// The coordinator path serving this page of `requester_id`'s history.
+    pub fn path(&self, requester_id: &str) -> String {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        query.append_pair("limit", &self.limit.to_string());
+        if self.offset > 0 {
+            query.append_pair("offset", &self.offset.to_string());
+        }
+        if let Some(since) = self.since {
+            query.append_pair("since", &since.format(TIME_FORMAT).to_string());
+        }
+        if let Some(until) = self.until {
+            query.append_pair("until", &until.format(TIME_FORMAT).to_string());
+        }
+        format!("users/{requester_id}/transactions?{}", query.finish())
+    }
+}
+
+/// Parse a `--since`/`--until` bound: a date, taken as midnight UTC, or an RFC 3339 time.
+pub fn parse_time(value: &str) -> Result<NaiveDateTime, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_time(chrono::NaiveTime::MIN));
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc).naive_utc())
+        .map_err(|_| format!("expected YYYY-MM-DD or an RFC 3339 time, got '{value}'"))
+}
+
+/// The change `txn` made to `me`'s balance.
+pub fn signed_credits(txn: &Transaction, me: &str) -> i64 {
+    let mut credits = 0;
+    if txn.worker.as_deref() == Some(me) {
+        credits += txn.credits;
+    }
+    if txn.requester.as_deref() == Some(me) {
+        credits -= txn.credits;
+    }
+    credits
+}
+
+/// The other side of `txn` from `me`'s point of view, if any.
+pub fn counterparty<'a>(txn: &'a Transaction, me: &str) -> Option<&'a str> {
+    if txn.requester.as_deref() == Some(me) {
+        txn.worker.as_deref()
+    } else {
+        txn.requester.as_deref()
+    }
+}
+
+/// Credits earned and spent over a range of transactions.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Totals {
+    pub transactions: usize,
+    pub earned: i64,
+    /// The part of `earned` granted by the coordinator rather than paid for serving
+    pub granted: i64,
+    pub spent: i64,
+    pub net: i64,
+}
+
+impl Totals {
+    pub fn add(&mut self, txns: &[Transaction], me: &str) {
+        for txn in txns {
+            self.transactions += 1;
+            if txn.worker.as_deref() == Some(me) {
+                self.earned += txn.credits;
+                if txn.requester.is_none() {
+                    self.granted += txn.credits;
+                }
+            }
+            if txn.requester.as_deref() == Some(me) {
+                self.spent += txn.credits;
+            }
+        }
+        self.net = self.earned - self.spent;
+    }
+}
+
+/// Total every transaction of `requester_id` in `[since, until)`.
+pub async fn sum(
+    coordinators: &Coordinators,
+    requester_id: &str,
+    since: Option<NaiveDateTime>,
+    until: Option<NaiveDateTime>,
+) -> Result<Totals> {
+    let mut totals = Totals::default();
+    totals.add(
+        &all(coordinators, requester_id, since, until).await?,
+        requester_id,
+    );
+    Ok(totals)
+}
+
+/// Every transaction of `requester_id` in `[since, until)`, most recent first, fetched a
+/// page at a time.
+pub async fn all(
+    coordinators: &Coordinators,
+    requester_id: &str,
+    since: Option<NaiveDateTime>,
+    until: Option<NaiveDateTime>,
+) -> Result<Vec<Transaction>> {
+    let mut txns = Vec::new();
+    let mut query = Query {
+        limit: RANGE_PAGE_SIZE,
+        offset: 0,
+        since,
+        until,
+    };
+    let mut previous_first = None;
+    loop {
+        let page: TransactionsResponse = coordinators.get_json(&query.path(requester_id)).await?;
+        let first = page.transactions.first().map(|txn| (txn.id, txn.timestamp));
+        // A coordinator ignoring `offset` would serve the first page forever
+        if query.offset > 0 && first.is_some() && first == previous_first {
+            anyhow::bail!("The coordinator does not support paging through transactions");
+        }
+        let last_page = page.transactions.len() < RANGE_PAGE_SIZE as usize;
+        txns.extend(page.transactions);
+        if last_page {
+            return Ok(txns);
+        }
+        previous_first = first;
+        query.offset += u64::from(RANGE_PAGE_SIZE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn txn(requester: Option<&str>, worker: Option<&str>, credits: i64) -> Transaction {
+        Transaction {
+            id: Some(1),
+            requester: requester.map(str::to_string),
+            worker: worker.map(str::to_string),
+            credits,
+            timestamp: parse_time("2026-01-01").unwrap(),
+            kind: "job_completion".to_string(),
+            model: None,
+        }
+    }
+
+    #[test]
+    fn test_query_path() {
+        let query = Query {
+            limit: 20,
+            offset: 40,
+            since: Some(parse_time("2026-01-01").unwrap()),
+            until: Some(parse_time("2026-02-01T12:30:00+02:00").unwrap()),
+        };
+        assert_eq!(
+            query.path("alice"),
+            "users/alice/transactions?limit=20&offset=40\
+             &since=2026-01-01T00%3A00%3A00&until=2026-02-01T10%3A30%3A00"
+        );
+        let first_page = Query {
+            limit: 50,
+            ..Query::default()
+        };
+        assert_eq!(
+            first_page.path("alice"),
+            "users/alice/transactions?limit=50"
+        );
+    }
+
+    #[test]
+    fn test_parse_time_rejects_other_formats() {
+        assert!(parse_time("2026-01-01T00:00:00").is_err());
+        assert!(parse_time("yesterday").unwrap_err().contains("RFC 3339"));
+    }
+
+    #[test]
+    fn test_totals_from_my_point_of_view() {
+        let txns = [
+            txn(None, Some("me"), 3600),
+            txn(Some("me"), Some("worker"), 100),
+            txn(Some("other"), Some("me"), 40),
+            txn(Some("me"), Some("me"), 7),
+        ];
+        let mut totals = Totals::default();
+        totals.add(&txns, "me");
+        assert_eq!(
+            totals,
+            Totals {
+                transactions: 4,
+                earned: 3647,
+                granted: 3600,
+                spent: 107,
+                net: 3540,
+            }
+        );
+
+        let signed: Vec<i64> = txns.iter().map(|t| signed_credits(t, "me")).collect();
+        assert_eq!(signed, [3600, -100, 40, 0]);
+        let counterparties: Vec<_> = txns.iter().map(|t| counterparty(t, "me")).collect();
+        assert_eq!(
+            counterparties,
+            [None, Some("worker"), Some("other"), Some("me")]
+        );
+    }
+}
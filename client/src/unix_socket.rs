@@ -0,0 +1,85 @@
+//! Unix domain socket listener for the client proxy (`PROXY_UNIX_SOCKET`).
+//!
+//! Lets local tools reach the proxy without a TCP port, e.g. `curl --unix-socket`. The
+//! socket file is created with `PROXY_UNIX_SOCKET_MODE` permissions and removed again on
+//! shutdown. A socket file left behind by a proxy that crashed is detected by trying to
+//! connect to it and replaced; one that still accepts connections belongs to a running
+//! proxy and is left alone.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+use tokio::net::{UnixListener, UnixStream};
+use tracing::warn;
+
+/// Bind `path`, replacing a dead socket file, and restrict it to `mode`.
+pub async fn bind(path: &Path, mode: u32) -> Result<UnixListener> {
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            anyhow::bail!(
+                "PROXY_UNIX_SOCKET {} exists and is not a socket",
+                path.display()
+            );
+        }
+        if UnixStream::connect(path).await.is_ok() {
+            anyhow::bail!(
+                "PROXY_UNIX_SOCKET {} is in use by another process",
+                path.display()
+            );
+        }
+        warn!("Replacing stale socket file {}", path.display());
+        fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+    }
+
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind unix socket {}", path.display()))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to set permissions on {}", path.display()))?;
+    Ok(listener)
+}
+
+/// Remove the socket file once the proxy has stopped listening on it.
+pub fn remove(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        warn!("Failed to remove socket file {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn socket_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("troop-{}.sock", uuid::Uuid::new_v4().simple()))
+    }
+
+    #[tokio::test]
+    async fn test_bind_sets_permissions_and_replaces_only_dead_sockets() {
+        let path = socket_path();
+        let listener = bind(&path, 0o600).await.unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // Still accepting connections: another proxy owns it
+        let err = bind(&path, 0o600).await.unwrap_err();
+        assert!(err.to_string().contains("in use"));
+
+        // The owner died without cleaning up
+        drop(listener);
+        assert!(path.exists());
+        let _listener = bind(&path, 0o660).await.unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+        remove(&path);
+        assert!(!path.exists());
+
+        // Never delete something that is not a socket
+        fs::write(&path, "data").unwrap();
+        let err = bind(&path, 0o600).await.unwrap_err();
+        assert!(err.to_string().contains("not a socket"));
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
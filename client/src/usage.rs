@@ -0,0 +1,403 @@
+//! Local usage accounting for the client proxy.
+//!
+//! Tallies requests, tokens and latency per model since startup (or the last reset) so
+//! they can be compared against coordinator billing via `GET /usage`. Token counts come
+//! from the engine's `usage` field when present and are estimated from text length
+//! otherwise. When a usage file is configured the totals are written back in the
+//! background after every change and reloaded on startup.
+
+use crate::audit::{AuditLogger, AuditMessage, AuditRecord};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use monkey_troop_shared::{ChatMessage, MessageContent};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tracing::{error, warn};
+
+/// Rough characters-per-token ratio used when the engine reports no usage.
+const CHARS_PER_TOKEN: u64 = 4;
+
+/// Totals for one model.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelUsage {
+    pub requests: u64,
+    pub failed_requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Requests whose token counts were estimated rather than reported by the engine
+    pub estimated_requests: u64,
+    pub total_latency_ms: u64,
+}
+
+/// Everything served by `GET /usage`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub since: DateTime<Utc>,
+    pub models: BTreeMap<String, ModelUsage>,
+}
+
+impl UsageReport {
+    fn empty() -> Self {
+        Self {
+            since: Utc::now(),
+            models: BTreeMap::new(),
+        }
+    }
+}
+
+/// Token counts for a single exchange.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TokenCounts {
+    pub prompt: u64,
+    pub completion: u64,
+    pub estimated: bool,
+}
+
+impl TokenCounts {
+    /// Counts from an OpenAI-style `usage` object, if it has any.
+    pub fn from_usage(usage: &serde_json::Value) -> Option<Self> {
+        let prompt = usage["prompt_tokens"].as_u64();
+        let completion = usage["completion_tokens"].as_u64();
+        (prompt.is_some() || completion.is_some()).then(|| Self {
+            prompt: prompt.unwrap_or(0),
+            completion: completion.unwrap_or(0),
+            estimated: false,
+        })
+    }
+
+    pub fn estimate(prompt_chars: usize, completion_chars: usize) -> Self {
+        Self {
+            prompt: estimate_tokens(prompt_chars),
+            completion: estimate_tokens(completion_chars),
+            estimated: true,
+        }
+    }
+}
+
+/// Prompt length used to estimate prompt tokens for `messages`.
+pub fn prompt_chars(messages: &[ChatMessage]) -> usize {
+    messages
+        .iter()
+        .filter_map(|m| m.content.as_ref())
+        .map(|content| MessageContent::text(content).chars().count())
+        .sum()
+}
+
+fn estimate_tokens(chars: usize) -> u64 {
+    (chars as u64).div_ceil(CHARS_PER_TOKEN)
+}
+
+pub struct UsageTracker {
+    report: Mutex<UsageReport>,
+    changed: Notify,
+}
+
+impl UsageTracker {
+    /// Start from the totals saved at `path` (if any) and keep that file up to date.
+    pub fn open(path: Option<PathBuf>) -> Arc<Self> {
+        let report = path
+            .as_deref()
+            .and_then(load_report)
+            .unwrap_or_else(UsageReport::empty);
+        let tracker = Arc::new(Self {
+            report: Mutex::new(report),
+            changed: Notify::new(),
+        });
+        if let Some(path) = path {
+            tokio::spawn(run_persister(tracker.clone(), path));
+        }
+        tracker
+    }
+
+    pub fn report(&self) -> UsageReport {
+        self.lock().clone()
+    }
+
+    pub fn record(&self, model: &str, succeeded: bool, tokens: TokenCounts, latency: Duration) {
+        {
+            let mut report = self.lock();
+            let usage = report.models.entry(model.to_string()).or_default();
+            usage.requests += 1;
+            if !succeeded {
+                usage.failed_requests += 1;
+            }
+            usage.prompt_tokens += tokens.prompt;
+            usage.completion_tokens += tokens.completion;
+            if tokens.estimated {
+                usage.estimated_requests += 1;
+            }
+            usage.total_latency_ms += latency.as_millis() as u64;
+        }
+        self.changed.notify_one();
+    }
+
+    pub fn reset(&self) {
+        *self.lock() = UsageReport::empty();
+        self.changed.notify_one();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, UsageReport> {
+        self.report.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn load_report(path: &Path) -> Option<UsageReport> {
+    let bytes = std::fs::read(path).ok()?;
+    serde_json::from_slice(&bytes)
+        .inspect_err(|e| warn!("Ignoring unreadable usage file {}: {}", path.display(), e))
+        .ok()
+}
+
+/// Write the totals to `path` whenever they change. Bursts of updates are coalesced
+/// into a single write, and the file is replaced atomically.
+async fn run_persister(tracker: Arc<UsageTracker>, path: PathBuf) {
+    let tmp_path = path.with_extension("tmp");
+    loop {
+        tracker.changed.notified().await;
+        let json = match serde_json::to_vec_pretty(&tracker.report()) {
+            Ok(json) => json,
+            Err(e) => {
+                error!("Failed to serialize usage totals: {}", e);
+                continue;
+            }
+        };
+        let written = async {
+            tokio::fs::write(&tmp_path, &json).await?;
+            tokio::fs::rename(&tmp_path, &path).await
+        };
+        if let Err(e) = written.await {
+            error!("Failed to write usage file {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Measures a streamed chat completion as its SSE chunks pass through, and records it
+/// once the stream ends (or the client goes away). Uses the `usage` of the final chunk
+/// when the engine sends one, otherwise estimates from the streamed deltas. An audit
+/// record, if given, is completed and logged at the same time.
+pub struct StreamMeter {
+    tracker: Arc<UsageTracker>,
+    model: String,
+    prompt_chars: usize,
+    started: Instant,
+    pending: Vec<u8>,
+    completion_chars: usize,
+    usage: Option<TokenCounts>,
+    audit: Option<(AuditLogger, AuditRecord)>,
+    /// The streamed reply, only kept when the audit log records content
+    completion: String,
+}
+
+impl StreamMeter {
+    pub fn new(
+        tracker: Arc<UsageTracker>,
+        model: &str,
+        prompt_chars: usize,
+        started: Instant,
+    ) -> Self {
+        Self {
+            tracker,
+            model: model.to_string(),
+            prompt_chars,
+            started,
+            pending: Vec::new(),
+            completion_chars: 0,
+            usage: None,
+            audit: None,
+            completion: String::new(),
+        }
+    }
+
+    /// Log `record` to `audit` once the stream ends, with the stream's token counts (when
+    /// the engine reports them), reply and full latency filled in.
+    pub fn with_audit(mut self, audit: AuditLogger, record: AuditRecord) -> Self {
+        self.audit = Some((audit, record));
+        self
+    }
+
+    /// Pass `stream` through unchanged while observing each chunk.
+    pub fn meter<S, E>(mut self, stream: S) -> impl Stream<Item = Result<Bytes, E>>
+    where
+        S: Stream<Item = Result<Bytes, E>>,
+    {
+        stream.map(move |chunk| {
+            if let Ok(bytes) = &chunk {
+                self.observe(bytes);
+            }
+            chunk
+        })
+    }
+
+    fn observe(&mut self, chunk: &Bytes) {
+        self.pending.extend_from_slice(chunk);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            self.observe_line(&String::from_utf8_lossy(&line));
+        }
+    }
+
+    fn observe_line(&mut self, line: &str) {
+        let Some(data) = line.trim().strip_prefix("data:") else {
+            return;
+        };
+        let Ok(event) = serde_json::from_str::<serde_json::Value>(data.trim()) else {
+            return;
+        };
+        if let Some(usage) = TokenCounts::from_usage(&event["usage"]) {
+            self.usage = Some(usage);
+        }
+        if let Some(content) = event["choices"][0]["delta"]["content"].as_str() {
+            self.completion_chars += content.chars().count();
+            if let Some((audit, _)) = &self.audit {
+                if audit.include_content() {
+                    self.completion.push_str(content);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for StreamMeter {
+    fn drop(&mut self) {
+        let tokens = self
+            .usage
+            .unwrap_or_else(|| TokenCounts::estimate(self.prompt_chars, self.completion_chars));
+        let latency = self.started.elapsed();
+        self.tracker.record(&self.model, true, tokens, latency);
+
+        if let Some((audit, mut record)) = self.audit.take() {
+            record.latency_ms = latency.as_millis() as u64;
+            if let Some(usage) = self.usage {
+                record.prompt_tokens = Some(usage.prompt);
+                record.completion_tokens = Some(usage.completion);
+                record.total_tokens = Some(usage.prompt + usage.completion);
+            }
+            record.response = (self.completion_chars > 0).then(|| AuditMessage {
+                role: "assistant".to_string(),
+                content_chars: self.completion_chars,
+                content: audit
+                    .include_content()
+                    .then(|| std::mem::take(&mut self.completion)),
+            });
+            audit.log(record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_record_accumulates_per_model_and_reset_clears() {
+        let tracker = UsageTracker::open(None);
+        let reported = TokenCounts {
+            prompt: 10,
+            completion: 5,
+            estimated: false,
+        };
+        tracker.record("llama3:8b", true, reported, Duration::from_millis(100));
+        tracker.record(
+            "llama3:8b",
+            false,
+            TokenCounts::estimate(8, 0),
+            Duration::from_millis(50),
+        );
+
+        let report = tracker.report();
+        assert_eq!(
+            report.models["llama3:8b"],
+            ModelUsage {
+                requests: 2,
+                failed_requests: 1,
+                prompt_tokens: 12,
+                completion_tokens: 5,
+                estimated_requests: 1,
+                total_latency_ms: 150,
+            }
+        );
+
+        tracker.reset();
+        assert!(tracker.report().models.is_empty());
+    }
+
+    #[test]
+    fn test_token_counts_from_usage() {
+        assert_eq!(
+            TokenCounts::from_usage(&json!({"prompt_tokens": 3, "completion_tokens": 4})),
+            Some(TokenCounts {
+                prompt: 3,
+                completion: 4,
+                estimated: false
+            })
+        );
+        assert_eq!(TokenCounts::from_usage(&serde_json::Value::Null), None);
+        assert_eq!(TokenCounts::estimate(9, 0).prompt, 3);
+    }
+
+    #[tokio::test]
+    async fn test_stream_meter_counts_deltas_split_across_chunks() {
+        let tracker = UsageTracker::open(None);
+        let mut meter = StreamMeter::new(tracker.clone(), "llama3:8b", 16, Instant::now());
+        meter.observe(&Bytes::from(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hello \"}}]}\n\ndata: {\"choi",
+        ));
+        meter.observe(&Bytes::from(
+            "ces\":[{\"delta\":{\"content\":\"world!\"}}]}\n\ndata: [DONE]\n\n",
+        ));
+        drop(meter);
+
+        let usage = &tracker.report().models["llama3:8b"];
+        assert_eq!(usage.requests, 1);
+        assert_eq!(usage.prompt_tokens, 4);
+        assert_eq!(usage.completion_tokens, 3);
+        assert_eq!(usage.estimated_requests, 1);
+    }
+
+    #[tokio::test]
+    async fn test_stream_meter_prefers_reported_usage() {
+        let tracker = UsageTracker::open(None);
+        let mut meter = StreamMeter::new(tracker.clone(), "llama3:8b", 400, Instant::now());
+        meter.observe(&Bytes::from(
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":7,\"completion_tokens\":2}}\n\n",
+        ));
+        drop(meter);
+
+        let usage = &tracker.report().models["llama3:8b"];
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (7, 2));
+        assert_eq!(usage.estimated_requests, 0);
+    }
+
+    #[tokio::test]
+    async fn test_totals_survive_restart() {
+        let path = std::env::temp_dir().join(format!("troop-usage-{}.json", uuid::Uuid::new_v4()));
+        let tracker = UsageTracker::open(Some(path.clone()));
+        tracker.record(
+            "llama3:8b",
+            true,
+            TokenCounts::estimate(4, 4),
+            Duration::ZERO,
+        );
+
+        let mut reloaded = None;
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            if let Some(report) = load_report(&path) {
+                reloaded = Some(report);
+                break;
+            }
+        }
+        assert_eq!(reloaded.unwrap(), tracker.report());
+
+        let restarted = UsageTracker::open(Some(path.clone()));
+        assert_eq!(restarted.report().models["llama3:8b"].requests, 1);
+        let _ = std::fs::remove_file(&path);
+    }
+}
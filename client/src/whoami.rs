@@ -0,0 +1,264 @@
+//! Who the client is to the coordinator, for the `whoami` command.
+//!
+//! The requester ID is taken from, in order, `--requester-id`, `REQUESTER_ID`, the config
+//! file, and this machine's Tailscale IPv4 address, with "unknown" as the last resort. It
+//! is shown with where it came from, along with what Tailscale detection found (or why it
+//! found nothing) and whether the coordinator in use knows the ID.
+
+use crate::config::{self, Config, TailscaleError};
+use crate::config_file::{Effective, Source};
+use crate::coordinators::Coordinators;
+use monkey_troop_shared::{BalanceResponse, TroopError, DISCOVERY_TIMEOUT};
+use serde::Serialize;
+
+/// Where the requester ID came from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequesterSource {
+    Flag,
+    Env,
+    File {
+        line: usize,
+    },
+    /// This machine's Tailscale IPv4 address
+    Tailscale,
+    /// Nothing set it and Tailscale detection failed
+    Fallback,
+}
+
+/// One `tailscale ip` lookup: the address, or why there is none.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Detection {
+    pub ip: Option<String>,
+    pub error: Option<String>,
+}
+
+impl From<Result<String, TailscaleError>> for Detection {
+    fn from(result: Result<String, TailscaleError>) -> Self {
+        match result {
+            Ok(ip) => Self {
+                ip: Some(ip),
+                error: None,
+            },
+            Err(e) => Self {
+                ip: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Whether the coordinator knows the requester ID.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Recognition {
+    Known,
+    Unknown,
+    /// The coordinator could not be asked
+    Unchecked {
+        error: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct Identity {
+    pub requester_id: String,
+    pub requester_source: RequesterSource,
+    pub tailscale_ipv4: Detection,
+    pub tailscale_ipv6: Detection,
+    /// The coordinator answering, or the first one tried when none did
+    pub coordinator_url: String,
+    pub coordinator_knows_requester: Recognition,
+    /// Only with `--balance`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance_seconds: Option<i64>,
+}
+
+impl Identity {
+    /// The identity as lines of text.
+    pub fn lines(&self) -> Vec<String> {
+        let source = match &self.requester_source {
+            RequesterSource::Flag => "--requester-id".to_string(),
+            RequesterSource::Env => "REQUESTER_ID".to_string(),
+            RequesterSource::File { line } => format!("the config file, line {line}"),
+            RequesterSource::Tailscale => "Tailscale".to_string(),
+            RequesterSource::Fallback => "fallback, nothing else set it".to_string(),
+        };
+        let detection = |detection: &Detection| match (&detection.ip, &detection.error) {
+            (Some(ip), _) => ip.clone(),
+            (None, error) => format!("not detected ({})", error.as_deref().unwrap_or("-")),
+        };
+        let known = match &self.coordinator_knows_requester {
+            Recognition::Known => "yes".to_string(),
+            Recognition::Unknown => "no".to_string(),
+            Recognition::Unchecked { error } => format!("could not check ({error})"),
+        };
+
+        let mut lines = vec![
+            format!("Requester ID: {} (from {source})", self.requester_id),
+            format!("Tailscale IPv4: {}", detection(&self.tailscale_ipv4)),
+            format!("Tailscale IPv6: {}", detection(&self.tailscale_ipv6)),
+            format!("Coordinator: {}", self.coordinator_url),
+            format!("Known to the coordinator: {known}"),
+        ];
+        if let Some(balance) = self.balance_seconds {
+            lines.push(format!("Balance: {balance} seconds"));
+        }
+        lines
+    }
+}
+
+/// Where `requester_id`, as `settings` resolve it, came from.
+fn requester_source(settings: &[Effective], tailscale_ipv4: &Detection) -> RequesterSource {
+    let source = settings
+        .iter()
+        .find(|setting| setting.key == "requester_id")
+        .map(|setting| setting.source.clone());
+    match source {
+        Some(Source::Flag) => RequesterSource::Flag,
+        Some(Source::Env) => RequesterSource::Env,
+        Some(Source::File { line }) => RequesterSource::File { line },
+        Some(Source::Default) | None if tailscale_ipv4.ip.is_some() => RequesterSource::Tailscale,
+        Some(Source::Default) | None => RequesterSource::Fallback,
+    }
+}
+
+/// Ask the coordinator for `requester_id`'s balance, which it only has for users it knows.
+async fn recognize(
+    coordinators: &Coordinators,
+    requester_id: &str,
+) -> (Recognition, Option<BalanceResponse>) {
+    let path = format!("users/{requester_id}/balance");
+    match coordinators
+        .get_json_once::<BalanceResponse>(&path, DISCOVERY_TIMEOUT)
+        .await
+    {
+        Ok(balance) => (Recognition::Known, Some(balance)),
+        Err(TroopError::UpstreamError { status: 404, .. }) => (Recognition::Unknown, None),
+        Err(e) => (
+            Recognition::Unchecked {
+                error: e.to_string(),
+            },
+            None,
+        ),
+    }
+}
+
+/// Work out who `config` makes this client. `settings` are the effective settings
+/// `config` was loaded from.
+pub async fn check(config: &Config, settings: &[Effective], show_balance: bool) -> Identity {
+    let (ipv4, ipv6) = tokio::join!(
+        tokio::task::spawn_blocking(|| config::tailscale_ip(false)),
+        tokio::task::spawn_blocking(|| config::tailscale_ip(true)),
+    );
+    let join_failed = |e: tokio::task::JoinError| Err(TailscaleError::CommandFailed(e.to_string()));
+    let tailscale_ipv4 = Detection::from(ipv4.unwrap_or_else(join_failed));
+    let tailscale_ipv6 = Detection::from(ipv6.unwrap_or_else(join_failed));
+
+    let coordinators = Coordinators::new(config.coordinator_urls.clone());
+    let (recognition, balance) = recognize(&coordinators, &config.requester_id).await;
+
+    Identity {
+        requester_id: config.requester_id.clone(),
+        requester_source: requester_source(settings, &tailscale_ipv4),
+        tailscale_ipv4,
+        tailscale_ipv6,
+        coordinator_url: coordinators.active().to_string(),
+        coordinator_knows_requester: recognition,
+        balance_seconds: balance
+            .filter(|_| show_balance)
+            .map(|balance| balance.balance_seconds),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use serde_json::json;
+    use url::Url;
+
+    fn setting(source: Source) -> Vec<Effective> {
+        vec![Effective {
+            key: "requester_id",
+            value: None,
+            source,
+        }]
+    }
+
+    #[test]
+    fn test_requester_source() {
+        let detected = Detection::from(Ok("100.64.0.5".to_string()));
+        let missing = Detection::from(Err(TailscaleError::NotInstalled(
+            "Binary 'tailscale' not found in trusted system paths".to_string(),
+        )));
+
+        assert_eq!(
+            requester_source(&setting(Source::Env), &detected),
+            RequesterSource::Env
+        );
+        assert_eq!(
+            requester_source(&setting(Source::File { line: 3 }), &missing),
+            RequesterSource::File { line: 3 }
+        );
+        assert_eq!(
+            requester_source(&setting(Source::Default), &detected),
+            RequesterSource::Tailscale
+        );
+        assert_eq!(
+            requester_source(&setting(Source::Default), &missing),
+            RequesterSource::Fallback
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recognize_tells_known_from_unknown_requesters() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/users/alice/balance");
+            then.status(200).json_body(json!({
+                "public_key": "alice",
+                "balance_seconds": 7200,
+                "balance_hours": 2.0
+            }));
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/users/mallory/balance");
+            then.status(404)
+                .json_body(json!({"detail": "User not found"}));
+        });
+        let coordinators = Coordinators::new(vec![Url::parse(&server.base_url()).unwrap()]);
+
+        let (known, balance) = recognize(&coordinators, "alice").await;
+        assert_eq!(known, Recognition::Known);
+        assert_eq!(balance.unwrap().balance_seconds, 7200);
+        let (unknown, _) = recognize(&coordinators, "mallory").await;
+        assert_eq!(unknown, Recognition::Unknown);
+    }
+
+    #[test]
+    fn test_lines_show_why_tailscale_was_not_detected() {
+        let identity = Identity {
+            requester_id: "unknown".to_string(),
+            requester_source: RequesterSource::Fallback,
+            tailscale_ipv4: Detection::from(Err(TailscaleError::CommandFailed(
+                "exit status: 1: failed to connect to local tailscaled".to_string(),
+            ))),
+            tailscale_ipv6: Detection::from(Ok("fd7a:115c:a1e0::5".to_string())),
+            coordinator_url: "https://troop.100monkeys.ai/".to_string(),
+            coordinator_knows_requester: Recognition::Unknown,
+            balance_seconds: None,
+        };
+        assert_eq!(
+            identity.lines(),
+            [
+                "Requester ID: unknown (from fallback, nothing else set it)",
+                "Tailscale IPv4: not detected (tailscale ip failed: exit status: 1: failed to \
+                 connect to local tailscaled)",
+                "Tailscale IPv6: fd7a:115c:a1e0::5",
+                "Coordinator: https://troop.100monkeys.ai/",
+                "Known to the coordinator: no",
+            ]
+        );
+    }
+}
@@ -9,9 +9,20 @@ async fn test_client_requires_coordinator() {
         model: "llama3:8b".to_string(),
         messages: vec![ChatMessage {
             role: "user".to_string(),
-            content: "Hello".to_string(),
+            content: Some("Hello".into()),
+            tool_calls: None,
+            tool_call_id: None,
         }],
         stream: false,
+        tools: None,
+        tool_choice: None,
+        max_tokens: None,
+        stop: None,
+        temperature: None,
+        top_p: None,
+        seed: None,
+        stream_options: None,
+        extra: Default::default(),
     };
 
     // Should fail if coordinator is not running
@@ -49,14 +60,27 @@ fn test_chat_request_serialization() {
         messages: vec![
             ChatMessage {
                 role: "system".to_string(),
-                content: "You are a helpful assistant".to_string(),
+                content: Some("You are a helpful assistant".into()),
+                tool_calls: None,
+                tool_call_id: None,
             },
             ChatMessage {
                 role: "user".to_string(),
-                content: "Hello!".to_string(),
+                content: Some("Hello!".into()),
+                tool_calls: None,
+                tool_call_id: None,
             },
         ],
         stream: true,
+        tools: None,
+        tool_choice: None,
+        max_tokens: None,
+        stop: None,
+        temperature: None,
+        top_p: None,
+        seed: None,
+        stream_options: None,
+        extra: Default::default(),
     };
 
     let json = serde_json::to_string(&request).unwrap();
@@ -72,3 +96,78 @@ fn test_chat_request_default_stream() {
     // stream should default to false
     assert!(!request.stream);
 }
+
+/// Start the client binary serving only on a unix socket and send a chat completion
+/// through it, as `curl --unix-socket` would.
+#[cfg(unix)]
+#[tokio::test]
+async fn test_chat_completion_over_unix_socket() {
+    use http_body_util::{BodyExt, Full};
+    use httpmock::prelude::*;
+    use hyper_util::rt::TokioIo;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Duration;
+
+    let coordinator = MockServer::start();
+    coordinator.mock(|when, then| {
+        when.method(POST).path("/authorize");
+        then.status(200)
+            .json_body(serde_json::json!({"target_ip": "127.0.0.1", "token": "ticket"}));
+    });
+    let worker = MockServer::start();
+    let completion = worker.mock(|when, then| {
+        when.method(POST).path("/v1/chat/completions");
+        then.status(200)
+            .json_body(serde_json::json!({"choices": [{"message": {"content": "hi"}}]}));
+    });
+
+    let socket =
+        std::env::temp_dir().join(format!("troop-it-{}.sock", uuid::Uuid::new_v4().simple()));
+    let mut proxy = tokio::process::Command::new(env!("CARGO_BIN_EXE_monkey-troop-client"))
+        .arg("up")
+        .env("COORDINATOR_URL", coordinator.base_url())
+        .env("WORKER_PORT", worker.port().to_string())
+        .env("REQUESTER_ID", "uds-test")
+        .env("PROXY_UNIX_SOCKET", &socket)
+        .env("PROXY_TCP", "false")
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+
+    let stream = async {
+        loop {
+            if let Ok(stream) = tokio::net::UnixStream::connect(&socket).await {
+                return stream;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    };
+    let stream = tokio::time::timeout(Duration::from_secs(10), stream)
+        .await
+        .expect("proxy never opened its unix socket");
+    let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .unwrap();
+    tokio::spawn(connection);
+    let body = serde_json::json!({
+        "model": "llama3:8b",
+        "messages": [{"role": "user", "content": "Hello"}]
+    });
+    let request = hyper::Request::post("/v1/chat/completions")
+        .header("host", "localhost")
+        .header("content-type", "application/json")
+        .body(Full::new(bytes::Bytes::from(body.to_string())))
+        .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["choices"][0]["message"]["content"], "hi");
+    completion.assert();
+
+    proxy.kill().await.unwrap();
+    let _ = std::fs::remove_file(&socket);
+}
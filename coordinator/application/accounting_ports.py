@@ -1,9 +1,10 @@
 """Application layer ports (interfaces) for the Accounting context."""
 
 from abc import ABC, abstractmethod
+from datetime import datetime
 from typing import List, Optional
 
-from domain.accounting.models import Transaction, User
+from domain.accounting.models import TokenUsage, Transaction, User
 
 
 class UserRepository(ABC):
@@ -26,5 +27,20 @@ class TransactionRepository(ABC):
         pass
 
     @abstractmethod
-    def get_history_by_user(self, public_key: str, limit: int = 50) -> List[Transaction]:
+    def get_history_by_user(
+        self,
+        public_key: str,
+        limit: int = 50,
+        offset: int = 0,
+        since: Optional[datetime] = None,
+        until: Optional[datetime] = None,
+    ) -> List[Transaction]:
+        pass
+
+
+class TokenUsageRepository(ABC):
+    """Port for persistence of the token usage workers report."""
+
+    @abstractmethod
+    def record_usage(self, usage: TokenUsage) -> None:
         pass
@@ -5,20 +5,27 @@ from datetime import datetime, timezone
 from domain.accounting.models import (
     CreditAmount,
     JobCompletionParams,
+    TokenUsage,
     Transaction,
     TransactionType,
     User,
 )
 
-from .accounting_ports import TransactionRepository, UserRepository
+from .accounting_ports import TokenUsageRepository, TransactionRepository, UserRepository
 
 
 class AccountingService:
     """Orchestrates accounting use cases."""
 
-    def __init__(self, user_repo: UserRepository, txn_repo: TransactionRepository):
+    def __init__(
+        self,
+        user_repo: UserRepository,
+        txn_repo: TransactionRepository,
+        usage_repo: TokenUsageRepository,
+    ):
         self.user_repo = user_repo
         self.txn_repo = txn_repo
+        self.usage_repo = usage_repo
 
     def create_user_if_not_exists(self, public_key: str, starter_credits: int = 3600) -> User:
         """Use Case: Provision a new user with initial credits."""
@@ -96,3 +103,7 @@ class AccountingService:
             "requester_balance": requester.balance.seconds,
             "worker_balance": worker_owner.balance.seconds,
         }
+
+    def record_token_usage(self, usage: TokenUsage) -> None:
+        """Use Case: Keep the tokens a worker reports spending on a completion."""
+        self.usage_repo.record_usage(usage)
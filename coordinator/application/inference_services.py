@@ -2,7 +2,7 @@
 
 import random
 from datetime import datetime, timezone
-from typing import List, Optional
+from typing import Collection, List, Optional
 
 from domain.inference.models import ModelIdentity, Node
 from domain.inference.reputation import (
@@ -31,20 +31,39 @@ class DiscoveryService:
         self.discovery_repo.save_node(node, ttl_seconds)
         self.reputation_repo.record_heartbeat(node.node_id)
 
-    def select_node_for_model(self, identifier: str) -> Optional[Node]:
+    def select_node_for_model(
+        self,
+        identifier: str,
+        exclude_ips: Collection[str] = (),
+        preferred_ip: Optional[str] = None,
+    ) -> Optional[Node]:
         """Use Case: Find an idle node using reputation-weighted selection.
 
         If identifier starts with 'sha256:', match against content_hash;
-        otherwise match against model name.
+        otherwise match against model name. Nodes whose IP is in exclude_ips
+        (ones the requester already found failing) are never selected. The node
+        at preferred_ip (e.g. the one holding a conversation's KV cache) is
+        selected whenever it is idle and not suspended.
         """
         candidates = self.discovery_repo.find_nodes_by_model(identifier)
-        idle_candidates = [n for n in candidates if n.status == "IDLE"]
+        idle_candidates = [
+            n for n in candidates if n.status == "IDLE" and n.tailscale_ip not in exclude_ips
+        ]
 
         if not idle_candidates:
             return None
 
+        preferred = next((n for n in idle_candidates if n.tailscale_ip == preferred_ip), None)
+        if preferred and not self._is_suspended(preferred.node_id):
+            return preferred
+
         return self._weighted_select(idle_candidates)
 
+    def _is_suspended(self, node_id: str) -> bool:
+        rep = self.reputation_repo.get_reputation(node_id)
+        score = rep.score.value if rep else 0.5
+        return ReputationTier.from_score(ReputationScore(score)) == ReputationTier.SUSPENDED
+
     def _weighted_select(self, candidates: List[Node]) -> Optional[Node]:
         """Select a node using reputation-weighted random selection."""
         reps = self.reputation_repo.get_reputations_batch([n.node_id for n in candidates])
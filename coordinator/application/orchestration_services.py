@@ -1,7 +1,7 @@
 """Application layer use cases for orchestrated cross-context workflows."""
 
 from dataclasses import dataclass
-from typing import Optional
+from typing import Collection, Optional
 
 from domain.accounting.models import JobCompletionParams
 
@@ -49,11 +49,18 @@ class OrchestrationService:
         self.discovery_service = discovery_service
         self.security_service = security_service
 
-    def authorize_inference(self, requester_pk: str, model_name: str) -> AuthorizationResult:
+    def authorize_inference(
+        self,
+        requester_pk: str,
+        model_name: str,
+        exclude_nodes: Collection[str] = (),
+        preferred_node: Optional[str] = None,
+    ) -> AuthorizationResult:
         """
         Orchestrate the authorization of an inference request.
         1. Ensure user has sufficient credits.
-        2. Find an available node for the requested model.
+        2. Find an available node for the requested model, other than the excluded IPs,
+           taking the preferred one when it is available.
         3. Issue a signed ticket for the requester to present to the node.
         """
         # 1. Accounting: Ensure user has balance
@@ -63,7 +70,9 @@ class OrchestrationService:
             raise InsufficientCreditsError("Insufficient credits")
 
         # 2. Inference: Discovery an idle node
-        selected_node = self.discovery_service.select_node_for_model(model_name)
+        selected_node = self.discovery_service.select_node_for_model(
+            model_name, exclude_nodes, preferred_node
+        )
         if not selected_node:
             raise NoNodesAvailableError(f"No idle nodes found for model: {model_name}")
 
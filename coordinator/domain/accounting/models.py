@@ -88,3 +88,19 @@ class Transaction:
     amount: CreditAmount
     timestamp: datetime
     type: TransactionType
+
+
+@dataclass(frozen=True)
+class TokenUsage:
+    """Domain Event: tokens a worker node spent on one completion for a requester."""
+
+    node_id: str
+    requester_pk: str
+    model: str
+    prompt_tokens: int
+    completion_tokens: int
+    timestamp: datetime
+
+    def __post_init__(self):
+        if self.prompt_tokens < 0 or self.completion_tokens < 0:
+            raise ValueError("Token counts cannot be negative")
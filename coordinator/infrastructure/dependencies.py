@@ -16,6 +16,7 @@ from infrastructure.persistence.inference_repositories import RedisNodeDiscovery
 
 # Infrastructure Implementations
 from infrastructure.persistence.repositories import (
+    SqlAlchemyTokenUsageRepository,
     SqlAlchemyTransactionRepository,
     SqlAlchemyUserRepository,
 )
@@ -41,7 +42,11 @@ def get_redis_client() -> Redis:
 
 # Dependency Injection Providers
 def get_accounting_service(db: Session = Depends(get_db)) -> AccountingService:
-    return AccountingService(SqlAlchemyUserRepository(db), SqlAlchemyTransactionRepository(db))
+    return AccountingService(
+        SqlAlchemyUserRepository(db),
+        SqlAlchemyTransactionRepository(db),
+        SqlAlchemyTokenUsageRepository(db),
+    )
 
 
 def get_discovery_service(
@@ -139,6 +139,20 @@ class Transaction(Base):
     )
 
 
+class TokenUsageRecord(Base):
+    """Tokens a worker node reported spending on one completion."""
+
+    __tablename__ = "token_usage"
+
+    id = Column(Integer, primary_key=True, index=True)
+    node_id = Column(String(50), index=True, nullable=False)
+    requester = Column(String, index=True, nullable=False)  # Public Key
+    model = Column(String, nullable=False)
+    prompt_tokens = Column(BigInteger, nullable=False)
+    completion_tokens = Column(BigInteger, nullable=False)
+    timestamp = Column(DateTime, default=datetime.utcnow, index=True, nullable=False)
+
+
 class NodeReputationModel(Base):
     """Node reputation tracking data."""
 
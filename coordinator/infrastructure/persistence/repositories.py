@@ -1,11 +1,16 @@
 """Infrastructure layer implementations of the Accounting context repositories."""
 
+from datetime import datetime
 from typing import List, Optional
 
 from sqlalchemy.orm import Session
 
-from application.accounting_ports import TransactionRepository, UserRepository
-from domain.accounting.models import CreditAmount, Transaction, User
+from application.accounting_ports import (
+    TokenUsageRepository,
+    TransactionRepository,
+    UserRepository,
+)
+from domain.accounting.models import CreditAmount, TokenUsage, Transaction, User
 
 from . import database as db_models
 
@@ -70,14 +75,27 @@ class SqlAlchemyTransactionRepository(TransactionRepository):
         self.session.add(db_txn)
         self.session.commit()
 
-    def get_history_by_user(self, public_key: str, limit: int = 50) -> List[Transaction]:
+    def get_history_by_user(
+        self,
+        public_key: str,
+        limit: int = 50,
+        offset: int = 0,
+        since: Optional[datetime] = None,
+        until: Optional[datetime] = None,
+    ) -> List[Transaction]:
+        query = self.session.query(db_models.Transaction).filter(
+            (db_models.Transaction.from_user == public_key)
+            | (db_models.Transaction.to_user == public_key)
+        )
+        if since is not None:
+            query = query.filter(db_models.Transaction.timestamp >= since)
+        if until is not None:
+            query = query.filter(db_models.Transaction.timestamp < until)
         db_txns = (
-            self.session.query(db_models.Transaction)
-            .filter(
-                (db_models.Transaction.from_user == public_key)
-                | (db_models.Transaction.to_user == public_key)
+            query.order_by(
+                db_models.Transaction.timestamp.desc(), db_models.Transaction.id.desc()
             )
-            .order_by(db_models.Transaction.timestamp.desc())
+            .offset(offset)
             .limit(limit)
             .all()
         )
@@ -94,3 +112,23 @@ class SqlAlchemyTransactionRepository(TransactionRepository):
             )
             for txn in db_txns
         ]
+
+
+class SqlAlchemyTokenUsageRepository(TokenUsageRepository):
+    """SqlAlchemy implementation of the TokenUsageRepository."""
+
+    def __init__(self, session: Session):
+        self.session = session
+
+    def record_usage(self, usage: TokenUsage) -> None:
+        self.session.add(
+            db_models.TokenUsageRecord(
+                node_id=usage.node_id,
+                requester=usage.requester_pk,
+                model=usage.model,
+                prompt_tokens=usage.prompt_tokens,
+                completion_tokens=usage.completion_tokens,
+                timestamp=usage.timestamp,
+            )
+        )
+        self.session.commit()
@@ -1,13 +1,19 @@
 """FastAPI endpoints for the Accounting context."""
 
-from fastapi import APIRouter, Depends
+from datetime import datetime, timezone
+from typing import Optional
+
+from fastapi import APIRouter, Depends, Query
 
 from application.accounting_services import AccountingService
+from domain.accounting.models import TokenUsage
 from infrastructure.dependencies import get_accounting_service
 
-from .schemas import BalanceResponseSchema
+from .schemas import BalanceResponseSchema, UsageReportSchema
 
 router = APIRouter(prefix="/users", tags=["Accounting"])
+# Reports from worker nodes, outside the per-user routes
+usage_router = APIRouter(tags=["Accounting"])
 
 
 @router.get("/{public_key}/balance", response_model=BalanceResponseSchema)
@@ -27,11 +33,20 @@ def get_balance(
 @router.get("/{public_key}/transactions")
 def get_transactions(
     public_key: str,
-    limit: int = 50,
+    limit: int = Query(50, ge=1, le=1000),
+    offset: int = Query(0, ge=0),
+    since: Optional[datetime] = None,
+    until: Optional[datetime] = None,
     accounting_service: AccountingService = Depends(get_accounting_service),
 ):
-    """Get transaction history for a user."""
-    history = accounting_service.txn_repo.get_history_by_user(public_key, limit)
+    """Get transaction history for a user, most recent first.
+
+    `since` (inclusive) and `until` (exclusive) bound the timestamps, in UTC;
+    `offset` skips that many matching transactions, for paging.
+    """
+    history = accounting_service.txn_repo.get_history_by_user(
+        public_key, limit, offset=offset, since=since, until=until
+    )
 
     return {
         "transactions": [
@@ -46,3 +61,22 @@ def get_transactions(
             for txn in history
         ]
     }
+
+
+@usage_router.post("/usage")
+def report_usage(
+    report: UsageReportSchema,
+    accounting_service: AccountingService = Depends(get_accounting_service),
+):
+    """Record the tokens a worker spent on one completion, for billing."""
+    accounting_service.record_token_usage(
+        TokenUsage(
+            node_id=report.node_id,
+            requester_pk=report.requester,
+            model=report.model,
+            prompt_tokens=report.prompt_tokens,
+            completion_tokens=report.completion_tokens,
+            timestamp=datetime.now(timezone.utc),
+        )
+    )
+    return {"status": "recorded"}
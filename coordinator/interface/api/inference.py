@@ -30,7 +30,9 @@ async def authorize_request(
     from application.orchestration_services import InsufficientCreditsError, NoNodesAvailableError
 
     try:
-        result = orchestration_service.authorize_inference(req.requester, req.model)
+        result = orchestration_service.authorize_inference(
+            req.requester, req.model, req.exclude_nodes, req.preferred_node
+        )
     except InsufficientCreditsError as e:
         raise HTTPException(status_code=402, detail=str(e))
     except NoNodesAvailableError as e:
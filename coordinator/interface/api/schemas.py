@@ -2,7 +2,7 @@
 
 from typing import List, Optional
 
-from pydantic import BaseModel
+from pydantic import BaseModel, Field
 
 
 class EngineInfoSchema(BaseModel):
@@ -49,6 +49,10 @@ class VerifyRequestSchema(BaseModel):
 class AuthorizeRequestSchema(BaseModel):
     model: str
     requester: str
+    # IPs of nodes the requester found failing, which are not to be offered again
+    exclude_nodes: List[str] = []
+    # IP of the node the requester would like (e.g. its session's previous one), if idle
+    preferred_node: Optional[str] = None
 
 
 class AuthorizeResponseSchema(BaseModel):
@@ -58,6 +62,15 @@ class AuthorizeResponseSchema(BaseModel):
     encryption_public_key: Optional[str] = None
 
 
+class UsageReportSchema(BaseModel):
+    node_id: str
+    # The requester the ticket was issued to
+    requester: str
+    model: str
+    prompt_tokens: int = Field(ge=0)
+    completion_tokens: int = Field(ge=0)
+
+
 class BalanceResponseSchema(BaseModel):
     public_key: str
     balance_seconds: int
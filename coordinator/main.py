@@ -13,6 +13,7 @@ from infrastructure.security.key_repository import FileSystemKeyRepository
 
 # Import Context-Specific Routers (Interface Layer)
 from interface.api.accounting import router as accounting_router
+from interface.api.accounting import usage_router
 from interface.api.inference import router as inference_router
 from interface.api.security import router as security_router
 from interface.api.verification import router as verification_router
@@ -37,6 +38,7 @@ app.add_middleware(
 )
 
 app.include_router(accounting_router)
+app.include_router(usage_router)
 app.include_router(inference_router)
 app.include_router(verification_router)
 app.include_router(security_router)
@@ -0,0 +1,39 @@
+"""Add token_usage table for usage reported by worker nodes
+
+Revision ID: 005_token_usage
+Revises: 004_node_reputation
+Create Date: 2026-10-15 00:00:00
+
+"""
+
+import sqlalchemy as sa
+from alembic import op
+
+# revision identifiers, used by Alembic.
+revision = "005_token_usage"
+down_revision = "004_node_reputation"
+branch_labels = None
+depends_on = None
+
+
+def upgrade() -> None:
+    op.create_table(
+        "token_usage",
+        sa.Column("id", sa.Integer(), primary_key=True, index=True),
+        sa.Column("node_id", sa.String(50), nullable=False),
+        sa.Column("requester", sa.String(), nullable=False),
+        sa.Column("model", sa.String(), nullable=False),
+        sa.Column("prompt_tokens", sa.BigInteger(), nullable=False),
+        sa.Column("completion_tokens", sa.BigInteger(), nullable=False),
+        sa.Column("timestamp", sa.DateTime(), nullable=False),
+    )
+    op.create_index("ix_token_usage_node_id", "token_usage", ["node_id"], unique=False)
+    op.create_index("ix_token_usage_requester", "token_usage", ["requester"], unique=False)
+    op.create_index("ix_token_usage_timestamp", "token_usage", ["timestamp"], unique=False)
+
+
+def downgrade() -> None:
+    op.drop_index("ix_token_usage_timestamp", table_name="token_usage")
+    op.drop_index("ix_token_usage_requester", table_name="token_usage")
+    op.drop_index("ix_token_usage_node_id", table_name="token_usage")
+    op.drop_table("token_usage")
@@ -1,4 +1,4 @@
-from datetime import datetime
+from datetime import datetime, timedelta
 
 import pytest
 from fastapi.testclient import TestClient
@@ -50,3 +50,58 @@ def test_get_transactions(client, db_session):
     assert len(data["transactions"]) == 1
     assert data["transactions"][0]["requester"] == "user_a"
     assert data["transactions"][0]["credits"] == 100
+
+
+def test_get_transactions_pages_and_filters_by_date(client, db_session):
+    start = datetime(2026, 1, 1)
+    for day in range(5):
+        db_session.add(
+            db_models.Transaction(
+                job_id=f"job_{day}",
+                from_user="user_b",
+                to_user="worker_1",
+                duration_seconds=10,
+                credits_transferred=day + 1,
+                timestamp=start + timedelta(days=day),
+            )
+        )
+    db_session.commit()
+
+    def credits(**params):
+        response = client.get("/users/user_b/transactions", params=params)
+        assert response.status_code == 200
+        return [txn["credits"] for txn in response.json()["transactions"]]
+
+    # Most recent first, one page at a time
+    assert credits(limit=2) == [5, 4]
+    assert credits(limit=2, offset=2) == [3, 2]
+    assert credits(limit=2, offset=4) == [1]
+    # `since` is inclusive and `until` exclusive
+    assert credits(since="2026-01-02T00:00:00", until="2026-01-04T00:00:00") == [3, 2]
+    assert client.get("/users/user_b/transactions", params={"limit": 0}).status_code == 422
+
+
+def test_report_usage(client, db_session):
+    report = {
+        "node_id": "node_1",
+        "requester": "user_c",
+        "model": "llama3",
+        "prompt_tokens": 9,
+        "completion_tokens": 12,
+    }
+    response = client.post("/usage", json=report)
+    assert response.status_code == 200
+
+    row = (
+        db_session.query(db_models.TokenUsageRecord)
+        .filter(db_models.TokenUsageRecord.requester == "user_c")
+        .one()
+    )
+    assert (row.node_id, row.model, row.prompt_tokens, row.completion_tokens) == (
+        "node_1",
+        "llama3",
+        9,
+        12,
+    )
+
+    assert client.post("/usage", json={**report, "prompt_tokens": -1}).status_code == 422
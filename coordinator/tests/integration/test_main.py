@@ -76,6 +76,55 @@ def test_authorize_request_no_node(client, redis_client):
     assert "No idle nodes found" in response.json()["detail"]
 
 
+def test_authorize_request_skips_excluded_nodes(client, redis_client):
+    """Test a node the requester excluded is not offered again."""
+    node_id = "test_node_excluded"
+    model_name = "excluded_model"
+    node_data = {
+        "node_id": node_id,
+        "tailscale_ip": "100.64.0.9",
+        "status": "IDLE",
+        "models": [_model_dict(model_name)],
+        "hardware": {"gpu": "RTX 4090", "vram_free": 24576},
+        "engines": [],
+    }
+    redis_client.setex(f"node:{node_id}", 60, json.dumps(node_data))
+
+    response = client.post(
+        "/authorize",
+        json={"model": model_name, "requester": "user_main_test", "exclude_nodes": ["100.64.0.9"]},
+    )
+
+    assert response.status_code == 503
+
+
+def test_authorize_request_honours_preferred_node(client, redis_client):
+    """Test the requester's preferred node is assigned while it is idle."""
+    model_name = "preferred_model"
+    for index in range(3):
+        node_data = {
+            "node_id": f"test_node_preferred_{index}",
+            "tailscale_ip": f"100.64.1.{index}",
+            "status": "IDLE",
+            "models": [_model_dict(model_name)],
+            "hardware": {"gpu": "RTX 4090", "vram_free": 24576},
+            "engines": [],
+        }
+        redis_client.setex(f"node:test_node_preferred_{index}", 60, json.dumps(node_data))
+
+    for _ in range(5):
+        response = client.post(
+            "/authorize",
+            json={
+                "model": model_name,
+                "requester": "user_main_test",
+                "preferred_node": "100.64.1.2",
+            },
+        )
+        assert response.status_code == 200
+        assert response.json()["target_ip"] == "100.64.1.2"
+
+
 def test_authorize_request_insufficient_credits(client, db_session, redis_client):
     """Test authorization fails when user has low balance."""
     # Setup node
@@ -1,9 +1,15 @@
+from datetime import datetime, timezone
 from unittest.mock import MagicMock
 
 import pytest
 
 from coordinator.application.accounting_services import AccountingService
-from coordinator.domain.accounting.models import JobCompletionParams, TransactionType, User
+from coordinator.domain.accounting.models import (
+    JobCompletionParams,
+    TokenUsage,
+    TransactionType,
+    User,
+)
 
 
 @pytest.fixture
@@ -17,8 +23,13 @@ def mock_txn_repo():
 
 
 @pytest.fixture
-def accounting_service(mock_user_repo, mock_txn_repo):
-    return AccountingService(mock_user_repo, mock_txn_repo)
+def mock_usage_repo():
+    return MagicMock()
+
+
+@pytest.fixture
+def accounting_service(mock_user_repo, mock_txn_repo, mock_usage_repo):
+    return AccountingService(mock_user_repo, mock_txn_repo, mock_usage_repo)
 
 
 def test_create_user_if_not_exists_new_user(accounting_service, mock_user_repo, mock_txn_repo):
@@ -165,3 +176,21 @@ def test_process_job_completion_worker_owner_not_exists(
 
     # Check that SOME saved user had 100 credits (it would be the last one saved)
     assert any(u.public_key == "new_worker" and u.balance.seconds == 100 for u in saved_users)
+
+
+def test_record_token_usage(accounting_service, mock_usage_repo):
+    usage = TokenUsage(
+        node_id="node1",
+        requester_pk="user1",
+        model="llama3",
+        prompt_tokens=9,
+        completion_tokens=12,
+        timestamp=datetime.now(timezone.utc),
+    )
+    accounting_service.record_token_usage(usage)
+    mock_usage_repo.record_usage.assert_called_once_with(usage)
+
+
+def test_token_usage_rejects_negative_counts():
+    with pytest.raises(ValueError):
+        TokenUsage("node1", "user1", "llama3", -1, 0, datetime.now(timezone.utc))
@@ -91,6 +91,44 @@ def test_select_node_for_model_by_hash(
     mock_discovery_repo.find_nodes_by_model.assert_called_once_with("sha256:abc")
 
 
+def test_select_node_for_model_skips_excluded_ips(
+    discovery_service, mock_discovery_repo, mock_reputation_repo
+):
+    node1 = _make_node("n1")
+    node2 = _make_node("n2")
+    mock_discovery_repo.find_nodes_by_model.return_value = [node1, node2]
+    mock_reputation_repo.get_reputations_batch.return_value = []
+
+    for _ in range(20):
+        assert discovery_service.select_node_for_model("m1", ["100.1.1.1"]) == node2
+    assert discovery_service.select_node_for_model("m1", ["100.1.1.1", "100.1.1.2"]) is None
+
+
+def test_select_node_for_model_honours_preferred_ip(
+    discovery_service, mock_discovery_repo, mock_reputation_repo
+):
+    node1 = _make_node("n1")
+    node2 = _make_node("n2")
+    busy = _make_node("n3", status="BUSY")
+    mock_discovery_repo.find_nodes_by_model.return_value = [node1, node2, busy]
+    mock_reputation_repo.get_reputations_batch.return_value = []
+    mock_reputation_repo.get_reputation.return_value = None
+
+    for _ in range(20):
+        assert discovery_service.select_node_for_model("m1", preferred_ip="100.1.1.2") == node2
+
+    # Only a preference: a busy, excluded or suspended node is not forced
+    assert discovery_service.select_node_for_model("m1", preferred_ip="100.1.1.3") != busy
+    assert (
+        discovery_service.select_node_for_model("m1", ["100.1.1.2"], preferred_ip="100.1.1.2")
+        == node1
+    )
+    mock_reputation_repo.get_reputation.return_value = _make_reputation("n2", 0.05)
+    mock_reputation_repo.get_reputations_batch.return_value = [_make_reputation("n2", 0.05)]
+    for _ in range(20):
+        assert discovery_service.select_node_for_model("m1", preferred_ip="100.1.1.2") == node1
+
+
 def test_select_node_for_model_none_idle(discovery_service, mock_discovery_repo):
     node1 = Node("n1", "ip1", "BUSY", [_mi("m1")], HardwareSpec("g1", 1), [])
     mock_discovery_repo.find_nodes_by_model.return_value = [node1]
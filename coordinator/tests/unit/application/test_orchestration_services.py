@@ -64,10 +64,26 @@ def test_authorize_inference_success(
     assert result.encryption_public_key == "key1"
 
     mock_accounting_service.create_user_if_not_exists.assert_called_once_with("user1")
-    mock_discovery_service.select_node_for_model.assert_called_once_with("gpt-4")
+    mock_discovery_service.select_node_for_model.assert_called_once_with("gpt-4", (), None)
     mock_security_service.issue_authorization_ticket.assert_called_once_with("user1", "node1")
 
 
+def test_authorize_inference_passes_node_choices(
+    orchestration_service, mock_accounting_service, mock_discovery_service
+):
+    mock_user = MagicMock()
+    mock_user.balance.seconds = 1000
+    mock_accounting_service.create_user_if_not_exists.return_value = mock_user
+    mock_discovery_service.select_node_for_model.return_value = None
+
+    with pytest.raises(NoNodesAvailableError):
+        orchestration_service.authorize_inference("user1", "gpt-4", ["1.2.3.4"], "5.6.7.8")
+
+    mock_discovery_service.select_node_for_model.assert_called_once_with(
+        "gpt-4", ["1.2.3.4"], "5.6.7.8"
+    )
+
+
 def test_authorize_inference_insufficient_credits(orchestration_service, mock_accounting_service):
     # Setup mock user with less than 300 credits
     mock_user = MagicMock()
@@ -1,7 +1,9 @@
 from datetime import datetime
 
-from domain.accounting.models import CreditAmount, Transaction, User
+from domain.accounting.models import CreditAmount, TokenUsage, Transaction, User
+from infrastructure.persistence import database as db_models
 from infrastructure.persistence.repositories import (
+    SqlAlchemyTokenUsageRepository,
     SqlAlchemyTransactionRepository,
     SqlAlchemyUserRepository,
 )
@@ -63,3 +65,22 @@ def test_sqlalchemy_transaction_repository_record_and_get_history(db_session):
 
     history_none = repo.get_history_by_user("user_c")
     assert len(history_none) == 0
+
+
+def test_sqlalchemy_token_usage_repository_records_usage(db_session):
+    repo = SqlAlchemyTokenUsageRepository(db_session)
+    repo.record_usage(
+        TokenUsage(
+            node_id="node_1",
+            requester_pk="user_a",
+            model="llama3",
+            prompt_tokens=9,
+            completion_tokens=12,
+            timestamp=datetime.utcnow(),
+        )
+    )
+
+    rows = db_session.query(db_models.TokenUsageRecord).all()
+    assert len(rows) == 1
+    assert (rows[0].node_id, rows[0].requester, rows[0].model) == ("node_1", "user_a", "llama3")
+    assert (rows[0].prompt_tokens, rows[0].completion_tokens) == (9, 12)
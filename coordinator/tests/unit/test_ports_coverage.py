@@ -1,6 +1,10 @@
 import pytest
 
-from application.accounting_ports import TransactionRepository, UserRepository
+from application.accounting_ports import (
+    TokenUsageRepository,
+    TransactionRepository,
+    UserRepository,
+)
 from application.inference_ports import NodeDiscoveryRepository
 from application.security_ports import KeyRepository, TokenService
 from application.verification_ports import BenchmarkRepository, ChallengeRepository
@@ -14,6 +18,9 @@ def test_ports_are_abstract():
     with pytest.raises(TypeError):
         TransactionRepository()
 
+    with pytest.raises(TypeError):
+        TokenUsageRepository()
+
     with pytest.raises(TypeError):
         NodeDiscoveryRepository()
 
@@ -1,3 +1,4 @@
+use serde::Serialize;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
@@ -5,62 +6,166 @@ use tokio::sync::RwLock;
 use tokio::time::Instant;
 
 /// Circuit breaker state
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum CircuitState {
     Closed,   // Normal operation
     Open,     // Failures exceeded threshold, blocking requests
     HalfOpen, // Testing if service recovered
 }
 
+/// Callback invoked with `(from, to)` whenever the breaker changes state
+pub type TransitionCallback = Box<dyn Fn(CircuitState, CircuitState) + Send + Sync>;
+
 /// Simple circuit breaker implementation
 pub struct CircuitBreaker {
     failure_count: AtomicU32,
+    success_count: AtomicU32,
     threshold: u32,
+    success_threshold: u32,
+    half_open_in_flight: AtomicU32,
+    half_open_max_probes: u32,
+    /// Bumped on every move to HalfOpen, so a probe from an earlier one frees no slot
+    probe_epoch: AtomicU32,
     timeout: Duration,
     state: Arc<RwLock<CircuitState>>,
     last_failure_time: Arc<RwLock<Option<Instant>>>,
+    on_state_change: Option<TransitionCallback>,
 }
 
 impl CircuitBreaker {
     pub fn new(threshold: u32, timeout: Duration) -> Self {
         Self {
             failure_count: AtomicU32::new(0),
+            success_count: AtomicU32::new(0),
             threshold,
+            success_threshold: 1,
+            half_open_in_flight: AtomicU32::new(0),
+            half_open_max_probes: 1,
+            probe_epoch: AtomicU32::new(0),
             timeout,
             state: Arc::new(RwLock::new(CircuitState::Closed)),
             last_failure_time: Arc::new(RwLock::new(None)),
+            on_state_change: None,
+        }
+    }
+
+    /// Require `n` consecutive successes while HalfOpen before closing the circuit
+    pub fn with_success_threshold(mut self, n: u32) -> Self {
+        self.success_threshold = n.max(1);
+        self
+    }
+
+    /// Admit at most `n` concurrent probe requests while HalfOpen (default 1)
+    pub fn with_half_open_max_probes(mut self, n: u32) -> Self {
+        self.half_open_max_probes = n.max(1);
+        self
+    }
+
+    /// Register a callback fired on every state change (e.g. for logging or metrics).
+    /// It is invoked after the state lock has been released.
+    pub fn on_transition<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(CircuitState, CircuitState) + Send + Sync + 'static,
+    {
+        self.on_state_change = Some(Box::new(callback));
+        self
+    }
+
+    fn notify(&self, from: CircuitState, to: CircuitState) {
+        if from != to {
+            if let Some(callback) = &self.on_state_change {
+                callback(from, to);
+            }
         }
     }
 
     /// Check if request should be allowed
     pub async fn allow_request(&self) -> bool {
+        self.acquire().await.is_some()
+    }
+
+    /// Like `allow_request`, but the admitted request is handed back as an `Admission`,
+    /// which frees its HalfOpen probe slot if it is dropped before an outcome is recorded.
+    pub async fn admit(self: &Arc<Self>) -> Option<Admission> {
+        let probe = self.acquire().await?;
+        Some(Admission {
+            breaker: self.clone(),
+            probe,
+            settled: false,
+        })
+    }
+
+    /// Admit a request, returning the probe epoch when it took a HalfOpen probe slot.
+    async fn acquire(&self) -> Option<Option<u32>> {
         let state = *self.state.read().await;
 
         match state {
-            CircuitState::Closed => true,
+            CircuitState::Closed => Some(None),
             CircuitState::Open => {
                 // Check if timeout has elapsed
-                let last_failure = self.last_failure_time.read().await;
-                if let Some(time) = *last_failure {
-                    if time.elapsed() >= self.timeout {
+                let timed_out = match *self.last_failure_time.read().await {
+                    Some(time) => time.elapsed() >= self.timeout,
+                    None => false,
+                };
+                if !timed_out {
+                    return None;
+                }
+
+                // Re-check under the write lock so only one caller performs the transition
+                let mut state = self.state.write().await;
+                match *state {
+                    CircuitState::Closed => Some(None),
+                    CircuitState::Open => {
                         // Try half-open
-                        *self.state.write().await = CircuitState::HalfOpen;
-                        true
-                    } else {
-                        false
+                        self.success_count.store(0, Ordering::Relaxed);
+                        self.half_open_in_flight.store(0, Ordering::Relaxed);
+                        self.probe_epoch.fetch_add(1, Ordering::AcqRel);
+                        *state = CircuitState::HalfOpen;
+                        let admitted = self.try_acquire_probe();
+                        drop(state);
+                        self.notify(CircuitState::Open, CircuitState::HalfOpen);
+                        admitted
                     }
-                } else {
-                    false
+                    CircuitState::HalfOpen => self.try_acquire_probe(),
                 }
             }
-            CircuitState::HalfOpen => true,
+            CircuitState::HalfOpen => self.try_acquire_probe(),
         }
     }
 
+    fn try_acquire_probe(&self) -> Option<Option<u32>> {
+        let epoch = self.probe_epoch.load(Ordering::Acquire);
+        self.half_open_in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < self.half_open_max_probes).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| Some(epoch))
+    }
+
+    fn release_probe(&self) {
+        let _ = self
+            .half_open_in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1));
+    }
+
     /// Record successful request
     pub async fn record_success(&self) {
+        let mut state = self.state.write().await;
+        let previous = *state;
+        if previous == CircuitState::HalfOpen {
+            self.release_probe();
+            let successes = self.success_count.fetch_add(1, Ordering::Relaxed) + 1;
+            if successes < self.success_threshold {
+                return;
+            }
+        }
         self.failure_count.store(0, Ordering::Relaxed);
-        *self.state.write().await = CircuitState::Closed;
+        self.success_count.store(0, Ordering::Relaxed);
+        *state = CircuitState::Closed;
+        drop(state);
+        self.notify(previous, CircuitState::Closed);
     }
 
     /// Record failed request
@@ -68,8 +173,15 @@ impl CircuitBreaker {
         let count = self.failure_count.fetch_add(1, Ordering::Relaxed) + 1;
         *self.last_failure_time.write().await = Some(Instant::now());
 
-        if count >= self.threshold {
-            *self.state.write().await = CircuitState::Open;
+        let mut state = self.state.write().await;
+        let previous = *state;
+        // Any failure while probing re-opens the circuit immediately
+        if previous == CircuitState::HalfOpen || count >= self.threshold {
+            self.success_count.store(0, Ordering::Relaxed);
+            self.half_open_in_flight.store(0, Ordering::Relaxed);
+            *state = CircuitState::Open;
+            drop(state);
+            self.notify(previous, CircuitState::Open);
         }
     }
 
@@ -79,6 +191,46 @@ impl CircuitBreaker {
     }
 }
 
+/// A request admitted by `CircuitBreaker::admit`. Its outcome is recorded with
+/// `record_success` or `record_failure`; dropped without one (cancelled, or abandoned
+/// before it reached the service) it counts neither way, but gives back the HalfOpen
+/// probe slot it holds so another request can probe.
+pub struct Admission {
+    breaker: Arc<CircuitBreaker>,
+    /// The HalfOpen epoch whose probe slot this holds, if any
+    probe: Option<u32>,
+    settled: bool,
+}
+
+impl Admission {
+    pub fn breaker(&self) -> &Arc<CircuitBreaker> {
+        &self.breaker
+    }
+
+    pub async fn record_success(mut self) {
+        self.settled = true;
+        self.breaker.record_success().await;
+    }
+
+    pub async fn record_failure(mut self) {
+        self.settled = true;
+        self.breaker.record_failure().await;
+    }
+}
+
+impl Drop for Admission {
+    fn drop(&mut self) {
+        if self.settled {
+            return;
+        }
+        if let Some(epoch) = self.probe {
+            if self.breaker.probe_epoch.load(Ordering::Acquire) == epoch {
+                self.breaker.release_probe();
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -154,4 +306,160 @@ mod tests {
         assert_eq!(cb.state().await, CircuitState::Open);
         assert!(!cb.allow_request().await);
     }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_success_threshold_prevents_flapping() {
+        tokio::time::pause();
+        let cb = CircuitBreaker::new(1, Duration::from_millis(50)).with_success_threshold(3);
+
+        cb.record_failure().await;
+        tokio::time::advance(Duration::from_millis(60)).await;
+        assert!(cb.allow_request().await);
+        assert_eq!(cb.state().await, CircuitState::HalfOpen);
+
+        // One success is not enough to close
+        cb.record_success().await;
+        assert_eq!(cb.state().await, CircuitState::HalfOpen);
+        cb.record_success().await;
+        assert_eq!(cb.state().await, CircuitState::HalfOpen);
+
+        // A failure while probing re-opens immediately and discards progress
+        cb.record_failure().await;
+        assert_eq!(cb.state().await, CircuitState::Open);
+
+        tokio::time::advance(Duration::from_millis(60)).await;
+        assert!(cb.allow_request().await);
+        cb.record_success().await;
+        cb.record_success().await;
+        assert_eq!(cb.state().await, CircuitState::HalfOpen);
+        cb.record_success().await;
+        assert_eq!(cb.state().await, CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_half_open_failure_below_threshold() {
+        tokio::time::pause();
+        let cb = CircuitBreaker::new(3, Duration::from_millis(50)).with_success_threshold(2);
+
+        for _ in 0..3 {
+            cb.record_failure().await;
+        }
+        tokio::time::advance(Duration::from_millis(60)).await;
+        assert!(cb.allow_request().await);
+
+        cb.record_success().await;
+        assert_eq!(cb.state().await, CircuitState::HalfOpen);
+        cb.record_failure().await;
+        assert_eq!(cb.state().await, CircuitState::Open);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_half_open_limits_concurrent_probes() {
+        tokio::time::pause();
+        let cb =
+            Arc::new(CircuitBreaker::new(1, Duration::from_millis(50)).with_success_threshold(2));
+
+        cb.record_failure().await;
+        tokio::time::advance(Duration::from_millis(60)).await;
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let cb = cb.clone();
+                tokio::spawn(async move { cb.allow_request().await })
+            })
+            .collect();
+        let mut admitted = 0;
+        for handle in handles {
+            if handle.await.unwrap() {
+                admitted += 1;
+            }
+        }
+        assert_eq!(admitted, 1);
+        assert_eq!(cb.state().await, CircuitState::HalfOpen);
+
+        // The probe's result frees the slot for the next one
+        cb.record_success().await;
+        assert!(cb.allow_request().await);
+        assert!(!cb.allow_request().await);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_half_open_custom_probe_limit() {
+        tokio::time::pause();
+        let cb = CircuitBreaker::new(1, Duration::from_millis(50)).with_half_open_max_probes(3);
+
+        cb.record_failure().await;
+        tokio::time::advance(Duration::from_millis(60)).await;
+
+        let admitted = [
+            cb.allow_request().await,
+            cb.allow_request().await,
+            cb.allow_request().await,
+            cb.allow_request().await,
+        ];
+        assert_eq!(admitted, [true, true, true, false]);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_dropped_admission_frees_its_probe() {
+        tokio::time::pause();
+        let cb = Arc::new(CircuitBreaker::new(1, Duration::from_millis(50)));
+
+        // Admitted while closed: holds no probe slot
+        let closed = cb.admit().await.unwrap();
+        cb.record_failure().await;
+        tokio::time::advance(Duration::from_millis(60)).await;
+        let probe = cb.admit().await.unwrap();
+        drop(closed);
+        assert!(cb.admit().await.is_none());
+
+        // A probe abandoned without an outcome lets the next request probe
+        drop(probe);
+        assert_eq!(cb.state().await, CircuitState::HalfOpen);
+        let probe = cb.admit().await.unwrap();
+        assert!(cb.admit().await.is_none());
+
+        // One from an earlier HalfOpen period does not free a newer probe's slot
+        probe.record_failure().await;
+        let stale = Admission {
+            breaker: cb.clone(),
+            probe: Some(cb.probe_epoch.load(Ordering::Acquire)),
+            settled: false,
+        };
+        tokio::time::advance(Duration::from_millis(60)).await;
+        let probe = cb.admit().await.unwrap();
+        drop(stale);
+        assert!(cb.admit().await.is_none());
+
+        probe.record_success().await;
+        assert_eq!(cb.state().await, CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_transition_callback() {
+        tokio::time::pause();
+        let transitions = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = transitions.clone();
+        let cb =
+            CircuitBreaker::new(2, Duration::from_millis(50)).on_transition(move |from, to| {
+                recorded.lock().unwrap().push((from, to));
+            });
+
+        cb.record_failure().await;
+        cb.record_failure().await;
+        cb.record_failure().await; // Already open: no duplicate notification
+        tokio::time::advance(Duration::from_millis(60)).await;
+        cb.allow_request().await;
+        cb.record_success().await;
+        cb.record_success().await; // Already closed: no notification
+
+        assert_eq!(
+            *transitions.lock().unwrap(),
+            vec![
+                (CircuitState::Closed, CircuitState::Open),
+                (CircuitState::Open, CircuitState::HalfOpen),
+                (CircuitState::HalfOpen, CircuitState::Closed),
+            ]
+        );
+    }
 }
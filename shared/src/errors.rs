@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::time::Duration;
 
@@ -10,6 +11,12 @@ pub const INFERENCE_TIMEOUT: Duration = Duration::from_secs(300);
 pub const MAX_RETRIES: u32 = 3;
 pub const RETRY_DELAYS: [u64; 3] = [1, 2, 4]; // seconds
 
+/// Longest `Retry-After` hint honored; a coordinator asking for more is retried sooner
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// What to check when a coordinator cannot be reached, appended to its error for people
+pub const COORDINATOR_HINT: &str = "is the coordinator running, and is COORDINATOR_URL right?";
+
 /// Circuit breaker configuration
 pub const CIRCUIT_BREAKER_THRESHOLD: u32 = 5;
 pub const CIRCUIT_BREAKER_TIMEOUT: Duration = Duration::from_secs(60);
@@ -20,6 +27,9 @@ pub enum TroopError {
     /// Network connection failed
     NetworkError(String),
 
+    /// No connection could be made to the coordinator
+    CoordinatorUnreachable(String),
+
     /// Request timed out
     Timeout(String),
 
@@ -35,12 +45,31 @@ pub enum TroopError {
     /// Invalid request format
     InvalidRequest(String),
 
-    /// Worker is busy or unavailable
+    /// The requested model is not served here
+    ModelNotFound(String),
+
+    /// The caller may not do what it asked
+    PermissionDenied(String),
+
+    /// The upstream does not offer the operation, typically because it is an older version
+    Unsupported(String),
+
+    /// Worker failed to serve a request it was sent
     WorkerUnavailable(String),
 
     /// Circuit breaker is open
     CircuitBreakerOpen,
 
+    /// Upstream asked us to slow down (HTTP 429), optionally with a Retry-After hint
+    /// and the reason it gave
+    RateLimited {
+        retry_after: Option<Duration>,
+        message: Option<String>,
+    },
+
+    /// Upstream answered with a non-success status; the body is kept so it can be relayed
+    UpstreamError { status: u16, body: String },
+
     /// Internal server error
     InternalError(String),
 }
@@ -49,6 +78,7 @@ impl fmt::Display for TroopError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             TroopError::NetworkError(msg) => write!(f, "Network error: {msg}"),
+            TroopError::CoordinatorUnreachable(msg) => write!(f, "Coordinator unreachable: {msg}"),
             TroopError::Timeout(msg) => write!(f, "Timeout: {msg}"),
             TroopError::AuthError(msg) => write!(f, "Authentication error: {msg}"),
             TroopError::NoNodesAvailable => write!(f, "No nodes available to service request"),
@@ -59,26 +89,281 @@ impl fmt::Display for TroopError {
                 write!(f, "Insufficient credits: need {required}, have {available}")
             }
             TroopError::InvalidRequest(msg) => write!(f, "Invalid request: {msg}"),
+            TroopError::ModelNotFound(model) => write!(f, "Model not found: {model}"),
+            TroopError::PermissionDenied(msg) => write!(f, "Permission denied: {msg}"),
+            TroopError::Unsupported(msg) => write!(f, "Not supported: {msg}"),
             TroopError::WorkerUnavailable(msg) => write!(f, "Worker unavailable: {msg}"),
             TroopError::CircuitBreakerOpen => {
                 write!(f, "Circuit breaker open, service temporarily unavailable")
             }
+            TroopError::RateLimited {
+                retry_after,
+                message,
+            } => {
+                write!(f, "Rate limited")?;
+                if let Some(message) = message {
+                    write!(f, ": {message}")?;
+                }
+                if let Some(delay) = retry_after {
+                    write!(f, ", retry after {}s", delay.as_secs())?;
+                }
+                Ok(())
+            }
+            TroopError::UpstreamError { status, body } => {
+                write!(f, "Upstream returned status {status}: {body}")
+            }
             TroopError::InternalError(msg) => write!(f, "Internal error: {msg}"),
         }
     }
 }
 
+impl TroopError {
+    /// Whether retrying the failed operation could plausibly succeed.
+    /// Permanent failures (bad input, rejected credentials, empty balance) are not retried.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            TroopError::NetworkError(_)
+            | TroopError::CoordinatorUnreachable(_)
+            | TroopError::Timeout(_)
+            | TroopError::NoNodesAvailable
+            | TroopError::WorkerUnavailable(_)
+            | TroopError::CircuitBreakerOpen
+            | TroopError::RateLimited { .. }
+            | TroopError::InternalError(_) => true,
+            // Server-side failures may be transient; client errors will fail the same way again
+            TroopError::UpstreamError { status, .. } => *status >= 500 || *status == 429,
+            TroopError::AuthError(_)
+            | TroopError::InsufficientCredits { .. }
+            | TroopError::InvalidRequest(_)
+            | TroopError::ModelNotFound(_)
+            | TroopError::PermissionDenied(_)
+            | TroopError::Unsupported(_) => false,
+        }
+    }
+
+    /// Error for a non-success upstream response: `RateLimited` for a 429, carrying its
+    /// `Retry-After` hint (header or `retry_after` body field, capped at
+    /// `MAX_RETRY_AFTER`) and message, otherwise `UpstreamError` with the body.
+    pub async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        let header_hint = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_retry_after);
+        let body = response.text().await.unwrap_or_default();
+        if status != 429 {
+            return TroopError::UpstreamError { status, body };
+        }
+
+        let body_hint = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|v| v.get("retry_after")?.as_f64())
+            .filter(|secs| secs.is_finite() && *secs >= 0.0)
+            .map(|secs| Duration::from_secs_f64(secs.min(MAX_RETRY_AFTER.as_secs_f64())));
+        TroopError::RateLimited {
+            retry_after: header_hint.or(body_hint).map(|d| d.min(MAX_RETRY_AFTER)),
+            message: Some(upstream_message(status, &body)),
+        }
+    }
+
+    /// Delay suggested by the upstream service before the next attempt, if any
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            TroopError::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
 impl std::error::Error for TroopError {}
 
+/// OpenAI-compatible error body: `{"error": {"message", "type", "code"}}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiErrorBody {
+    pub error: ApiErrorDetail,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiErrorDetail {
+    pub message: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub code: String,
+}
+
+impl ApiErrorBody {
+    pub fn new(message: impl Into<String>, kind: &str, code: &str) -> Self {
+        Self {
+            error: ApiErrorDetail {
+                message: message.into(),
+                kind: kind.to_string(),
+                code: code.to_string(),
+            },
+        }
+    }
+
+    /// Error body for a bare HTTP status, with the type and code OpenAI uses for it
+    pub fn for_status(status: u16, message: impl Into<String>) -> Self {
+        let (kind, code) = match status {
+            400 | 422 => ("invalid_request_error", "invalid_request"),
+            401 | 403 => ("authentication_error", "unauthorized"),
+            402 => ("insufficient_quota", "insufficient_credits"),
+            404 => ("invalid_request_error", "model_not_found"),
+            429 => ("rate_limit_error", "rate_limited"),
+            503 => ("service_unavailable", "service_unavailable"),
+            500 => ("api_error", "internal_error"),
+            504 => ("timeout", "request_timeout"),
+            _ => ("api_error", "upstream_error"),
+        };
+        Self::new(message, kind, code)
+    }
+}
+
+impl TroopError {
+    /// HTTP status this error is reported with
+    pub fn http_status(&self) -> u16 {
+        match self {
+            TroopError::NetworkError(_) | TroopError::WorkerUnavailable(_) => 502,
+            TroopError::Timeout(_) => 504,
+            TroopError::AuthError(_) => 401,
+            TroopError::CoordinatorUnreachable(_)
+            | TroopError::NoNodesAvailable
+            | TroopError::CircuitBreakerOpen => 503,
+            TroopError::InsufficientCredits { .. } => 402,
+            TroopError::InvalidRequest(_) => 400,
+            TroopError::ModelNotFound(_) => 404,
+            TroopError::PermissionDenied(_) => 403,
+            TroopError::Unsupported(_) => 501,
+            TroopError::RateLimited { .. } => 429,
+            TroopError::UpstreamError { status, .. } => *status,
+            TroopError::InternalError(_) => 500,
+        }
+    }
+
+    /// `http_status` as a `StatusCode`, for the proxies to answer with; an upstream
+    /// status outside the valid range becomes 502.
+    pub fn status_code(&self) -> reqwest::StatusCode {
+        reqwest::StatusCode::from_u16(self.http_status())
+            .unwrap_or(reqwest::StatusCode::BAD_GATEWAY)
+    }
+
+    /// OpenAI-compatible body describing this error, so SDKs can surface the detail
+    pub fn api_error_body(&self) -> ApiErrorBody {
+        let message = self.to_string();
+        match self {
+            TroopError::NetworkError(_) => {
+                ApiErrorBody::new(message, "api_error", "worker_unreachable")
+            }
+            TroopError::CoordinatorUnreachable(_) => ApiErrorBody::new(
+                format!("{message}; {COORDINATOR_HINT}"),
+                "service_unavailable",
+                "coordinator_unreachable",
+            ),
+            TroopError::Timeout(_) => ApiErrorBody::new(message, "timeout", "request_timeout"),
+            TroopError::AuthError(_) => {
+                ApiErrorBody::new(message, "authentication_error", "unauthorized")
+            }
+            TroopError::NoNodesAvailable => {
+                ApiErrorBody::new(message, "service_unavailable", "no_nodes_available")
+            }
+            TroopError::InsufficientCredits { .. } => {
+                ApiErrorBody::new(message, "insufficient_quota", "insufficient_credits")
+            }
+            TroopError::InvalidRequest(_) => {
+                ApiErrorBody::new(message, "invalid_request_error", "invalid_request")
+            }
+            TroopError::ModelNotFound(_) => {
+                ApiErrorBody::new(message, "invalid_request_error", "model_not_found")
+            }
+            TroopError::PermissionDenied(_) => {
+                ApiErrorBody::new(message, "permission_error", "permission_denied")
+            }
+            TroopError::Unsupported(_) => ApiErrorBody::new(message, "api_error", "not_supported"),
+            TroopError::WorkerUnavailable(_) => {
+                ApiErrorBody::new(message, "api_error", "worker_unavailable")
+            }
+            TroopError::CircuitBreakerOpen => {
+                ApiErrorBody::new(message, "service_unavailable", "circuit_open")
+            }
+            TroopError::RateLimited { .. } => {
+                ApiErrorBody::new(message, "rate_limit_error", "rate_limited")
+            }
+            TroopError::UpstreamError { status, body } => {
+                ApiErrorBody::for_status(*status, upstream_message(*status, body))
+            }
+            TroopError::InternalError(_) => {
+                ApiErrorBody::new(message, "api_error", "internal_error")
+            }
+        }
+    }
+}
+
+/// A `Retry-After` value: delay-seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    // A date in the past means "now"
+    Some(
+        at.signed_duration_since(chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Best human-readable message in an upstream error body: FastAPI's `detail`, a plain
+/// `error` string, or the raw text.
+fn upstream_message(status: u16, body: &str) -> String {
+    let from_json = serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| {
+            v.get("detail")
+                .or_else(|| v.get("error"))
+                .and_then(|m| m.as_str())
+                .map(str::to_string)
+        });
+    match from_json {
+        Some(message) => message,
+        None if !body.trim().is_empty() => body.trim().to_string(),
+        None => format!("Upstream returned status {status}"),
+    }
+}
+
 // Convert from common error types
 impl From<reqwest::Error> for TroopError {
+    /// Which upstream could not be reached is not known here; callers that talk to the
+    /// coordinator turn `NetworkError` into `CoordinatorUnreachable`.
     fn from(err: reqwest::Error) -> Self {
+        // The error's own message leaves out the cause, such as "Connection refused"
+        let mut message = err.to_string();
+        let mut source = std::error::Error::source(&err);
+        while let Some(cause) = source {
+            message = format!("{message}: {cause}");
+            source = cause.source();
+        }
+
         if err.is_timeout() {
-            TroopError::Timeout(err.to_string())
+            TroopError::Timeout(message)
         } else if err.is_connect() {
-            TroopError::NetworkError(err.to_string())
+            TroopError::NetworkError(message)
+        } else if let Some(status) = err.status() {
+            // From `error_for_status`, which keeps no body
+            TroopError::UpstreamError {
+                status: status.as_u16(),
+                body: String::new(),
+            }
+        } else if err.is_builder() {
+            TroopError::InvalidRequest(message)
+        } else if err.is_decode() {
+            TroopError::InternalError(format!("Unreadable response: {message}"))
+        } else if err.is_request() || err.is_body() {
+            // Connected, then the connection failed while sending or receiving
+            TroopError::NetworkError(message)
         } else {
-            TroopError::InternalError(err.to_string())
+            TroopError::InternalError(message)
         }
     }
 }
@@ -103,3 +388,287 @@ impl From<anyhow::Error> for TroopError {
 
 /// Result type alias using TroopError
 pub type TroopResult<T> = Result<T, TroopError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transient_errors_are_retryable() {
+        assert!(TroopError::NetworkError("refused".to_string()).is_retryable());
+        assert!(TroopError::Timeout("slow".to_string()).is_retryable());
+        assert!(TroopError::WorkerUnavailable("busy".to_string()).is_retryable());
+        assert!(TroopError::CircuitBreakerOpen.is_retryable());
+        assert!(TroopError::RateLimited {
+            retry_after: None,
+            message: None,
+        }
+        .is_retryable());
+        assert!(TroopError::UpstreamError {
+            status: 503,
+            body: String::new()
+        }
+        .is_retryable());
+    }
+
+    #[test]
+    fn test_permanent_errors_are_not_retryable() {
+        assert!(!TroopError::InvalidRequest("bad".to_string()).is_retryable());
+        assert!(!TroopError::AuthError("denied".to_string()).is_retryable());
+        assert!(!TroopError::InsufficientCredits {
+            required: 10,
+            available: 0
+        }
+        .is_retryable());
+        assert!(!TroopError::UpstreamError {
+            status: 400,
+            body: "context length exceeded".to_string()
+        }
+        .is_retryable());
+    }
+
+    #[test]
+    fn test_api_error_body_for_each_variant() {
+        let cases = [
+            (
+                TroopError::NetworkError("refused".to_string()),
+                502,
+                "api_error",
+                "worker_unreachable",
+            ),
+            (
+                TroopError::CoordinatorUnreachable("refused".to_string()),
+                503,
+                "service_unavailable",
+                "coordinator_unreachable",
+            ),
+            (
+                TroopError::Timeout("slow".to_string()),
+                504,
+                "timeout",
+                "request_timeout",
+            ),
+            (
+                TroopError::AuthError("denied".to_string()),
+                401,
+                "authentication_error",
+                "unauthorized",
+            ),
+            (
+                TroopError::NoNodesAvailable,
+                503,
+                "service_unavailable",
+                "no_nodes_available",
+            ),
+            (
+                TroopError::InsufficientCredits {
+                    required: 300,
+                    available: 10,
+                },
+                402,
+                "insufficient_quota",
+                "insufficient_credits",
+            ),
+            (
+                TroopError::InvalidRequest("bad".to_string()),
+                400,
+                "invalid_request_error",
+                "invalid_request",
+            ),
+            (
+                TroopError::ModelNotFound("llama3:8b".to_string()),
+                404,
+                "invalid_request_error",
+                "model_not_found",
+            ),
+            (
+                TroopError::PermissionDenied("admins only".to_string()),
+                403,
+                "permission_error",
+                "permission_denied",
+            ),
+            (
+                TroopError::Unsupported("no credit requests".to_string()),
+                501,
+                "api_error",
+                "not_supported",
+            ),
+            (
+                TroopError::WorkerUnavailable("reset".to_string()),
+                502,
+                "api_error",
+                "worker_unavailable",
+            ),
+            (
+                TroopError::CircuitBreakerOpen,
+                503,
+                "service_unavailable",
+                "circuit_open",
+            ),
+            (
+                TroopError::RateLimited {
+                    retry_after: None,
+                    message: None,
+                },
+                429,
+                "rate_limit_error",
+                "rate_limited",
+            ),
+            (
+                TroopError::UpstreamError {
+                    status: 404,
+                    body: String::new(),
+                },
+                404,
+                "invalid_request_error",
+                "model_not_found",
+            ),
+            (
+                TroopError::InternalError("boom".to_string()),
+                500,
+                "api_error",
+                "internal_error",
+            ),
+        ];
+
+        for (error, status, kind, code) in cases {
+            assert_eq!(error.http_status(), status, "{error}");
+            assert_eq!(error.status_code().as_u16(), status, "{error}");
+            let body = serde_json::to_value(error.api_error_body()).unwrap();
+            assert_eq!(body["error"]["type"], kind, "{error}");
+            assert_eq!(body["error"]["code"], code, "{error}");
+            assert!(!body["error"]["message"].as_str().unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reqwest_errors_are_classified() {
+        // Nothing listens on the discard port
+        let error = TroopError::from(reqwest::get("http://127.0.0.1:9").await.unwrap_err());
+        assert!(matches!(error, TroopError::NetworkError(_)), "{error}");
+
+        let server = httpmock::MockServer::start();
+        server.mock(|when, then| {
+            when.path("/slow");
+            then.status(200).delay(Duration::from_secs(2));
+        });
+        server.mock(|when, then| {
+            when.path("/unavailable");
+            then.status(503);
+        });
+        server.mock(|when, then| {
+            when.path("/text");
+            then.status(200).body("not json");
+        });
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let error = TroopError::from(client.get(server.url("/slow")).send().await.unwrap_err());
+        assert!(matches!(error, TroopError::Timeout(_)), "{error}");
+
+        let response = reqwest::get(server.url("/unavailable")).await.unwrap();
+        let error = TroopError::from(response.error_for_status().unwrap_err());
+        assert!(
+            matches!(error, TroopError::UpstreamError { status: 503, .. }),
+            "{error}"
+        );
+
+        let response = reqwest::get(server.url("/text")).await.unwrap();
+        let error = TroopError::from(response.json::<serde_json::Value>().await.unwrap_err());
+        assert!(error.to_string().contains("Unreadable response"), "{error}");
+
+        let error = TroopError::from(reqwest::get("not a url").await.unwrap_err());
+        assert!(matches!(error, TroopError::InvalidRequest(_)), "{error}");
+    }
+
+    #[test]
+    fn test_invalid_upstream_status_is_a_bad_gateway() {
+        let error = TroopError::UpstreamError {
+            status: 1200,
+            body: String::new(),
+        };
+        assert_eq!(error.status_code(), reqwest::StatusCode::BAD_GATEWAY);
+    }
+
+    #[test]
+    fn test_coordinator_unreachable_carries_a_hint() {
+        let error = TroopError::CoordinatorUnreachable("http://localhost:8000/".to_string());
+        assert!(error.is_retryable());
+        assert!(error
+            .api_error_body()
+            .error
+            .message
+            .ends_with(COORDINATOR_HINT));
+    }
+
+    #[test]
+    fn test_upstream_error_message_is_extracted() {
+        let fastapi = TroopError::UpstreamError {
+            status: 402,
+            body: r#"{"detail":"Insufficient credits"}"#.to_string(),
+        };
+        assert_eq!(
+            fastapi.api_error_body().error.message,
+            "Insufficient credits"
+        );
+
+        let empty = TroopError::UpstreamError {
+            status: 404,
+            body: String::new(),
+        };
+        assert_eq!(
+            empty.api_error_body().error.message,
+            "Upstream returned status 404"
+        );
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after(" 12 "), Some(Duration::from_secs(12)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_response_carries_hint_and_message() {
+        let server = httpmock::MockServer::start();
+        server.mock(|when, then| {
+            when.path("/header");
+            then.status(429)
+                .header("Retry-After", "3600")
+                .json_body(serde_json::json!({"detail": "Too many requests"}));
+        });
+        server.mock(|when, then| {
+            when.path("/body");
+            then.status(429)
+                .json_body(serde_json::json!({"detail": "Slow down", "retry_after": 2.5}));
+        });
+        server.mock(|when, then| {
+            when.path("/other");
+            then.status(400).body("bad");
+        });
+        let get = |path: &str| reqwest::get(server.url(path));
+
+        let error = TroopError::from_response(get("/header").await.unwrap()).await;
+        assert_eq!(error.retry_after(), Some(MAX_RETRY_AFTER));
+        assert_eq!(error.http_status(), 429);
+        assert_eq!(
+            error.api_error_body().error.message,
+            "Rate limited: Too many requests, retry after 60s"
+        );
+
+        let error = TroopError::from_response(get("/body").await.unwrap()).await;
+        assert_eq!(error.retry_after(), Some(Duration::from_millis(2500)));
+
+        let error = TroopError::from_response(get("/other").await.unwrap()).await;
+        assert!(matches!(
+            error,
+            TroopError::UpstreamError { status: 400, ref body } if body == "bad"
+        ));
+    }
+}
@@ -0,0 +1,68 @@
+use reqwest::{Client, ClientBuilder};
+use std::time::Duration;
+
+/// Connection pool configuration for outbound HTTP clients
+pub const HTTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const HTTP_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+pub const HTTP_POOL_MAX_IDLE_PER_HOST: usize = 32;
+pub const HTTP_TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// Build the HTTP client a component should share for all its outbound calls, so
+/// connections (and TLS sessions) are pooled instead of re-established per request.
+/// `timeout` bounds each request unless the request sets its own.
+pub fn http_client(timeout: Duration) -> Client {
+    build(pooled_builder(timeout))
+}
+
+/// Like `http_client`, for proxies that relay response bodies byte for byte. Automatic
+/// decompression stays off even if a dependency enables reqwest's compression features,
+/// so a relayed `Content-Encoding` always matches the bytes.
+pub fn passthrough_http_client(timeout: Duration) -> Client {
+    build(
+        pooled_builder(timeout)
+            .no_gzip()
+            .no_brotli()
+            .no_deflate()
+            .no_zstd(),
+    )
+}
+
+fn pooled_builder(timeout: Duration) -> ClientBuilder {
+    Client::builder()
+        .timeout(timeout)
+        .connect_timeout(HTTP_CONNECT_TIMEOUT)
+        .pool_idle_timeout(HTTP_POOL_IDLE_TIMEOUT)
+        .pool_max_idle_per_host(HTTP_POOL_MAX_IDLE_PER_HOST)
+        .tcp_keepalive(HTTP_TCP_KEEPALIVE)
+}
+
+fn build(builder: ClientBuilder) -> Client {
+    builder
+        .build()
+        // Only fails if the TLS backend cannot be initialized, as `Client::new` would
+        .expect("failed to initialize HTTP client")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DISCOVERY_TIMEOUT;
+
+    #[tokio::test]
+    async fn test_default_timeout_applies_to_requests() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Accept the connection but never answer
+        let _server = tokio::spawn(async move {
+            let (_socket, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(DISCOVERY_TIMEOUT).await;
+        });
+
+        let result = http_client(Duration::from_millis(100))
+            .get(format!("http://{addr}/"))
+            .send()
+            .await;
+
+        assert!(result.unwrap_err().is_timeout());
+    }
+}
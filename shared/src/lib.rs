@@ -1,6 +1,7 @@
 pub mod circuit_breaker;
 pub mod crypto;
 pub mod errors;
+pub mod http;
 pub mod models;
 pub mod retry;
 pub mod system;
@@ -8,6 +9,7 @@ pub mod system;
 pub use circuit_breaker::*;
 pub use crypto::*;
 pub use errors::*;
+pub use http::*;
 pub use models::*;
 pub use retry::*;
 pub use system::*;
@@ -1,4 +1,13 @@
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Header carrying the correlation ID of a request across client proxy, worker and engine
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Header carrying the instant (unix milliseconds) after which the client proxy has given
+/// up on a request, so the worker stops working on it too
+pub const DEADLINE_HEADER: &str = "x-troop-deadline";
 
 /// Content-addressed model identity ensuring integrity via cryptographic hash
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -15,13 +24,43 @@ pub struct EngineInfo {
     pub engine_type: String, // "ollama", "lmstudio", "vllm"
     pub version: String,
     pub port: u16,
+    /// Whether the engine passed its health check; absent from older workers and
+    /// coordinators, and then taken as healthy
+    #[serde(default = "healthy_by_default")]
+    pub healthy: bool,
+}
+
+fn healthy_by_default() -> bool {
+    true
 }
 
 /// Hardware specifications of a node
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HardwareInfo {
+    /// GPU model, e.g. "8x NVIDIA A100-SXM4-80GB" on a node with several of the same
     pub gpu: String,
-    pub vram_free: u64, // MB
+    pub vram_free: u64, // MB, over all GPUs
+    /// GPU utilization percent, of the busiest GPU on multi-GPU nodes; absent from older
+    /// workers
+    #[serde(default)]
+    pub gpu_util: f32,
+    /// The totals and per-GPU readings below are only sent by workers that read their GPUs
+    /// one by one (NVIDIA), and are absent from older workers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vram_total: Option<u64>, // MB, over all GPUs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpu_count: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpus: Option<Vec<GpuInfo>>,
+}
+
+/// One GPU of a multi-GPU node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuInfo {
+    pub name: String,
+    pub vram_free: u64,  // MB
+    pub vram_total: u64, // MB
+    pub gpu_util: f32,
 }
 
 /// Node status broadcast to coordinator
@@ -35,10 +74,20 @@ pub struct NodeHeartbeat {
     pub engines: Vec<EngineInfo>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub encryption_public_key: Option<String>,
+    /// Absent from older workers; parsed as zero load
+    #[serde(default)]
+    pub load: NodeLoad,
+}
+
+/// How busy a node's proxy is, so the coordinator can prefer the least-loaded node
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct NodeLoad {
+    pub in_flight: u32,
+    pub max_concurrent: u32,
 }
 
 /// Current operational status of a node
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum NodeStatus {
     Idle,
@@ -93,6 +142,13 @@ pub struct JWTClaims {
 pub struct AuthorizeRequest {
     pub model: String,
     pub requester: String, // Tailscale IP or user ID
+    /// Node IPs the requester wants to avoid (e.g. their circuit breaker is open)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_nodes: Vec<String>,
+    /// Node IP the requester would like, e.g. the one holding a conversation's KV cache.
+    /// Only a preference: any other node may be assigned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_node: Option<String>,
 }
 
 /// Authorization ticket response
@@ -102,13 +158,179 @@ pub struct AuthorizeResponse {
     pub token: String, // Signed JWT
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub encryption_public_key: Option<String>,
+    /// Worker proxy port; older coordinators omit it and the client's `WORKER_PORT` is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_port: Option<u16>,
+    /// Nodes to try, most preferred first, each with its own ticket. Older coordinators
+    /// send none; the top-level fields stay the first candidate for older clients.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<Candidate>,
+}
+
+/// A node the coordinator offers for a request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Candidate {
+    pub target_ip: String,
+    pub token: String, // Signed JWT for this node
+    /// How busy the coordinator believes the node is, from 0.0 (idle) to 1.0 (full)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_load: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption_public_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_port: Option<u16>,
+}
+
+impl AuthorizeResponse {
+    /// The nodes to try, in the coordinator's order: `candidates`, or just the top-level
+    /// target when there are none.
+    pub fn candidates(&self) -> Vec<Candidate> {
+        if !self.candidates.is_empty() {
+            return self.candidates.clone();
+        }
+        vec![Candidate {
+            target_ip: self.target_ip.clone(),
+            token: self.token.clone(),
+            estimated_load: None,
+            encryption_public_key: self.encryption_public_key.clone(),
+            target_port: self.target_port,
+        }]
+    }
+
+    /// A ticket for `candidate` alone.
+    pub fn for_candidate(candidate: Candidate) -> Self {
+        Self {
+            target_ip: candidate.target_ip,
+            token: candidate.token,
+            encryption_public_key: candidate.encryption_public_key,
+            target_port: candidate.target_port,
+            candidates: Vec::new(),
+        }
+    }
+}
+
+/// Tokens a worker spent on one completion, reported to the coordinator for billing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub node_id: String,
+    /// The requester the ticket was issued to
+    pub requester: String,
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
 }
 
 /// OpenAI-compatible chat message
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub role: String,
-    pub content: String,
+    /// Absent or null on assistant messages that only carry `tool_calls`
+    #[serde(default)]
+    pub content: Option<MessageContent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// Set on `tool` role messages to reference the call being answered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+/// Message content: a plain string, or an array of parts for multimodal (vision) requests
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+impl MessageContent {
+    /// The textual content, with the text parts of a multimodal message joined by newlines
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            MessageContent::Text(text) => Cow::Borrowed(text),
+            MessageContent::Parts(parts) => {
+                let texts: Vec<&str> = parts
+                    .iter()
+                    .filter_map(|part| match part {
+                        ContentPart::Text { text } => Some(text.as_str()),
+                        ContentPart::ImageUrl { .. } | ContentPart::Other(_) => None,
+                    })
+                    .collect();
+                Cow::Owned(texts.join("\n"))
+            }
+        }
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        MessageContent::Text(text)
+    }
+}
+
+impl From<&str> for MessageContent {
+    fn from(text: &str) -> Self {
+        MessageContent::Text(text.to_string())
+    }
+}
+
+/// One part of a multimodal message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text {
+        text: String,
+    },
+    ImageUrl {
+        image_url: ImageUrl,
+    },
+    /// Any other part (`input_audio`, `file`, ...), forwarded as it came for engines that
+    /// support it
+    #[serde(untagged)]
+    Other(serde_json::Value),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageUrl {
+    /// An `http(s)` URL or a `data:image/...;base64,...` URL
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// A function call requested by the model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionCall {
+    pub name: String,
+    /// JSON-encoded arguments, exactly as produced by the model
+    pub arguments: String,
+}
+
+/// A tool the model may call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tool {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: FunctionDefinition,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionDefinition {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// JSON Schema for the arguments, forwarded untouched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
 }
 
 /// OpenAI-compatible chat completion request
@@ -118,6 +340,53 @@ pub struct ChatCompletionRequest {
     pub messages: Vec<ChatMessage>,
     #[serde(default)]
     pub stream: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    /// `"none"`, `"auto"`, `"required"` or `{"type": "function", "function": {"name": ...}}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// A stop sequence or list of them, forwarded untouched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    /// For reproducible sampling, on engines that support it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    /// e.g. `{"include_usage": true}` to get token usage in the final stream chunk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<serde_json::Value>,
+    /// Any other fields (`response_format`, `logit_bias`, `user`, ...), forwarded untouched
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+/// OpenAI-compatible embeddings request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingsRequest {
+    pub model: String,
+    pub input: EmbeddingInput,
+}
+
+/// Embeddings input: a single string or a batch of strings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EmbeddingInput {
+    Single(String),
+    Batch(Vec<String>),
+}
+
+impl EmbeddingInput {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            EmbeddingInput::Single(text) => vec![text],
+            EmbeddingInput::Batch(texts) => texts,
+        }
+    }
 }
 
 /// List of available peers
@@ -135,6 +404,65 @@ pub struct BalanceResponse {
     pub balance_hours: f64,
 }
 
+/// One credit movement in a user's transaction history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transaction {
+    pub id: Option<i64>,
+    /// Public key of the user paying, absent for system grants
+    pub requester: Option<String>,
+    /// Public key of the user paid
+    pub worker: Option<String>,
+    pub credits: i64,
+    /// When the transaction was recorded, in UTC
+    pub timestamp: chrono::NaiveDateTime,
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Model the job ran, when the coordinator records it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+/// User transaction history response, most recent first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionsResponse {
+    pub transactions: Vec<Transaction>,
+}
+
+/// Request for credits to be added to a user's balance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreditRequest {
+    /// Credits asked for, in seconds
+    pub amount: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Where a credit request stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CreditRequestStatus {
+    /// Waiting for an operator to approve it
+    Pending,
+    /// Granted; the credits are in the balance
+    Completed,
+    Rejected,
+}
+
+/// The coordinator's answer to a credit request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreditRequestResponse {
+    /// For following up on a pending request
+    #[serde(default)]
+    pub request_id: Option<String>,
+    pub status: CreditRequestStatus,
+    /// The grant, once completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<Transaction>,
+    /// Why it was rejected or is pending, when the coordinator says
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
 /// OpenAI-compatible model list
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelInfo {
@@ -171,3 +499,307 @@ pub struct ReputationComponents {
     pub reliability: f64,
     pub performance: f64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_heartbeat_load_and_gpu_util_default_when_absent() {
+        let heartbeat: NodeHeartbeat = serde_json::from_value(json!({
+            "node_id": "node-1",
+            "tailscale_ip": "100.64.0.1",
+            "status": "IDLE",
+            "models": [],
+            "hardware": {"gpu": "RTX 4090", "vram_free": 24576},
+            "engines": []
+        }))
+        .unwrap();
+        assert_eq!(heartbeat.load, NodeLoad::default());
+        assert_eq!(heartbeat.hardware.gpu_util, 0.0);
+        assert_eq!(heartbeat.hardware.gpu_count, None);
+        assert!(heartbeat.hardware.gpus.is_none());
+
+        let mut value = serde_json::to_value(&heartbeat).unwrap();
+        // Nothing is sent for the per-GPU fields a node doesn't have
+        assert_eq!(
+            value["hardware"],
+            json!({"gpu": "RTX 4090", "vram_free": 24576, "gpu_util": 0.0})
+        );
+        value["load"] = json!({"in_flight": 2, "max_concurrent": 4});
+        let heartbeat: NodeHeartbeat = serde_json::from_value(value).unwrap();
+        assert_eq!(
+            heartbeat.load,
+            NodeLoad {
+                in_flight: 2,
+                max_concurrent: 4
+            }
+        );
+    }
+
+    #[test]
+    fn test_authorize_response_without_candidates_offers_its_target() {
+        let old = r#"{"target_ip": "100.64.0.1", "token": "jwt-1", "target_port": 8081}"#;
+        let response: AuthorizeResponse = serde_json::from_str(old).unwrap();
+        assert!(response.candidates.is_empty());
+        assert_eq!(
+            response.candidates(),
+            [Candidate {
+                target_ip: "100.64.0.1".to_string(),
+                token: "jwt-1".to_string(),
+                estimated_load: None,
+                encryption_public_key: None,
+                target_port: Some(8081),
+            }]
+        );
+
+        // Serialized back, it is the shape older clients read
+        let value = serde_json::to_value(&response).unwrap();
+        assert_eq!(
+            value,
+            json!({"target_ip": "100.64.0.1", "token": "jwt-1", "target_port": 8081})
+        );
+    }
+
+    #[test]
+    fn test_authorize_response_candidates_round_trip_in_order() {
+        let new = json!({
+            "target_ip": "100.64.0.2",
+            "token": "jwt-2",
+            "candidates": [
+                {"target_ip": "100.64.0.2", "token": "jwt-2", "estimated_load": 0.1},
+                {"target_ip": "100.64.0.3", "token": "jwt-3", "estimated_load": 0.6,
+                 "encryption_public_key": "key-3", "target_port": 8082}
+            ]
+        });
+        let response: AuthorizeResponse = serde_json::from_value(new.clone()).unwrap();
+        let candidates = response.candidates();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[1].target_ip, "100.64.0.3");
+        assert_eq!(candidates[1].estimated_load, Some(0.6));
+        assert_eq!(serde_json::to_value(&response).unwrap(), new);
+
+        let ticket = AuthorizeResponse::for_candidate(candidates[1].clone());
+        assert_eq!(ticket.token, "jwt-3");
+        assert_eq!(ticket.encryption_public_key.as_deref(), Some("key-3"));
+        assert_eq!(ticket.target_port, Some(8082));
+        assert!(ticket.candidates.is_empty());
+    }
+
+    #[test]
+    fn test_chat_request_with_tools_round_trips() {
+        let original = json!({
+            "model": "qwen2.5:14b",
+            "messages": [
+                {"role": "system", "content": "You can look up the weather."},
+                {"role": "user", "content": "What's the weather in Paris?"},
+                {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_abc123",
+                        "type": "function",
+                        "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}
+                    }]
+                },
+                {"role": "tool", "tool_call_id": "call_abc123", "content": "18C and sunny"}
+            ],
+            "stream": false,
+            "tools": [{
+                "type": "function",
+                "function": {
+                    "name": "get_weather",
+                    "description": "Current weather for a city",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {"city": {"type": "string"}},
+                        "required": ["city"]
+                    }
+                }
+            }],
+            "tool_choice": {"type": "function", "function": {"name": "get_weather"}},
+            "max_tokens": 256,
+            "stop": ["\n\n"]
+        });
+
+        let request: ChatCompletionRequest = serde_json::from_value(original.clone()).unwrap();
+        assert_eq!(
+            request.tools.as_ref().unwrap()[0].function.name,
+            "get_weather"
+        );
+        let assistant = &request.messages[2];
+        assert!(assistant.content.is_none());
+        assert_eq!(
+            assistant.tool_calls.as_ref().unwrap()[0].function.arguments,
+            r#"{"city":"Paris"}"#
+        );
+        assert_eq!(
+            request.messages[3].tool_call_id.as_deref(),
+            Some("call_abc123")
+        );
+
+        assert_eq!(serde_json::to_value(&request).unwrap(), original);
+    }
+
+    #[test]
+    fn test_chat_message_content_may_be_absent() {
+        let message: ChatMessage = serde_json::from_value(json!({
+            "role": "assistant",
+            "tool_calls": []
+        }))
+        .unwrap();
+        assert!(message.content.is_none());
+
+        let plain: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "llama3:8b",
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+        let serialized = serde_json::to_value(&plain).unwrap();
+        assert!(serialized.get("tools").is_none());
+        assert!(serialized.get("tool_choice").is_none());
+        assert!(serialized.get("max_tokens").is_none());
+        assert!(serialized.get("stop").is_none());
+        assert!(serialized.get("temperature").is_none());
+        assert!(serialized.get("seed").is_none());
+        assert!(serialized["messages"][0].get("tool_calls").is_none());
+        assert!(plain.extra.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_fields_survive_round_trip() {
+        let original = json!({
+            "model": "llama3:8b",
+            "messages": [{"role": "user", "content": "List three colours"}],
+            "stream": false,
+            "tools": [{
+                "type": "function",
+                "function": {"name": "lookup", "parameters": {"type": "object"}}
+            }],
+            "response_format": {"type": "json_object"},
+            "logit_bias": {"50256": -100},
+            "user": "user-1234"
+        });
+
+        let request: ChatCompletionRequest = serde_json::from_value(original.clone()).unwrap();
+        assert_eq!(request.tools.as_ref().unwrap()[0].function.name, "lookup");
+        assert_eq!(request.extra["user"], json!("user-1234"));
+        assert!(!request.extra.contains_key("tools"));
+        assert_eq!(serde_json::to_value(&request).unwrap(), original);
+    }
+
+    #[test]
+    fn test_sampling_parameters_survive_round_trip() {
+        let original = json!({
+            "model": "llama3:8b",
+            "messages": [{"role": "user", "content": "Pick a number"}],
+            "stream": false,
+            "max_tokens": 16,
+            "stop": "\n",
+            "temperature": 0.2,
+            "top_p": 0.9,
+            "seed": 42
+        });
+
+        let request: ChatCompletionRequest = serde_json::from_value(original.clone()).unwrap();
+        assert_eq!(request.temperature, Some(0.2));
+        assert_eq!(request.top_p, Some(0.9));
+        assert_eq!(request.seed, Some(42));
+
+        let outgoing = serde_json::to_value(&request).unwrap();
+        assert_eq!(outgoing["temperature"], json!(0.2));
+        assert_eq!(outgoing, original);
+    }
+
+    #[test]
+    fn test_multimodal_content_parts() {
+        let original = json!({
+            "model": "llava:13b",
+            "messages": [
+                {"role": "system", "content": "Describe images briefly."},
+                {
+                    "role": "user",
+                    "content": [
+                        {"type": "text", "text": "What is in this picture?"},
+                        {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo=", "detail": "low"}}
+                    ]
+                },
+                {"role": "assistant", "content": "A cat."},
+                {
+                    "role": "user",
+                    "content": [{"type": "image_url", "image_url": {"url": "https://example.com/dog.jpg"}}]
+                }
+            ],
+            "stream": false
+        });
+
+        let request: ChatCompletionRequest = serde_json::from_value(original.clone()).unwrap();
+        assert_eq!(
+            request.messages[0].content,
+            Some(MessageContent::from("Describe images briefly."))
+        );
+        let Some(MessageContent::Parts(parts)) = &request.messages[1].content else {
+            panic!("expected content parts");
+        };
+        assert_eq!(
+            parts[1],
+            ContentPart::ImageUrl {
+                image_url: ImageUrl {
+                    url: "data:image/png;base64,iVBORw0KGgo=".to_string(),
+                    detail: Some("low".to_string()),
+                }
+            }
+        );
+        assert_eq!(
+            request.messages[1].content.as_ref().unwrap().text(),
+            "What is in this picture?"
+        );
+
+        assert_eq!(serde_json::to_value(&request).unwrap(), original);
+    }
+
+    #[test]
+    fn test_unknown_content_parts_are_kept_as_they_came() {
+        let original = json!({
+            "model": "gpt-4o-audio",
+            "messages": [{
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "Transcribe this."},
+                    {"type": "input_audio", "input_audio": {"data": "UklGRg==", "format": "wav"}}
+                ]
+            }],
+            "stream": false
+        });
+
+        let request: ChatCompletionRequest = serde_json::from_value(original.clone()).unwrap();
+        let Some(MessageContent::Parts(parts)) = &request.messages[0].content else {
+            panic!("expected content parts");
+        };
+        assert_eq!(
+            parts[1],
+            ContentPart::Other(original["messages"][0]["content"][1].clone())
+        );
+        assert_eq!(
+            request.messages[0].content.as_ref().unwrap().text(),
+            "Transcribe this."
+        );
+
+        assert_eq!(serde_json::to_value(&request).unwrap(), original);
+    }
+
+    #[test]
+    fn test_plain_text_content_serializes_as_string() {
+        let message = ChatMessage {
+            role: "user".to_string(),
+            content: Some("hi".into()),
+            tool_calls: None,
+            tool_call_id: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&message).unwrap(),
+            json!({"role": "user", "content": "hi"})
+        );
+    }
+}
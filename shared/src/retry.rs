@@ -1,19 +1,195 @@
 use crate::{TroopError, TroopResult, MAX_RETRIES, RETRY_DELAYS};
+use rand_core::{OsRng, RngCore};
 use std::time::Duration;
-use tokio::time::sleep;
+use tokio::time::{sleep, Instant};
 
 // Use println! instead of tracing since we don't have tracing in shared crate
 // Each application will log through their own tracing setup
 
+/// Backoff schedule for retried operations.
+///
+/// The delay before retry `n` (zero-based) is `base_delay * 2^n`. With `jitter`
+/// enabled the delay is instead drawn uniformly from `[0, base_delay * 2^n]`
+/// ("full jitter"), which spreads out retries from many clients that failed at
+/// the same moment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub base_delay: Duration,
+    pub max_retries: u32,
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    /// Matches the fixed `RETRY_DELAYS` schedule without jitter.
+    fn default() -> Self {
+        Self {
+            base_delay: Duration::from_secs(RETRY_DELAYS[0]),
+            max_retries: MAX_RETRIES,
+            jitter: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Upper bound of the delay before the retry following `attempt` (zero-based).
+    pub fn max_delay_for_attempt(&self, attempt: u32) -> Duration {
+        self.base_delay.saturating_mul(1u32 << attempt.min(31))
+    }
+
+    /// Delay to wait before the retry following `attempt` (zero-based).
+    pub fn delay_for_attempt(&self, attempt: u32) -> Duration {
+        let ceiling = self.max_delay_for_attempt(attempt);
+        if !self.jitter {
+            return ceiling;
+        }
+        let ceiling_ms = ceiling.as_millis().min(u64::MAX as u128) as u64;
+        Duration::from_millis(OsRng.next_u64() % (ceiling_ms + 1))
+    }
+}
+
 /// Retry a fallible async operation with exponential backoff
-pub async fn retry_with_backoff<F, Fut, T>(operation_name: &str, mut operation: F) -> TroopResult<T>
+pub async fn retry_with_backoff<F, Fut, T>(operation_name: &str, operation: F) -> TroopResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = TroopResult<T>>,
+{
+    retry_loop(
+        operation_name,
+        &RetryPolicy::default(),
+        operation,
+        false,
+        None,
+        |_, _| {},
+    )
+    .await
+}
+
+/// `retry_with_backoff`, calling `on_retry` before each retry with the number of the
+/// attempt that failed (from 1) and its error, e.g. to count retries in metrics.
+pub async fn retry_with_backoff_observed<F, Fut, T, R>(
+    operation_name: &str,
+    on_retry: R,
+    operation: F,
+) -> TroopResult<T>
 where
     F: FnMut() -> Fut,
     Fut: std::future::Future<Output = TroopResult<T>>,
+    R: Fn(u32, &TroopError),
+{
+    retry_loop(
+        operation_name,
+        &RetryPolicy::default(),
+        operation,
+        false,
+        None,
+        on_retry,
+    )
+    .await
+}
+
+/// `retry_with_backoff` for an operation that is of no use after `deadline`: a retry
+/// that could only start after it is not attempted, and the last error is returned
+/// instead of sleeping into a request its caller has given up on. `on_retry` is called
+/// before each retry, as with `retry_with_backoff_observed`.
+pub async fn retry_with_deadline<F, Fut, T, R>(
+    operation_name: &str,
+    deadline: Instant,
+    on_retry: R,
+    operation: F,
+) -> TroopResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = TroopResult<T>>,
+    R: Fn(u32, &TroopError),
+{
+    retry_loop(
+        operation_name,
+        &RetryPolicy::default(),
+        operation,
+        false,
+        Some(deadline),
+        on_retry,
+    )
+    .await
+}
+
+/// Retry a fallible async operation following a custom `RetryPolicy`.
+/// Delays suggested by the error (`TroopError::retry_after`) take precedence.
+pub async fn retry_with_policy<F, Fut, T>(
+    operation_name: &str,
+    policy: &RetryPolicy,
+    operation: F,
+) -> TroopResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = TroopResult<T>>,
+{
+    retry_loop(operation_name, policy, operation, true, None, |_, _| {}).await
+}
+
+/// `retry_with_policy`, giving up on retries that could only start after `deadline`
+/// and calling `on_retry` before each retry, like `retry_with_deadline`.
+pub async fn retry_with_policy_until<F, Fut, T, R>(
+    operation_name: &str,
+    policy: &RetryPolicy,
+    deadline: Instant,
+    on_retry: R,
+    operation: F,
+) -> TroopResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = TroopResult<T>>,
+    R: Fn(u32, &TroopError),
+{
+    retry_loop(
+        operation_name,
+        policy,
+        operation,
+        true,
+        Some(deadline),
+        on_retry,
+    )
+    .await
+}
+
+/// Retry a fallible async operation, preferring the delay suggested by the error
+/// (e.g. `TroopError::RateLimited` built from a `Retry-After` header) over the
+/// default exponential schedule.
+pub async fn retry_with_backoff_hinted<F, Fut, T>(
+    operation_name: &str,
+    operation: F,
+) -> TroopResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = TroopResult<T>>,
+{
+    retry_loop(
+        operation_name,
+        &RetryPolicy::default(),
+        operation,
+        true,
+        None,
+        |_, _| {},
+    )
+    .await
+}
+
+async fn retry_loop<F, Fut, T, R>(
+    operation_name: &str,
+    policy: &RetryPolicy,
+    mut operation: F,
+    honor_hints: bool,
+    deadline: Option<Instant>,
+    on_retry: R,
+) -> TroopResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = TroopResult<T>>,
+    R: Fn(u32, &TroopError),
 {
     let mut last_error = None;
 
-    for attempt in 0..MAX_RETRIES {
+    for attempt in 0..policy.max_retries {
         match operation().await {
             Ok(result) => {
                 if attempt > 0 {
@@ -26,8 +202,26 @@ where
                 return Ok(result);
             }
             Err(e) => {
-                if attempt < MAX_RETRIES - 1 {
-                    let delay = Duration::from_secs(RETRY_DELAYS[attempt as usize]);
+                if !e.is_retryable() {
+                    eprintln!("{operation_name} failed with non-retryable error: {e}");
+                    return Err(e);
+                }
+                if attempt + 1 < policy.max_retries {
+                    let default_delay = policy.delay_for_attempt(attempt);
+                    let delay = if honor_hints {
+                        e.retry_after().unwrap_or(default_delay)
+                    } else {
+                        default_delay
+                    };
+                    if deadline.is_some_and(|deadline| Instant::now() + delay >= deadline) {
+                        eprintln!(
+                            "{} failed (attempt {}): {}. Not retrying, the deadline would pass",
+                            operation_name,
+                            attempt + 1,
+                            e
+                        );
+                        return Err(e);
+                    }
                     eprintln!(
                         "{} failed (attempt {}): {}. Retrying in {:?}...",
                         operation_name,
@@ -35,6 +229,7 @@ where
                         e,
                         delay
                     );
+                    on_retry(attempt + 1, &e);
                     sleep(delay).await;
                 }
                 last_error = Some(e);
@@ -99,4 +294,242 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(counter.load(Ordering::SeqCst), MAX_RETRIES);
     }
+
+    #[tokio::test]
+    async fn test_retry_stops_on_permanent_error() {
+        let counter = Arc::new(AtomicU32::new(0));
+        let counter_clone = counter.clone();
+
+        let result = retry_with_backoff("test_op", move || {
+            let c = counter_clone.clone();
+            async move {
+                c.fetch_add(1, Ordering::SeqCst);
+                Err::<i32, _>(TroopError::AuthError("Invalid ticket".to_string()))
+            }
+        })
+        .await;
+
+        assert!(matches!(result, Err(TroopError::AuthError(_))));
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_hinted_retry_honors_retry_after() {
+        tokio::time::pause();
+        let counter = Arc::new(AtomicU32::new(0));
+        let counter_clone = counter.clone();
+        let start = tokio::time::Instant::now();
+
+        let result = retry_with_backoff_hinted("test_op", move || {
+            let c = counter_clone.clone();
+            async move {
+                if c.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Err(TroopError::RateLimited {
+                        retry_after: Some(Duration::from_secs(7)),
+                        message: None,
+                    })
+                } else {
+                    Ok(42)
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+        assert_eq!(start.elapsed().as_secs(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_hinted_retry_falls_back_to_default_schedule() {
+        tokio::time::pause();
+        let counter = Arc::new(AtomicU32::new(0));
+        let counter_clone = counter.clone();
+        let start = tokio::time::Instant::now();
+
+        let result = retry_with_backoff_hinted("test_op", move || {
+            let c = counter_clone.clone();
+            async move {
+                if c.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Err(TroopError::RateLimited {
+                        retry_after: None,
+                        message: None,
+                    })
+                } else {
+                    Ok(42)
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(start.elapsed().as_secs(), RETRY_DELAYS[0]);
+    }
+
+    #[tokio::test]
+    async fn test_unhinted_retry_ignores_retry_after() {
+        tokio::time::pause();
+        let counter = Arc::new(AtomicU32::new(0));
+        let counter_clone = counter.clone();
+        let start = tokio::time::Instant::now();
+
+        let result = retry_with_backoff("test_op", move || {
+            let c = counter_clone.clone();
+            async move {
+                if c.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Err(TroopError::RateLimited {
+                        retry_after: Some(Duration::from_secs(30)),
+                        message: None,
+                    })
+                } else {
+                    Ok(42)
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(start.elapsed().as_secs(), RETRY_DELAYS[0]);
+    }
+
+    #[test]
+    fn test_default_policy_matches_fixed_schedule() {
+        let policy = RetryPolicy::default();
+        for (attempt, secs) in RETRY_DELAYS.iter().enumerate() {
+            assert_eq!(
+                policy.delay_for_attempt(attempt as u32),
+                Duration::from_secs(*secs)
+            );
+        }
+    }
+
+    #[test]
+    fn test_jittered_delays_stay_within_bounds() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_retries: 5,
+            jitter: true,
+        };
+        for attempt in 0..policy.max_retries {
+            let ceiling = Duration::from_millis(100 * (1 << attempt));
+            for _ in 0..200 {
+                assert!(policy.delay_for_attempt(attempt) <= ceiling);
+            }
+        }
+    }
+
+    #[test]
+    fn test_jittered_delays_vary() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_secs(10),
+            max_retries: 3,
+            jitter: true,
+        };
+        let delays: std::collections::HashSet<_> =
+            (0..20).map(|_| policy.delay_for_attempt(0)).collect();
+        assert!(delays.len() > 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_with_policy_respects_max_retries() {
+        tokio::time::pause();
+        let counter = Arc::new(AtomicU32::new(0));
+        let counter_clone = counter.clone();
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(10),
+            max_retries: 5,
+            jitter: true,
+        };
+
+        let result = retry_with_policy("test_op", &policy, move || {
+            let c = counter_clone.clone();
+            async move {
+                c.fetch_add(1, Ordering::SeqCst);
+                Err::<i32, _>(TroopError::NetworkError("down".to_string()))
+            }
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(counter.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test]
+    async fn test_retry_with_deadline_does_not_retry_past_it() {
+        tokio::time::pause();
+        let counter = Arc::new(AtomicU32::new(0));
+        let counter_clone = counter.clone();
+        let start = Instant::now();
+        // Room for the first retry (after RETRY_DELAYS[0]) but not the second
+        let deadline = start + Duration::from_secs(RETRY_DELAYS[0] + RETRY_DELAYS[1]);
+
+        let result = retry_with_deadline(
+            "test_op",
+            deadline,
+            |_, _| {},
+            move || {
+                let c = counter_clone.clone();
+                async move {
+                    c.fetch_add(1, Ordering::SeqCst);
+                    Err::<i32, _>(TroopError::NetworkError("down".to_string()))
+                }
+            },
+        )
+        .await;
+
+        assert!(matches!(result, Err(TroopError::NetworkError(_))));
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+        // The error is returned as soon as the attempt fails, without sleeping
+        assert_eq!(start.elapsed().as_secs(), RETRY_DELAYS[0]);
+    }
+
+    #[tokio::test]
+    async fn test_retry_with_policy_until_counts_retry_after_against_deadline() {
+        tokio::time::pause();
+        let counter = Arc::new(AtomicU32::new(0));
+        let counter_clone = counter.clone();
+        let start = Instant::now();
+
+        let result = retry_with_policy_until(
+            "test_op",
+            &RetryPolicy::default(),
+            start + Duration::from_secs(5),
+            |_, _| {},
+            move || {
+                let c = counter_clone.clone();
+                async move {
+                    c.fetch_add(1, Ordering::SeqCst);
+                    Err::<i32, _>(TroopError::RateLimited {
+                        retry_after: Some(Duration::from_secs(10)),
+                        message: None,
+                    })
+                }
+            },
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_on_retry_fires_before_each_retry() {
+        tokio::time::pause();
+        let observed = std::sync::Mutex::new(Vec::new());
+
+        let result = retry_with_backoff_observed(
+            "test_op",
+            |attempt, e: &TroopError| observed.lock().unwrap().push((attempt, e.to_string())),
+            || async { Err::<i32, _>(TroopError::NetworkError("down".to_string())) },
+        )
+        .await;
+
+        assert!(result.is_err());
+        let observed = observed.into_inner().unwrap();
+        assert_eq!(observed.len() as u32, MAX_RETRIES - 1);
+        let attempts: Vec<u32> = observed.iter().map(|(attempt, _)| *attempt).collect();
+        assert_eq!(attempts, (1..MAX_RETRIES).collect::<Vec<_>>());
+        assert!(observed[0].1.contains("down"));
+    }
 }
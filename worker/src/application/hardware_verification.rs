@@ -0,0 +1,127 @@
+use crate::application::ports::HardwareVerifier;
+use crate::infrastructure::system::benchmark::BenchmarkResult;
+use anyhow::Result;
+use monkey_troop_shared::{retry_with_backoff, VerifyRequest, VerifyResponse};
+use std::future::Future;
+use tracing::info;
+
+/// Prove this node's hardware to the coordinator: run `benchmark` with the seed and
+/// matrix size of a fresh challenge, then submit the result for the multiplier and tier
+/// the coordinator assigns. Coordinator calls are retried; the benchmark is not.
+pub async fn verify_hardware<B, Fut>(
+    coordinator: &dyn HardwareVerifier,
+    node_id: &str,
+    benchmark: B,
+) -> Result<VerifyResponse>
+where
+    B: FnOnce(String, usize) -> Fut,
+    Fut: Future<Output = Result<BenchmarkResult>>,
+{
+    let challenge = retry_with_backoff("Hardware challenge", || {
+        coordinator.request_challenge(node_id)
+    })
+    .await?;
+    info!(
+        "Received hardware challenge (matrix size {})",
+        challenge.matrix_size
+    );
+
+    let result = benchmark(challenge.seed, challenge.matrix_size as usize).await?;
+    let proof = VerifyRequest {
+        node_id: node_id.to_string(),
+        challenge_token: challenge.challenge_token,
+        proof_hash: result.proof_hash,
+        duration: result.duration,
+        device_name: result.device_name,
+    };
+    Ok(retry_with_backoff("Hardware proof submission", || {
+        coordinator.submit_proof(&proof)
+    })
+    .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use monkey_troop_shared::{ChallengeResponse, TroopError, TroopResult};
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MockCoordinator {
+        challenges: Mutex<VecDeque<TroopResult<ChallengeResponse>>>,
+        proofs: Mutex<Vec<VerifyRequest>>,
+    }
+
+    #[async_trait]
+    impl HardwareVerifier for MockCoordinator {
+        async fn request_challenge(&self, node_id: &str) -> TroopResult<ChallengeResponse> {
+            assert_eq!(node_id, "node-1");
+            self.challenges.lock().unwrap().pop_front().unwrap()
+        }
+
+        async fn submit_proof(&self, proof: &VerifyRequest) -> TroopResult<VerifyResponse> {
+            self.proofs.lock().unwrap().push(proof.clone());
+            Ok(VerifyResponse {
+                status: "verified".to_string(),
+                assigned_multiplier: 2.5,
+                tier: "Standard".to_string(),
+            })
+        }
+    }
+
+    fn challenge() -> ChallengeResponse {
+        ChallengeResponse {
+            challenge_token: "token-1".to_string(),
+            seed: "deadbeef".to_string(),
+            matrix_size: 256,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_benchmark_runs_on_the_challenge_and_is_submitted() {
+        let coordinator = MockCoordinator::default();
+        coordinator.challenges.lock().unwrap().extend([
+            Err(TroopError::NetworkError("connection refused".to_string())),
+            Ok(challenge()),
+        ]);
+
+        let verified = verify_hardware(&coordinator, "node-1", |seed, matrix_size| async move {
+            assert_eq!((seed.as_str(), matrix_size), ("deadbeef", 256));
+            Ok(BenchmarkResult {
+                proof_hash: "abc123".to_string(),
+                duration: 4.2,
+                device_name: "RTX 4090".to_string(),
+            })
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(verified.assigned_multiplier, 2.5);
+        assert_eq!(verified.tier, "Standard");
+        let proofs = coordinator.proofs.lock().unwrap();
+        assert_eq!(proofs.len(), 1);
+        assert_eq!(proofs[0].challenge_token, "token-1");
+        assert_eq!(proofs[0].proof_hash, "abc123");
+        assert_eq!(proofs[0].device_name, "RTX 4090");
+    }
+
+    #[tokio::test]
+    async fn test_failed_benchmark_submits_nothing() {
+        let coordinator = MockCoordinator::default();
+        coordinator
+            .challenges
+            .lock()
+            .unwrap()
+            .push_back(Ok(challenge()));
+
+        let result = verify_hardware(&coordinator, "node-1", |_, _| async {
+            anyhow::bail!("benchmark.py not found")
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert!(coordinator.proofs.lock().unwrap().is_empty());
+    }
+}
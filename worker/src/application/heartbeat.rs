@@ -0,0 +1,90 @@
+use crate::application::services::WorkerService;
+use monkey_troop_shared::{
+    CircuitBreaker, CircuitState, NodeLoad, CIRCUIT_BREAKER_THRESHOLD, CIRCUIT_BREAKER_TIMEOUT,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+/// Periodically report this node to the coordinator.
+///
+/// Heartbeats go through a circuit breaker so an unreachable coordinator is probed
+/// once per timeout instead of being hit (and logged) every interval. `in_flight` is the
+/// proxy's live request count, reported against `max_concurrent` as the node's load.
+/// Each change of the breaker's state is logged, so an operator can tell a node cut off
+/// from the coordinator from a healthy one.
+pub async fn run_heartbeat_loop(
+    service: Arc<WorkerService>,
+    every: Duration,
+    in_flight: Arc<AtomicU32>,
+    max_concurrent: u32,
+) {
+    let breaker = CircuitBreaker::new(CIRCUIT_BREAKER_THRESHOLD, CIRCUIT_BREAKER_TIMEOUT);
+    let mut logged = breaker.state().await;
+
+    let mut interval = tokio::time::interval(every);
+    loop {
+        interval.tick().await;
+        let allowed = breaker.allow_request().await;
+        let state = breaker.state().await;
+        log_state_change(&mut logged, state);
+        if !allowed {
+            debug!("Skipping heartbeat, coordinator circuit is open");
+            continue;
+        }
+        let load = NodeLoad {
+            in_flight: in_flight.load(Ordering::SeqCst),
+            max_concurrent,
+        };
+        match service
+            .send_heartbeat(load, state == CircuitState::Closed)
+            .await
+        {
+            Ok(()) => breaker.record_success().await,
+            Err(e) => {
+                error!("Heartbeat failed (coordinator circuit {:?}): {}", state, e);
+                breaker.record_failure().await;
+            }
+        }
+        log_state_change(&mut logged, breaker.state().await);
+    }
+}
+
+/// Log `state` if it differs from the last state logged, and remember it. Returns
+/// whether anything was logged.
+fn log_state_change(logged: &mut CircuitState, state: CircuitState) -> bool {
+    if *logged == state {
+        return false;
+    }
+    match state {
+        CircuitState::Open => warn!(
+            "Coordinator heartbeat circuit opened ({:?} -> {:?}), pausing heartbeats for {:?}",
+            logged, state, CIRCUIT_BREAKER_TIMEOUT
+        ),
+        CircuitState::HalfOpen => info!(
+            "Coordinator heartbeat circuit half-open, probing whether the coordinator is back"
+        ),
+        CircuitState::Closed => {
+            info!("Coordinator heartbeat circuit closed, coordinator reachable")
+        }
+    }
+    *logged = state;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_state_changes_are_logged() {
+        let mut logged = CircuitState::Closed;
+        assert!(!log_state_change(&mut logged, CircuitState::Closed));
+        assert!(log_state_change(&mut logged, CircuitState::Open));
+        assert!(!log_state_change(&mut logged, CircuitState::Open));
+        assert!(log_state_change(&mut logged, CircuitState::HalfOpen));
+        assert!(log_state_change(&mut logged, CircuitState::Closed));
+        assert_eq!(logged, CircuitState::Closed);
+    }
+}
@@ -0,0 +1,20 @@
+use crate::application::services::WorkerService;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Keep `models` loaded in their engines by warming them every `every`, so the first
+/// request after a quiet spell does not wait for (or time out on) a cold start; Ollama,
+/// for one, unloads a model after a few idle minutes. The first warm-up is right away,
+/// so the models are loaded before the first request. Warming is skipped while the GPU
+/// is busy.
+pub async fn run_keep_alive_loop(
+    service: Arc<WorkerService>,
+    models: Vec<String>,
+    every: Duration,
+) {
+    let mut interval = tokio::time::interval(every);
+    loop {
+        interval.tick().await;
+        service.keep_warm(&models).await;
+    }
+}
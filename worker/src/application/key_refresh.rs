@@ -0,0 +1,96 @@
+use crate::application::ports::{AuthTokenVerifier, PublicKeySource};
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{interval_at, Instant};
+use tracing::{info, warn};
+
+/// Fetch the coordinator's ticket-signing key and install it in the verifier.
+/// On failure the verifier keeps its current key.
+pub async fn refresh_public_key(
+    source: &dyn PublicKeySource,
+    verifier: &dyn AuthTokenVerifier,
+) -> Result<()> {
+    let key = source.fetch_public_key().await?;
+    verifier.rotate_key(key).await
+}
+
+/// Re-fetch the coordinator's public key every `every`, so a key rotation does not
+/// require a worker restart. The first refresh happens one interval from now.
+pub async fn run_public_key_refresh_loop(
+    source: Arc<dyn PublicKeySource>,
+    verifier: Arc<dyn AuthTokenVerifier>,
+    every: Duration,
+) {
+    let mut interval = interval_at(Instant::now() + every, every);
+    loop {
+        interval.tick().await;
+        match refresh_public_key(source.as_ref(), verifier.as_ref()).await {
+            Ok(()) => info!("Coordinator public key refreshed"),
+            Err(e) => warn!("Public key refresh failed, keeping current key: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    struct MockKeySource {
+        responses: Mutex<VecDeque<Result<String>>>,
+    }
+
+    #[async_trait]
+    impl PublicKeySource for MockKeySource {
+        async fn fetch_public_key(&self) -> Result<String> {
+            self.responses.lock().unwrap().pop_front().unwrap()
+        }
+    }
+
+    /// Accepts any key except "invalid" and remembers the current one.
+    #[derive(Default)]
+    struct RecordingVerifier {
+        key: Mutex<Option<String>>,
+    }
+
+    #[async_trait]
+    impl AuthTokenVerifier for RecordingVerifier {
+        async fn verify_ticket(&self, _: &str, _: &str) -> Result<Option<String>> {
+            Ok(Some("requester-1".to_string()))
+        }
+        async fn rotate_key(&self, public_key_pem: String) -> Result<()> {
+            anyhow::ensure!(public_key_pem != "invalid", "bad key");
+            *self.key.lock().unwrap() = Some(public_key_pem);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_swaps_key_and_keeps_it_on_failure() {
+        let source = MockKeySource {
+            responses: Mutex::new(VecDeque::from([
+                Ok("key-1".to_string()),
+                Err(anyhow::anyhow!("coordinator unreachable")),
+                Ok("invalid".to_string()),
+                Ok("key-2".to_string()),
+            ])),
+        };
+        let verifier = RecordingVerifier::default();
+        let current = || verifier.key.lock().unwrap().clone();
+
+        refresh_public_key(&source, &verifier).await.unwrap();
+        assert_eq!(current().as_deref(), Some("key-1"));
+
+        assert!(refresh_public_key(&source, &verifier).await.is_err());
+        assert_eq!(current().as_deref(), Some("key-1"));
+
+        assert!(refresh_public_key(&source, &verifier).await.is_err());
+        assert_eq!(current().as_deref(), Some("key-1"));
+
+        refresh_public_key(&source, &verifier).await.unwrap();
+        assert_eq!(current().as_deref(), Some("key-2"));
+    }
+}
@@ -1,2 +1,7 @@
+pub mod hardware_verification;
+pub mod heartbeat;
+pub mod keep_alive;
+pub mod key_refresh;
+pub mod model_refresh;
 pub mod ports;
 pub mod services;
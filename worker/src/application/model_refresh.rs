@@ -0,0 +1,18 @@
+use crate::application::services::WorkerService;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{interval_at, Instant};
+use tracing::warn;
+
+/// Rebuild the model registry every `every`, so models pulled or removed while the worker
+/// runs are picked up, and a node whose engines have stopped offering models reports
+/// itself Offline until they come back. The first refresh happens one interval from now.
+pub async fn run_model_refresh_loop(service: Arc<WorkerService>, every: Duration) {
+    let mut interval = interval_at(Instant::now() + every, every);
+    loop {
+        interval.tick().await;
+        if let Err(e) = service.refresh_model_registry().await {
+            warn!("Model registry refresh failed: {}", e);
+        }
+    }
+}
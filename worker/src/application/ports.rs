@@ -1,45 +1,85 @@
-use crate::domain::inference::{ChatMessage, InferenceResponse, StreamingChunk};
-use crate::domain::models::{HardwareStatus, Model, NodeStatus};
+use crate::domain::inference::{
+    ChatMessage, EmbeddingsResponse, EngineReply, InferenceResponse, SamplingOptions,
+    StreamingChunk, Tool,
+};
+use crate::domain::models::{HardwareStatus, HeartbeatReport, Model};
 use anyhow::Result;
 use async_trait::async_trait;
 use futures::Stream;
-use monkey_troop_shared::ModelIdentity;
+use monkey_troop_shared::{
+    ChallengeResponse, EngineInfo, TroopResult, UsageReport, VerifyRequest, VerifyResponse,
+};
 use std::pin::Pin;
 
+pub type ChunkStream = Pin<Box<dyn Stream<Item = Result<StreamingChunk>> + Send>>;
+
 #[async_trait]
 pub trait InferenceEngine: Send + Sync {
     async fn get_models(&self) -> Result<Vec<Model>>;
-    async fn is_healthy(&self) -> bool;
-    async fn chat(&self, model: &str, messages: Vec<ChatMessage>) -> Result<InferenceResponse>;
+    /// Type, version and port, with `healthy` from a quick health check.
+    async fn get_info(&self) -> EngineInfo;
+    /// Load `model` into memory, or keep it there, without generating anything.
+    async fn warm_up(&self, model: &str) -> Result<()>;
+    /// `request_id` is the caller's correlation ID, forwarded to the engine when supported.
+    /// Non-success upstream responses are reported as `EngineHttpError`.
+    /// `tools` are offered to the model; any calls it makes come back as `tool_calls`.
+    /// `sampling` options the engine has no equivalent for are ignored.
+    async fn chat(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: Option<Vec<Tool>>,
+        sampling: &SamplingOptions,
+        request_id: Option<&str>,
+    ) -> Result<EngineReply<InferenceResponse>>;
     async fn chat_stream(
         &self,
         model: &str,
         messages: Vec<ChatMessage>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamingChunk>> + Send>>>;
+        tools: Option<Vec<Tool>>,
+        sampling: &SamplingOptions,
+        request_id: Option<&str>,
+    ) -> Result<EngineReply<ChunkStream>>;
+    async fn embed(
+        &self,
+        model: &str,
+        input: Vec<String>,
+        request_id: Option<&str>,
+    ) -> Result<EngineReply<EmbeddingsResponse>>;
 }
 
 #[async_trait]
 pub trait HardwareMonitor: Send + Sync {
     async fn get_status(&self) -> Result<HardwareStatus>;
-    async fn is_idle(&self) -> Result<bool>;
 }
 
 #[async_trait]
 pub trait CoordinatorClient: Send + Sync {
-    async fn send_heartbeat(
-        &self,
-        node_id: &str,
-        status: NodeStatus,
-        models: Vec<ModelIdentity>,
-        hardware: HardwareStatus,
-        engines: Vec<String>,
-        encryption_public_key: Option<String>,
-    ) -> Result<()>;
+    async fn send_heartbeat(&self, report: HeartbeatReport) -> Result<()>;
+    async fn report_usage(&self, report: &UsageReport) -> Result<()>;
 }
 
 #[async_trait]
 pub trait AuthTokenVerifier: Send + Sync {
-    async fn verify_ticket(&self, token: &str, target_node_id: &str) -> Result<bool>;
+    /// The requester the ticket was issued to, or `None` if it is not valid for
+    /// `target_node_id`.
+    async fn verify_ticket(&self, token: &str, target_node_id: &str) -> Result<Option<String>>;
+    /// Replace the key tickets are verified against. An invalid key is rejected and the
+    /// current one kept.
+    async fn rotate_key(&self, public_key_pem: String) -> Result<()>;
+}
+
+/// Where the coordinator's ticket-signing public key is obtained from.
+#[async_trait]
+pub trait PublicKeySource: Send + Sync {
+    async fn fetch_public_key(&self) -> Result<String>;
+}
+
+/// The coordinator's proof-of-hardware endpoints, which set a node's credit multiplier.
+#[async_trait]
+pub trait HardwareVerifier: Send + Sync {
+    async fn request_challenge(&self, node_id: &str) -> TroopResult<ChallengeResponse>;
+    async fn submit_proof(&self, proof: &VerifyRequest) -> TroopResult<VerifyResponse>;
 }
 
 /// Port for E2E encryption operations. Synchronous because crypto is CPU-bound and fast.
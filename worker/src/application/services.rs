@@ -1,15 +1,21 @@
 use crate::application::ports::{
-    AuthTokenVerifier, CoordinatorClient, E2EDecryptor, HardwareMonitor, InferenceEngine,
+    AuthTokenVerifier, ChunkStream, CoordinatorClient, E2EDecryptor, HardwareMonitor,
+    InferenceEngine,
+};
+use crate::domain::inference::{
+    ChatMessage, EmbeddingsResponse, EngineReply, InferenceResponse, SamplingOptions, TokenUsage,
+    Tool,
+};
+use crate::domain::models::{
+    EngineType, HardwareStatus, HeartbeatReport, ModelRegistry, NodeStatus,
 };
-use crate::domain::inference::{ChatMessage, InferenceResponse, StreamingChunk};
-use crate::domain::models::{EngineType, ModelRegistry, NodeStatus};
 use anyhow::Result;
-use futures::Stream;
+use monkey_troop_shared::{EngineInfo, NodeLoad, UsageReport};
 use std::collections::HashMap;
-use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{error, info};
+use tracing::{debug, info, warn};
 
 pub struct WorkerService {
     pub node_id: String,
@@ -19,6 +25,9 @@ pub struct WorkerService {
     coordinator: Arc<dyn CoordinatorClient>,
     verifier: Arc<dyn AuthTokenVerifier>,
     e2e: Arc<dyn E2EDecryptor>,
+    /// Set while the last registry refresh found no model; the registry from the refresh
+    /// before is kept, but is no sign the engines can serve it
+    no_models_found: AtomicBool,
 }
 
 impl WorkerService {
@@ -39,10 +48,12 @@ impl WorkerService {
             coordinator,
             verifier,
             e2e,
+            no_models_found: AtomicBool::new(false),
         }
     }
 
-    pub async fn verify_ticket(&self, token: &str) -> Result<bool> {
+    /// The requester the ticket was issued to, if it is valid for this node.
+    pub async fn verify_ticket(&self, token: &str) -> Result<Option<String>> {
         self.verifier.verify_ticket(token, &self.node_id).await
     }
 
@@ -58,18 +69,38 @@ impl WorkerService {
         &self,
         model_id: &str,
         messages: Vec<ChatMessage>,
-    ) -> Result<InferenceResponse> {
+        tools: Option<Vec<Tool>>,
+        sampling: &SamplingOptions,
+        request_id: Option<&str>,
+    ) -> Result<EngineReply<InferenceResponse>> {
         let engine = self.engine_for_model(model_id).await?;
-        engine.chat(model_id, messages).await
+        engine
+            .chat(model_id, messages, tools, sampling, request_id)
+            .await
     }
 
     pub async fn chat_stream(
         &self,
         model_id: &str,
         messages: Vec<ChatMessage>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamingChunk>> + Send>>> {
+        tools: Option<Vec<Tool>>,
+        sampling: &SamplingOptions,
+        request_id: Option<&str>,
+    ) -> Result<EngineReply<ChunkStream>> {
+        let engine = self.engine_for_model(model_id).await?;
+        engine
+            .chat_stream(model_id, messages, tools, sampling, request_id)
+            .await
+    }
+
+    pub async fn embed(
+        &self,
+        model_id: &str,
+        input: Vec<String>,
+        request_id: Option<&str>,
+    ) -> Result<EngineReply<EmbeddingsResponse>> {
         let engine = self.engine_for_model(model_id).await?;
-        engine.chat_stream(model_id, messages).await
+        engine.embed(model_id, input, request_id).await
     }
 
     async fn engine_for_model(&self, model_id: &str) -> Result<&dyn InferenceEngine> {
@@ -87,33 +118,55 @@ impl WorkerService {
             .ok_or_else(|| anyhow::anyhow!("No engine registered for type {engine_type:?}"))
     }
 
+    /// Rebuild the registry from every engine. An engine that is unhealthy or fails to
+    /// list its models is skipped with a warning, so one broken engine does not take the
+    /// others down; only when no engine yields a model is this an error, and the current
+    /// registry is then kept. A model served by several engines is registered from the one
+    /// first in `EngineType` priority order.
     pub async fn refresh_model_registry(&self) -> Result<()> {
         let registry_futures: Vec<_> = self
             .engines
-            .values()
-            .map(|engine| async move {
-                if engine.is_healthy().await {
-                    match engine.get_models().await {
-                        Ok(models) => Some(models),
-                        Err(e) => {
-                            error!("Failed to fetch models from engine: {}", e);
-                            None
-                        }
+            .iter()
+            .map(|(engine_type, engine)| async move {
+                if !engine.get_info().await.healthy {
+                    warn!("Skipping {:?} engine: health check failed", engine_type);
+                    return None;
+                }
+                match engine.get_models().await {
+                    Ok(models) => Some((*engine_type, models)),
+                    Err(e) => {
+                        warn!(
+                            "Skipping {:?} engine: failed to list models: {}",
+                            engine_type, e
+                        );
+                        None
                     }
-                } else {
-                    None
                 }
             })
             .collect();
 
-        let results = futures::future::join_all(registry_futures).await;
+        // Engines are held in a map, so its iteration order says nothing about priority
+        let mut results: Vec<_> = futures::future::join_all(registry_futures)
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+        results.sort_by_key(|(engine_type, _)| *engine_type);
 
         let mut new_registry = ModelRegistry::new();
-        for models in results.into_iter().flatten() {
+        for (_, models) in results {
             for model in models {
                 new_registry.add_model(model);
             }
         }
+        self.no_models_found
+            .store(new_registry.models.is_empty(), Ordering::SeqCst);
+        if new_registry.models.is_empty() {
+            anyhow::bail!(
+                "No models found on any of the {} inference engine(s)",
+                self.engines.len()
+            );
+        }
 
         let mut registry = self.registry.write().await;
         *registry = new_registry;
@@ -124,67 +177,150 @@ impl WorkerService {
         Ok(())
     }
 
-    pub async fn run_initial_benchmark(&self) -> Result<()> {
-        info!("Running initial hardware benchmark...");
-        let result =
-            crate::infrastructure::system::benchmark::run_benchmark("startup", 512).await?;
-        info!(
-            "✓ Hardware verified: {} ({:.4}s)",
-            result.device_name, result.duration
-        );
-        Ok(())
+    /// Ask the engine of each of `models` to keep it loaded, unless the GPU is busy (or
+    /// cannot be read), so warming never competes with real requests. Models not in the
+    /// registry are skipped; they may not have been pulled yet.
+    pub async fn keep_warm(&self, models: &[String]) {
+        match self.monitor.get_status().await {
+            Ok(hardware) if hardware.is_idle() => {}
+            Ok(_) => {
+                debug!("Skipping keep-alive, the GPU is busy");
+                return;
+            }
+            Err(e) => {
+                debug!("Skipping keep-alive, hardware status unavailable: {}", e);
+                return;
+            }
+        }
+        for model in models {
+            let engine = match self.engine_for_model(model).await {
+                Ok(engine) => engine,
+                Err(e) => {
+                    debug!("Not keeping {} warm: {}", model, e);
+                    continue;
+                }
+            };
+            match engine.warm_up(model).await {
+                Ok(()) => debug!("Kept {} warm", model),
+                Err(e) => warn!("Keep-alive for {} failed: {}", model, e),
+            }
+        }
     }
 
-    pub async fn send_heartbeat(&self) -> Result<()> {
-        let is_idle = self.monitor.is_idle().await.unwrap_or(false);
-        let status = if is_idle {
-            NodeStatus::Idle
-        } else {
-            NodeStatus::Busy
+    /// Every engine's health check, in `EngineType` priority order.
+    async fn engine_infos(&self) -> Vec<(EngineType, EngineInfo)> {
+        let mut infos =
+            futures::future::join_all(self.engines.iter().map(
+                |(engine_type, engine)| async move { (*engine_type, engine.get_info().await) },
+            ))
+            .await;
+        infos.sort_by_key(|(engine_type, _)| *engine_type);
+        infos
+    }
+
+    /// `load` is the proxy's current request load, reported so the coordinator can
+    /// prefer less busy nodes; `coordinator_reachable` is whether the previous heartbeats
+    /// got through.
+    ///
+    /// A node that cannot serve reports itself Offline, so the coordinator stops routing
+    /// to it at once rather than when its heartbeats time out: that is when the registry
+    /// is empty or its last refresh found no model, or when the hardware cannot be read
+    /// (the GPU is gone). Heartbeats carry on while Offline, so the coordinator sees the
+    /// node recover.
+    ///
+    /// Only models of engines that pass their health check now are advertised; the
+    /// registry may be minutes old, and an engine that has since gone down cannot serve.
+    pub async fn send_heartbeat(&self, load: NodeLoad, coordinator_reachable: bool) -> Result<()> {
+        let infos = self.engine_infos().await;
+        let mut healthy = Vec::new();
+        for (engine_type, info) in &infos {
+            if info.healthy {
+                healthy.push(*engine_type);
+            } else {
+                warn!(
+                    "Not advertising {:?} models: health check failed",
+                    engine_type
+                );
+            }
+        }
+        let models = self.registry.read().await.to_model_identities(&healthy);
+        let no_models = models.is_empty() || self.no_models_found.load(Ordering::SeqCst);
+        let (status, hardware) = match self.monitor.get_status().await {
+            Err(e) => {
+                warn!("Reporting Offline, hardware status unavailable: {}", e);
+                (NodeStatus::Offline, HardwareStatus::unavailable())
+            }
+            Ok(hardware) if no_models => {
+                warn!("Reporting Offline, no inference engine offers a model");
+                (NodeStatus::Offline, hardware)
+            }
+            Ok(hardware) if hardware.is_idle() => (NodeStatus::Idle, hardware),
+            Ok(hardware) => (NodeStatus::Busy, hardware),
         };
-        let hardware = self.monitor.get_status().await?;
-        let models = self.registry.read().await.to_model_identities();
 
         self.coordinator
-            .send_heartbeat(
-                &self.node_id,
+            .send_heartbeat(HeartbeatReport {
+                node_id: self.node_id.clone(),
                 status,
                 models,
                 hardware,
-                Vec::new(),
-                Some(self.encryption_public_key().to_string()),
-            )
+                engines: infos.into_iter().map(|(_, info)| info).collect(),
+                encryption_public_key: Some(self.encryption_public_key().to_string()),
+                load,
+                coordinator_reachable,
+            })
             .await?;
 
         Ok(())
     }
+
+    /// Report the tokens a completion for `requester` used, for billing. Engines that
+    /// report no usage leave nothing to bill, so nothing is sent.
+    pub async fn report_usage(
+        &self,
+        requester: &str,
+        model: &str,
+        usage: &TokenUsage,
+    ) -> Result<()> {
+        if usage.prompt_tokens == 0 && usage.completion_tokens == 0 {
+            return Ok(());
+        }
+        self.coordinator
+            .report_usage(&UsageReport {
+                node_id: self.node_id.clone(),
+                requester: requester.to_string(),
+                model: model.to_string(),
+                prompt_tokens: usage.prompt_tokens.into(),
+                completion_tokens: usage.completion_tokens.into(),
+            })
+            .await
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::application::ports::{
-        AuthTokenVerifier, CoordinatorClient, E2EDecryptor, HardwareMonitor, InferenceEngine,
+        AuthTokenVerifier, ChunkStream, CoordinatorClient, E2EDecryptor, HardwareMonitor,
+        InferenceEngine,
     };
     use crate::domain::inference::{
-        ChatMessage, ChatMessageDelta, InferenceChoice, InferenceResponse, StreamingChoice,
-        StreamingChunk, TokenUsage,
+        ChatMessage, ChatMessageDelta, EmbeddingData, EmbeddingUsage, EmbeddingsResponse,
+        EngineReply, InferenceChoice, InferenceResponse, SamplingOptions, StreamingChoice,
+        StreamingChunk, TokenUsage, Tool,
     };
     use crate::domain::models::{EngineType, HardwareStatus, Model, NodeStatus};
     use anyhow::Result;
     use async_trait::async_trait;
-    use futures::Stream;
-    use monkey_troop_shared::ModelIdentity;
-    use std::pin::Pin;
     use tokio::sync::Mutex;
 
-    type HeartbeatCall = (String, NodeStatus, Vec<ModelIdentity>, HardwareStatus);
-    type HeartbeatHistory = Arc<Mutex<Vec<HeartbeatCall>>>;
+    type HeartbeatHistory = Arc<Mutex<Vec<HeartbeatReport>>>;
 
     struct MockInferenceEngine {
         models: Vec<Model>,
         healthy: bool,
         fail_get_models: bool,
+        warmed: Arc<std::sync::Mutex<Vec<String>>>,
     }
 
     #[async_trait]
@@ -198,39 +334,59 @@ mod tests {
                 Err(anyhow::anyhow!("Unhealthy engine"))
             }
         }
-        async fn is_healthy(&self) -> bool {
-            self.healthy
+        async fn get_info(&self) -> EngineInfo {
+            EngineInfo {
+                engine_type: "mock".to_string(),
+                version: "test".to_string(),
+                port: 0,
+                healthy: self.healthy,
+            }
+        }
+        async fn warm_up(&self, model: &str) -> Result<()> {
+            self.warmed.lock().unwrap().push(model.to_string());
+            Ok(())
         }
         async fn chat(
             &self,
             model: &str,
             _messages: Vec<ChatMessage>,
-        ) -> Result<InferenceResponse> {
-            Ok(InferenceResponse {
-                id: "mock-id".to_string(),
-                object: "chat.completion".to_string(),
-                created: 0,
-                model: model.to_string(),
-                choices: vec![InferenceChoice {
-                    index: 0,
-                    message: ChatMessage {
-                        role: "assistant".to_string(),
-                        content: "mock response".to_string(),
+            _tools: Option<Vec<Tool>>,
+            _sampling: &SamplingOptions,
+            _request_id: Option<&str>,
+        ) -> Result<EngineReply<InferenceResponse>> {
+            Ok(EngineReply {
+                body: InferenceResponse {
+                    id: "mock-id".to_string(),
+                    object: "chat.completion".to_string(),
+                    created: 0,
+                    model: model.to_string(),
+                    choices: vec![InferenceChoice {
+                        index: 0,
+                        message: ChatMessage {
+                            role: "assistant".to_string(),
+                            content: Some("mock response".into()),
+                            tool_calls: None,
+                            tool_call_id: None,
+                        },
+                        finish_reason: "stop".to_string(),
+                    }],
+                    usage: TokenUsage {
+                        prompt_tokens: 0,
+                        completion_tokens: 0,
+                        total_tokens: 0,
                     },
-                    finish_reason: "stop".to_string(),
-                }],
-                usage: TokenUsage {
-                    prompt_tokens: 0,
-                    completion_tokens: 0,
-                    total_tokens: 0,
                 },
+                headers: vec![],
             })
         }
         async fn chat_stream(
             &self,
             model: &str,
             _messages: Vec<ChatMessage>,
-        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamingChunk>> + Send>>> {
+            _tools: Option<Vec<Tool>>,
+            _sampling: &SamplingOptions,
+            _request_id: Option<&str>,
+        ) -> Result<EngineReply<ChunkStream>> {
             let chunk = StreamingChunk {
                 id: "mock-id".to_string(),
                 object: "chat.completion.chunk".to_string(),
@@ -241,17 +397,48 @@ mod tests {
                     delta: ChatMessageDelta {
                         role: Some("assistant".to_string()),
                         content: Some("mock".to_string()),
+                        tool_calls: None,
                     },
                     finish_reason: Some("stop".to_string()),
                 }],
+                usage: None,
             };
-            Ok(Box::pin(futures::stream::iter(vec![Ok(chunk)])))
+            Ok(EngineReply {
+                body: Box::pin(futures::stream::iter(vec![Ok(chunk)])),
+                headers: vec![],
+            })
+        }
+        async fn embed(
+            &self,
+            model: &str,
+            input: Vec<String>,
+            _request_id: Option<&str>,
+        ) -> Result<EngineReply<EmbeddingsResponse>> {
+            Ok(EngineReply {
+                body: EmbeddingsResponse {
+                    object: "list".to_string(),
+                    data: input
+                        .iter()
+                        .enumerate()
+                        .map(|(index, text)| EmbeddingData {
+                            object: "embedding".to_string(),
+                            index,
+                            embedding: vec![text.len() as f32],
+                        })
+                        .collect(),
+                    model: model.to_string(),
+                    usage: EmbeddingUsage {
+                        prompt_tokens: 0,
+                        total_tokens: 0,
+                    },
+                },
+                headers: vec![],
+            })
         }
     }
 
     struct MockHardwareMonitor {
         status: HardwareStatus,
-        is_idle: bool,
     }
 
     #[async_trait]
@@ -259,28 +446,21 @@ mod tests {
         async fn get_status(&self) -> Result<HardwareStatus> {
             Ok(self.status.clone())
         }
-        async fn is_idle(&self) -> Result<bool> {
-            Ok(self.is_idle)
-        }
     }
 
     struct MockCoordinatorClient {
         heartbeat_calls: HeartbeatHistory,
+        usage_reports: Mutex<Vec<UsageReport>>,
     }
 
     #[async_trait]
     impl CoordinatorClient for MockCoordinatorClient {
-        async fn send_heartbeat(
-            &self,
-            node_id: &str,
-            status: NodeStatus,
-            models: Vec<ModelIdentity>,
-            hardware: HardwareStatus,
-            _engines: Vec<String>,
-            _encryption_public_key: Option<String>,
-        ) -> Result<()> {
-            let mut calls = self.heartbeat_calls.lock().await;
-            calls.push((node_id.to_string(), status, models, hardware));
+        async fn send_heartbeat(&self, report: HeartbeatReport) -> Result<()> {
+            self.heartbeat_calls.lock().await.push(report);
+            Ok(())
+        }
+        async fn report_usage(&self, report: &UsageReport) -> Result<()> {
+            self.usage_reports.lock().await.push(report.clone());
             Ok(())
         }
     }
@@ -291,8 +471,14 @@ mod tests {
 
     #[async_trait]
     impl AuthTokenVerifier for MockAuthTokenVerifier {
-        async fn verify_ticket(&self, token: &str, target_node_id: &str) -> Result<bool> {
-            Ok(token == self.valid_token && target_node_id.starts_with("node-"))
+        async fn verify_ticket(&self, token: &str, target_node_id: &str) -> Result<Option<String>> {
+            Ok(
+                (token == self.valid_token && target_node_id.starts_with("node-"))
+                    .then(|| "requester-1".to_string()),
+            )
+        }
+        async fn rotate_key(&self, _: String) -> Result<()> {
+            Ok(())
         }
     }
 
@@ -330,6 +516,7 @@ mod tests {
             }],
             healthy: true,
             fail_get_models: false,
+            warmed: Arc::default(),
         });
 
         let engine2 = Box::new(MockInferenceEngine {
@@ -341,6 +528,7 @@ mod tests {
             }],
             healthy: false,
             fail_get_models: false,
+            warmed: Arc::default(),
         });
 
         let engine3 = Box::new(MockInferenceEngine {
@@ -352,18 +540,21 @@ mod tests {
             }],
             healthy: true,
             fail_get_models: true,
+            warmed: Arc::default(),
         });
 
         let monitor = Arc::new(MockHardwareMonitor {
             status: HardwareStatus {
                 gpu_name: "GPU1".to_string(),
                 vram_free_mb: 1024,
+                gpu_util: 0.0,
+                gpus: Vec::new(),
             },
-            is_idle: true,
         });
 
         let coordinator = Arc::new(MockCoordinatorClient {
             heartbeat_calls: Arc::new(Mutex::new(Vec::new())),
+            usage_reports: Mutex::default(),
         });
 
         let verifier = Arc::new(MockAuthTokenVerifier {
@@ -391,6 +582,171 @@ mod tests {
         assert_eq!(registry_read.models[0].id, "model1");
     }
 
+    #[tokio::test]
+    async fn test_refresh_model_registry_prefers_engines_in_priority_order() {
+        let served_by = |engine_type| {
+            Box::new(MockInferenceEngine {
+                models: vec![
+                    Model {
+                        id: "shared".to_string(),
+                        content_hash: "sha256:shared".to_string(),
+                        size_bytes: 100,
+                        engine_type,
+                    },
+                    Model {
+                        id: format!("{engine_type:?}-only"),
+                        content_hash: format!("sha256:{engine_type:?}"),
+                        size_bytes: 100,
+                        engine_type,
+                    },
+                ],
+                healthy: true,
+                fail_get_models: false,
+                warmed: Arc::default(),
+            }) as Box<dyn InferenceEngine>
+        };
+
+        // Each map hashes with fresh keys, so a few rounds cover different iteration orders
+        for _ in 0..8 {
+            let registry = Arc::new(RwLock::new(ModelRegistry::new()));
+            let service = WorkerService::new(
+                "node-1".to_string(),
+                registry.clone(),
+                make_engines(vec![
+                    (EngineType::LmStudio, served_by(EngineType::LmStudio)),
+                    (EngineType::Vllm, served_by(EngineType::Vllm)),
+                    (EngineType::Ollama, served_by(EngineType::Ollama)),
+                ]),
+                Arc::new(MockHardwareMonitor {
+                    status: HardwareStatus {
+                        gpu_name: "GPU1".to_string(),
+                        vram_free_mb: 1024,
+                        gpu_util: 0.0,
+                        gpus: Vec::new(),
+                    },
+                }),
+                Arc::new(MockCoordinatorClient {
+                    heartbeat_calls: Arc::new(Mutex::new(Vec::new())),
+                    usage_reports: Mutex::default(),
+                }),
+                Arc::new(MockAuthTokenVerifier {
+                    valid_token: "secret".to_string(),
+                }),
+                Arc::new(MockE2EDecryptor),
+            );
+
+            service.refresh_model_registry().await.unwrap();
+
+            let registry = registry.read().await;
+            let ids: Vec<_> = registry.models.iter().map(|m| m.id.as_str()).collect();
+            assert_eq!(ids, ["shared", "Ollama-only", "Vllm-only", "LmStudio-only"]);
+            assert_eq!(
+                registry.find_by_name("shared").unwrap().engine_type,
+                EngineType::Ollama
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_model_registry_fails_when_no_engine_has_models() {
+        let registry = Arc::new(RwLock::new(ModelRegistry::new()));
+        registry.write().await.add_model(Model {
+            id: "previous".to_string(),
+            content_hash: "sha256:aaa".to_string(),
+            size_bytes: 100,
+            engine_type: EngineType::Ollama,
+        });
+        let failing = Box::new(MockInferenceEngine {
+            models: Vec::new(),
+            healthy: true,
+            fail_get_models: true,
+            warmed: Arc::default(),
+        });
+        let empty = Box::new(MockInferenceEngine {
+            models: Vec::new(),
+            healthy: true,
+            fail_get_models: false,
+            warmed: Arc::default(),
+        });
+
+        let service = WorkerService::new(
+            "node-1".to_string(),
+            registry.clone(),
+            make_engines(vec![
+                (EngineType::Ollama, failing),
+                (EngineType::Vllm, empty),
+            ]),
+            Arc::new(MockHardwareMonitor {
+                status: HardwareStatus {
+                    gpu_name: "GPU1".to_string(),
+                    vram_free_mb: 1024,
+                    gpu_util: 0.0,
+                    gpus: Vec::new(),
+                },
+            }),
+            Arc::new(MockCoordinatorClient {
+                heartbeat_calls: Arc::new(Mutex::new(Vec::new())),
+                usage_reports: Mutex::default(),
+            }),
+            Arc::new(MockAuthTokenVerifier {
+                valid_token: "secret".to_string(),
+            }),
+            Arc::new(MockE2EDecryptor),
+        );
+
+        let err = service.refresh_model_registry().await.unwrap_err();
+        assert!(err.to_string().contains("No models found"));
+        // The registry from the last good refresh is kept
+        assert_eq!(registry.read().await.models[0].id, "previous");
+    }
+
+    #[tokio::test]
+    async fn test_send_heartbeat_reports_offline_without_models() {
+        let heartbeat_calls = Arc::new(Mutex::new(Vec::new()));
+        let service = WorkerService::new(
+            "node-1".to_string(),
+            Arc::new(RwLock::new(ModelRegistry::new())),
+            make_engines(vec![(
+                EngineType::Ollama,
+                Box::new(MockInferenceEngine {
+                    models: Vec::new(),
+                    healthy: false,
+                    fail_get_models: false,
+                    warmed: Arc::default(),
+                }),
+            )]),
+            Arc::new(MockHardwareMonitor {
+                status: HardwareStatus {
+                    gpu_name: "GPU1".to_string(),
+                    vram_free_mb: 1024,
+                    gpu_util: 0.0,
+                    gpus: Vec::new(),
+                },
+            }),
+            Arc::new(MockCoordinatorClient {
+                heartbeat_calls: heartbeat_calls.clone(),
+                usage_reports: Mutex::default(),
+            }),
+            Arc::new(MockAuthTokenVerifier {
+                valid_token: "secret".to_string(),
+            }),
+            Arc::new(MockE2EDecryptor),
+        );
+        let load = NodeLoad {
+            in_flight: 0,
+            max_concurrent: 1,
+        };
+
+        assert!(service.refresh_model_registry().await.is_err());
+        // Still sent, so the coordinator hears when the engine comes back
+        service.send_heartbeat(load, true).await.unwrap();
+
+        let calls = heartbeat_calls.lock().await;
+        assert_eq!(calls.len(), 1);
+        assert!(matches!(calls[0].status, NodeStatus::Offline));
+        assert!(calls[0].models.is_empty());
+    }
+
     #[tokio::test]
     async fn test_send_heartbeat() {
         let node_id = "node-1".to_string();
@@ -409,36 +765,58 @@ mod tests {
             status: HardwareStatus {
                 gpu_name: "GPU1".to_string(),
                 vram_free_mb: 8192,
+                gpu_util: 0.0,
+                gpus: Vec::new(),
             },
-            is_idle: true,
         });
 
         let heartbeat_calls = Arc::new(Mutex::new(Vec::new()));
         let coordinator = Arc::new(MockCoordinatorClient {
             heartbeat_calls: heartbeat_calls.clone(),
+            usage_reports: Mutex::default(),
         });
 
         let verifier = Arc::new(MockAuthTokenVerifier {
             valid_token: "secret".to_string(),
         });
 
+        let engine = Box::new(MockInferenceEngine {
+            models: Vec::new(),
+            healthy: true,
+            fail_get_models: false,
+            warmed: Arc::default(),
+        });
         let service = WorkerService::new(
             node_id.clone(),
             registry,
-            empty_engines(),
+            make_engines(vec![(EngineType::Ollama, engine)]),
             monitor,
             coordinator,
             verifier,
             Arc::new(MockE2EDecryptor),
         );
 
-        service.send_heartbeat().await.unwrap();
+        let load = NodeLoad {
+            in_flight: 2,
+            max_concurrent: 4,
+        };
+        service.send_heartbeat(load, false).await.unwrap();
 
         let calls = heartbeat_calls.lock().await;
         assert_eq!(calls.len(), 1);
-        let (sent_node_id, status, models, hardware) = &calls[0];
+        let HeartbeatReport {
+            node_id: sent_node_id,
+            status,
+            models,
+            hardware,
+            load: sent_load,
+            coordinator_reachable,
+            ..
+        } = &calls[0];
         assert_eq!(sent_node_id, &node_id);
         assert!(matches!(status, NodeStatus::Idle));
+        assert_eq!(sent_load, &load);
+        assert!(!coordinator_reachable);
         assert_eq!(models.len(), 1);
         assert_eq!(models[0].name, "model1");
         assert_eq!(models[0].content_hash, "sha256:aaa");
@@ -447,6 +825,119 @@ mod tests {
         assert_eq!(hardware.vram_free_mb, 8192);
     }
 
+    #[tokio::test]
+    async fn test_send_heartbeat_drops_models_of_unhealthy_engines() {
+        let registry = Arc::new(RwLock::new(ModelRegistry::new()));
+        {
+            let mut reg = registry.write().await;
+            reg.add_model(Model {
+                id: "model1".to_string(),
+                content_hash: "sha256:aaa".to_string(),
+                size_bytes: 100,
+                engine_type: EngineType::Ollama,
+            });
+            reg.add_model(Model {
+                id: "model2".to_string(),
+                content_hash: "sha256:bbb".to_string(),
+                size_bytes: 200,
+                engine_type: EngineType::Vllm,
+            });
+        }
+        let engine = |healthy| -> Box<dyn InferenceEngine> {
+            Box::new(MockInferenceEngine {
+                models: Vec::new(),
+                healthy,
+                fail_get_models: false,
+                warmed: Arc::default(),
+            })
+        };
+        let heartbeat_calls = Arc::new(Mutex::new(Vec::new()));
+        let service = WorkerService::new(
+            "node-1".to_string(),
+            registry,
+            make_engines(vec![
+                (EngineType::Ollama, engine(true)),
+                (EngineType::Vllm, engine(false)),
+            ]),
+            Arc::new(MockHardwareMonitor {
+                status: HardwareStatus {
+                    gpu_name: "GPU1".to_string(),
+                    vram_free_mb: 8192,
+                    gpu_util: 0.0,
+                    gpus: Vec::new(),
+                },
+            }),
+            Arc::new(MockCoordinatorClient {
+                heartbeat_calls: heartbeat_calls.clone(),
+                usage_reports: Mutex::default(),
+            }),
+            Arc::new(MockAuthTokenVerifier {
+                valid_token: "secret".to_string(),
+            }),
+            Arc::new(MockE2EDecryptor),
+        );
+
+        service
+            .send_heartbeat(NodeLoad::default(), true)
+            .await
+            .unwrap();
+
+        let calls = heartbeat_calls.lock().await;
+        let names: Vec<_> = calls[0].models.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["model1"]);
+        let health: Vec<_> = calls[0].engines.iter().map(|e| e.healthy).collect();
+        assert_eq!(health, [true, false]);
+        assert!(matches!(calls[0].status, NodeStatus::Idle));
+    }
+
+    #[tokio::test]
+    async fn test_keep_warm_skips_a_busy_gpu_and_unknown_models() {
+        let warmed = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let service = |gpu_util| {
+            let mut registry = ModelRegistry::new();
+            registry.add_model(Model {
+                id: "model1".to_string(),
+                content_hash: "sha256:aaa".to_string(),
+                size_bytes: 100,
+                engine_type: EngineType::Ollama,
+            });
+            let engine: Box<dyn InferenceEngine> = Box::new(MockInferenceEngine {
+                models: Vec::new(),
+                healthy: true,
+                fail_get_models: false,
+                warmed: warmed.clone(),
+            });
+            WorkerService::new(
+                "node-1".to_string(),
+                Arc::new(RwLock::new(registry)),
+                make_engines(vec![(EngineType::Ollama, engine)]),
+                Arc::new(MockHardwareMonitor {
+                    status: HardwareStatus {
+                        gpu_name: "GPU1".to_string(),
+                        vram_free_mb: 8192,
+                        gpu_util,
+                        gpus: Vec::new(),
+                    },
+                }),
+                Arc::new(MockCoordinatorClient {
+                    heartbeat_calls: Arc::default(),
+                    usage_reports: Mutex::default(),
+                }),
+                Arc::new(MockAuthTokenVerifier {
+                    valid_token: "secret".to_string(),
+                }),
+                Arc::new(MockE2EDecryptor),
+            )
+        };
+        let models = ["model1".to_string(), "not-pulled".to_string()];
+
+        service(95.0).keep_warm(&models).await;
+        assert!(warmed.lock().unwrap().is_empty());
+
+        service(0.0).keep_warm(&models).await;
+        assert_eq!(*warmed.lock().unwrap(), ["model1"]);
+    }
+
     #[tokio::test]
     async fn test_verify_ticket() {
         let node_id = "node-1".to_string();
@@ -455,11 +946,13 @@ mod tests {
             status: HardwareStatus {
                 gpu_name: "GPU1".to_string(),
                 vram_free_mb: 0,
+                gpu_util: 0.0,
+                gpus: Vec::new(),
             },
-            is_idle: true,
         });
         let coordinator = Arc::new(MockCoordinatorClient {
             heartbeat_calls: Arc::new(Mutex::new(Vec::new())),
+            usage_reports: Mutex::default(),
         });
         let verifier = Arc::new(MockAuthTokenVerifier {
             valid_token: "secret".to_string(),
@@ -475,23 +968,79 @@ mod tests {
             Arc::new(MockE2EDecryptor),
         );
 
-        assert!(service.verify_ticket("secret").await.unwrap());
-        assert!(!service.verify_ticket("wrong").await.unwrap());
+        assert_eq!(
+            service.verify_ticket("secret").await.unwrap().as_deref(),
+            Some("requester-1")
+        );
+        assert!(service.verify_ticket("wrong").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_report_usage_skips_empty_usage() {
+        let coordinator = Arc::new(MockCoordinatorClient {
+            heartbeat_calls: Arc::new(Mutex::new(Vec::new())),
+            usage_reports: Mutex::default(),
+        });
+        let service = WorkerService::new(
+            "node-1".to_string(),
+            Arc::new(RwLock::new(ModelRegistry::new())),
+            empty_engines(),
+            Arc::new(MockHardwareMonitor {
+                status: HardwareStatus {
+                    gpu_name: "GPU1".to_string(),
+                    vram_free_mb: 0,
+                    gpu_util: 0.0,
+                    gpus: Vec::new(),
+                },
+            }),
+            coordinator.clone(),
+            Arc::new(MockAuthTokenVerifier {
+                valid_token: "secret".to_string(),
+            }),
+            Arc::new(MockE2EDecryptor),
+        );
+        let usage = |prompt_tokens, completion_tokens| TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        };
+
+        service
+            .report_usage("requester-1", "llama3", &usage(0, 0))
+            .await
+            .unwrap();
+        service
+            .report_usage("requester-1", "llama3", &usage(9, 12))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *coordinator.usage_reports.lock().await,
+            [UsageReport {
+                node_id: "node-1".to_string(),
+                requester: "requester-1".to_string(),
+                model: "llama3".to_string(),
+                prompt_tokens: 9,
+                completion_tokens: 12,
+            }]
+        );
     }
 
     #[tokio::test]
-    async fn test_run_initial_benchmark() {
+    async fn test_encryption_public_key() {
         let node_id = "node-1".to_string();
         let registry = Arc::new(RwLock::new(ModelRegistry::new()));
         let monitor = Arc::new(MockHardwareMonitor {
             status: HardwareStatus {
                 gpu_name: "GPU1".to_string(),
                 vram_free_mb: 0,
+                gpu_util: 0.0,
+                gpus: Vec::new(),
             },
-            is_idle: true,
         });
         let coordinator = Arc::new(MockCoordinatorClient {
             heartbeat_calls: Arc::new(Mutex::new(Vec::new())),
+            usage_reports: Mutex::default(),
         });
         let verifier = Arc::new(MockAuthTokenVerifier {
             valid_token: "secret".to_string(),
@@ -507,26 +1056,44 @@ mod tests {
             Arc::new(MockE2EDecryptor),
         );
 
-        let result = service.run_initial_benchmark().await;
-        assert!(
-            result.is_ok() || result.is_err(),
-            "run_initial_benchmark should return a Result"
+        assert_eq!(
+            service.encryption_public_key(),
+            "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="
         );
     }
 
     #[tokio::test]
-    async fn test_encryption_public_key() {
+    async fn test_chat_delegates_to_engine() {
         let node_id = "node-1".to_string();
         let registry = Arc::new(RwLock::new(ModelRegistry::new()));
+        {
+            let mut reg = registry.write().await;
+            reg.add_model(Model {
+                id: "llama3".to_string(),
+                content_hash: "sha256:aaa".to_string(),
+                size_bytes: 100,
+                engine_type: EngineType::Ollama,
+            });
+        }
+
+        let engine = Box::new(MockInferenceEngine {
+            models: vec![],
+            healthy: true,
+            fail_get_models: false,
+            warmed: Arc::default(),
+        });
+
         let monitor = Arc::new(MockHardwareMonitor {
             status: HardwareStatus {
                 gpu_name: "GPU1".to_string(),
                 vram_free_mb: 0,
+                gpu_util: 0.0,
+                gpus: Vec::new(),
             },
-            is_idle: true,
         });
         let coordinator = Arc::new(MockCoordinatorClient {
             heartbeat_calls: Arc::new(Mutex::new(Vec::new())),
+            usage_reports: Mutex::default(),
         });
         let verifier = Arc::new(MockAuthTokenVerifier {
             valid_token: "secret".to_string(),
@@ -535,21 +1102,32 @@ mod tests {
         let service = WorkerService::new(
             node_id,
             registry,
-            empty_engines(),
+            make_engines(vec![(EngineType::Ollama, engine)]),
             monitor,
             coordinator,
             verifier,
             Arc::new(MockE2EDecryptor),
         );
 
+        let messages = vec![ChatMessage {
+            role: "user".to_string(),
+            content: Some("hi".into()),
+            tool_calls: None,
+            tool_call_id: None,
+        }];
+        let resp = service
+            .chat("llama3", messages, None, &SamplingOptions::default(), None)
+            .await
+            .unwrap()
+            .body;
         assert_eq!(
-            service.encryption_public_key(),
-            "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="
+            resp.choices[0].message.content,
+            Some("mock response".into())
         );
     }
 
     #[tokio::test]
-    async fn test_chat_delegates_to_engine() {
+    async fn test_embed_delegates_to_engine() {
         let node_id = "node-1".to_string();
         let registry = Arc::new(RwLock::new(ModelRegistry::new()));
         {
@@ -566,17 +1144,20 @@ mod tests {
             models: vec![],
             healthy: true,
             fail_get_models: false,
+            warmed: Arc::default(),
         });
 
         let monitor = Arc::new(MockHardwareMonitor {
             status: HardwareStatus {
                 gpu_name: "GPU1".to_string(),
                 vram_free_mb: 0,
+                gpu_util: 0.0,
+                gpus: Vec::new(),
             },
-            is_idle: true,
         });
         let coordinator = Arc::new(MockCoordinatorClient {
             heartbeat_calls: Arc::new(Mutex::new(Vec::new())),
+            usage_reports: Mutex::default(),
         });
         let verifier = Arc::new(MockAuthTokenVerifier {
             valid_token: "secret".to_string(),
@@ -592,12 +1173,14 @@ mod tests {
             Arc::new(MockE2EDecryptor),
         );
 
-        let messages = vec![ChatMessage {
-            role: "user".to_string(),
-            content: "hi".to_string(),
-        }];
-        let resp = service.chat("llama3", messages).await.unwrap();
-        assert_eq!(resp.choices[0].message.content, "mock response");
+        let resp = service
+            .embed("llama3", vec!["hi".to_string(), "there".to_string()], None)
+            .await
+            .unwrap()
+            .body;
+        assert_eq!(resp.data.len(), 2);
+        assert_eq!(resp.data[1].index, 1);
+        assert_eq!(resp.data[1].embedding, vec![5.0]);
     }
 
     #[tokio::test]
@@ -620,17 +1203,20 @@ mod tests {
             models: vec![],
             healthy: true,
             fail_get_models: false,
+            warmed: Arc::default(),
         });
 
         let monitor = Arc::new(MockHardwareMonitor {
             status: HardwareStatus {
                 gpu_name: "GPU1".to_string(),
                 vram_free_mb: 0,
+                gpu_util: 0.0,
+                gpus: Vec::new(),
             },
-            is_idle: true,
         });
         let coordinator = Arc::new(MockCoordinatorClient {
             heartbeat_calls: Arc::new(Mutex::new(Vec::new())),
+            usage_reports: Mutex::default(),
         });
         let verifier = Arc::new(MockAuthTokenVerifier {
             valid_token: "secret".to_string(),
@@ -648,9 +1234,15 @@ mod tests {
 
         let messages = vec![ChatMessage {
             role: "user".to_string(),
-            content: "hi".to_string(),
+            content: Some("hi".into()),
+            tool_calls: None,
+            tool_call_id: None,
         }];
-        let mut stream = service.chat_stream("llama3", messages).await.unwrap();
+        let mut stream = service
+            .chat_stream("llama3", messages, None, &SamplingOptions::default(), None)
+            .await
+            .unwrap()
+            .body;
         let chunk = stream.next().await.unwrap().unwrap();
         assert_eq!(chunk.choices[0].delta.content, Some("mock".to_string()));
     }
@@ -663,11 +1255,13 @@ mod tests {
             status: HardwareStatus {
                 gpu_name: "GPU1".to_string(),
                 vram_free_mb: 0,
+                gpu_util: 0.0,
+                gpus: Vec::new(),
             },
-            is_idle: true,
         });
         let coordinator = Arc::new(MockCoordinatorClient {
             heartbeat_calls: Arc::new(Mutex::new(Vec::new())),
+            usage_reports: Mutex::default(),
         });
         let verifier = Arc::new(MockAuthTokenVerifier {
             valid_token: "secret".to_string(),
@@ -685,9 +1279,19 @@ mod tests {
 
         let messages = vec![ChatMessage {
             role: "user".to_string(),
-            content: "hi".to_string(),
+            content: Some("hi".into()),
+            tool_calls: None,
+            tool_call_id: None,
         }];
-        let result = service.chat("nonexistent", messages).await;
+        let result = service
+            .chat(
+                "nonexistent",
+                messages,
+                None,
+                &SamplingOptions::default(),
+                None,
+            )
+            .await;
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("Model not found"));
     }
@@ -710,11 +1314,13 @@ mod tests {
             status: HardwareStatus {
                 gpu_name: "GPU1".to_string(),
                 vram_free_mb: 0,
+                gpu_util: 0.0,
+                gpus: Vec::new(),
             },
-            is_idle: true,
         });
         let coordinator = Arc::new(MockCoordinatorClient {
             heartbeat_calls: Arc::new(Mutex::new(Vec::new())),
+            usage_reports: Mutex::default(),
         });
         let verifier = Arc::new(MockAuthTokenVerifier {
             valid_token: "secret".to_string(),
@@ -732,9 +1338,13 @@ mod tests {
 
         let messages = vec![ChatMessage {
             role: "user".to_string(),
-            content: "hi".to_string(),
+            content: Some("hi".into()),
+            tool_calls: None,
+            tool_call_id: None,
         }];
-        let result = service.chat("llama3", messages).await;
+        let result = service
+            .chat("llama3", messages, None, &SamplingOptions::default(), None)
+            .await;
         assert!(result.is_err());
         assert!(result
             .unwrap_err()
@@ -1,16 +1,71 @@
+pub use monkey_troop_shared::{ContentPart, FunctionCall, MessageContent, Tool, ToolCall};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub role: String,
-    pub content: String,
+    /// Absent or null on assistant messages that only carry `tool_calls`
+    #[serde(default)]
+    pub content: Option<MessageContent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InferenceRequest {
+    #[serde(alias = "model")]
     pub model_id: String,
     pub messages: Vec<ChatMessage>,
+    #[serde(default)]
     pub stream: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<serde_json::Value>,
+    #[serde(flatten)]
+    pub sampling: SamplingOptions,
+}
+
+/// OpenAI sampling parameters from the request, passed on to the engine when set.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SamplingOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// A stop sequence or a list of them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+}
+
+impl SamplingOptions {
+    /// Stop sequences as a list, whichever form the request used.
+    pub fn stop_sequences(&self) -> Vec<String> {
+        match &self.stop {
+            Some(serde_json::Value::String(stop)) => vec![stop.clone()],
+            Some(serde_json::Value::Array(stops)) => stops
+                .iter()
+                .filter_map(|stop| stop.as_str().map(str::to_string))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl InferenceRequest {
+    /// Tools to offer the engine; `tool_choice: "none"` means the model must not call any.
+    pub fn offered_tools(&self) -> Option<Vec<Tool>> {
+        match &self.tool_choice {
+            Some(serde_json::Value::String(choice)) if choice == "none" => None,
+            _ => self.tools.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,6 +99,9 @@ pub struct StreamingChunk {
     pub created: u64,
     pub model: String,
     pub choices: Vec<StreamingChoice>,
+    /// On the final chunk, when the engine reports token counts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,8 +117,63 @@ pub struct ChatMessageDelta {
     pub role: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+/// Streamed tool call; `index` identifies the call across chunks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallDelta {
+    pub index: usize,
+    #[serde(flatten)]
+    pub call: ToolCall,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingData {
+    pub object: String,
+    pub index: usize,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingUsage {
+    pub prompt_tokens: u32,
+    pub total_tokens: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingsResponse {
+    pub object: String,
+    pub data: Vec<EmbeddingData>,
+    pub model: String,
+    pub usage: EmbeddingUsage,
 }
 
+/// An engine result together with the upstream response headers, so the proxy can relay them.
+#[derive(Debug, Clone)]
+pub struct EngineReply<T> {
+    pub body: T,
+    pub headers: Vec<(String, String)>,
+}
+
+/// A non-success response from an inference engine, kept intact so the proxy can relay
+/// the exact status, headers and body instead of collapsing it into a generic 500.
+#[derive(Debug, Clone)]
+pub struct EngineHttpError {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl std::fmt::Display for EngineHttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "engine returned status {}: {}", self.status, self.body)
+    }
+}
+
+impl std::error::Error for EngineHttpError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -72,9 +185,14 @@ mod tests {
             model_id: "test-model".to_string(),
             messages: vec![ChatMessage {
                 role: "user".to_string(),
-                content: "hello".to_string(),
+                content: Some("hello".into()),
+                tool_calls: None,
+                tool_call_id: None,
             }],
             stream: false,
+            tools: None,
+            tool_choice: None,
+            sampling: SamplingOptions::default(),
         };
 
         let serialized = serde_json::to_string(&request).unwrap();
@@ -82,10 +200,46 @@ mod tests {
 
         assert_eq!(deserialized.model_id, "test-model");
         assert_eq!(deserialized.messages.len(), 1);
-        assert_eq!(deserialized.messages[0].content, "hello");
+        assert_eq!(deserialized.messages[0].content, Some("hello".into()));
         assert!(!deserialized.stream);
     }
 
+    #[test]
+    fn test_inference_request_keeps_sampling_options() {
+        let request: InferenceRequest = serde_json::from_value(serde_json::json!({
+            "model": "llama3:8b",
+            "messages": [],
+            "temperature": 0.2,
+            "top_p": 0.9,
+            "max_tokens": 64,
+            "stop": "END",
+            "seed": 7
+        }))
+        .unwrap();
+        assert_eq!(request.sampling.temperature, Some(0.2));
+        assert_eq!(request.sampling.top_p, Some(0.9));
+        assert_eq!(request.sampling.max_tokens, Some(64));
+        assert_eq!(request.sampling.seed, Some(7));
+        assert_eq!(request.sampling.stop_sequences(), ["END"]);
+
+        let request: InferenceRequest = serde_json::from_value(serde_json::json!({
+            "model": "llama3:8b",
+            "messages": [],
+            "stop": ["a", "b"]
+        }))
+        .unwrap();
+        assert_eq!(request.sampling.stop_sequences(), ["a", "b"]);
+        assert_eq!(request.sampling.temperature, None);
+    }
+
+    #[test]
+    fn test_inference_request_accepts_openai_model_field() {
+        let request: InferenceRequest =
+            serde_json::from_str(r#"{"model": "llama3:8b", "messages": [], "stream": false}"#)
+                .unwrap();
+        assert_eq!(request.model_id, "llama3:8b");
+    }
+
     #[test]
     fn test_inference_response_serialization() {
         let response = InferenceResponse {
@@ -97,7 +251,9 @@ mod tests {
                 index: 0,
                 message: ChatMessage {
                     role: "assistant".to_string(),
-                    content: "response content".to_string(),
+                    content: Some("response content".into()),
+                    tool_calls: None,
+                    tool_call_id: None,
                 },
                 finish_reason: "stop".to_string(),
             }],
@@ -112,7 +268,10 @@ mod tests {
         let deserialized: InferenceResponse = serde_json::from_str(&serialized).unwrap();
 
         assert_eq!(deserialized.id, "test-id");
-        assert_eq!(deserialized.choices[0].message.content, "response content");
+        assert_eq!(
+            deserialized.choices[0].message.content,
+            Some("response content".into())
+        );
     }
 
     #[test]
@@ -127,12 +286,15 @@ mod tests {
                 delta: ChatMessageDelta {
                     role: None,
                     content: Some("Hello".to_string()),
+                    tool_calls: None,
                 },
                 finish_reason: None,
             }],
+            usage: None,
         };
 
         let serialized = serde_json::to_string(&chunk).unwrap();
+        assert!(!serialized.contains("usage"));
         let deserialized: StreamingChunk = serde_json::from_str(&serialized).unwrap();
 
         assert_eq!(deserialized.id, "chatcmpl-123");
@@ -150,6 +312,7 @@ mod tests {
         let delta = ChatMessageDelta {
             role: None,
             content: None,
+            tool_calls: None,
         };
 
         let serialized = serde_json::to_string(&delta).unwrap();
@@ -164,6 +327,7 @@ mod tests {
             delta: ChatMessageDelta {
                 role: Some("assistant".to_string()),
                 content: None,
+                tool_calls: None,
             },
             finish_reason: Some("stop".to_string()),
         };
@@ -175,4 +339,41 @@ mod tests {
         assert!(deserialized.delta.content.is_none());
         assert_eq!(deserialized.finish_reason, Some("stop".to_string()));
     }
+
+    #[test]
+    fn test_inference_request_with_tools() {
+        let request: InferenceRequest = serde_json::from_value(serde_json::json!({
+            "model": "llama3.1:8b",
+            "messages": [
+                {"role": "user", "content": "Weather in Paris?"},
+                {"role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1", "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}
+                }]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "18C"}
+            ],
+            "tools": [{"type": "function", "function": {"name": "get_weather"}}],
+            "tool_choice": "auto"
+        }))
+        .unwrap();
+
+        assert!(request.messages[1].content.is_none());
+        assert_eq!(request.messages[2].tool_call_id.as_deref(), Some("call_1"));
+        assert_eq!(
+            request.offered_tools().unwrap()[0].function.name,
+            "get_weather"
+        );
+    }
+
+    #[test]
+    fn test_tool_choice_none_offers_no_tools() {
+        let request: InferenceRequest = serde_json::from_value(serde_json::json!({
+            "model": "llama3.1:8b",
+            "messages": [],
+            "tools": [{"type": "function", "function": {"name": "get_weather"}}],
+            "tool_choice": "none"
+        }))
+        .unwrap();
+        assert!(request.offered_tools().is_none());
+    }
 }
@@ -1,4 +1,4 @@
-use monkey_troop_shared::ModelIdentity;
+use monkey_troop_shared::{EngineInfo, GpuInfo, HardwareInfo, ModelIdentity, NodeLoad};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,17 +9,70 @@ pub struct Model {
     pub engine_type: EngineType,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+/// Declared in priority order: when engines serve the same model, the registry keeps the
+/// copy from the earliest one.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum EngineType {
     Ollama,
     Vllm,
     LmStudio,
 }
 
+impl EngineType {
+    /// Name reported to the coordinator as the engine's `type`
+    pub fn name(self) -> &'static str {
+        match self {
+            EngineType::Ollama => "ollama",
+            EngineType::Vllm => "vllm",
+            EngineType::LmStudio => "lmstudio",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HardwareStatus {
     pub gpu_name: String,
+    /// Summed over all GPUs
     pub vram_free_mb: u64,
+    /// GPU utilization percent, or CPU utilization on nodes without an NVIDIA GPU. The
+    /// busiest GPU's on multi-GPU nodes, so one busy GPU makes the whole node busy
+    pub gpu_util: f32,
+    /// Each GPU's readings, when they were read one by one (NVIDIA)
+    pub gpus: Vec<GpuInfo>,
+}
+
+impl HardwareStatus {
+    /// Utilization percent below which the node reports itself as idle
+    pub const IDLE_THRESHOLD: f32 = 10.0;
+
+    /// Reported alongside an Offline status when the hardware could not be read
+    pub fn unavailable() -> Self {
+        Self {
+            gpu_name: "Unavailable".to_string(),
+            vram_free_mb: 0,
+            gpu_util: 0.0,
+            gpus: Vec::new(),
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.gpu_util < Self::IDLE_THRESHOLD
+    }
+
+    /// As the heartbeat reports it, with totals over the GPUs when they were read one by one.
+    pub fn info(&self) -> HardwareInfo {
+        let gpus = (!self.gpus.is_empty()).then(|| self.gpus.clone());
+        HardwareInfo {
+            gpu: self.gpu_name.clone(),
+            vram_free: self.vram_free_mb,
+            gpu_util: self.gpu_util,
+            vram_total: gpus
+                .as_ref()
+                .map(|gpus| gpus.iter().map(|gpu| gpu.vram_total).sum()),
+            gpu_count: gpus.as_ref().map(|gpus| gpus.len() as u32),
+            gpus,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,10 +82,33 @@ pub enum NodeStatus {
     Offline,
 }
 
+/// Everything a worker reports to the coordinator in one heartbeat.
+#[derive(Debug, Clone)]
+pub struct HeartbeatReport {
+    pub node_id: String,
+    pub status: NodeStatus,
+    pub models: Vec<ModelIdentity>,
+    pub hardware: HardwareStatus,
+    pub engines: Vec<EngineInfo>,
+    pub encryption_public_key: Option<String>,
+    pub load: NodeLoad,
+    /// False while the heartbeat circuit is not closed: recent heartbeats failed, and
+    /// this one is a probe of whether the coordinator is back
+    pub coordinator_reachable: bool,
+}
+
 pub struct ModelRegistry {
     pub models: Vec<Model>,
 }
 
+/// Serializable view of the registry, served by the worker's debug endpoint.
+#[derive(Debug, Serialize)]
+pub struct RegistrySnapshot {
+    pub models: Vec<Model>,
+    /// Engines that registered at least one model, in registration order
+    pub engines: Vec<EngineType>,
+}
+
 impl ModelRegistry {
     pub fn new() -> Self {
         Self { models: Vec::new() }
@@ -56,9 +132,11 @@ impl ModelRegistry {
         self.models.iter().find(|m| m.content_hash == hash)
     }
 
-    pub fn to_model_identities(&self) -> Vec<ModelIdentity> {
+    /// Identities of the models registered from one of `engines`.
+    pub fn to_model_identities(&self, engines: &[EngineType]) -> Vec<ModelIdentity> {
         self.models
             .iter()
+            .filter(|m| engines.contains(&m.engine_type))
             .map(|m| ModelIdentity {
                 name: m.id.clone(),
                 content_hash: m.content_hash.clone(),
@@ -66,6 +144,19 @@ impl ModelRegistry {
             })
             .collect()
     }
+
+    pub fn snapshot(&self) -> RegistrySnapshot {
+        let mut engines = Vec::new();
+        for model in &self.models {
+            if !engines.contains(&model.engine_type) {
+                engines.push(model.engine_type);
+            }
+        }
+        RegistrySnapshot {
+            models: self.models.clone(),
+            engines,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -140,7 +231,7 @@ mod tests {
         registry.add_model(make_model("model1", "sha256:aaa", 100, EngineType::Ollama));
         registry.add_model(make_model("model2", "sha256:bbb", 200, EngineType::Vllm));
 
-        let identities = registry.to_model_identities();
+        let identities = registry.to_model_identities(&[EngineType::Ollama, EngineType::Vllm]);
         assert_eq!(identities.len(), 2);
         assert_eq!(identities[0].name, "model1");
         assert_eq!(identities[0].content_hash, "sha256:aaa");
@@ -148,5 +239,21 @@ mod tests {
         assert_eq!(identities[1].name, "model2");
         assert_eq!(identities[1].content_hash, "sha256:bbb");
         assert_eq!(identities[1].size_bytes, 200);
+
+        let identities = registry.to_model_identities(&[EngineType::Vllm]);
+        assert_eq!(identities.len(), 1);
+        assert_eq!(identities[0].name, "model2");
+    }
+
+    #[test]
+    fn test_snapshot_lists_each_engine_once() {
+        let mut registry = ModelRegistry::new();
+        registry.add_model(make_model("model1", "sha256:aaa", 100, EngineType::Vllm));
+        registry.add_model(make_model("model2", "sha256:bbb", 200, EngineType::Ollama));
+        registry.add_model(make_model("model3", "sha256:ccc", 300, EngineType::Vllm));
+
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot.models.len(), 3);
+        assert_eq!(snapshot.engines, [EngineType::Vllm, EngineType::Ollama]);
     }
 }
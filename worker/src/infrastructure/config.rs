@@ -1,22 +1,38 @@
+use crate::infrastructure::system::benchmark::DEFAULT_BENCHMARK_TIMEOUT;
 use anyhow::{Context, Result};
+use monkey_troop_shared::{TroopError, TroopResult};
 use std::env;
+use std::time::Duration;
 
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct Config {
     pub node_id: String,
     pub coordinator_url: String,
-    // This field is deserialized from environment/config and kept for compatibility with
-    // external components, even if it's not currently read within this crate.
-    #[allow(dead_code)]
     pub proxy_port: u16,
-    // This field controls worker heartbeat behavior; it's retained for future/optional use
-    // and may be read by other parts of the system not analyzed here.
-    #[allow(dead_code)]
-    pub heartbeat_interval: u64, // seconds
-    // This field configures how often models are refreshed; it's intentionally kept for
-    // future/optional use, so we suppress dead_code warnings.
-    #[allow(dead_code)]
-    pub model_refresh_interval: u64, // seconds
+    pub heartbeat_interval: u64,          // seconds
+    pub model_refresh_interval: u64,      // seconds
+    pub public_key_refresh_interval: u64, // seconds
+    /// Ticket audiences accepted by the proxy (`JWT_AUDIENCE`, comma-separated)
+    pub jwt_audiences: Vec<String>,
+    /// Secret shared with the coordinator when tickets are HS256-signed
+    /// (`JWT_ALGORITHM=HS256`, `JWT_SECRET`), as a local or dev coordinator may do;
+    /// otherwise tickets are RS256-signed and checked against the coordinator's public key
+    pub jwt_secret: Option<String>,
+    /// Proxy requests served at once; derived from the hardware when unset
+    pub max_concurrent_requests: Option<usize>,
+    /// Requests per minute each requester may send (`REQUESTS_PER_MINUTE`); unlimited
+    /// when unset
+    pub requests_per_minute: Option<u32>,
+    /// Benchmark the hardware against a coordinator challenge at startup (`RUN_INITIAL_BENCHMARK`)
+    pub run_initial_benchmark: bool,
+    /// Benchmark runs combined into one result (`BENCHMARK_RUNS`)
+    pub benchmark_runs: usize,
+    /// How long each benchmark run may take (`BENCHMARK_TIMEOUT_SECS`)
+    pub benchmark_timeout: Duration,
+    /// Models kept loaded in their engines (`KEEP_ALIVE_MODELS`, comma-separated); none
+    /// when unset
+    pub keep_alive_models: Vec<String>,
+    pub keep_alive_interval: u64, // seconds
 }
 
 impl Config {
@@ -50,8 +66,114 @@ impl Config {
                 "MODEL_REFRESH_INTERVAL",
                 180u64, // 3 minutes default
             )?,
+            public_key_refresh_interval: Self::parse_env_with_default(
+                "PUBLIC_KEY_REFRESH_INTERVAL",
+                3600u64,
+            )?,
+            jwt_audiences: env::var("JWT_AUDIENCE")
+                .unwrap_or_else(|_| "swarm-worker".to_string())
+                .split(',')
+                .map(str::trim)
+                .filter(|audience| !audience.is_empty())
+                .map(str::to_string)
+                .collect(),
+            jwt_secret: match env::var("JWT_ALGORITHM") {
+                Ok(algorithm) => match algorithm.trim().to_ascii_uppercase().as_str() {
+                    "RS256" => None,
+                    "HS256" => match env::var("JWT_SECRET") {
+                        Ok(secret) if !secret.is_empty() => Some(secret),
+                        _ => anyhow::bail!("JWT_ALGORITHM=HS256 requires JWT_SECRET"),
+                    },
+                    _ => anyhow::bail!("JWT_ALGORITHM must be RS256 or HS256, got '{algorithm}'"),
+                },
+                Err(_) => None,
+            },
+            max_concurrent_requests: match env::var("MAX_CONCURRENT_REQUESTS") {
+                Ok(_) => match Self::parse_env_with_default("MAX_CONCURRENT_REQUESTS", 0usize)? {
+                    0 => anyhow::bail!("MAX_CONCURRENT_REQUESTS must be at least 1"),
+                    limit => Some(limit),
+                },
+                Err(_) => None,
+            },
+            requests_per_minute: match env::var("REQUESTS_PER_MINUTE") {
+                Ok(_) => match Self::parse_env_with_default("REQUESTS_PER_MINUTE", 0u32)? {
+                    0 => anyhow::bail!("REQUESTS_PER_MINUTE must be at least 1"),
+                    limit => Some(limit),
+                },
+                Err(_) => None,
+            },
+            run_initial_benchmark: Self::parse_env_with_default("RUN_INITIAL_BENCHMARK", false)?,
+            benchmark_runs: Self::parse_env_with_default("BENCHMARK_RUNS", 3usize)?,
+            benchmark_timeout: Duration::from_secs(Self::parse_env_with_default(
+                "BENCHMARK_TIMEOUT_SECS",
+                DEFAULT_BENCHMARK_TIMEOUT.as_secs(),
+            )?),
+            keep_alive_models: env::var("KEEP_ALIVE_MODELS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|model| !model.is_empty())
+                .map(str::to_string)
+                .collect(),
+            keep_alive_interval: Self::parse_env_with_default(
+                "KEEP_ALIVE_INTERVAL",
+                240u64, // under Ollama's default 5 minute keep_alive
+            )?,
         })
     }
+
+    /// Reject values that parse but cannot work, so a misconfigured worker fails at
+    /// startup instead of misbehaving later (a zero interval would panic the timers).
+    pub fn validate(&self) -> TroopResult<()> {
+        let url = reqwest::Url::parse(&self.coordinator_url).map_err(|e| {
+            TroopError::InvalidRequest(format!(
+                "COORDINATOR_URL '{}' is not a valid URL: {e}",
+                self.coordinator_url
+            ))
+        })?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(TroopError::InvalidRequest(format!(
+                "COORDINATOR_URL must use http or https: {}",
+                self.coordinator_url
+            )));
+        }
+        if self.proxy_port == 0 {
+            return Err(TroopError::InvalidRequest(
+                "PROXY_PORT must be between 1 and 65535".to_string(),
+            ));
+        }
+        for (name, secs) in [
+            ("HEARTBEAT_INTERVAL", self.heartbeat_interval),
+            ("MODEL_REFRESH_INTERVAL", self.model_refresh_interval),
+            (
+                "PUBLIC_KEY_REFRESH_INTERVAL",
+                self.public_key_refresh_interval,
+            ),
+            ("KEEP_ALIVE_INTERVAL", self.keep_alive_interval),
+        ] {
+            if secs == 0 {
+                return Err(TroopError::InvalidRequest(format!(
+                    "{name} must be at least 1 second"
+                )));
+            }
+        }
+        if self.benchmark_runs == 0 {
+            return Err(TroopError::InvalidRequest(
+                "BENCHMARK_RUNS must be at least 1".to_string(),
+            ));
+        }
+        if self.benchmark_timeout.is_zero() {
+            return Err(TroopError::InvalidRequest(
+                "BENCHMARK_TIMEOUT_SECS must be at least 1 second".to_string(),
+            ));
+        }
+        if self.jwt_audiences.is_empty() {
+            return Err(TroopError::InvalidRequest(
+                "JWT_AUDIENCE must list at least one audience".to_string(),
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -77,6 +199,17 @@ mod tests {
         let orig_port = env::var("PROXY_PORT").ok();
         let orig_hb = env::var("HEARTBEAT_INTERVAL").ok();
         let orig_refresh = env::var("MODEL_REFRESH_INTERVAL").ok();
+        let orig_key_refresh = env::var("PUBLIC_KEY_REFRESH_INTERVAL").ok();
+        let orig_audience = env::var("JWT_AUDIENCE").ok();
+        let orig_algorithm = env::var("JWT_ALGORITHM").ok();
+        let orig_secret = env::var("JWT_SECRET").ok();
+        let orig_concurrency = env::var("MAX_CONCURRENT_REQUESTS").ok();
+        let orig_rate = env::var("REQUESTS_PER_MINUTE").ok();
+        let orig_benchmark = env::var("RUN_INITIAL_BENCHMARK").ok();
+        let orig_benchmark_runs = env::var("BENCHMARK_RUNS").ok();
+        let orig_benchmark_timeout = env::var("BENCHMARK_TIMEOUT_SECS").ok();
+        let orig_keep_alive_models = env::var("KEEP_ALIVE_MODELS").ok();
+        let orig_keep_alive_interval = env::var("KEEP_ALIVE_INTERVAL").ok();
 
         // Scenario 1: Defaults
         env::remove_var("NODE_ID");
@@ -84,12 +217,33 @@ mod tests {
         env::remove_var("PROXY_PORT");
         env::remove_var("HEARTBEAT_INTERVAL");
         env::remove_var("MODEL_REFRESH_INTERVAL");
+        env::remove_var("PUBLIC_KEY_REFRESH_INTERVAL");
+        env::remove_var("JWT_AUDIENCE");
+        env::remove_var("JWT_ALGORITHM");
+        env::remove_var("JWT_SECRET");
+        env::remove_var("MAX_CONCURRENT_REQUESTS");
+        env::remove_var("REQUESTS_PER_MINUTE");
+        env::remove_var("RUN_INITIAL_BENCHMARK");
+        env::remove_var("BENCHMARK_RUNS");
+        env::remove_var("BENCHMARK_TIMEOUT_SECS");
+        env::remove_var("KEEP_ALIVE_MODELS");
+        env::remove_var("KEEP_ALIVE_INTERVAL");
 
         let config = Config::from_env().unwrap();
         assert_eq!(config.coordinator_url, "https://troop.100monkeys.ai");
         assert_eq!(config.proxy_port, 8080);
         assert_eq!(config.heartbeat_interval, 10);
         assert_eq!(config.model_refresh_interval, 180);
+        assert_eq!(config.public_key_refresh_interval, 3600);
+        assert_eq!(config.jwt_audiences, vec!["swarm-worker"]);
+        assert_eq!(config.jwt_secret, None);
+        assert_eq!(config.max_concurrent_requests, None);
+        assert_eq!(config.requests_per_minute, None);
+        assert!(!config.run_initial_benchmark);
+        assert_eq!(config.benchmark_runs, 3);
+        assert_eq!(config.benchmark_timeout, Duration::from_secs(300));
+        assert!(config.keep_alive_models.is_empty());
+        assert_eq!(config.keep_alive_interval, 240);
         assert!(!config.node_id.is_empty());
 
         // Scenario 2: Custom
@@ -98,6 +252,17 @@ mod tests {
         env::set_var("PROXY_PORT", "9999");
         env::set_var("HEARTBEAT_INTERVAL", "30");
         env::set_var("MODEL_REFRESH_INTERVAL", "600");
+        env::set_var("PUBLIC_KEY_REFRESH_INTERVAL", "900");
+        env::set_var("JWT_AUDIENCE", "swarm-worker, troop-worker");
+        env::set_var("JWT_ALGORITHM", "hs256");
+        env::set_var("JWT_SECRET", "dev-secret");
+        env::set_var("MAX_CONCURRENT_REQUESTS", "3");
+        env::set_var("REQUESTS_PER_MINUTE", "120");
+        env::set_var("RUN_INITIAL_BENCHMARK", "true");
+        env::set_var("BENCHMARK_RUNS", "5");
+        env::set_var("BENCHMARK_TIMEOUT_SECS", "20");
+        env::set_var("KEEP_ALIVE_MODELS", "llama3:8b, ,mistral:7b");
+        env::set_var("KEEP_ALIVE_INTERVAL", "60");
 
         let config = Config::from_env().unwrap();
         assert_eq!(config.node_id, "test-node");
@@ -105,6 +270,36 @@ mod tests {
         assert_eq!(config.proxy_port, 9999);
         assert_eq!(config.heartbeat_interval, 30);
         assert_eq!(config.model_refresh_interval, 600);
+        assert_eq!(config.public_key_refresh_interval, 900);
+        assert_eq!(config.jwt_audiences, vec!["swarm-worker", "troop-worker"]);
+        assert_eq!(config.jwt_secret.as_deref(), Some("dev-secret"));
+        assert_eq!(config.max_concurrent_requests, Some(3));
+        assert_eq!(config.requests_per_minute, Some(120));
+        assert!(config.run_initial_benchmark);
+        assert_eq!(config.benchmark_runs, 5);
+        assert_eq!(config.benchmark_timeout, Duration::from_secs(20));
+        assert_eq!(config.keep_alive_models, vec!["llama3:8b", "mistral:7b"]);
+        assert_eq!(config.keep_alive_interval, 60);
+
+        // Scenario 3: A zero limit would reject every request
+        env::set_var("MAX_CONCURRENT_REQUESTS", "0");
+        assert!(Config::from_env().is_err());
+        env::set_var("MAX_CONCURRENT_REQUESTS", "3");
+        env::set_var("REQUESTS_PER_MINUTE", "0");
+        assert!(Config::from_env().is_err());
+        env::set_var("REQUESTS_PER_MINUTE", "120");
+
+        // Scenario 4: HS256 needs its secret, and only the two algorithms are known
+        env::remove_var("JWT_SECRET");
+        assert!(Config::from_env()
+            .unwrap_err()
+            .to_string()
+            .contains("JWT_SECRET"));
+        env::set_var("JWT_ALGORITHM", "ES256");
+        assert!(Config::from_env().is_err());
+        env::set_var("JWT_ALGORITHM", "RS256");
+        env::set_var("JWT_SECRET", "ignored");
+        assert_eq!(Config::from_env().unwrap().jwt_secret, None);
 
         // Restore
         restore_env_var("NODE_ID", orig_node_id);
@@ -112,5 +307,80 @@ mod tests {
         restore_env_var("PROXY_PORT", orig_port);
         restore_env_var("HEARTBEAT_INTERVAL", orig_hb);
         restore_env_var("MODEL_REFRESH_INTERVAL", orig_refresh);
+        restore_env_var("PUBLIC_KEY_REFRESH_INTERVAL", orig_key_refresh);
+        restore_env_var("JWT_AUDIENCE", orig_audience);
+        restore_env_var("JWT_ALGORITHM", orig_algorithm);
+        restore_env_var("JWT_SECRET", orig_secret);
+        restore_env_var("MAX_CONCURRENT_REQUESTS", orig_concurrency);
+        restore_env_var("REQUESTS_PER_MINUTE", orig_rate);
+        restore_env_var("RUN_INITIAL_BENCHMARK", orig_benchmark);
+        restore_env_var("BENCHMARK_RUNS", orig_benchmark_runs);
+        restore_env_var("BENCHMARK_TIMEOUT_SECS", orig_benchmark_timeout);
+        restore_env_var("KEEP_ALIVE_MODELS", orig_keep_alive_models);
+        restore_env_var("KEEP_ALIVE_INTERVAL", orig_keep_alive_interval);
+    }
+
+    #[test]
+    fn test_validate() {
+        let config = Config {
+            node_id: "test-node".to_string(),
+            coordinator_url: "http://localhost:8000".to_string(),
+            proxy_port: 8080,
+            heartbeat_interval: 10,
+            model_refresh_interval: 180,
+            public_key_refresh_interval: 3600,
+            jwt_audiences: vec!["swarm-worker".to_string()],
+            jwt_secret: None,
+            max_concurrent_requests: None,
+            requests_per_minute: None,
+            run_initial_benchmark: false,
+            benchmark_runs: 3,
+            benchmark_timeout: Duration::from_secs(300),
+            keep_alive_models: Vec::new(),
+            keep_alive_interval: 240,
+        };
+        assert!(config.validate().is_ok());
+
+        let invalid = |config: Config| config.validate().unwrap_err().to_string();
+        assert!(invalid(Config {
+            coordinator_url: "troop.example".to_string(),
+            ..config.clone()
+        })
+        .contains("COORDINATOR_URL 'troop.example' is not a valid URL"));
+        assert!(invalid(Config {
+            coordinator_url: "ftp://troop.example".to_string(),
+            ..config.clone()
+        })
+        .contains("http or https"));
+        assert!(invalid(Config {
+            proxy_port: 0,
+            ..config.clone()
+        })
+        .contains("PROXY_PORT"));
+        assert!(invalid(Config {
+            heartbeat_interval: 0,
+            ..config.clone()
+        })
+        .contains("HEARTBEAT_INTERVAL must be at least 1 second"));
+        assert!(invalid(Config {
+            benchmark_runs: 0,
+            ..config.clone()
+        })
+        .contains("BENCHMARK_RUNS"));
+        assert!(invalid(Config {
+            benchmark_timeout: Duration::ZERO,
+            ..config.clone()
+        })
+        .contains("BENCHMARK_TIMEOUT_SECS"));
+        assert!(invalid(Config {
+            keep_alive_interval: 0,
+            ..config.clone()
+        })
+        .contains("KEEP_ALIVE_INTERVAL"));
+        assert!(invalid(Config {
+            jwt_audiences: Vec::new(),
+            ..config
+        })
+        .contains("JWT_AUDIENCE"));
     }
 }
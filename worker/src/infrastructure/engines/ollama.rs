@@ -1,17 +1,19 @@
-use crate::application::ports::InferenceEngine;
+use crate::application::ports::{ChunkStream, InferenceEngine};
 use crate::domain::inference::{
-    ChatMessage, ChatMessageDelta, InferenceChoice, InferenceResponse, StreamingChoice,
-    StreamingChunk, TokenUsage,
+    ChatMessage, ChatMessageDelta, ContentPart, EmbeddingData, EmbeddingUsage, EmbeddingsResponse,
+    EngineHttpError, EngineReply, FunctionCall, InferenceChoice, InferenceResponse, MessageContent,
+    SamplingOptions, StreamingChoice, StreamingChunk, TokenUsage, Tool, ToolCall, ToolCallDelta,
 };
 use crate::domain::models::{EngineType, Model};
 use anyhow::Result;
 use async_trait::async_trait;
 use bytes::BytesMut;
 use futures::stream::{self, StreamExt};
-use futures::Stream;
+use monkey_troop_shared::{http_client, EngineInfo, INFERENCE_TIMEOUT, REQUEST_ID_HEADER};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::env;
-use std::pin::Pin;
+use tracing::warn;
 
 #[derive(Deserialize)]
 struct OllamaModels {
@@ -25,24 +27,151 @@ struct OllamaModel {
     size: u64,
 }
 
+#[derive(Deserialize)]
+struct OllamaVersion {
+    version: String,
+}
+
 #[derive(Serialize)]
 struct OllamaChatRequest {
     model: String,
     messages: Vec<OllamaChatMessage>,
     stream: bool,
+    /// Ollama accepts OpenAI-style tool definitions as-is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<Tool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<OllamaOptions>,
+}
+
+/// Ollama's names for the OpenAI sampling parameters.
+#[derive(Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+}
+
+impl OllamaOptions {
+    /// `None` when the request set no sampling parameters, leaving the model's defaults.
+    fn from_sampling(sampling: &SamplingOptions) -> Option<Self> {
+        if *sampling == SamplingOptions::default() {
+            return None;
+        }
+        Some(Self {
+            temperature: sampling.temperature,
+            top_p: sampling.top_p,
+            num_predict: sampling.max_tokens,
+            stop: sampling.stop_sequences(),
+            seed: sampling.seed,
+        })
+    }
 }
 
 #[derive(Serialize)]
 struct OllamaChatMessage {
     role: String,
     content: String,
+    /// Base64-encoded images, without the `data:` URL prefix
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<OllamaToolCall>,
 }
 
-impl From<&ChatMessage> for OllamaChatMessage {
-    fn from(msg: &ChatMessage) -> Self {
+impl From<ChatMessage> for OllamaChatMessage {
+    fn from(msg: ChatMessage) -> Self {
+        let mut images = Vec::new();
+        let content = match msg.content {
+            None => String::new(),
+            Some(MessageContent::Text(text)) => text,
+            Some(MessageContent::Parts(parts)) => {
+                let mut texts = Vec::new();
+                for part in parts {
+                    match part {
+                        ContentPart::Text { text } => texts.push(text),
+                        ContentPart::ImageUrl { image_url } => {
+                            match base64_image_data(image_url.url) {
+                                Some(data) => images.push(data),
+                                None => warn!(
+                                    "Ollama only accepts inline base64 images, dropping image URL"
+                                ),
+                            }
+                        }
+                        ContentPart::Other(part) => warn!(
+                            "Ollama only accepts text and image parts, dropping a {} part",
+                            part["type"]
+                        ),
+                    }
+                }
+                texts.join("\n")
+            }
+        };
         Self {
-            role: msg.role.clone(),
-            content: msg.content.clone(),
+            role: msg.role,
+            content,
+            images,
+            tool_calls: msg
+                .tool_calls
+                .iter()
+                .flatten()
+                .map(OllamaToolCall::from)
+                .collect(),
+        }
+    }
+}
+
+/// Strip the `data:<mime>;base64,` prefix in place, so large payloads are not copied.
+fn base64_image_data(mut url: String) -> Option<String> {
+    let prefix_len = url
+        .strip_prefix("data:")?
+        .find(";base64,")
+        .map(|pos| "data:".len() + pos + ";base64,".len())?;
+    url.drain(..prefix_len);
+    Some(url)
+}
+
+/// Ollama's tool call shape: no ID, and arguments as a JSON object rather than a string.
+#[derive(Serialize, Deserialize)]
+struct OllamaToolCall {
+    function: OllamaFunctionCall,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OllamaFunctionCall {
+    name: String,
+    arguments: Value,
+}
+
+impl From<&ToolCall> for OllamaToolCall {
+    fn from(call: &ToolCall) -> Self {
+        let arguments = serde_json::from_str(&call.function.arguments)
+            .unwrap_or_else(|_| Value::String(call.function.arguments.clone()));
+        Self {
+            function: OllamaFunctionCall {
+                name: call.function.name.clone(),
+                arguments,
+            },
+        }
+    }
+}
+
+impl From<OllamaToolCall> for ToolCall {
+    fn from(call: OllamaToolCall) -> Self {
+        Self {
+            id: format!("call_{}", uuid::Uuid::new_v4().simple()),
+            kind: "function".to_string(),
+            function: FunctionCall {
+                name: call.function.name,
+                arguments: call.function.arguments.to_string(),
+            },
         }
     }
 }
@@ -59,13 +188,132 @@ struct OllamaChatResponse {
 #[derive(Deserialize)]
 struct OllamaResponseMessage {
     role: String,
+    #[serde(default)]
     content: String,
+    #[serde(default)]
+    tool_calls: Vec<OllamaToolCall>,
+}
+
+impl OllamaResponseMessage {
+    fn into_parts(self) -> (String, String, Option<Vec<ToolCall>>) {
+        let calls = (!self.tool_calls.is_empty())
+            .then(|| self.tool_calls.into_iter().map(ToolCall::from).collect());
+        (self.role, self.content, calls)
+    }
 }
 
 #[derive(Deserialize)]
 struct OllamaStreamChunk {
     message: OllamaResponseMessage,
     done: bool,
+    /// Only on the final chunk
+    #[serde(default)]
+    prompt_eval_count: Option<u32>,
+    #[serde(default)]
+    eval_count: Option<u32>,
+}
+
+/// Converts Ollama NDJSON stream lines into OpenAI chunks for a single completion.
+struct ChunkTranslator {
+    completion_id: String,
+    created: u64,
+    model: String,
+    called_tools: bool,
+}
+
+impl ChunkTranslator {
+    fn translate(&mut self, line: &str) -> serde_json::Result<StreamingChunk> {
+        let ollama_chunk: OllamaStreamChunk = serde_json::from_str(line)?;
+        let delta = if ollama_chunk.done {
+            ChatMessageDelta {
+                role: None,
+                content: None,
+                tool_calls: None,
+            }
+        } else {
+            let (role, content, calls) = ollama_chunk.message.into_parts();
+            self.called_tools |= calls.is_some();
+            ChatMessageDelta {
+                role: Some(role),
+                content: Some(content),
+                tool_calls: calls.map(|calls| {
+                    calls
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, call)| ToolCallDelta { index, call })
+                        .collect()
+                }),
+            }
+        };
+        let finish_reason = ollama_chunk.done.then(|| finish_reason(self.called_tools));
+        let usage = match (ollama_chunk.prompt_eval_count, ollama_chunk.eval_count) {
+            (None, None) => None,
+            (prompt, completion) => {
+                let (prompt_tokens, completion_tokens) =
+                    (prompt.unwrap_or(0), completion.unwrap_or(0));
+                Some(TokenUsage {
+                    prompt_tokens,
+                    completion_tokens,
+                    total_tokens: prompt_tokens + completion_tokens,
+                })
+            }
+        };
+
+        Ok(StreamingChunk {
+            id: self.completion_id.clone(),
+            object: "chat.completion.chunk".to_string(),
+            created: self.created,
+            model: self.model.clone(),
+            choices: vec![StreamingChoice {
+                index: 0,
+                delta,
+                finish_reason,
+            }],
+            usage,
+        })
+    }
+}
+
+fn finish_reason(called_tools: bool) -> String {
+    if called_tools { "tool_calls" } else { "stop" }.to_string()
+}
+
+#[derive(Serialize)]
+struct OllamaEmbedRequest {
+    model: String,
+    input: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct OllamaEmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+    #[serde(default)]
+    prompt_eval_count: Option<u32>,
+}
+
+fn response_headers(response: &reqwest::Response) -> Vec<(String, String)> {
+    response
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| {
+            value
+                .to_str()
+                .ok()
+                .map(|v| (name.as_str().to_string(), v.to_string()))
+        })
+        .collect()
+}
+
+/// Capture a non-success Ollama response so the proxy can relay it unchanged.
+async fn upstream_error(response: reqwest::Response) -> EngineHttpError {
+    let status = response.status().as_u16();
+    let headers = response_headers(&response);
+    let body = response.text().await.unwrap_or_default();
+    EngineHttpError {
+        status,
+        headers,
+        body,
+    }
 }
 
 fn generate_completion_id() -> String {
@@ -90,7 +338,21 @@ impl OllamaEngine {
             env::var("OLLAMA_HOST").unwrap_or_else(|_| "http://localhost:11434".to_string());
         Self {
             base_url,
-            client: reqwest::Client::new(),
+            client: http_client(INFERENCE_TIMEOUT),
+        }
+    }
+
+    fn api_request_builder(
+        &self,
+        endpoint: &str,
+        request_id: Option<&str>,
+    ) -> reqwest::RequestBuilder {
+        let builder = self
+            .client
+            .post(format!("{}/api/{endpoint}", self.base_url));
+        match request_id {
+            Some(id) => builder.header(REQUEST_ID_HEADER, id),
+            None => builder,
         }
     }
 }
@@ -118,45 +380,82 @@ impl InferenceEngine for OllamaEngine {
             .collect())
     }
 
-    async fn is_healthy(&self) -> bool {
+    /// Healthy when `/api/version` answers; its version is reported when it can be read.
+    async fn get_info(&self) -> EngineInfo {
         let response = self
             .client
             .get(format!("{}/api/version", self.base_url))
             .timeout(std::time::Duration::from_secs(2))
             .send()
-            .await;
+            .await
+            .ok()
+            .filter(|resp| resp.status().is_success());
+        let healthy = response.is_some();
+        let version = match response {
+            Some(resp) => resp.json::<OllamaVersion>().await.ok().map(|v| v.version),
+            None => None,
+        };
 
-        match response {
-            Ok(resp) => resp.status().is_success(),
-            Err(_) => false,
+        EngineInfo {
+            engine_type: EngineType::Ollama.name().to_string(),
+            version: version.unwrap_or_else(|| "unknown".to_string()),
+            port: reqwest::Url::parse(&self.base_url)
+                .ok()
+                .and_then(|url| url.port_or_known_default())
+                .unwrap_or(0),
+            healthy,
         }
     }
 
-    async fn chat(&self, model: &str, messages: Vec<ChatMessage>) -> Result<InferenceResponse> {
+    /// A generate request without a prompt loads the model and restarts its idle timer.
+    async fn warm_up(&self, model: &str) -> Result<()> {
+        let response = self
+            .api_request_builder("generate", None)
+            .json(&serde_json::json!({"model": model, "stream": false}))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(upstream_error(response).await.into());
+        }
+        Ok(())
+    }
+
+    async fn chat(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: Option<Vec<Tool>>,
+        sampling: &SamplingOptions,
+        request_id: Option<&str>,
+    ) -> Result<EngineReply<InferenceResponse>> {
         let request = OllamaChatRequest {
             model: model.to_string(),
-            messages: messages.iter().map(OllamaChatMessage::from).collect(),
+            messages: messages.into_iter().map(OllamaChatMessage::from).collect(),
             stream: false,
+            tools,
+            options: OllamaOptions::from_sampling(sampling),
         };
 
         let response = self
-            .client
-            .post(format!("{}/api/chat", self.base_url))
+            .api_request_builder("chat", request_id)
             .json(&request)
             .send()
             .await?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            anyhow::bail!("Ollama chat failed with status {status}: {body}");
+            return Err(upstream_error(response).await.into());
         }
+        let headers = response_headers(&response);
 
         let ollama_resp: OllamaChatResponse = response.json().await?;
         let prompt_tokens = ollama_resp.prompt_eval_count.unwrap_or(0);
         let completion_tokens = ollama_resp.eval_count.unwrap_or(0);
+        let (role, content, tool_calls) = ollama_resp.message.into_parts();
+        let finish_reason = finish_reason(tool_calls.is_some());
+        // OpenAI clients expect null content alongside tool calls
+        let content = (tool_calls.is_none() || !content.is_empty()).then_some(content.into());
 
-        Ok(InferenceResponse {
+        let body = InferenceResponse {
             id: generate_completion_id(),
             object: "chat.completion".to_string(),
             created: current_unix_timestamp(),
@@ -164,57 +463,60 @@ impl InferenceEngine for OllamaEngine {
             choices: vec![InferenceChoice {
                 index: 0,
                 message: ChatMessage {
-                    role: ollama_resp.message.role,
-                    content: ollama_resp.message.content,
+                    role,
+                    content,
+                    tool_calls,
+                    tool_call_id: None,
                 },
-                finish_reason: "stop".to_string(),
+                finish_reason,
             }],
             usage: TokenUsage {
                 prompt_tokens,
                 completion_tokens,
                 total_tokens: prompt_tokens + completion_tokens,
             },
-        })
+        };
+        Ok(EngineReply { body, headers })
     }
 
     async fn chat_stream(
         &self,
         model: &str,
         messages: Vec<ChatMessage>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamingChunk>> + Send>>> {
+        tools: Option<Vec<Tool>>,
+        sampling: &SamplingOptions,
+        request_id: Option<&str>,
+    ) -> Result<EngineReply<ChunkStream>> {
         let request = OllamaChatRequest {
             model: model.to_string(),
-            messages: messages.iter().map(OllamaChatMessage::from).collect(),
+            messages: messages.into_iter().map(OllamaChatMessage::from).collect(),
             stream: true,
+            tools,
+            options: OllamaOptions::from_sampling(sampling),
         };
 
         let response = self
-            .client
-            .post(format!("{}/api/chat", self.base_url))
+            .api_request_builder("chat", request_id)
             .json(&request)
             .send()
             .await?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            anyhow::bail!("Ollama chat_stream failed with status {status}: {body}");
+            return Err(upstream_error(response).await.into());
         }
+        let headers = response_headers(&response);
 
-        let completion_id = generate_completion_id();
-        let created = current_unix_timestamp();
-        let model_owned = model.to_string();
+        let translator = ChunkTranslator {
+            completion_id: generate_completion_id(),
+            created: current_unix_timestamp(),
+            model: model.to_string(),
+            called_tools: false,
+        };
         let byte_stream = response.bytes_stream();
 
         let chunk_stream = stream::unfold(
-            (
-                byte_stream,
-                BytesMut::new(),
-                completion_id,
-                created,
-                model_owned,
-            ),
-            |(mut byte_stream, mut buffer, completion_id, created, model_name)| async move {
+            (byte_stream, BytesMut::new(), translator),
+            |(mut byte_stream, mut buffer, mut translator)| async move {
                 loop {
                     if let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
                         let line_bytes = buffer.split_to(pos + 1);
@@ -222,47 +524,10 @@ impl InferenceEngine for OllamaEngine {
                         if line.is_empty() {
                             continue;
                         }
-                        match serde_json::from_str::<OllamaStreamChunk>(&line) {
-                            Ok(ollama_chunk) => {
-                                let finish_reason = if ollama_chunk.done {
-                                    Some("stop".to_string())
-                                } else {
-                                    None
-                                };
-                                let chunk = StreamingChunk {
-                                    id: completion_id.clone(),
-                                    object: "chat.completion.chunk".to_string(),
-                                    created,
-                                    model: model_name.clone(),
-                                    choices: vec![StreamingChoice {
-                                        index: 0,
-                                        delta: ChatMessageDelta {
-                                            role: if ollama_chunk.done {
-                                                None
-                                            } else {
-                                                Some(ollama_chunk.message.role)
-                                            },
-                                            content: if ollama_chunk.done {
-                                                None
-                                            } else {
-                                                Some(ollama_chunk.message.content)
-                                            },
-                                        },
-                                        finish_reason,
-                                    }],
-                                };
-                                return Some((
-                                    Ok(chunk),
-                                    (byte_stream, buffer, completion_id, created, model_name),
-                                ));
-                            }
-                            Err(e) => {
-                                return Some((
-                                    Err(anyhow::anyhow!("Failed to parse stream chunk: {e}")),
-                                    (byte_stream, buffer, completion_id, created, model_name),
-                                ));
-                            }
-                        }
+                        let chunk = translator
+                            .translate(&line)
+                            .map_err(|e| anyhow::anyhow!("Failed to parse stream chunk: {e}"));
+                        return Some((chunk, (byte_stream, buffer, translator)));
                     }
 
                     match byte_stream.next().await {
@@ -272,79 +537,75 @@ impl InferenceEngine for OllamaEngine {
                         Some(Err(e)) => {
                             return Some((
                                 Err(anyhow::anyhow!("Stream read error: {e}")),
-                                (byte_stream, buffer, completion_id, created, model_name),
+                                (byte_stream, buffer, translator),
                             ));
                         }
                         None => {
-                            if !buffer.is_empty() {
-                                let remaining = String::from_utf8_lossy(&buffer).trim().to_string();
-                                buffer.clear();
-                                if !remaining.is_empty() {
-                                    match serde_json::from_str::<OllamaStreamChunk>(&remaining) {
-                                        Ok(ollama_chunk) => {
-                                            let finish_reason = if ollama_chunk.done {
-                                                Some("stop".to_string())
-                                            } else {
-                                                None
-                                            };
-                                            let chunk = StreamingChunk {
-                                                id: completion_id.clone(),
-                                                object: "chat.completion.chunk".to_string(),
-                                                created,
-                                                model: model_name.clone(),
-                                                choices: vec![StreamingChoice {
-                                                    index: 0,
-                                                    delta: ChatMessageDelta {
-                                                        role: if ollama_chunk.done {
-                                                            None
-                                                        } else {
-                                                            Some(ollama_chunk.message.role)
-                                                        },
-                                                        content: if ollama_chunk.done {
-                                                            None
-                                                        } else {
-                                                            Some(ollama_chunk.message.content)
-                                                        },
-                                                    },
-                                                    finish_reason,
-                                                }],
-                                            };
-                                            return Some((
-                                                Ok(chunk),
-                                                (
-                                                    byte_stream,
-                                                    buffer,
-                                                    completion_id,
-                                                    created,
-                                                    model_name,
-                                                ),
-                                            ));
-                                        }
-                                        Err(e) => {
-                                            return Some((
-                                                Err(anyhow::anyhow!(
-                                                    "Failed to parse final chunk: {e}"
-                                                )),
-                                                (
-                                                    byte_stream,
-                                                    buffer,
-                                                    completion_id,
-                                                    created,
-                                                    model_name,
-                                                ),
-                                            ));
-                                        }
-                                    }
-                                }
+                            let remaining = String::from_utf8_lossy(&buffer).trim().to_string();
+                            buffer.clear();
+                            if remaining.is_empty() {
+                                return None;
                             }
-                            return None;
+                            let chunk = translator
+                                .translate(&remaining)
+                                .map_err(|e| anyhow::anyhow!("Failed to parse final chunk: {e}"));
+                            return Some((chunk, (byte_stream, buffer, translator)));
                         }
                     }
                 }
             },
         );
 
-        Ok(Box::pin(chunk_stream))
+        Ok(EngineReply {
+            body: Box::pin(chunk_stream),
+            headers,
+        })
+    }
+
+    async fn embed(
+        &self,
+        model: &str,
+        input: Vec<String>,
+        request_id: Option<&str>,
+    ) -> Result<EngineReply<EmbeddingsResponse>> {
+        let request = OllamaEmbedRequest {
+            model: model.to_string(),
+            input,
+        };
+
+        let response = self
+            .api_request_builder("embed", request_id)
+            .json(&request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(upstream_error(response).await.into());
+        }
+        let headers = response_headers(&response);
+
+        let ollama_resp: OllamaEmbedResponse = response.json().await?;
+        let prompt_tokens = ollama_resp.prompt_eval_count.unwrap_or(0);
+
+        let body = EmbeddingsResponse {
+            object: "list".to_string(),
+            data: ollama_resp
+                .embeddings
+                .into_iter()
+                .enumerate()
+                .map(|(index, embedding)| EmbeddingData {
+                    object: "embedding".to_string(),
+                    index,
+                    embedding,
+                })
+                .collect(),
+            model: model.to_string(),
+            usage: EmbeddingUsage {
+                prompt_tokens,
+                total_tokens: prompt_tokens,
+            },
+        };
+        Ok(EngineReply { body, headers })
     }
 }
 
@@ -385,6 +646,33 @@ mod tests {
         assert_eq!(models[1].size_bytes, 7_000_000_000);
     }
 
+    #[tokio::test]
+    async fn test_ollama_warm_up_loads_without_a_prompt() {
+        let server = MockServer::start();
+        let engine = OllamaEngine {
+            base_url: server.base_url(),
+            client: reqwest::Client::new(),
+        };
+        let mut load = server.mock(|when, then| {
+            when.method(POST)
+                .path("/api/generate")
+                .json_body(json!({"model": "llama3:8b", "stream": false}));
+            then.status(200)
+                .json_body(json!({"model": "llama3:8b", "response": "", "done": true}));
+        });
+
+        engine.warm_up("llama3:8b").await.unwrap();
+        load.assert();
+        load.delete();
+
+        server.mock(|when, then| {
+            when.method(POST).path("/api/generate");
+            then.status(404)
+                .json_body(json!({"error": "model 'llama3:8b' not found"}));
+        });
+        assert!(engine.warm_up("llama3:8b").await.is_err());
+    }
+
     #[tokio::test]
     async fn test_ollama_health_check() {
         let server = MockServer::start();
@@ -395,10 +683,14 @@ mod tests {
 
         let mut mock_success = server.mock(|when, then| {
             when.method(GET).path("/api/version");
-            then.status(200);
+            then.status(200).json_body(json!({"version": "0.5.7"}));
         });
 
-        assert!(engine.is_healthy().await);
+        let info = engine.get_info().await;
+        assert!(info.healthy);
+        assert_eq!(info.engine_type, "ollama");
+        assert_eq!(info.version, "0.5.7");
+        assert_eq!(info.port, server.port());
         mock_success.assert();
         mock_success.delete();
 
@@ -407,7 +699,9 @@ mod tests {
             then.status(500);
         });
 
-        assert!(!engine.is_healthy().await);
+        let info = engine.get_info().await;
+        assert!(!info.healthy);
+        assert_eq!(info.version, "unknown");
     }
 
     #[tokio::test]
@@ -431,14 +725,26 @@ mod tests {
 
         let messages = vec![ChatMessage {
             role: "user".to_string(),
-            content: "Hi".to_string(),
+            content: Some("Hi".into()),
+            tool_calls: None,
+            tool_call_id: None,
         }];
-        let resp = engine.chat("llama3:8b", messages).await.unwrap();
+        let resp = engine
+            .chat(
+                "llama3:8b",
+                messages,
+                None,
+                &SamplingOptions::default(),
+                None,
+            )
+            .await
+            .unwrap()
+            .body;
 
         assert_eq!(resp.object, "chat.completion");
         assert_eq!(resp.model, "llama3:8b");
         assert_eq!(resp.choices.len(), 1);
-        assert_eq!(resp.choices[0].message.content, "Hello there!");
+        assert_eq!(resp.choices[0].message.content, Some("Hello there!".into()));
         assert_eq!(resp.choices[0].finish_reason, "stop");
         assert_eq!(resp.usage.prompt_tokens, 10);
         assert_eq!(resp.usage.completion_tokens, 5);
@@ -460,9 +766,19 @@ mod tests {
 
         let messages = vec![ChatMessage {
             role: "user".to_string(),
-            content: "Hi".to_string(),
+            content: Some("Hi".into()),
+            tool_calls: None,
+            tool_call_id: None,
         }];
-        let result = engine.chat("llama3:8b", messages).await;
+        let result = engine
+            .chat(
+                "llama3:8b",
+                messages,
+                None,
+                &SamplingOptions::default(),
+                None,
+            )
+            .await;
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("500"));
     }
@@ -491,9 +807,21 @@ mod tests {
 
         let messages = vec![ChatMessage {
             role: "user".to_string(),
-            content: "Hi".to_string(),
+            content: Some("Hi".into()),
+            tool_calls: None,
+            tool_call_id: None,
         }];
-        let mut stream = engine.chat_stream("llama3:8b", messages).await.unwrap();
+        let mut stream = engine
+            .chat_stream(
+                "llama3:8b",
+                messages,
+                None,
+                &SamplingOptions::default(),
+                None,
+            )
+            .await
+            .unwrap()
+            .body;
 
         let first = stream.next().await.unwrap().unwrap();
         assert_eq!(first.choices[0].delta.content, Some("Hello".to_string()));
@@ -506,6 +834,8 @@ mod tests {
         let third = stream.next().await.unwrap().unwrap();
         assert!(third.choices[0].delta.content.is_none());
         assert_eq!(third.choices[0].finish_reason, Some("stop".to_string()));
+        let usage = third.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (5, 2));
 
         assert!(stream.next().await.is_none());
     }
@@ -525,10 +855,335 @@ mod tests {
 
         let messages = vec![ChatMessage {
             role: "user".to_string(),
-            content: "Hi".to_string(),
+            content: Some("Hi".into()),
+            tool_calls: None,
+            tool_call_id: None,
         }];
-        let result = engine.chat_stream("llama3:8b", messages).await;
+        let result = engine
+            .chat_stream(
+                "llama3:8b",
+                messages,
+                None,
+                &SamplingOptions::default(),
+                None,
+            )
+            .await;
         let err = result.err().expect("should be an error");
         assert!(err.to_string().contains("500"));
     }
+
+    #[tokio::test]
+    async fn test_chat_forwards_request_id() {
+        let server = MockServer::start();
+        let engine = OllamaEngine {
+            base_url: server.base_url(),
+            client: reqwest::Client::new(),
+        };
+
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/api/chat")
+                .header(REQUEST_ID_HEADER, "req-abc-123");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({
+                    "message": { "role": "assistant", "content": "ok" }
+                }));
+        });
+
+        let messages = vec![ChatMessage {
+            role: "user".to_string(),
+            content: Some("Hi".into()),
+            tool_calls: None,
+            tool_call_id: None,
+        }];
+        engine
+            .chat(
+                "llama3:8b",
+                messages,
+                None,
+                &SamplingOptions::default(),
+                Some("req-abc-123"),
+            )
+            .await
+            .unwrap();
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_chat_forwards_sampling_options() {
+        let server = MockServer::start();
+        let engine = OllamaEngine {
+            base_url: server.base_url(),
+            client: reqwest::Client::new(),
+        };
+
+        let tuned = server.mock(|when, then| {
+            when.method(POST).path("/api/chat").json_body_includes(
+                r#"{"options": {"temperature": 0.2, "top_p": 0.9, "num_predict": 64, "stop": ["END"], "seed": 7}}"#,
+            );
+            then.status(200)
+                .json_body(json!({"message": {"role": "assistant", "content": "ok"}}));
+        });
+        let sampling = SamplingOptions {
+            temperature: Some(0.2),
+            top_p: Some(0.9),
+            max_tokens: Some(64),
+            stop: Some(json!("END")),
+            seed: Some(7),
+        };
+        engine
+            .chat("llama3:8b", vec![], None, &sampling, None)
+            .await
+            .unwrap();
+        tuned.assert();
+
+        // Without sampling parameters the model's own defaults apply
+        let untuned = server.mock(|when, then| {
+            when.method(POST)
+                .path("/api/chat")
+                .is_true(|req| !String::from_utf8_lossy(req.body().as_ref()).contains("options"));
+            then.status(200)
+                .json_body(json!({"message": {"role": "assistant", "content": "ok"}}));
+        });
+        engine
+            .chat_stream("llama3:8b", vec![], None, &SamplingOptions::default(), None)
+            .await
+            .unwrap();
+        untuned.assert();
+    }
+
+    #[tokio::test]
+    async fn test_ollama_embed() {
+        let server = MockServer::start();
+        let engine = OllamaEngine {
+            base_url: server.base_url(),
+            client: reqwest::Client::new(),
+        };
+
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/api/embed")
+                .json_body(json!({ "model": "nomic-embed-text", "input": ["a", "b"] }));
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({
+                    "model": "nomic-embed-text",
+                    "embeddings": [[0.1, 0.2], [0.3, 0.4]],
+                    "prompt_eval_count": 2
+                }));
+        });
+
+        let resp = engine
+            .embed(
+                "nomic-embed-text",
+                vec!["a".to_string(), "b".to_string()],
+                None,
+            )
+            .await
+            .unwrap()
+            .body;
+        mock.assert();
+        assert_eq!(resp.object, "list");
+        assert_eq!(resp.data.len(), 2);
+        assert_eq!(resp.data[1].index, 1);
+        assert_eq!(resp.data[1].embedding, vec![0.3, 0.4]);
+        assert_eq!(resp.usage.prompt_tokens, 2);
+    }
+
+    #[tokio::test]
+    async fn test_chat_error_preserves_upstream_response() {
+        let server = MockServer::start();
+        let engine = OllamaEngine {
+            base_url: server.base_url(),
+            client: reqwest::Client::new(),
+        };
+
+        server.mock(|when, then| {
+            when.method(POST).path("/api/chat");
+            then.status(404)
+                .header("content-type", "application/json")
+                .header("x-upstream", "ollama")
+                .body(r#"{"error":"model not found"}"#);
+        });
+
+        let err = engine
+            .chat("missing", vec![], None, &SamplingOptions::default(), None)
+            .await
+            .unwrap_err();
+        let upstream = err.downcast_ref::<EngineHttpError>().unwrap();
+        assert_eq!(upstream.status, 404);
+        assert_eq!(upstream.body, r#"{"error":"model not found"}"#);
+        assert!(upstream
+            .headers
+            .contains(&("x-upstream".to_string(), "ollama".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_chat_forwards_tools_and_maps_tool_calls() {
+        let server = MockServer::start();
+        let engine = OllamaEngine {
+            base_url: server.base_url(),
+            client: reqwest::Client::new(),
+        };
+
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/api/chat").json_body_includes(
+                json!({
+                    "messages": [
+                        { "role": "user", "content": "Weather in Paris?" },
+                        {
+                            "role": "assistant",
+                            "content": "",
+                            "tool_calls": [{ "function": { "name": "get_weather", "arguments": { "city": "Paris" } } }]
+                        },
+                        { "role": "tool", "content": "18C" }
+                    ],
+                    "tools": [{ "type": "function", "function": { "name": "get_weather" } }]
+                })
+                .to_string(),
+            );
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({
+                    "message": {
+                        "role": "assistant",
+                        "content": "",
+                        "tool_calls": [{ "function": { "name": "get_weather", "arguments": { "city": "Lyon" } } }]
+                    }
+                }));
+        });
+
+        let messages: Vec<ChatMessage> = serde_json::from_value(json!([
+            { "role": "user", "content": "Weather in Paris?" },
+            {
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" }
+                }]
+            },
+            { "role": "tool", "tool_call_id": "call_1", "content": "18C" }
+        ]))
+        .unwrap();
+        let tools: Vec<Tool> = serde_json::from_value(
+            json!([{ "type": "function", "function": { "name": "get_weather" } }]),
+        )
+        .unwrap();
+
+        let resp = engine
+            .chat(
+                "llama3.1:8b",
+                messages,
+                Some(tools),
+                &SamplingOptions::default(),
+                None,
+            )
+            .await
+            .unwrap()
+            .body;
+        mock.assert();
+
+        let choice = &resp.choices[0];
+        assert_eq!(choice.finish_reason, "tool_calls");
+        assert!(choice.message.content.is_none());
+        let call = &choice.message.tool_calls.as_ref().unwrap()[0];
+        assert!(call.id.starts_with("call_"));
+        assert_eq!(call.kind, "function");
+        assert_eq!(call.function.name, "get_weather");
+        assert_eq!(call.function.arguments, r#"{"city":"Lyon"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_tool_calls_finish_reason() {
+        let server = MockServer::start();
+        let engine = OllamaEngine {
+            base_url: server.base_url(),
+            client: reqwest::Client::new(),
+        };
+
+        let ndjson = [
+            json!({"message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"get_weather","arguments":{"city":"Paris"}}}]},"done":false}).to_string(),
+            json!({"message":{"role":"assistant","content":""},"done":true}).to_string(),
+        ]
+        .join("\n");
+
+        server.mock(|when, then| {
+            when.method(POST).path("/api/chat");
+            then.status(200)
+                .header("content-type", "application/x-ndjson")
+                .body(ndjson);
+        });
+
+        let mut stream = engine
+            .chat_stream(
+                "llama3.1:8b",
+                vec![],
+                None,
+                &SamplingOptions::default(),
+                None,
+            )
+            .await
+            .unwrap()
+            .body;
+
+        let first = stream.next().await.unwrap().unwrap();
+        let calls = first.choices[0].delta.tool_calls.as_ref().unwrap();
+        assert_eq!(calls[0].index, 0);
+        assert_eq!(calls[0].call.function.arguments, r#"{"city":"Paris"}"#);
+
+        let last = stream.next().await.unwrap().unwrap();
+        assert_eq!(last.choices[0].finish_reason.as_deref(), Some("tool_calls"));
+    }
+
+    #[tokio::test]
+    async fn test_chat_sends_images_to_ollama() {
+        let server = MockServer::start();
+        let engine = OllamaEngine {
+            base_url: server.base_url(),
+            client: reqwest::Client::new(),
+        };
+
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/api/chat").json_body_includes(
+                json!({
+                    "messages": [
+                        { "role": "user", "content": "Describe\nbriefly", "images": ["iVBORw0KGgo="] },
+                        { "role": "assistant", "content": "A cat." }
+                    ]
+                })
+                .to_string(),
+            );
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({ "message": { "role": "assistant", "content": "Still a cat." } }));
+        });
+
+        let messages: Vec<ChatMessage> = serde_json::from_value(json!([
+            { "role": "user", "content": [
+                { "type": "text", "text": "Describe" },
+                { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBORw0KGgo=" } },
+                { "type": "text", "text": "briefly" }
+            ]},
+            { "role": "assistant", "content": "A cat." }
+        ]))
+        .unwrap();
+
+        engine
+            .chat("llava", messages, None, &SamplingOptions::default(), None)
+            .await
+            .unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_base64_image_data_strips_data_url_prefix() {
+        assert_eq!(
+            base64_image_data("data:image/jpeg;base64,/9j/4AAQ".to_string()).as_deref(),
+            Some("/9j/4AAQ")
+        );
+        assert!(base64_image_data("https://example.com/cat.png".to_string()).is_none());
+    }
 }
@@ -1,10 +1,26 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
 use tokio::process::Command;
 use tracing::{error, info, warn};
 
-const BENCHMARK_TIMEOUT_SECS: u64 = 300;
+/// How long a benchmark may run when `BENCHMARK_TIMEOUT_SECS` is not set.
+pub const DEFAULT_BENCHMARK_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A benchmark that did not finish within its timeout, as opposed to one that failed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchmarkTimedOut {
+    pub timeout: Duration,
+}
+
+impl std::fmt::Display for BenchmarkTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "benchmark timed out after {:?}", self.timeout)
+    }
+}
+
+impl std::error::Error for BenchmarkTimedOut {}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BenchmarkResult {
@@ -22,30 +38,49 @@ struct BenchmarkOutput {
 
 /// Run hardware benchmark using a Python subprocess.
 ///
+/// Without PyTorch this falls back to a numpy benchmark, and without Python or numpy
+/// to a native one, so every node can produce a result.
+///
 /// The `seed` parameter is passed through to the Python benchmark code, which
 /// first attempts to interpret it as a hexadecimal string (`int(seed, 16)`).
 /// If the string is not valid hexadecimal, the Python code falls back to
 /// deriving a deterministic 32‑bit integer from the seed bytes instead.
 /// Callers may therefore use either a hex string or an arbitrary UTF‑8 string
 /// as the seed, but should be aware that hex seeds receive special handling.
-pub async fn run_benchmark(seed: &str, matrix_size: usize) -> Result<BenchmarkResult> {
+///
+/// Each benchmark attempted is given `timeout`; running out is reported as
+/// `BenchmarkTimedOut`.
+pub async fn run_benchmark(
+    seed: &str,
+    matrix_size: usize,
+    timeout: Duration,
+) -> Result<BenchmarkResult> {
     info!(
         "🔬 Starting hardware benchmark (seed: {}, size: {})",
         seed, matrix_size
     );
 
+    let python = match monkey_troop_shared::get_secure_binary_path("python3") {
+        Ok(python) => python,
+        Err(e) => {
+            warn!("{}, falling back to native CPU benchmark", e);
+            return run_native_cpu_benchmark(seed, matrix_size, timeout).await;
+        }
+    };
+
     // Spawn Python subprocess
     // The benchmark.py is at the root of the worker directory
     let output = tokio::time::timeout(
-        Duration::from_secs(BENCHMARK_TIMEOUT_SECS), // 5 minute timeout
-        Command::new(monkey_troop_shared::get_secure_binary_path("python3")?)
+        timeout,
+        Command::new(python)
             .arg("benchmark.py")
             .arg(seed)
             .arg(matrix_size.to_string())
+            .kill_on_drop(true)
             .output(),
     )
     .await
-    .context("Benchmark timed out after 300 seconds")?
+    .map_err(|_| BenchmarkTimedOut { timeout })?
     .context("Failed to execute benchmark subprocess")?;
 
     if !output.status.success() {
@@ -53,9 +88,15 @@ pub async fn run_benchmark(seed: &str, matrix_size: usize) -> Result<BenchmarkRe
         error!("Benchmark failed: {}", stderr);
 
         // Check if it's a PyTorch import error
-        if stderr.contains("No module named 'torch'") {
+        if stderr.contains("No module named 'torch'") || stderr.contains("torch is not installed") {
             warn!("PyTorch not installed, falling back to CPU benchmark");
-            return run_cpu_fallback_benchmark(seed, matrix_size).await;
+            return match run_cpu_fallback_benchmark(seed, matrix_size, timeout).await {
+                Err(e) if e.to_string().contains("No module named 'numpy'") => {
+                    warn!("numpy not installed, falling back to native CPU benchmark");
+                    run_native_cpu_benchmark(seed, matrix_size, timeout).await
+                }
+                result => result,
+            };
         }
 
         anyhow::bail!("Benchmark subprocess failed: {stderr}");
@@ -78,8 +119,61 @@ pub async fn run_benchmark(seed: &str, matrix_size: usize) -> Result<BenchmarkRe
     })
 }
 
+/// Run the benchmark `runs` times and report the median duration, so a single run slowed
+/// by thermal throttling or a cold cache does not decide the node's tier. The slowest run
+/// is discarded first; the proof hash covers every run. `timeout` applies to each run.
+pub async fn run_benchmark_averaged(
+    seed: &str,
+    matrix_size: usize,
+    runs: usize,
+    timeout: Duration,
+) -> Result<BenchmarkResult> {
+    anyhow::ensure!(runs > 0, "Benchmark run count must be at least 1");
+
+    let mut results = Vec::with_capacity(runs);
+    for run in 1..=runs {
+        info!("Benchmark run {}/{}", run, runs);
+        results.push(run_benchmark(seed, matrix_size, timeout).await?);
+    }
+    let combined = combine_runs(results);
+
+    info!(
+        "✓ Benchmark median over {} runs: {}s on {}",
+        runs, combined.duration, combined.device_name
+    );
+    Ok(combined)
+}
+
+/// Fold the results of consecutive runs, which must not be empty, into one.
+fn combine_runs(mut results: Vec<BenchmarkResult>) -> BenchmarkResult {
+    let proofs: Vec<&str> = results.iter().map(|r| r.proof_hash.as_str()).collect();
+    let proof_hash = format!("{:x}", Sha256::digest(proofs.join(":")));
+
+    let mut durations: Vec<f64> = results.iter().map(|r| r.duration).collect();
+    durations.sort_by(f64::total_cmp);
+    if durations.len() > 1 {
+        durations.pop();
+    }
+    let mid = durations.len() / 2;
+    let duration = if durations.len().is_multiple_of(2) {
+        (durations[mid - 1] + durations[mid]) / 2.0
+    } else {
+        durations[mid]
+    };
+
+    BenchmarkResult {
+        proof_hash,
+        duration,
+        device_name: results.swap_remove(0).device_name,
+    }
+}
+
 /// Fallback CPU benchmark when GPU/PyTorch unavailable
-async fn run_cpu_fallback_benchmark(seed: &str, matrix_size: usize) -> Result<BenchmarkResult> {
+async fn run_cpu_fallback_benchmark(
+    seed: &str,
+    matrix_size: usize,
+    timeout: Duration,
+) -> Result<BenchmarkResult> {
     info!("Running CPU fallback benchmark...");
 
     // Simple CPU benchmark using numpy
@@ -128,16 +222,17 @@ print(json.dumps(output))
 "#;
 
     let output = tokio::time::timeout(
-        Duration::from_secs(BENCHMARK_TIMEOUT_SECS),
+        timeout,
         Command::new(monkey_troop_shared::get_secure_binary_path("python3")?)
             .arg("-c")
             .arg(python_code)
             .arg(seed)
             .arg(matrix_size.to_string())
+            .kill_on_drop(true)
             .output(),
     )
     .await
-    .context("CPU fallback benchmark timed out")?
+    .map_err(|_| BenchmarkTimedOut { timeout })?
     .context("Failed to execute CPU fallback")?;
 
     if !output.status.success() {
@@ -156,14 +251,108 @@ print(json.dumps(output))
     })
 }
 
+/// CPU benchmark in plain Rust, for nodes without Python or numpy. It mirrors the numpy
+/// fallback: seeded random matrices are generated and multiplied, and the time taken and
+/// the product's sum go into the proof.
+async fn run_native_cpu_benchmark(
+    seed: &str,
+    matrix_size: usize,
+    timeout: Duration,
+) -> Result<BenchmarkResult> {
+    info!("Running native CPU benchmark...");
+    anyhow::ensure!(matrix_size > 0, "Benchmark matrix size must be positive");
+
+    let seed = seed.to_string();
+    let benchmark = tokio::task::spawn_blocking(move || {
+        let start = Instant::now();
+        let (a, b) = seeded_matrices(&seed, matrix_size);
+        let product = matmul(&a, &b, matrix_size);
+        let duration = start.elapsed().as_secs_f64();
+
+        let result_sum: f64 = product.iter().map(|&x| f64::from(x)).sum();
+        let proof_data = format!("{seed}:{duration:.6}:{result_sum:.6}");
+        BenchmarkResult {
+            proof_hash: format!("{:x}", Sha256::digest(proof_data)),
+            duration,
+            device_name: "CPU (native)".to_string(),
+        }
+    });
+
+    // A timed-out multiplication keeps its blocking thread until it finishes; only the
+    // result is abandoned
+    let result = tokio::time::timeout(timeout, benchmark)
+        .await
+        .map_err(|_| BenchmarkTimedOut { timeout })?
+        .context("Native CPU benchmark failed")?;
+
+    info!(
+        "✓ Benchmark complete: {}s on {}",
+        result.duration, result.device_name
+    );
+    Ok(result)
+}
+
+/// The 32-bit seed the numpy fallback derives from `seed`: its value if it is hex,
+/// otherwise its bytes folded together.
+fn seed_value(seed: &str) -> u32 {
+    let hex = seed.strip_prefix("0x").unwrap_or(seed);
+    if !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return hex.chars().fold(0u32, |value, c| {
+            (value << 4) | c.to_digit(16).unwrap_or_default()
+        });
+    }
+    seed.bytes().enumerate().fold(0u32, |sum, (i, b)| {
+        sum.wrapping_add(u32::from(b) << (8 * (i % 4)))
+    })
+}
+
+/// Two `n`×`n` row-major matrices of standard normal values, determined by `seed`.
+fn seeded_matrices(seed: &str, n: usize) -> (Vec<f32>, Vec<f32>) {
+    let mut state = u64::from(seed_value(seed));
+    // SplitMix64, reduced to a uniform value in (0, 1]
+    let mut uniform = move || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        ((z >> 11) + 1) as f64 / (1u64 << 53) as f64
+    };
+    // Box-Muller transform
+    let mut normal = move || {
+        let (u1, u2) = (uniform(), uniform());
+        ((-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()) as f32
+    };
+
+    let a = (0..n * n).map(|_| normal()).collect();
+    let b = (0..n * n).map(|_| normal()).collect();
+    (a, b)
+}
+
+/// Row-major `n`×`n` matrix product, looping i-k-j so the inner loop runs along rows.
+fn matmul(a: &[f32], b: &[f32], n: usize) -> Vec<f32> {
+    let mut product = vec![0.0; n * n];
+    for (a_row, product_row) in a.chunks_exact(n).zip(product.chunks_exact_mut(n)) {
+        for (&a_ik, b_row) in a_row.iter().zip(b.chunks_exact(n)) {
+            for (p, &b_kj) in product_row.iter_mut().zip(b_row) {
+                *p += a_ik * b_kj;
+            }
+        }
+    }
+    product
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Short enough to keep a hung benchmark from stalling the test run.
+    const TEST_TIMEOUT: Duration = Duration::from_secs(30);
+
     #[tokio::test]
     async fn test_run_cpu_fallback_benchmark_success() {
         // This test requires python3 and numpy to be available in the environment
-        let result = run_cpu_fallback_benchmark("test-seed", 128).await;
+        let result = run_cpu_fallback_benchmark("test-seed", 128, TEST_TIMEOUT).await;
         if let Ok(res) = result {
             assert!(!res.proof_hash.is_empty());
             assert!(res.duration > 0.0);
@@ -171,11 +360,114 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_seed_value_matches_numpy_fallback() {
+        assert_eq!(seed_value("deadbeef"), 0xdeadbeef);
+        assert_eq!(seed_value("0xAB"), 0xab);
+        // Only the low 32 bits of long hex seeds are kept
+        assert_eq!(seed_value("123456789"), 0x23456789);
+        // Non-hex seeds fold their bytes, as `sum(b << (8 * (i % 4)))` does in Python
+        assert_eq!(seed_value("test-seed"), 3_654_867_205);
+    }
+
+    #[test]
+    fn test_seeded_matrices_are_deterministic() {
+        let (a, b) = seeded_matrices("deadbeef", 8);
+        assert_eq!(a.len(), 64);
+        assert_ne!(a, b);
+        assert_eq!(seeded_matrices("deadbeef", 8), (a.clone(), b));
+        assert_ne!(seeded_matrices("cafebabe", 8).0, a);
+
+        // Standard normal values: roughly zero mean and unit variance
+        let (a, _) = seeded_matrices("deadbeef", 100);
+        let mean = a.iter().sum::<f32>() / a.len() as f32;
+        let variance = a.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / a.len() as f32;
+        assert!(mean.abs() < 0.05, "mean {mean}");
+        assert!((variance - 1.0).abs() < 0.1, "variance {variance}");
+    }
+
+    #[test]
+    fn test_matmul() {
+        let a = [1.0, 2.0, 3.0, 4.0];
+        let b = [5.0, 6.0, 7.0, 8.0];
+        assert_eq!(matmul(&a, &b, 2), [19.0, 22.0, 43.0, 50.0]);
+    }
+
+    #[tokio::test]
+    async fn test_run_native_cpu_benchmark() {
+        let result = run_native_cpu_benchmark("deadbeef", 64, TEST_TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(result.proof_hash.len(), 64);
+        assert!(result.proof_hash.chars().all(|c| c.is_ascii_hexdigit()));
+        assert!(result.duration > 0.0);
+        assert_eq!(result.device_name, "CPU (native)");
+
+        assert!(run_native_cpu_benchmark("deadbeef", 0, TEST_TIMEOUT)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_timeout_is_reported_distinctly() {
+        let err = run_native_cpu_benchmark("deadbeef", 256, Duration::ZERO)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<BenchmarkTimedOut>(),
+            Some(&BenchmarkTimedOut {
+                timeout: Duration::ZERO
+            })
+        );
+        assert_eq!(err.to_string(), "benchmark timed out after 0ns");
+    }
+
+    fn run(proof_hash: &str, duration: f64) -> BenchmarkResult {
+        BenchmarkResult {
+            proof_hash: proof_hash.to_string(),
+            duration,
+            device_name: "CPU".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_combine_runs_discards_slowest_and_takes_median() {
+        // The 9s outlier is dropped, leaving the median of 2s and 3s
+        let combined = combine_runs(vec![run("a", 3.0), run("b", 9.0), run("c", 2.0)]);
+        assert_eq!(combined.duration, 2.5);
+        assert_eq!(combined.device_name, "CPU");
+        assert_eq!(
+            combined.proof_hash,
+            format!("{:x}", Sha256::digest("a:b:c"))
+        );
+
+        let combined = combine_runs(vec![
+            run("a", 4.0),
+            run("b", 1.0),
+            run("c", 2.0),
+            run("d", 3.0),
+        ]);
+        assert_eq!(combined.duration, 2.0);
+
+        let combined = combine_runs(vec![run("a", 4.0)]);
+        assert_eq!(combined.duration, 4.0);
+        assert_ne!(combined.proof_hash, "a");
+    }
+
+    #[tokio::test]
+    async fn test_run_benchmark_averaged_rejects_zero_runs() {
+        assert!(run_benchmark_averaged("deadbeef", 64, 0, TEST_TIMEOUT)
+            .await
+            .is_err());
+    }
+
     #[tokio::test]
     async fn test_run_benchmark_not_found() {
         // Test that it handles missing benchmark.py
-        let result = run_benchmark("test-seed", 128).await;
+        let result = run_benchmark("test-seed", 128, TEST_TIMEOUT).await;
         match result {
+            // Without Python, PyTorch or numpy the native benchmark runs instead
+            Ok(res) => assert_eq!(res.device_name, "CPU (native)"),
             Err(err) => {
                 let msg = err.to_string();
                 // Ensure we are exercising the expected error path
@@ -186,9 +478,6 @@ mod tests {
                     "unexpected error message for missing benchmark.py: {msg}"
                 );
             }
-            Ok(_) => {
-                panic!("expected run_benchmark to fail when benchmark.py is missing");
-            }
         }
     }
 }
@@ -1,9 +1,13 @@
-use crate::application::ports::CoordinatorClient;
-use crate::domain::models::{HardwareStatus, NodeStatus};
+use crate::application::ports::{CoordinatorClient, HardwareVerifier, PublicKeySource};
+use crate::domain::models::HeartbeatReport;
 use anyhow::Result;
 use async_trait::async_trait;
-use monkey_troop_shared::ModelIdentity;
+use monkey_troop_shared::{
+    http_client, ChallengeResponse, TroopError, TroopResult, UsageReport, VerifyRequest,
+    VerifyResponse, DISCOVERY_TIMEOUT,
+};
 use reqwest::Client;
+use serde::Deserialize;
 use serde_json::json;
 use std::env;
 
@@ -11,6 +15,11 @@ fn resolve_tailscale_ip() -> Option<String> {
     env::var("TAILSCALE_IP").ok()
 }
 
+#[derive(Deserialize)]
+struct PublicKeyResponse {
+    public_key: String,
+}
+
 pub struct HttpCoordinatorClient {
     base_url: String,
     client: Client,
@@ -20,37 +29,28 @@ impl HttpCoordinatorClient {
     pub fn new(base_url: String) -> Self {
         Self {
             base_url,
-            client: Client::new(),
+            client: http_client(DISCOVERY_TIMEOUT),
         }
     }
 }
 
 #[async_trait]
 impl CoordinatorClient for HttpCoordinatorClient {
-    async fn send_heartbeat(
-        &self,
-        node_id: &str,
-        status: NodeStatus,
-        models: Vec<ModelIdentity>,
-        hardware: HardwareStatus,
-        engines: Vec<String>,
-        encryption_public_key: Option<String>,
-    ) -> Result<()> {
+    async fn send_heartbeat(&self, report: HeartbeatReport) -> Result<()> {
         let endpoint = format!("{}/heartbeat", self.base_url);
 
         let mut payload = json!({
-            "node_id": node_id,
-            "status": format!("{status:?}").to_uppercase(),
-            "models": models,
-            "hardware": {
-                "gpu": hardware.gpu_name,
-                "vram_free": hardware.vram_free_mb
-            },
+            "node_id": report.node_id,
+            "status": format!("{:?}", report.status).to_uppercase(),
+            "models": report.models,
+            "hardware": report.hardware.info(),
             "tailscale_ip": resolve_tailscale_ip(),
-            "engines": engines
+            "engines": report.engines,
+            "load": report.load,
+            "coordinator_reachable": report.coordinator_reachable
         });
 
-        if let (Some(key), Some(obj)) = (encryption_public_key, payload.as_object_mut()) {
+        if let (Some(key), Some(obj)) = (report.encryption_public_key, payload.as_object_mut()) {
             obj.insert(
                 "encryption_public_key".to_string(),
                 serde_json::Value::String(key),
@@ -65,19 +65,97 @@ impl CoordinatorClient for HttpCoordinatorClient {
             anyhow::bail!("Heartbeat failed with status: {}", response.status())
         }
     }
+
+    async fn report_usage(&self, report: &UsageReport) -> Result<()> {
+        let response = self
+            .client
+            .post(format!("{}/usage", self.base_url))
+            .json(report)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("Usage report failed with status: {}", response.status())
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PublicKeySource for HttpCoordinatorClient {
+    async fn fetch_public_key(&self) -> Result<String> {
+        let response = self
+            .client
+            .get(format!("{}/public-key", self.base_url))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("Public key fetch failed with status: {}", response.status())
+        }
+        Ok(response.json::<PublicKeyResponse>().await?.public_key)
+    }
+}
+
+#[async_trait]
+impl HardwareVerifier for HttpCoordinatorClient {
+    async fn request_challenge(&self, node_id: &str) -> TroopResult<ChallengeResponse> {
+        let mut url = reqwest::Url::parse(&format!("{}/hardware/challenge", self.base_url))
+            .map_err(|e| TroopError::InvalidRequest(format!("Invalid coordinator URL: {e}")))?;
+        url.query_pairs_mut().append_pair("node_id", node_id);
+        let response = self.client.post(url).send().await?;
+
+        if !response.status().is_success() {
+            return Err(TroopError::from_response(response).await);
+        }
+        Ok(response.json().await?)
+    }
+
+    async fn submit_proof(&self, proof: &VerifyRequest) -> TroopResult<VerifyResponse> {
+        let response = self
+            .client
+            .post(format!("{}/hardware/verify", self.base_url))
+            .json(proof)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(TroopError::from_response(response).await);
+        }
+        Ok(response.json().await?)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::models::{HardwareStatus, NodeStatus};
     use httpmock::prelude::*;
+    use monkey_troop_shared::{ModelIdentity, NodeLoad};
 
-    fn test_model_identities() -> Vec<ModelIdentity> {
-        vec![ModelIdentity {
-            name: "llama3".to_string(),
-            content_hash: "sha256:abc123".to_string(),
-            size_bytes: 4_000_000_000,
-        }]
+    fn test_report(encryption_public_key: Option<String>) -> HeartbeatReport {
+        HeartbeatReport {
+            node_id: "node-1".to_string(),
+            status: NodeStatus::Idle,
+            models: vec![ModelIdentity {
+                name: "llama3".to_string(),
+                content_hash: "sha256:abc123".to_string(),
+                size_bytes: 4_000_000_000,
+            }],
+            hardware: HardwareStatus {
+                gpu_name: "RTX 4090".to_string(),
+                vram_free_mb: 24576,
+                gpu_util: 42.0,
+                gpus: Vec::new(),
+            },
+            engines: Vec::new(),
+            encryption_public_key,
+            load: NodeLoad {
+                in_flight: 1,
+                max_concurrent: 3,
+            },
+            coordinator_reachable: true,
+        }
     }
 
     #[tokio::test]
@@ -85,28 +163,19 @@ mod tests {
         let server = MockServer::start();
         let coordinator = HttpCoordinatorClient::new(server.base_url());
 
-        let _mock = server.mock(|when, then| {
-            when.method(POST).path("/heartbeat");
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/heartbeat")
+                .json_body_includes(r#"{"load": {"in_flight": 1, "max_concurrent": 3}}"#)
+                .json_body_includes(r#"{"hardware": {"gpu_util": 42.0}}"#)
+                .json_body_includes(r#"{"coordinator_reachable": true}"#);
             then.status(200);
         });
 
-        let hardware = HardwareStatus {
-            gpu_name: "RTX 4090".to_string(),
-            vram_free_mb: 24576,
-        };
-
-        let result = coordinator
-            .send_heartbeat(
-                "node-1",
-                NodeStatus::Idle,
-                test_model_identities(),
-                hardware,
-                Vec::new(),
-                None,
-            )
-            .await;
+        let result = coordinator.send_heartbeat(test_report(None)).await;
 
         assert!(result.is_ok());
+        mock.assert();
     }
 
     #[tokio::test]
@@ -119,20 +188,8 @@ mod tests {
             then.status(200);
         });
 
-        let hardware = HardwareStatus {
-            gpu_name: "RTX 4090".to_string(),
-            vram_free_mb: 24576,
-        };
-
         let result = coordinator
-            .send_heartbeat(
-                "node-1",
-                NodeStatus::Idle,
-                test_model_identities(),
-                hardware,
-                Vec::new(),
-                Some("test-public-key-b64".to_string()),
-            )
+            .send_heartbeat(test_report(Some("test-public-key-b64".to_string())))
             .await;
 
         assert!(result.is_ok());
@@ -148,23 +205,120 @@ mod tests {
             then.status(500);
         });
 
-        let hardware = HardwareStatus {
-            gpu_name: "RTX 4090".to_string(),
-            vram_free_mb: 24576,
-        };
-
-        let result = coordinator
-            .send_heartbeat(
-                "node-1",
-                NodeStatus::Idle,
-                test_model_identities(),
-                hardware,
-                Vec::new(),
-                None,
-            )
-            .await;
+        let result = coordinator.send_heartbeat(test_report(None)).await;
 
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("500"));
     }
+
+    #[tokio::test]
+    async fn test_report_usage() {
+        let server = MockServer::start();
+        let coordinator = HttpCoordinatorClient::new(server.base_url());
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/usage").json_body(json!({
+                "node_id": "node-1",
+                "requester": "requester-1",
+                "model": "llama3:8b",
+                "prompt_tokens": 9,
+                "completion_tokens": 12
+            }));
+            then.status(200);
+        });
+
+        let report = UsageReport {
+            node_id: "node-1".to_string(),
+            requester: "requester-1".to_string(),
+            model: "llama3:8b".to_string(),
+            prompt_tokens: 9,
+            completion_tokens: 12,
+        };
+        coordinator.report_usage(&report).await.unwrap();
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_fetch_public_key() {
+        let server = MockServer::start();
+        let coordinator = HttpCoordinatorClient::new(server.base_url());
+
+        server.mock(|when, then| {
+            when.method(GET).path("/public-key");
+            then.status(200)
+                .json_body(json!({ "public_key": "-----BEGIN PUBLIC KEY-----" }));
+        });
+
+        assert_eq!(
+            coordinator.fetch_public_key().await.unwrap(),
+            "-----BEGIN PUBLIC KEY-----"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hardware_verification_endpoints() {
+        let server = MockServer::start();
+        let coordinator = HttpCoordinatorClient::new(server.base_url());
+        let challenge = server.mock(|when, then| {
+            when.method(POST)
+                .path("/hardware/challenge")
+                .query_param("node_id", "node-1");
+            then.status(200).json_body(json!({
+                "challenge_token": "token-1",
+                "seed": "deadbeef",
+                "matrix_size": 4096
+            }));
+        });
+        let verify = server.mock(|when, then| {
+            when.method(POST)
+                .path("/hardware/verify")
+                .json_body_includes(r#"{"challenge_token": "token-1", "duration": 4.2}"#);
+            then.status(200).json_body(json!({
+                "status": "verified",
+                "assigned_multiplier": 3.5,
+                "tier": "High Performance"
+            }));
+        });
+
+        let issued = coordinator.request_challenge("node-1").await.unwrap();
+        assert_eq!(issued.seed, "deadbeef");
+        assert_eq!(issued.matrix_size, 4096);
+        let verified = coordinator
+            .submit_proof(&VerifyRequest {
+                node_id: "node-1".to_string(),
+                challenge_token: issued.challenge_token,
+                proof_hash: "abc123".to_string(),
+                duration: 4.2,
+                device_name: "RTX 4090".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(verified.assigned_multiplier, 3.5);
+        assert_eq!(verified.tier, "High Performance");
+        challenge.assert();
+        verify.assert();
+    }
+
+    #[tokio::test]
+    async fn test_rejected_proof_is_not_retryable() {
+        let server = MockServer::start();
+        let coordinator = HttpCoordinatorClient::new(server.base_url());
+        server.mock(|when, then| {
+            when.method(POST).path("/hardware/verify");
+            then.status(400)
+                .json_body(json!({"detail": "Challenge expired or invalid"}));
+        });
+
+        let err = coordinator
+            .submit_proof(&VerifyRequest {
+                node_id: "node-1".to_string(),
+                challenge_token: "stale".to_string(),
+                proof_hash: "abc123".to_string(),
+                duration: 4.2,
+                device_name: "RTX 4090".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert!(!err.is_retryable());
+        assert!(err.to_string().contains("Challenge expired or invalid"));
+    }
 }
@@ -2,107 +2,188 @@ use crate::application::ports::HardwareMonitor;
 use crate::domain::models::HardwareStatus;
 use anyhow::Result;
 use async_trait::async_trait;
+use monkey_troop_shared::GpuInfo;
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
 use sysinfo::System;
 
-pub struct NvidiaGpuMonitor;
+/// Reads the GPU through nvidia-smi, or on macOS through system_profiler and ioreg,
+/// falling back to the CPU on nodes without one.
+#[derive(Default)]
+pub struct NvidiaGpuMonitor {
+    /// Set once nvidia-smi has answered: from then on a failing nvidia-smi means the GPU
+    /// has gone (driver crash, device lost), not that the node never had one
+    seen_gpu: AtomicBool,
+}
 
 #[async_trait]
 impl HardwareMonitor for NvidiaGpuMonitor {
     async fn get_status(&self) -> Result<HardwareStatus> {
-        let (name, vram) = get_gpu_info();
-        Ok(HardwareStatus {
-            gpu_name: name,
-            vram_free_mb: vram,
-        })
-    }
-
-    async fn is_idle(&self) -> Result<bool> {
-        // Use a 10% utilization threshold for "IDLE" status
-        self.is_gpu_idle(10.0).await
-    }
-}
+        // Query nvidia-smi on a blocking thread to avoid blocking the async runtime
+        match tokio::task::spawn_blocking(query_nvidia).await {
+            Ok(Ok(status)) => {
+                self.seen_gpu.store(true, Ordering::Relaxed);
+                return Ok(status);
+            }
+            Ok(Err(e)) if self.seen_gpu.load(Ordering::Relaxed) => {
+                anyhow::bail!("GPU is no longer available: {e}")
+            }
+            _ => {}
+        }
 
-impl NvidiaGpuMonitor {
-    /// Check if GPU is idle based on utilization threshold
-    pub async fn is_gpu_idle(&self, threshold: f32) -> Result<bool> {
-        // Try nvidia-smi first on a blocking thread to avoid blocking the async runtime
-        if let Ok(Ok(nvidia_idle)) =
-            tokio::task::spawn_blocking(move || check_nvidia_idle(threshold)).await
-        {
-            return Ok(nvidia_idle);
+        #[cfg(target_os = "macos")]
+        if let Ok(Ok((name, utilization))) = tokio::task::spawn_blocking(query_apple_gpu).await {
+            // Apple Silicon GPUs share the system's memory, so what is free of it is free VRAM
+            let vram_free_mb = if name.starts_with("Apple") {
+                available_memory_mb()
+            } else {
+                0
+            };
+            return Ok(HardwareStatus {
+                gpu_name: name,
+                vram_free_mb,
+                gpu_util: match utilization {
+                    Some(utilization) => utilization,
+                    None => cpu_utilization().await,
+                },
+                gpus: Vec::new(),
+            });
         }
 
-        // Fallback: check CPU idle as proxy
-        Ok(check_cpu_idle(threshold).await)
+        // Fallback: report CPU utilization so idle detection still works without a GPU
+        Ok(HardwareStatus {
+            gpu_name: "Unknown GPU".to_string(),
+            vram_free_mb: 0,
+            gpu_util: cpu_utilization().await,
+            gpus: Vec::new(),
+        })
     }
 }
 
-/// Free functions for GPU and CPU checks to avoid lifetime issues with spawn_blocking
-fn check_nvidia_idle(threshold: f32) -> Result<bool> {
+/// Name, free and total VRAM and utilization of every GPU from a single nvidia-smi call
+fn query_nvidia() -> Result<HardwareStatus> {
     let output = Command::new(monkey_troop_shared::get_secure_binary_path("nvidia-smi")?)
         .args([
-            "--query-gpu=utilization.gpu",
+            "--query-gpu=name,memory.free,memory.total,utilization.gpu",
             "--format=csv,noheader,nounits",
         ])
         .output()?;
 
-    if output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        if let Some(line) = stdout.lines().next() {
-            if let Ok(util) = line.trim().parse::<f32>() {
-                return Ok(util < threshold);
-            }
-        }
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("nvidia-smi exited with {}", output.status));
     }
-
-    Err(anyhow::anyhow!("Failed to parse nvidia-smi output"))
+    parse_nvidia_query(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| anyhow::anyhow!("Failed to parse nvidia-smi output"))
 }
 
-async fn check_cpu_idle(threshold: f32) -> bool {
-    let mut sys = System::new_all();
-    sys.refresh_cpu_all();
+/// Parse `name, memory.free, memory.total, utilization.gpu` CSV output, one line per GPU,
+/// into the node's totals: VRAM is summed and the busiest GPU's utilization is taken.
+fn parse_nvidia_query(stdout: &str) -> Option<HardwareStatus> {
+    let gpus = stdout
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(parse_nvidia_gpu)
+        .collect::<Option<Vec<_>>>()?;
+    let first = gpus.first()?;
+    let gpu_name = if gpus.len() == 1 {
+        first.name.clone()
+    } else if gpus.iter().all(|gpu| gpu.name == first.name) {
+        format!("{}x {}", gpus.len(), first.name)
+    } else {
+        let names: Vec<&str> = gpus.iter().map(|gpu| gpu.name.as_str()).collect();
+        names.join(" + ")
+    };
 
-    // Wait a bit for accurate CPU measurement
-    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
-    sys.refresh_cpu_all();
+    Some(HardwareStatus {
+        gpu_name,
+        vram_free_mb: gpus.iter().map(|gpu| gpu.vram_free).sum(),
+        gpu_util: gpus.iter().map(|gpu| gpu.gpu_util).fold(0.0, f32::max),
+        gpus,
+    })
+}
 
-    let avg_usage =
-        sys.cpus().iter().map(|cpu| cpu.cpu_usage()).sum::<f32>() / sys.cpus().len() as f32;
+/// One line of the query. GPU names may contain commas, so the numeric fields are split
+/// off from the right.
+fn parse_nvidia_gpu(line: &str) -> Option<GpuInfo> {
+    let mut fields = line.rsplitn(4, ',');
+    let gpu_util = fields.next()?.trim().parse::<f32>().ok()?;
+    let vram_total = fields.next()?.trim().parse::<u64>().unwrap_or(0);
+    let vram_free = fields.next()?.trim().parse::<u64>().unwrap_or(0);
+    let name = fields.next()?.trim().to_string();
 
-    avg_usage < threshold
+    Some(GpuInfo {
+        name,
+        vram_free,
+        vram_total,
+        gpu_util,
+    })
 }
 
-fn get_gpu_info() -> (String, u64) {
-    if let Ok((name, vram)) = get_nvidia_info() {
-        return (name, vram);
+/// Chip name from system_profiler, and utilization from ioreg when it reports one
+/// (powermetrics would need root).
+#[cfg(target_os = "macos")]
+fn query_apple_gpu() -> Result<(String, Option<f32>)> {
+    let output = Command::new(monkey_troop_shared::get_secure_binary_path(
+        "system_profiler",
+    )?)
+    .args(["SPDisplaysDataType", "-json"])
+    .output()?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "system_profiler exited with {}",
+            output.status
+        ));
     }
+    let name = parse_display_name(&output.stdout)
+        .ok_or_else(|| anyhow::anyhow!("No GPU in system_profiler output"))?;
 
-    // Fallback
-    ("Unknown GPU".to_string(), 0)
+    let utilization = monkey_troop_shared::get_secure_binary_path("ioreg")
+        .and_then(|ioreg| {
+            Ok(Command::new(ioreg)
+                .args(["-r", "-d", "1", "-c", "IOAccelerator"])
+                .output()?)
+        })
+        .ok()
+        .and_then(|output| parse_device_utilization(&String::from_utf8_lossy(&output.stdout)));
+    Ok((name, utilization))
 }
 
-fn get_nvidia_info() -> Result<(String, u64)> {
-    // Get GPU name
-    let name_output = Command::new(monkey_troop_shared::get_secure_binary_path("nvidia-smi")?)
-        .args(["--query-gpu=name", "--format=csv,noheader"])
-        .output()?;
+/// Model of the first display adapter in `system_profiler SPDisplaysDataType -json` output.
+#[cfg(any(target_os = "macos", test))]
+fn parse_display_name(stdout: &[u8]) -> Option<String> {
+    let profile: serde_json::Value = serde_json::from_slice(stdout).ok()?;
+    let adapter = profile["SPDisplaysDataType"].get(0)?;
+    adapter["sppci_model"]
+        .as_str()
+        .or_else(|| adapter["_name"].as_str())
+        .map(str::to_string)
+}
 
-    let name = String::from_utf8_lossy(&name_output.stdout)
-        .trim()
-        .to_string();
+/// `"Device Utilization %"` from the accelerator's `PerformanceStatistics` in ioreg output.
+#[cfg(any(target_os = "macos", test))]
+fn parse_device_utilization(stdout: &str) -> Option<f32> {
+    let (_, rest) = stdout.split_once("\"Device Utilization %\"=")?;
+    let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok()
+}
 
-    // Get free VRAM in MB
-    let vram_output = Command::new(monkey_troop_shared::get_secure_binary_path("nvidia-smi")?)
-        .args(["--query-gpu=memory.free", "--format=csv,noheader,nounits"])
-        .output()?;
+#[cfg(target_os = "macos")]
+fn available_memory_mb() -> u64 {
+    let mut sys = System::new();
+    sys.refresh_memory();
+    sys.available_memory() / (1024 * 1024)
+}
+
+async fn cpu_utilization() -> f32 {
+    let mut sys = System::new_all();
+    sys.refresh_cpu_all();
 
-    let vram = String::from_utf8_lossy(&vram_output.stdout)
-        .trim()
-        .parse::<u64>()
-        .unwrap_or(0);
+    // Wait a bit for accurate CPU measurement
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    sys.refresh_cpu_all();
 
-    Ok((name, vram))
+    sys.cpus().iter().map(|cpu| cpu.cpu_usage()).sum::<f32>() / sys.cpus().len().max(1) as f32
 }
 
 #[cfg(test)]
@@ -111,17 +192,92 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_status() {
-        let monitor = NvidiaGpuMonitor;
+        let monitor = NvidiaGpuMonitor::default();
         let status = monitor.get_status().await.unwrap();
-        // Even without nvidia-smi, it should return "Unknown GPU"
+        // Even without nvidia-smi, it should return "Unknown GPU" and a CPU reading
         assert!(!status.gpu_name.is_empty());
+        assert!((0.0..=100.0).contains(&status.gpu_util));
     }
 
-    #[tokio::test]
-    async fn test_is_idle() {
-        let monitor = NvidiaGpuMonitor;
-        let idle = monitor.is_idle().await;
-        // Should fallback to CPU check if nvidia-smi fails
-        assert!(idle.is_ok());
+    #[test]
+    fn test_parse_nvidia_query() {
+        let status = parse_nvidia_query("NVIDIA GeForce RTX 4090, 23010, 24564, 37\n").unwrap();
+        assert_eq!(status.gpu_name, "NVIDIA GeForce RTX 4090");
+        assert_eq!(status.vram_free_mb, 23010);
+        assert_eq!(status.gpu_util, 37.0);
+        assert_eq!(status.gpus.len(), 1);
+        assert_eq!(status.gpus[0].vram_total, 24564);
+
+        let status = parse_nvidia_query("Tesla T4, Rev. B, 15000, 15360, 0").unwrap();
+        assert_eq!(status.gpu_name, "Tesla T4, Rev. B");
+
+        assert!(parse_nvidia_query("").is_none());
+        assert!(parse_nvidia_query("NVIDIA GeForce RTX 4090, 23010, 24564, [N/A]").is_none());
+    }
+
+    #[test]
+    fn test_parse_nvidia_query_aggregates_every_gpu() {
+        let stdout = "NVIDIA A100-SXM4-80GB, 81000, 81920, 0\n\
+                      NVIDIA A100-SXM4-80GB, 40000, 81920, 3\n\
+                      NVIDIA A100-SXM4-80GB, 1000, 81920, 97\n\
+                      NVIDIA A100-SXM4-80GB, 81000, 81920, 0\n";
+        let status = parse_nvidia_query(stdout).unwrap();
+        assert_eq!(status.gpu_name, "4x NVIDIA A100-SXM4-80GB");
+        assert_eq!(status.vram_free_mb, 203000);
+        assert_eq!(status.gpus.len(), 4);
+        assert_eq!(status.gpus[2].vram_free, 1000);
+        // One busy GPU makes the node busy
+        assert_eq!(status.gpu_util, 97.0);
+        assert!(!status.is_idle());
+
+        let info = status.info();
+        assert_eq!(info.gpu_count, Some(4));
+        assert_eq!(info.vram_total, Some(4 * 81920));
+
+        let mixed = parse_nvidia_query(
+            "NVIDIA GeForce RTX 4090, 20000, 24564, 1\nNVIDIA GeForce RTX 3090, 20000, 24576, 2\n",
+        )
+        .unwrap();
+        assert_eq!(
+            mixed.gpu_name,
+            "NVIDIA GeForce RTX 4090 + NVIDIA GeForce RTX 3090"
+        );
+        assert!(mixed.is_idle());
+
+        // A GPU that can't be read fails the whole query rather than being left out
+        assert!(
+            parse_nvidia_query("Tesla T4, 15000, 15360, 0\nTesla T4, 15000, 15360, [N/A]")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_parse_display_name() {
+        let profile = br#"{"SPDisplaysDataType": [{
+            "_name": "kHW_AppleM2MaxItem",
+            "sppci_cores": "38",
+            "sppci_model": "Apple M2 Max",
+            "spdisplays_mtlgpufamilysupport": "spdisplays_metal3"
+        }]}"#;
+        assert_eq!(parse_display_name(profile).unwrap(), "Apple M2 Max");
+
+        let profile = br#"{"SPDisplaysDataType": [{"_name": "Intel UHD Graphics 630"}]}"#;
+        assert_eq!(
+            parse_display_name(profile).unwrap(),
+            "Intel UHD Graphics 630"
+        );
+
+        assert!(parse_display_name(br#"{"SPDisplaysDataType": []}"#).is_none());
+        assert!(parse_display_name(b"not json").is_none());
+    }
+
+    #[test]
+    fn test_parse_device_utilization() {
+        let ioreg = r#"+-o AGXAcceleratorG14X  <class AGXAcceleratorG14X, id 0x1000003e3>
+    {
+      "PerformanceStatistics" = {"In use system memory"=1234567,"Device Utilization %"=42,"Renderer Utilization %"=40}
+    }"#;
+        assert_eq!(parse_device_utilization(ioreg), Some(42.0));
+        assert_eq!(parse_device_utilization("+-o IOAccelerator"), None);
     }
 }
@@ -3,27 +3,89 @@ mod domain;
 mod infrastructure;
 mod presentation;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use std::sync::atomic::AtomicU32;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
+use crate::application::hardware_verification::verify_hardware;
+use crate::application::heartbeat::run_heartbeat_loop;
+use crate::application::keep_alive::run_keep_alive_loop;
+use crate::application::key_refresh::{refresh_public_key, run_public_key_refresh_loop};
+use crate::application::model_refresh::run_model_refresh_loop;
+use crate::application::ports::HardwareMonitor;
 use crate::application::services::WorkerService;
 use crate::domain::models::ModelRegistry;
 use crate::infrastructure::config::Config;
 use crate::infrastructure::engines::ollama::OllamaEngine;
 use crate::infrastructure::system::auth::JwtVerifier;
+use crate::infrastructure::system::benchmark::{run_benchmark, run_benchmark_averaged};
 use crate::infrastructure::system::coordinator::HttpCoordinatorClient;
 use crate::infrastructure::system::e2e_crypto::X25519Decryptor;
 use crate::infrastructure::system::gpu::NvidiaGpuMonitor;
+use crate::presentation::api::concurrency::{default_max_concurrent_requests, ConcurrencyLimiter};
 use crate::presentation::api::proxy::{create_proxy_router, ProxyState};
+use crate::presentation::api::rate_limit::{run_eviction_loop, RateLimiter, EVICTION_INTERVAL};
+
+#[derive(Parser)]
+#[command(name = "monkey-troop-worker")]
+#[command(about = "Monkey Troop Worker - Share GPU compute with the troop", long_about = None)]
+struct Cli {
+    /// Runs the worker when omitted
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Run the hardware benchmark once, print the result as JSON and exit
+    Benchmark {
+        /// Size of the square matrices multiplied
+        #[arg(long, default_value_t = 4096, value_parser = clap::value_parser!(u32).range(1..))]
+        size: u32,
+        /// Seed for the matrices; random when omitted
+        #[arg(long)]
+        seed: Option<String>,
+    },
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
-    info!("Monkey Troop Worker (DDD Aligned) starting...");
+    let cli = Cli::parse();
+
+    match cli.command {
+        Some(Commands::Benchmark { size, seed }) => {
+            // stdout is reserved for the JSON result
+            tracing_subscriber::fmt()
+                .with_writer(std::io::stderr)
+                .init();
+            let config = load_config()?;
+            let seed = seed.unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string());
+            let result = run_benchmark(&seed, size as usize, config.benchmark_timeout).await?;
+            println!("{}", serde_json::to_string_pretty(&result)?);
+            Ok(())
+        }
+        None => {
+            tracing_subscriber::fmt::init();
+            run_worker().await
+        }
+    }
+}
 
+/// Load the worker configuration from the environment and reject unusable values.
+fn load_config() -> Result<Config> {
     let config = Config::from_env()?;
+    config.validate().context("Invalid worker configuration")?;
+    Ok(config)
+}
+
+/// Register with the coordinator and serve inference until a core task stops.
+async fn run_worker() -> Result<()> {
+    info!("Monkey Troop Worker (DDD Aligned) starting...");
+
+    let config = load_config()?;
 
     // Core state
     let registry = Arc::new(RwLock::new(ModelRegistry::new()));
@@ -37,12 +99,39 @@ async fn main() -> Result<()> {
         crate::domain::models::EngineType::Ollama,
         Box::new(OllamaEngine::new()),
     );
-    let monitor = Arc::new(NvidiaGpuMonitor);
+    let monitor = Arc::new(NvidiaGpuMonitor::default());
+    let max_concurrent_requests = match config.max_concurrent_requests {
+        Some(limit) => limit,
+        None => {
+            let hardware = monitor.get_status().await?;
+            let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+            default_max_concurrent_requests(&hardware, cpus)
+        }
+    };
     let coordinator = Arc::new(HttpCoordinatorClient::new(config.coordinator_url.clone()));
 
-    // Fetch public key from coordinator for JWT verification (Simulated for MVP, should be fetch logic)
-    let public_key = "---PUBLIC KEY---".to_string();
-    let verifier = Arc::new(JwtVerifier { public_key });
+    // Fetch the coordinator's public key for JWT verification, then keep it fresh.
+    // A shared HS256 secret has nothing to fetch, so the refresh task just idles.
+    let (verifier, key_refresh_handle) = match &config.jwt_secret {
+        Some(secret) => {
+            info!("Verifying HS256 tickets with JWT_SECRET");
+            let verifier = Arc::new(JwtVerifier::with_secret(
+                config.jwt_audiences.clone(),
+                secret.as_bytes(),
+            ));
+            (verifier, tokio::spawn(std::future::pending::<()>()))
+        }
+        None => {
+            let verifier = Arc::new(JwtVerifier::new(config.jwt_audiences.clone()));
+            refresh_public_key(coordinator.as_ref(), verifier.as_ref()).await?;
+            let handle = tokio::spawn(run_public_key_refresh_loop(
+                coordinator.clone(),
+                verifier.clone(),
+                std::time::Duration::from_secs(config.public_key_refresh_interval),
+            ));
+            (verifier, handle)
+        }
+    };
 
     // E2E encryption keypair
     let e2e_decryptor = Arc::new(X25519Decryptor::new());
@@ -54,34 +143,84 @@ async fn main() -> Result<()> {
         registry.clone(),
         engines,
         monitor,
-        coordinator,
+        coordinator.clone(),
         verifier,
         e2e_decryptor,
     ));
-    // 1. Initial registry refresh
-    service.refresh_model_registry().await?;
+    // 1. Initial registry refresh, then keep it current. A node whose engines offer no
+    // model yet still starts, reporting itself Offline until they do
+    if let Err(e) = service.refresh_model_registry().await {
+        warn!("Starting without models: {}", e);
+    }
+    tokio::spawn(run_model_refresh_loop(
+        service.clone(),
+        std::time::Duration::from_secs(config.model_refresh_interval),
+    ));
 
-    // 2. Initial hardware benchmark (log performance)
-    if let Err(e) = service.run_initial_benchmark().await {
-        error!("Initial hardware benchmark failed (non-fatal): {}", e);
+    // Keep the chosen models loaded, so their first request is not a cold start
+    if !config.keep_alive_models.is_empty() {
+        info!(
+            "Keeping {} warm every {}s",
+            config.keep_alive_models.join(", "),
+            config.keep_alive_interval
+        );
+        tokio::spawn(run_keep_alive_loop(
+            service.clone(),
+            config.keep_alive_models.clone(),
+            std::time::Duration::from_secs(config.keep_alive_interval),
+        ));
     }
 
-    // 3. Start heartbeat loop
-    let service_heartbeat = service.clone();
-    let heartbeat_handle = tokio::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(10));
-        loop {
-            interval.tick().await;
-            if let Err(e) = service_heartbeat.send_heartbeat().await {
-                error!("Heartbeat failed: {}", e);
+    // 2. Start heartbeat loop
+    // The proxy's in-flight counter is shared with the heartbeat to report load
+    let in_flight = Arc::new(AtomicU32::new(0));
+    let heartbeat_handle = tokio::spawn(run_heartbeat_loop(
+        service.clone(),
+        std::time::Duration::from_secs(10),
+        in_flight.clone(),
+        u32::try_from(max_concurrent_requests).unwrap_or(u32::MAX),
+    ));
+
+    // 3. Prove the hardware to the coordinator for a credit multiplier. Runs in the
+    // background, after the first heartbeat has registered the node, as the benchmark
+    // can take minutes
+    if config.run_initial_benchmark {
+        let coordinator = coordinator.clone();
+        let node_id = config.node_id.clone();
+        let (runs, timeout) = (config.benchmark_runs, config.benchmark_timeout);
+        tokio::spawn(async move {
+            let benchmark = |seed: String, matrix_size| async move {
+                run_benchmark_averaged(&seed, matrix_size, runs, timeout).await
+            };
+            match verify_hardware(coordinator.as_ref(), &node_id, benchmark).await {
+                Ok(verified) => info!(
+                    "✓ Hardware verified: {} tier, multiplier {:.2}",
+                    verified.tier, verified.assigned_multiplier
+                ),
+                Err(e) => error!("Hardware verification failed (non-fatal): {}", e),
             }
-        }
-    });
+        });
+    }
 
-    // 3. Start Proxy API (Presentation Layer)
-    let proxy_state = Arc::new(ProxyState {
-        service: service.clone(),
-    });
+    // 4. Start Proxy API (Presentation Layer)
+    let mut proxy_state = ProxyState::new(
+        service.clone(),
+        ConcurrencyLimiter::new(max_concurrent_requests, in_flight),
+    );
+    info!(
+        "Serving up to {} concurrent requests",
+        proxy_state.limiter.limit()
+    );
+    if let Some(per_minute) = config.requests_per_minute {
+        let rate_limiter = Arc::new(RateLimiter::new(per_minute));
+        tokio::spawn(run_eviction_loop(rate_limiter.clone(), EVICTION_INTERVAL));
+        proxy_state = proxy_state.with_rate_limiter(rate_limiter);
+        info!(
+            "Limiting each requester to {} requests per minute",
+            per_minute
+        );
+    }
+    let proxy_state = Arc::new(proxy_state);
     let app = create_proxy_router(proxy_state);
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8001").await?;
     info!("Proxy API listening on :8001");
@@ -92,6 +231,9 @@ async fn main() -> Result<()> {
         res = heartbeat_handle => {
             error!("Heartbeat task ended: {:?}", res);
         }
+        res = key_refresh_handle => {
+            error!("Public key refresh task ended: {:?}", res);
+        }
         res = proxy_handle => {
             error!("Proxy task ended: {:?}", res);
             if let Ok(Err(e)) = res {
@@ -0,0 +1,222 @@
+//! In-flight request limit for the worker proxy.
+//!
+//! A permit is taken before a request is forwarded to the engine and held until the
+//! response body (including a whole SSE stream) has been sent. Requests arriving while
+//! every permit is taken get a 503 with `Retry-After`, so the client fails over to
+//! another node instead of queueing here.
+
+use crate::domain::models::HardwareStatus;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use http_body::{Frame, SizeHint};
+use monkey_troop_shared::ApiErrorBody;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+/// Seconds a rejected caller is asked to wait before retrying this node.
+const BUSY_RETRY_AFTER_SECS: u64 = 1;
+
+/// VRAM budgeted per concurrent request when picking a default limit.
+const VRAM_MB_PER_REQUEST: u64 = 8192;
+
+/// CPU cores budgeted per concurrent request on nodes without a GPU.
+const CPUS_PER_REQUEST: usize = 4;
+
+pub struct ConcurrencyLimiter {
+    semaphore: Arc<Semaphore>,
+    limit: usize,
+    in_flight: Arc<AtomicU32>,
+}
+
+impl ConcurrencyLimiter {
+    /// `in_flight` is kept up to date with the number of requests holding a permit, so
+    /// it can be shared with the heartbeat.
+    pub fn new(limit: usize, in_flight: Arc<AtomicU32>) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            limit,
+            in_flight,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Requests currently holding a permit.
+    pub fn in_flight(&self) -> u32 {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    fn try_acquire(&self) -> Option<Permit> {
+        let permit = self.semaphore.clone().try_acquire_owned().ok()?;
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        Some(Permit {
+            _permit: permit,
+            in_flight: self.in_flight.clone(),
+        })
+    }
+}
+
+struct Permit {
+    _permit: OwnedSemaphorePermit,
+    in_flight: Arc<AtomicU32>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Limit used when `MAX_CONCURRENT_REQUESTS` is unset: one request per
+/// `VRAM_MB_PER_REQUEST` of free VRAM, or per `CPUS_PER_REQUEST` cores without a GPU.
+pub fn default_max_concurrent_requests(hardware: &HardwareStatus, cpus: usize) -> usize {
+    let slots = if hardware.vram_free_mb > 0 {
+        usize::try_from(hardware.vram_free_mb / VRAM_MB_PER_REQUEST).unwrap_or(usize::MAX)
+    } else {
+        cpus / CPUS_PER_REQUEST
+    };
+    slots.max(1)
+}
+
+/// Middleware enforcing the limit on every proxied route.
+pub async fn limit_concurrency(
+    State(limiter): State<Arc<ConcurrencyLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(permit) = limiter.try_acquire() else {
+        warn!(
+            "Rejecting request, {}/{} requests already in flight",
+            limiter.in_flight(),
+            limiter.limit()
+        );
+        return busy_response();
+    };
+
+    let response = next.run(request).await;
+    response.map(|body| {
+        Body::new(PermitBody {
+            inner: body,
+            _permit: permit,
+        })
+    })
+}
+
+fn busy_response() -> Response {
+    let body = ApiErrorBody::for_status(
+        StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+        "Worker is at its concurrent request limit",
+    );
+    let mut response = (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response();
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(BUSY_RETRY_AFTER_SECS));
+    response
+}
+
+/// Response body that releases its permit once it has been sent or dropped.
+struct PermitBody {
+    inner: Body,
+    _permit: Permit,
+}
+
+impl http_body::Body for PermitBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+    use tower::ServiceExt;
+
+    fn hardware(vram_free_mb: u64) -> HardwareStatus {
+        HardwareStatus {
+            gpu_name: "test".to_string(),
+            vram_free_mb,
+            gpu_util: 0.0,
+            gpus: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_default_limit_scales_with_vram_or_cpus() {
+        assert_eq!(default_max_concurrent_requests(&hardware(24576), 16), 3);
+        assert_eq!(default_max_concurrent_requests(&hardware(4096), 16), 1);
+        assert_eq!(default_max_concurrent_requests(&hardware(0), 16), 4);
+        assert_eq!(default_max_concurrent_requests(&hardware(0), 2), 1);
+    }
+
+    #[tokio::test]
+    async fn test_permit_held_until_body_is_sent() {
+        let limiter = ConcurrencyLimiter::new(1, Arc::default());
+        let body = Body::new(PermitBody {
+            inner: Body::from("hello"),
+            _permit: limiter.try_acquire().unwrap(),
+        });
+        assert_eq!(limiter.in_flight(), 1);
+        assert!(limiter.try_acquire().is_none());
+
+        axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(limiter.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_requests_over_the_limit_get_retry_after() {
+        let limiter = Arc::new(ConcurrencyLimiter::new(1, Arc::default()));
+        let app = Router::new()
+            .route("/v1/chat/completions", post(|| async { "ok" }))
+            .route_layer(axum::middleware::from_fn_with_state(
+                limiter.clone(),
+                limit_concurrency,
+            ));
+        let request = || {
+            Request::post("/v1/chat/completions")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let held = limiter.try_acquire().unwrap();
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "1");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["type"], "service_unavailable");
+
+        drop(held);
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
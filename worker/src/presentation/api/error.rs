@@ -0,0 +1,77 @@
+//! Errors the worker proxy answers with.
+//!
+//! Every failure is sent with its HTTP status and an OpenAI-style body,
+//! `{"error": {"message", "type", "code"}}`, so callers and SDKs can tell an unknown model
+//! from a bad ticket without parsing free text.
+
+use axum::{
+    http::{header::RETRY_AFTER, HeaderValue},
+    response::{IntoResponse, Response},
+    Json,
+};
+use monkey_troop_shared::TroopError;
+
+#[derive(Debug)]
+pub struct ApiError(pub TroopError);
+
+impl From<TroopError> for ApiError {
+    fn from(error: TroopError) -> Self {
+        ApiError(error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut response = (self.0.status_code(), Json(self.0.api_error_body())).into_response();
+        if let Some(delay) = self.0.retry_after() {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(delay.as_secs()));
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use monkey_troop_shared::ApiErrorBody;
+
+    async fn body_of(response: Response) -> ApiErrorBody {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_insufficient_credits_is_a_402_saying_what_is_missing() {
+        let response = ApiError(TroopError::InsufficientCredits {
+            required: 120,
+            available: 30,
+        })
+        .into_response();
+        assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+
+        let body = body_of(response).await;
+        assert_eq!(body.error.kind, "insufficient_quota");
+        assert_eq!(body.error.code, "insufficient_credits");
+        assert_eq!(
+            body.error.message,
+            "Insufficient credits: need 120, have 30"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_carries_retry_after() {
+        let response = ApiError(TroopError::RateLimited {
+            retry_after: Some(std::time::Duration::from_secs(7)),
+            message: None,
+        })
+        .into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "7");
+        assert_eq!(body_of(response).await.error.code, "rate_limited");
+    }
+}
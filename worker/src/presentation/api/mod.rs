@@ -1 +1,4 @@
+pub mod concurrency;
+pub mod error;
 pub mod proxy;
+pub mod rate_limit;
@@ -1,107 +1,476 @@
 use crate::application::services::WorkerService;
-use crate::domain::inference::InferenceRequest;
+use crate::domain::inference::{EngineHttpError, InferenceRequest, StreamingChunk, TokenUsage};
+use crate::domain::models::RegistrySnapshot;
+use crate::presentation::api::concurrency::{limit_concurrency, ConcurrencyLimiter};
+use crate::presentation::api::error::ApiError;
+use crate::presentation::api::rate_limit::{rate_limited_response, RateLimiter};
 use axum::{
     extract::{Json, State},
-    http::{HeaderMap, StatusCode},
+    http::{header::CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware,
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{get, post},
     Router,
 };
 use bytes::Bytes;
 use futures::StreamExt;
 use http_body::Frame;
 use http_body_util::StreamBody;
+use monkey_troop_shared::{EmbeddingsRequest, TroopError, DEADLINE_HEADER, REQUEST_ID_HEADER};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::future::Future;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
-use tracing::{error, info};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{error, info, info_span, warn, Instrument};
+
+/// Longest caller-supplied request ID reused; longer ones are replaced with a fresh one.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Slack allowed past a request's deadline, for clock skew between client and worker.
+const DEADLINE_GRACE: Duration = Duration::from_secs(2);
 
 pub struct ProxyState {
     pub service: Arc<WorkerService>,
+    pub limiter: Arc<ConcurrencyLimiter>,
+    /// Per-requester rate limit; unlimited when `None`
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+impl ProxyState {
+    pub fn new(service: Arc<WorkerService>, limiter: ConcurrencyLimiter) -> Self {
+        Self {
+            service,
+            limiter: Arc::new(limiter),
+            rate_limiter: None,
+        }
+    }
+
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Count a request against `requester`'s rate, returning the 429 to send when it is
+    /// over it.
+    fn check_rate(&self, requester: &str) -> Option<Response> {
+        let limiter = self.rate_limiter.as_ref()?;
+        let retry_after = limiter.acquire(requester, Instant::now()).err()?;
+        warn!(
+            "Rejecting request from {}, over {} requests per minute",
+            requester,
+            limiter.per_minute()
+        );
+        Some(rate_limited_response(limiter.per_minute(), retry_after))
+    }
 }
 
 pub fn create_proxy_router(state: Arc<ProxyState>) -> Router {
     Router::new()
         .route("/v1/chat/completions", post(handle_chat_completion))
+        .route("/v1/embeddings", post(handle_embeddings))
+        .route_layer(middleware::from_fn_with_state(
+            state.limiter.clone(),
+            limit_concurrency,
+        ))
+        // Outside the concurrency limit so a busy node still answers. Inference routes
+        // verify the JWT ticket in their handlers, so this route needs none.
+        .route("/health", get(handle_health))
+        .route("/debug/registry", get(handle_debug_registry))
         .with_state(state)
 }
 
+/// Unauthenticated liveness check, used by clients to measure round-trip latency.
+async fn handle_health(State(state): State<Arc<ProxyState>>) -> Json<Value> {
+    let models = state.service.registry.read().await.models.len();
+    Json(serde_json::json!({
+        "status": "healthy",
+        "node_id": state.service.node_id,
+        "models": models,
+        "in_flight": state.limiter.in_flight(),
+        "max_concurrent": state.limiter.limit(),
+    }))
+}
+
+/// The models and engines this worker registered, for debugging routing. Requires a
+/// valid ticket, like the inference routes.
+async fn handle_debug_registry(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> Result<Json<RegistrySnapshot>, ApiError> {
+    verify_bearer_ticket(&state, &headers).await?;
+    Ok(Json(state.service.registry.read().await.snapshot()))
+}
+
+/// The caller's request ID when it is a sane token, otherwise a fresh UUID, so requests
+/// arriving without one can still be followed through the logs.
+fn request_id_from(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Return the request ID so responses can be correlated end to end.
+fn with_request_id(mut response: Response, request_id: &str) -> Response {
+    if let Ok(value) = HeaderValue::from_str(request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
 async fn handle_chat_completion(
     State(state): State<Arc<ProxyState>>,
     headers: HeaderMap,
     Json(raw): Json<Value>,
-) -> Result<Response, StatusCode> {
-    // 1. Authentication (JWT verification via Header)
+) -> Result<Response, ApiError> {
+    let request_id = request_id_from(&headers);
+    let span = info_span!("chat_completion", request_id = %request_id);
+
+    let work = process_chat_completion(&state, &headers, raw, &request_id);
+    let response = within_deadline(&headers, work).instrument(span).await?;
+    Ok(with_request_id(response, &request_id))
+}
+
+async fn handle_embeddings(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(raw): Json<Value>,
+) -> Result<Response, ApiError> {
+    let request_id = request_id_from(&headers);
+    let span = info_span!("embeddings", request_id = %request_id);
+
+    let work = process_embeddings(&state, &headers, raw, &request_id);
+    let response = within_deadline(&headers, work).instrument(span).await?;
+    Ok(with_request_id(response, &request_id))
+}
+
+/// Time left before the request's `X-Troop-Deadline` (unix milliseconds) plus
+/// `DEADLINE_GRACE`, or `None` when it carries no usable deadline.
+fn time_remaining(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let millis: u64 = headers
+        .get(DEADLINE_HEADER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    let deadline = UNIX_EPOCH + Duration::from_millis(millis) + DEADLINE_GRACE;
+    Some(deadline.duration_since(now).unwrap_or_default())
+}
+
+/// Run `work` within the request's deadline, if it has one. By then the client proxy has
+/// given up, so an expired request is answered 504 without being started, and one still
+/// waiting on the engine is cancelled. A stream that has started is left to the client,
+/// which drops the connection at the same deadline.
+async fn within_deadline(
+    headers: &HeaderMap,
+    work: impl Future<Output = Result<Response, ApiError>>,
+) -> Result<Response, ApiError> {
+    let expired = || TroopError::Timeout("the request's deadline passed".to_string()).into();
+    match time_remaining(headers, SystemTime::now()) {
+        None => work.await,
+        Some(remaining) if remaining.is_zero() => {
+            warn!("Request deadline passed before it arrived, rejecting");
+            Err(expired())
+        }
+        Some(remaining) => tokio::time::timeout(remaining, work)
+            .await
+            .unwrap_or_else(|_| {
+                warn!("Request deadline passed, cancelling the engine call");
+                Err(expired())
+            }),
+    }
+}
+
+// Standard HTTP hop-by-hop headers that must not be forwarded by a proxy (RFC 7230).
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "transfer-encoding",
+    "te",
+    "trailer",
+    "upgrade",
+    "proxy-authorization",
+    "proxy-authenticate",
+];
+
+/// Append upstream headers to `response`, skipping hop-by-hop headers and any in `skip`.
+fn append_upstream_headers(response: &mut Response, upstream: &[(String, String)], skip: &[&str]) {
+    for (name, value) in upstream {
+        let lower = name.to_ascii_lowercase();
+        if HOP_BY_HOP.contains(&lower.as_str()) || skip.contains(&lower.as_str()) {
+            continue;
+        }
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            response.headers_mut().append(name, value);
+        }
+    }
+}
+
+/// Relay engine headers on a successful reply. The body has been re-encoded by the
+/// proxy, so the upstream entity headers no longer apply and are dropped.
+fn with_upstream_headers(mut response: Response, upstream: &[(String, String)]) -> Response {
+    append_upstream_headers(&mut response, upstream, &["content-type", "content-length"]);
+    response
+}
+
+/// Relay an engine failure with its original status, headers and body; anything that is
+/// not an upstream HTTP error becomes a 500.
+fn engine_error_response(e: anyhow::Error) -> Response {
+    match e.downcast::<EngineHttpError>() {
+        Ok(upstream) => {
+            let status = StatusCode::from_u16(upstream.status).unwrap_or(StatusCode::BAD_GATEWAY);
+            let mut response = (status, upstream.body).into_response();
+            response.headers_mut().remove(CONTENT_TYPE);
+            append_upstream_headers(&mut response, &upstream.headers, &["content-length"]);
+            response
+        }
+        Err(e) => {
+            error!("Engine request failed: {}", e);
+            ApiError(TroopError::InternalError(format!(
+                "Engine request failed: {e}"
+            )))
+            .into_response()
+        }
+    }
+}
+
+/// Check the `Authorization: Bearer` ticket was issued for this node, and return the
+/// requester it was issued to.
+async fn verify_bearer_ticket(state: &ProxyState, headers: &HeaderMap) -> Result<String, ApiError> {
     let auth_header = headers
         .get("Authorization")
         .and_then(|h| h.to_str().ok())
         .and_then(|s| s.strip_prefix("Bearer "))
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+        .ok_or_else(|| TroopError::AuthError("missing Bearer ticket".to_string()))?;
 
-    if !state
+    let requester = state
         .service
         .verify_ticket(auth_header)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+        .map_err(|e| TroopError::InternalError(format!("Could not verify the ticket: {e}")))?
+        .ok_or_else(|| {
+            TroopError::AuthError("ticket is invalid, expired or for another node".to_string())
+        })?;
+    Ok(requester)
+}
+
+/// A request that passed ticket verification, with its body decrypted.
+struct OpenedRequest {
+    body: Value,
+    /// Key to encrypt the reply with, when the request came in over E2E
+    session_key: Option<[u8; 32]>,
+    /// Who the ticket was issued to, for usage reports
+    requester: String,
+}
+
+/// Verify the ticket and, if the body is an E2E envelope, decrypt it.
+async fn authorize_and_open(
+    state: &ProxyState,
+    headers: &HeaderMap,
+    raw: Value,
+) -> Result<OpenedRequest, ApiError> {
+    // 1. Authentication (JWT verification via Header)
+    let requester = verify_bearer_ticket(state, headers).await?;
 
     // 2. Detect E2E encryption and decrypt if present
-    let (payload, session_key) = if let Some(e2e_value) = raw.get("e2e") {
+    if let Some(e2e_value) = raw.get("e2e") {
+        let invalid = |message: &str| TroopError::InvalidRequest(message.to_string());
         let envelope: monkey_troop_shared::EncryptedPayload =
-            serde_json::from_value(e2e_value.clone()).map_err(|_| StatusCode::BAD_REQUEST)?;
+            serde_json::from_value(e2e_value.clone())
+                .map_err(|_| invalid("malformed e2e envelope"))?;
 
         let client_pub = envelope
             .client_public_key
             .as_ref()
-            .ok_or(StatusCode::BAD_REQUEST)?;
+            .ok_or_else(|| invalid("e2e envelope has no client public key"))?;
 
         let key = state
             .service
             .derive_e2e_session_key(client_pub)
-            .map_err(|_| StatusCode::BAD_REQUEST)?;
+            .map_err(|_| invalid("unusable e2e client public key"))?;
 
         let plaintext = monkey_troop_shared::decrypt_payload(&key, &envelope)
-            .map_err(|_| StatusCode::BAD_REQUEST)?;
+            .map_err(|_| invalid("e2e payload could not be decrypted"))?;
+
+        let body: Value = serde_json::from_slice(&plaintext)
+            .map_err(|_| invalid("decrypted e2e payload is not JSON"))?;
+        Ok(OpenedRequest {
+            body,
+            session_key: Some(key),
+            requester,
+        })
+    } else {
+        Ok(OpenedRequest {
+            body: raw,
+            session_key: None,
+            requester,
+        })
+    }
+}
 
-        let req: InferenceRequest =
-            serde_json::from_slice(&plaintext).map_err(|_| StatusCode::BAD_REQUEST)?;
+/// Report a completion's token usage to the coordinator in the background, so billing
+/// never holds up or fails the reply. A failed report is only logged.
+fn report_usage(service: &Arc<WorkerService>, requester: &str, model: &str, usage: &TokenUsage) {
+    let service = service.clone();
+    let (requester, model, usage) = (requester.to_string(), model.to_string(), usage.clone());
+    tokio::spawn(async move {
+        if let Err(e) = service.report_usage(&requester, &model, &usage).await {
+            warn!("Failed to report token usage to the coordinator: {}", e);
+        }
+    });
+}
+
+/// The only fields the proxy routes on; the rest of the body is endpoint-specific
+/// and is not inspected until the request has been routed.
+#[derive(Debug, Deserialize)]
+struct ModelOnly {
+    #[serde(alias = "model_id")]
+    model: String,
+    #[serde(default)]
+    stream: bool,
+}
+
+fn extract_route(body: &Value) -> Result<ModelOnly, TroopError> {
+    ModelOnly::deserialize(body).map_err(|e| TroopError::InvalidRequest(e.to_string()))
+}
 
-        (req, Some(key))
+/// Resolve a requested model (by name or content hash) to its registry ID.
+async fn resolve_model(state: &ProxyState, requested: &str) -> Result<String, TroopError> {
+    let registry = state.service.registry.read().await;
+    let resolved_model = if requested.starts_with("sha256:") {
+        registry.find_by_hash(requested)
+    } else {
+        registry.find_by_name(requested)
+    };
+    resolved_model
+        .map(|m| m.id.clone())
+        .ok_or_else(|| TroopError::ModelNotFound(requested.to_string()))
+}
+
+/// Serialize a non-streaming reply, encrypting it when the request came in over E2E.
+fn json_response<T: Serialize>(
+    body: &T,
+    session_key: Option<[u8; 32]>,
+) -> Result<Response, TroopError> {
+    let internal = |e: &dyn std::fmt::Display| TroopError::InternalError(e.to_string());
+    if let Some(key) = session_key {
+        let body_json = serde_json::to_vec(body).map_err(|e| internal(&e))?;
+        let encrypted =
+            monkey_troop_shared::encrypt_payload(&key, &body_json).map_err(|e| internal(&e))?;
+        let envelope = monkey_troop_shared::E2EEnvelope { e2e: encrypted };
+        let value = serde_json::to_value(envelope).map_err(|e| internal(&e))?;
+        Ok(Json(value).into_response())
     } else {
-        let req: InferenceRequest =
-            serde_json::from_value(raw).map_err(|_| StatusCode::BAD_REQUEST)?;
-        (req, None)
+        let value = serde_json::to_value(body).map_err(|e| internal(&e))?;
+        Ok(Json(value).into_response())
+    }
+}
+
+async fn process_embeddings(
+    state: &ProxyState,
+    headers: &HeaderMap,
+    raw: Value,
+    request_id: &str,
+) -> Result<Response, ApiError> {
+    let OpenedRequest {
+        body,
+        session_key,
+        requester,
+    } = authorize_and_open(state, headers, raw).await?;
+    if let Some(rejection) = state.check_rate(&requester) {
+        return Ok(rejection);
+    }
+    let route = extract_route(&body)?;
+
+    info!(
+        "Authorized embeddings request for model {} on node {}",
+        route.model, state.service.node_id
+    );
+    let resolved_model_id = resolve_model(state, &route.model).await?;
+    let payload: EmbeddingsRequest =
+        serde_json::from_value(body).map_err(|e| TroopError::InvalidRequest(e.to_string()))?;
+
+    let reply = match state
+        .service
+        .embed(
+            &resolved_model_id,
+            payload.input.into_vec(),
+            Some(request_id),
+        )
+        .await
+    {
+        Ok(reply) => reply,
+        Err(e) => return Ok(engine_error_response(e)),
     };
 
+    let response = json_response(&reply.body, session_key)?;
+    Ok(with_upstream_headers(response, &reply.headers))
+}
+
+async fn process_chat_completion(
+    state: &ProxyState,
+    headers: &HeaderMap,
+    raw: Value,
+    request_id: &str,
+) -> Result<Response, ApiError> {
+    let OpenedRequest {
+        body,
+        session_key,
+        requester,
+    } = authorize_and_open(state, headers, raw).await?;
+    if let Some(rejection) = state.check_rate(&requester) {
+        return Ok(rejection);
+    }
+    let route = extract_route(&body)?;
+
     // 3. Business Logic: Delegate to Application Service
     info!(
         "Authorized inference request for model {} on node {}",
-        payload.model_id, state.service.node_id
+        route.model, state.service.node_id
     );
 
     // Verify model exists in registry (supports lookup by name or content hash)
-    let registry = state.service.registry.read().await;
-    let resolved_model = if payload.model_id.starts_with("sha256:") {
-        registry.find_by_hash(&payload.model_id)
-    } else {
-        registry.find_by_name(&payload.model_id)
-    };
-    let resolved_model_id = match resolved_model {
-        Some(m) => m.id.clone(),
-        None => return Err(StatusCode::NOT_FOUND),
-    };
-    // Explicitly drop the read lock before proceeding to response construction.
-    drop(registry);
+    let resolved_model_id = resolve_model(state, &route.model).await?;
+    let payload: InferenceRequest =
+        serde_json::from_value(body).map_err(|e| TroopError::InvalidRequest(e.to_string()))?;
+    let tools = payload.offered_tools();
 
     // 4. Routing: Select engine and forward
-    if payload.stream {
-        let chunk_stream = state
+    if route.stream {
+        let reply = match state
             .service
-            .chat_stream(&resolved_model_id, payload.messages)
+            .chat_stream(
+                &resolved_model_id,
+                payload.messages,
+                tools,
+                &payload.sampling,
+                Some(request_id),
+            )
             .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        {
+            Ok(reply) => reply,
+            Err(e) => return Ok(engine_error_response(e)),
+        };
+        let upstream_headers = reply.headers;
+        // The engine's token counts arrive on the final chunk, if it sends them
+        let (service, model) = (state.service.clone(), resolved_model_id.clone());
+        let chunk_stream = reply.body.inspect(move |chunk| {
+            if let Ok(StreamingChunk {
+                usage: Some(usage), ..
+            }) = chunk
+            {
+                report_usage(&service, &requester, &model, usage);
+            }
+        });
 
         let response_body = if let Some(key) = session_key {
             let base_nonce = monkey_troop_shared::generate_base_nonce();
@@ -197,57 +566,62 @@ async fn handle_chat_completion(
             axum::body::Body::new(StreamBody::new(full_stream))
         };
 
-        return Response::builder()
+        let response = Response::builder()
             .header("Content-Type", "text/event-stream")
             .header("Cache-Control", "no-cache")
             .header("Connection", "keep-alive")
             .body(response_body)
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+            .map_err(|e| TroopError::InternalError(e.to_string()))?;
+        return Ok(with_upstream_headers(response, &upstream_headers));
     }
 
-    let response = state
+    let reply = match state
         .service
-        .chat(&resolved_model_id, payload.messages)
+        .chat(
+            &resolved_model_id,
+            payload.messages,
+            tools,
+            &payload.sampling,
+            Some(request_id),
+        )
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let response_json =
-        serde_json::to_vec(&response).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    {
+        Ok(reply) => reply,
+        Err(e) => return Ok(engine_error_response(e)),
+    };
+    report_usage(
+        &state.service,
+        &requester,
+        &resolved_model_id,
+        &reply.body.usage,
+    );
 
-    if let Some(key) = session_key {
-        let encrypted = monkey_troop_shared::encrypt_payload(&key, &response_json)
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        let envelope = monkey_troop_shared::E2EEnvelope { e2e: encrypted };
-        let value =
-            serde_json::to_value(envelope).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        Ok(Json(value).into_response())
-    } else {
-        let value =
-            serde_json::to_value(response).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        Ok(Json(value).into_response())
-    }
+    let response = json_response(&reply.body, session_key)?;
+    Ok(with_upstream_headers(response, &reply.headers))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::application::ports::{
-        AuthTokenVerifier, CoordinatorClient, E2EDecryptor, HardwareMonitor, InferenceEngine,
+        AuthTokenVerifier, ChunkStream, CoordinatorClient, E2EDecryptor, HardwareMonitor,
+        InferenceEngine,
     };
     use crate::domain::inference::{
-        ChatMessage, ChatMessageDelta, InferenceChoice, InferenceResponse, StreamingChoice,
-        StreamingChunk, TokenUsage,
+        ChatMessage, ChatMessageDelta, EmbeddingData, EmbeddingUsage, EmbeddingsResponse,
+        EngineReply, InferenceChoice, InferenceResponse, SamplingOptions, StreamingChoice,
+        StreamingChunk, TokenUsage, Tool,
+    };
+    use crate::domain::models::{
+        EngineType, HardwareStatus, HeartbeatReport, Model, ModelRegistry,
     };
-    use crate::domain::models::{EngineType, HardwareStatus, Model, ModelRegistry};
     use anyhow::Result;
     use async_trait::async_trait;
     use axum::body::Body;
     use axum::http::{Request, StatusCode};
-    use futures::Stream;
-    use monkey_troop_shared::ModelIdentity;
+    use monkey_troop_shared::{EngineInfo, UsageReport};
     use serde_json::json;
     use std::collections::HashMap;
-    use std::pin::Pin;
     use tokio::sync::RwLock;
     use tower::ServiceExt;
 
@@ -257,39 +631,80 @@ mod tests {
         async fn get_models(&self) -> Result<Vec<Model>> {
             Ok(vec![])
         }
-        async fn is_healthy(&self) -> bool {
-            true
+        async fn get_info(&self) -> EngineInfo {
+            EngineInfo {
+                engine_type: "ollama".to_string(),
+                version: "test".to_string(),
+                port: 0,
+                healthy: true,
+            }
+        }
+        async fn warm_up(&self, _model: &str) -> Result<()> {
+            Ok(())
         }
         async fn chat(
             &self,
             model: &str,
             _messages: Vec<ChatMessage>,
-        ) -> Result<InferenceResponse> {
-            Ok(InferenceResponse {
-                id: "chatcmpl-123".to_string(),
-                object: "chat.completion".to_string(),
-                created: 1677652288,
-                model: model.to_string(),
-                choices: vec![InferenceChoice {
-                    index: 0,
-                    message: ChatMessage {
-                        role: "assistant".to_string(),
-                        content: "Hello from engine!".to_string(),
+            _tools: Option<Vec<Tool>>,
+            _sampling: &SamplingOptions,
+            request_id: Option<&str>,
+        ) -> Result<EngineReply<InferenceResponse>> {
+            if model == "slow" {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+            if model == "overloaded" {
+                return Err(EngineHttpError {
+                    status: 429,
+                    headers: vec![
+                        ("content-type".to_string(), "application/json".to_string()),
+                        ("retry-after".to_string(), "7".to_string()),
+                        ("connection".to_string(), "close".to_string()),
+                    ],
+                    body: r#"{"error":"busy"}"#.to_string(),
+                }
+                .into());
+            }
+            Ok(EngineReply {
+                body: InferenceResponse {
+                    id: request_id.unwrap_or("chatcmpl-123").to_string(),
+                    object: "chat.completion".to_string(),
+                    created: 1677652288,
+                    model: model.to_string(),
+                    choices: vec![InferenceChoice {
+                        index: 0,
+                        message: ChatMessage {
+                            role: "assistant".to_string(),
+                            content: Some("Hello from engine!".into()),
+                            tool_calls: None,
+                            tool_call_id: None,
+                        },
+                        finish_reason: "stop".to_string(),
+                    }],
+                    usage: TokenUsage {
+                        prompt_tokens: 9,
+                        completion_tokens: 12,
+                        total_tokens: 21,
                     },
-                    finish_reason: "stop".to_string(),
-                }],
-                usage: TokenUsage {
-                    prompt_tokens: 9,
-                    completion_tokens: 12,
-                    total_tokens: 21,
                 },
+                headers: vec![
+                    ("x-ratelimit-remaining".to_string(), "42".to_string()),
+                    (
+                        "content-type".to_string(),
+                        "application/x-ndjson".to_string(),
+                    ),
+                    ("transfer-encoding".to_string(), "chunked".to_string()),
+                ],
             })
         }
         async fn chat_stream(
             &self,
             model: &str,
             _messages: Vec<ChatMessage>,
-        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamingChunk>> + Send>>> {
+            _tools: Option<Vec<Tool>>,
+            _sampling: &SamplingOptions,
+            _request_id: Option<&str>,
+        ) -> Result<EngineReply<ChunkStream>> {
             let chunk = StreamingChunk {
                 id: "chatcmpl-123".to_string(),
                 object: "chat.completion.chunk".to_string(),
@@ -300,11 +715,45 @@ mod tests {
                     delta: ChatMessageDelta {
                         role: Some("assistant".to_string()),
                         content: Some("Hello".to_string()),
+                        tool_calls: None,
                     },
                     finish_reason: Some("stop".to_string()),
                 }],
+                usage: Some(TokenUsage {
+                    prompt_tokens: 4,
+                    completion_tokens: 1,
+                    total_tokens: 5,
+                }),
             };
-            Ok(Box::pin(futures::stream::iter(vec![Ok(chunk)])))
+            Ok(EngineReply {
+                body: Box::pin(futures::stream::iter(vec![Ok(chunk)])),
+                headers: vec![],
+            })
+        }
+        async fn embed(
+            &self,
+            model: &str,
+            input: Vec<String>,
+            _request_id: Option<&str>,
+        ) -> Result<EngineReply<EmbeddingsResponse>> {
+            Ok(EngineReply {
+                body: EmbeddingsResponse {
+                    object: "list".to_string(),
+                    data: (0..input.len())
+                        .map(|index| EmbeddingData {
+                            object: "embedding".to_string(),
+                            index,
+                            embedding: vec![0.5, 0.25],
+                        })
+                        .collect(),
+                    model: model.to_string(),
+                    usage: EmbeddingUsage {
+                        prompt_tokens: 4,
+                        total_tokens: 4,
+                    },
+                },
+                headers: vec![],
+            })
         }
     }
 
@@ -315,25 +764,24 @@ mod tests {
             Ok(HardwareStatus {
                 gpu_name: "test".to_string(),
                 vram_free_mb: 0,
+                gpu_util: 0.0,
+                gpus: Vec::new(),
             })
         }
-        async fn is_idle(&self) -> Result<bool> {
-            Ok(true)
-        }
     }
 
-    struct MockCoordinator;
+    /// Records usage reports
+    #[derive(Default)]
+    struct MockCoordinator {
+        usage: std::sync::Mutex<Vec<UsageReport>>,
+    }
     #[async_trait]
     impl CoordinatorClient for MockCoordinator {
-        async fn send_heartbeat(
-            &self,
-            _: &str,
-            _: crate::domain::models::NodeStatus,
-            _: Vec<ModelIdentity>,
-            _: HardwareStatus,
-            _: Vec<String>,
-            _: Option<String>,
-        ) -> Result<()> {
+        async fn send_heartbeat(&self, _: HeartbeatReport) -> Result<()> {
+            Ok(())
+        }
+        async fn report_usage(&self, report: &UsageReport) -> Result<()> {
+            self.usage.lock().unwrap().push(report.clone());
             Ok(())
         }
     }
@@ -343,8 +791,11 @@ mod tests {
     }
     #[async_trait]
     impl AuthTokenVerifier for MockVerifier {
-        async fn verify_ticket(&self, _: &str, _: &str) -> Result<bool> {
-            Ok(self.valid)
+        async fn verify_ticket(&self, _: &str, _: &str) -> Result<Option<String>> {
+            Ok(self.valid.then(|| "requester-1".to_string()))
+        }
+        async fn rotate_key(&self, _: String) -> Result<()> {
+            Ok(())
         }
     }
 
@@ -358,7 +809,19 @@ mod tests {
         }
     }
 
+    fn test_limiter() -> ConcurrencyLimiter {
+        ConcurrencyLimiter::new(8, Arc::default())
+    }
+
     fn make_service(valid_auth: bool, models: Vec<Model>) -> Arc<WorkerService> {
+        make_service_reporting_to(valid_auth, models, Arc::default())
+    }
+
+    fn make_service_reporting_to(
+        valid_auth: bool,
+        models: Vec<Model>,
+        coordinator: Arc<MockCoordinator>,
+    ) -> Arc<WorkerService> {
         let registry = Arc::new(RwLock::new(ModelRegistry::new()));
         let mut reg = registry.try_write().unwrap();
         for m in models {
@@ -374,12 +837,34 @@ mod tests {
             registry,
             engines,
             Arc::new(MockMonitor),
-            Arc::new(MockCoordinator),
+            coordinator,
             Arc::new(MockVerifier { valid: valid_auth }),
             Arc::new(MockE2EDecryptor),
         ))
     }
 
+    #[test]
+    fn test_extract_route_from_completions_body() {
+        let body = json!({
+            "model": "llama3",
+            "prompt": "Once upon a time",
+            "max_tokens": 16,
+            "stream": true
+        });
+        let route = extract_route(&body).unwrap();
+        assert_eq!(route.model, "llama3");
+        assert!(route.stream);
+
+        let route = extract_route(&json!({"model_id": "llama3", "input": "x"})).unwrap();
+        assert_eq!(route.model, "llama3");
+        assert!(!route.stream);
+
+        assert!(matches!(
+            extract_route(&json!({"prompt": "no model"})).unwrap_err(),
+            TroopError::InvalidRequest(_)
+        ));
+    }
+
     #[tokio::test]
     async fn test_proxy_auth_success() {
         let service = make_service(
@@ -392,7 +877,7 @@ mod tests {
             }],
         );
 
-        let app = create_proxy_router(Arc::new(ProxyState { service }));
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, test_limiter())));
 
         let response = app
             .oneshot(
@@ -412,11 +897,282 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_requester_over_its_rate_gets_retry_after() {
+        let service = make_service(
+            true,
+            vec![Model {
+                id: "llama3".to_string(),
+                content_hash: "sha256:abc123".to_string(),
+                size_bytes: 4_000_000_000,
+                engine_type: EngineType::Ollama,
+            }],
+        );
+        let state = ProxyState::new(service, test_limiter())
+            .with_rate_limiter(Arc::new(RateLimiter::new(2)));
+        let app = create_proxy_router(Arc::new(state));
+        let request = || {
+            Request::post("/v1/chat/completions")
+                .header("Authorization", "Bearer valid-token")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    json!({"model_id": "llama3", "messages": []}).to_string(),
+                ))
+                .unwrap()
+        };
+
+        // Up to the limit, then rejected until a token comes back
+        for _ in 0..2 {
+            let response = app.clone().oneshot(request()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[axum::http::header::RETRY_AFTER], "30");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], "rate_limited");
+    }
+
+    fn deadline_request(model: &str, deadline: SystemTime) -> Request<Body> {
+        let millis = deadline.duration_since(UNIX_EPOCH).unwrap().as_millis();
+        Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header("Authorization", "Bearer valid-token")
+            .header("Content-Type", "application/json")
+            .header(DEADLINE_HEADER, millis.to_string())
+            .body(Body::from(
+                json!({"model": model, "messages": []}).to_string(),
+            ))
+            .unwrap()
+    }
+
+    fn deadline_service() -> Arc<WorkerService> {
+        let model = |id: &str| Model {
+            id: id.to_string(),
+            content_hash: format!("sha256:{id}"),
+            size_bytes: 1,
+            engine_type: EngineType::Ollama,
+        };
+        make_service(true, vec![model("llama3"), model("slow")])
+    }
+
+    #[test]
+    fn test_time_remaining_allows_for_clock_skew() {
+        // Whole milliseconds, as the header carries
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let with_deadline = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(DEADLINE_HEADER, HeaderValue::from_str(value).unwrap());
+            time_remaining(&headers, now)
+        };
+        let millis = |t: SystemTime| {
+            t.duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis()
+                .to_string()
+        };
+
+        assert_eq!(
+            with_deadline(&millis(now + Duration::from_secs(10))),
+            Some(Duration::from_secs(12))
+        );
+        assert_eq!(
+            with_deadline(&millis(now - Duration::from_secs(1))),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            with_deadline(&millis(now - Duration::from_secs(30))),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(with_deadline("tomorrow"), None);
+        assert_eq!(time_remaining(&HeaderMap::new(), now), None);
+    }
+
+    #[tokio::test]
+    async fn test_expired_deadline_is_rejected_with_gateway_timeout() {
+        let app = create_proxy_router(Arc::new(ProxyState::new(
+            deadline_service(),
+            test_limiter(),
+        )));
+
+        // Already past when the worker receives it, beyond the grace for clock skew
+        let expired = SystemTime::now() - Duration::from_secs(30);
+        let response = app
+            .clone()
+            .oneshot(deadline_request("llama3", expired))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        // Just past, but within the grace
+        let skewed = SystemTime::now() - Duration::from_millis(500);
+        let response = app
+            .oneshot(deadline_request("llama3", skewed))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_deadline_cancels_a_slow_engine_call() {
+        let app = create_proxy_router(Arc::new(ProxyState::new(
+            deadline_service(),
+            test_limiter(),
+        )));
+
+        let started = tokio::time::Instant::now();
+        let deadline = SystemTime::now() + Duration::from_secs(5);
+        let response = app
+            .oneshot(deadline_request("slow", deadline))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert!(started.elapsed() < Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_load() {
+        // Rejects every ticket, so a 200 shows /health skips ticket verification
+        let service = make_service(
+            false,
+            vec![Model {
+                id: "llama3".to_string(),
+                content_hash: "sha256:abc123".to_string(),
+                size_bytes: 4_000_000_000,
+                engine_type: EngineType::Ollama,
+            }],
+        );
+        let limiter = ConcurrencyLimiter::new(1, Arc::new(AtomicU32::new(1)));
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, limiter)));
+
+        let response = app
+            .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["status"], "healthy");
+        assert_eq!(body["node_id"], "node-1");
+        assert_eq!(body["models"], 1);
+        assert_eq!(body["in_flight"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_debug_registry_requires_a_valid_ticket() {
+        let models = || {
+            vec![Model {
+                id: "llama3".to_string(),
+                content_hash: "sha256:abc123".to_string(),
+                size_bytes: 4_000_000_000,
+                engine_type: EngineType::Ollama,
+            }]
+        };
+        let debug_request = || {
+            Request::get("/debug/registry")
+                .header("Authorization", "Bearer token")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let app = create_proxy_router(Arc::new(ProxyState::new(
+            make_service(true, models()),
+            test_limiter(),
+        )));
+        let response = app.oneshot(debug_request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["models"][0]["id"], "llama3");
+        assert_eq!(body["models"][0]["content_hash"], "sha256:abc123");
+        assert_eq!(body["engines"], json!(["Ollama"]));
+
+        let app = create_proxy_router(Arc::new(ProxyState::new(
+            make_service(false, models()),
+            test_limiter(),
+        )));
+        let response = app.oneshot(debug_request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_embeddings() {
+        let service = make_service(
+            true,
+            vec![Model {
+                id: "nomic-embed-text".to_string(),
+                content_hash: "sha256:def456".to_string(),
+                size_bytes: 300_000_000,
+                engine_type: EngineType::Ollama,
+            }],
+        );
+
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, test_limiter())));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/embeddings")
+                    .header("Authorization", "Bearer valid-token")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        json!({"model": "nomic-embed-text", "input": ["a", "b"]}).to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body_json["object"], "list");
+        assert_eq!(body_json["data"].as_array().unwrap().len(), 2);
+        assert_eq!(body_json["model"], "nomic-embed-text");
+    }
+
+    #[tokio::test]
+    async fn test_proxy_embeddings_model_not_found() {
+        let service = make_service(true, vec![]);
+
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, test_limiter())));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/embeddings")
+                    .header("Authorization", "Bearer valid-token")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        json!({"model": "nomic-embed-text", "input": "a"}).to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
     #[tokio::test]
     async fn test_proxy_auth_failure() {
         let service = make_service(false, vec![]);
 
-        let app = create_proxy_router(Arc::new(ProxyState { service }));
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, test_limiter())));
 
         let response = app
             .oneshot(
@@ -440,7 +1196,7 @@ mod tests {
     async fn test_proxy_model_not_found() {
         let service = make_service(true, vec![]);
 
-        let app = create_proxy_router(Arc::new(ProxyState { service }));
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, test_limiter())));
 
         let response = app
             .oneshot(
@@ -459,6 +1215,12 @@ mod tests {
             .unwrap();
 
         assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], "model_not_found");
+        assert_eq!(body["error"]["message"], "Model not found: non-existent");
     }
 
     #[tokio::test]
@@ -473,7 +1235,7 @@ mod tests {
             }],
         );
 
-        let app = create_proxy_router(Arc::new(ProxyState { service }));
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, test_limiter())));
 
         let key = [0u8; 32];
         let plaintext =
@@ -526,7 +1288,7 @@ mod tests {
             }],
         );
 
-        let app = create_proxy_router(Arc::new(ProxyState { service }));
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, test_limiter())));
 
         let key = [0u8; 32];
         let plaintext =
@@ -563,7 +1325,7 @@ mod tests {
             }],
         );
 
-        let app = create_proxy_router(Arc::new(ProxyState { service }));
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, test_limiter())));
 
         let response = app
             .oneshot(
@@ -587,6 +1349,64 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_token_usage_is_reported_to_the_coordinator() {
+        let coordinator = Arc::new(MockCoordinator::default());
+        let service = make_service_reporting_to(
+            true,
+            vec![Model {
+                id: "llama3".to_string(),
+                content_hash: "sha256:abc123".to_string(),
+                size_bytes: 4_000_000_000,
+                engine_type: EngineType::Ollama,
+            }],
+            coordinator.clone(),
+        );
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, test_limiter())));
+
+        for stream in [false, true] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/v1/chat/completions")
+                        .header("Authorization", "Bearer valid-token")
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(
+                            json!({"model": "llama3", "messages": [], "stream": stream})
+                                .to_string(),
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            // The stream is only metered as it is relayed
+            axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+        }
+
+        // Reports are sent in the background
+        for _ in 0..100 {
+            if coordinator.usage.lock().unwrap().len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let reports = coordinator.usage.lock().unwrap().clone();
+        let mut tokens: Vec<(u64, u64)> = reports
+            .iter()
+            .map(|r| (r.prompt_tokens, r.completion_tokens))
+            .collect();
+        tokens.sort();
+        assert_eq!(tokens, [(4, 1), (9, 12)]);
+        assert!(reports
+            .iter()
+            .all(|r| r.node_id == "node-1" && r.requester == "requester-1" && r.model == "llama3"));
+    }
+
     #[tokio::test]
     async fn test_proxy_e2e_streaming_response() {
         let service = make_service(
@@ -599,7 +1419,7 @@ mod tests {
             }],
         );
 
-        let app = create_proxy_router(Arc::new(ProxyState { service }));
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, test_limiter())));
 
         let key = [0u8; 32];
         let plaintext =
@@ -640,4 +1460,182 @@ mod tests {
         assert!(body_str.contains("data: {"));
         assert!(body_str.contains("\"e2e\":"));
     }
+
+    #[tokio::test]
+    async fn test_proxy_propagates_request_id() {
+        let service = make_service(
+            true,
+            vec![Model {
+                id: "llama3".to_string(),
+                content_hash: "sha256:abc123".to_string(),
+                size_bytes: 4_000_000_000,
+                engine_type: EngineType::Ollama,
+            }],
+        );
+
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, test_limiter())));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Authorization", "Bearer valid-token")
+                    .header("Content-Type", "application/json")
+                    .header(REQUEST_ID_HEADER, "req-abc-123")
+                    .body(Body::from(
+                        json!({"model_id": "llama3", "messages": [], "stream": false}).to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(REQUEST_ID_HEADER).unwrap(),
+            "req-abc-123"
+        );
+
+        // The mock engine echoes the request ID it received as the completion ID
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body_json["id"], "req-abc-123");
+    }
+
+    #[test]
+    fn test_request_id_is_generated_when_missing_or_unusable() {
+        let generated = request_id_from(&HeaderMap::new());
+        assert!(uuid::Uuid::parse_str(&generated).is_ok());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static(" req-1 "));
+        assert_eq!(request_id_from(&headers), "req-1");
+
+        let long = "x".repeat(MAX_REQUEST_ID_LEN + 1);
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_str(&long).unwrap());
+        assert_ne!(request_id_from(&headers), long);
+    }
+
+    #[tokio::test]
+    async fn test_response_carries_generated_request_id() {
+        let service = make_service(
+            true,
+            vec![Model {
+                id: "llama3".to_string(),
+                content_hash: "sha256:abc123".to_string(),
+                size_bytes: 4_000_000_000,
+                engine_type: EngineType::Ollama,
+            }],
+        );
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, test_limiter())));
+
+        let response = app.oneshot(chat_request("llama3")).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let request_id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(request_id).is_ok());
+    }
+
+    fn chat_request(model: &str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header("Authorization", "Bearer valid-token")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                json!({"model": model, "messages": [], "stream": false}).to_string(),
+            ))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_proxy_forwards_upstream_headers() {
+        let service = make_service(
+            true,
+            vec![Model {
+                id: "llama3".to_string(),
+                content_hash: "sha256:abc123".to_string(),
+                size_bytes: 4_000_000_000,
+                engine_type: EngineType::Ollama,
+            }],
+        );
+
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, test_limiter())));
+        let response = app.oneshot(chat_request("llama3")).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(headers.get("x-ratelimit-remaining").unwrap(), "42");
+        // The body was re-encoded, so our content type wins over the engine's
+        assert_eq!(headers.get(CONTENT_TYPE).unwrap(), "application/json");
+        assert!(headers.get("transfer-encoding").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_proxy_accepts_multimodal_messages() {
+        let service = make_service(
+            true,
+            vec![Model {
+                id: "llava".to_string(),
+                content_hash: "sha256:abc123".to_string(),
+                size_bytes: 4_000_000_000,
+                engine_type: EngineType::Ollama,
+            }],
+        );
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header("Authorization", "Bearer valid-token")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                json!({
+                    "model": "llava",
+                    "messages": [
+                        {"role": "user", "content": [
+                            {"type": "text", "text": "What is this?"},
+                            {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}}
+                        ]},
+                        {"role": "assistant", "content": "A cat."},
+                        {"role": "user", "content": "Are you sure?"}
+                    ]
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, test_limiter())));
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_relays_upstream_error_status_and_body() {
+        let service = make_service(
+            true,
+            vec![Model {
+                id: "overloaded".to_string(),
+                content_hash: "sha256:fff999".to_string(),
+                size_bytes: 1,
+                engine_type: EngineType::Ollama,
+            }],
+        );
+
+        let app = create_proxy_router(Arc::new(ProxyState::new(service, test_limiter())));
+        let response = app.oneshot(chat_request("overloaded")).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let headers = response.headers();
+        assert_eq!(headers.get("retry-after").unwrap(), "7");
+        assert_eq!(headers.get(CONTENT_TYPE).unwrap(), "application/json");
+        assert!(headers.get("connection").is_none());
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], br#"{"error":"busy"}"#);
+    }
 }
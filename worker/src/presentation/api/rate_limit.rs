@@ -0,0 +1,160 @@
+//! Per-requester request rate limit for the worker proxy (`REQUESTS_PER_MINUTE`).
+//!
+//! Each requester, identified by the `sub` of its ticket, has a token bucket holding up
+//! to a minute's worth of requests and refilled continuously at that rate, so short
+//! bursts pass but no one requester can monopolize the node. A request finding its bucket
+//! empty gets a 429 with `Retry-After` set to when the next token arrives. A bucket idle
+//! for a whole minute is full again and carries no state, so idle buckets are evicted
+//! periodically to keep memory bounded by the number of active requesters.
+
+use axum::{
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use monkey_troop_shared::ApiErrorBody;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Time an empty bucket takes to refill completely.
+const REFILL_PERIOD: Duration = Duration::from_secs(60);
+
+/// How often idle buckets are evicted.
+pub const EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct RateLimiter {
+    per_minute: u32,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn per_minute(&self) -> u32 {
+        self.per_minute
+    }
+
+    /// Take a token from `requester`'s bucket, or return how long until one is available.
+    pub fn acquire(&self, requester: &str, now: Instant) -> Result<(), Duration> {
+        let capacity = f64::from(self.per_minute);
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = buckets.entry(requester.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.updated);
+        bucket.tokens = (bucket.tokens
+            + capacity * elapsed.as_secs_f64() / REFILL_PERIOD.as_secs_f64())
+        .min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) * REFILL_PERIOD.as_secs_f64() / capacity,
+            ))
+        }
+    }
+
+    /// Drop the buckets untouched for `REFILL_PERIOD`, which are full again.
+    pub fn evict_idle(&self, now: Instant) -> usize {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let before = buckets.len();
+        buckets.retain(|_, bucket| now.saturating_duration_since(bucket.updated) < REFILL_PERIOD);
+        before - buckets.len()
+    }
+}
+
+/// Evict idle buckets every `interval`, for as long as the worker runs.
+pub async fn run_eviction_loop(limiter: Arc<RateLimiter>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let evicted = limiter.evict_idle(Instant::now());
+        if evicted > 0 {
+            debug!("Evicted {} idle rate limit bucket(s)", evicted);
+        }
+    }
+}
+
+/// The 429 for a requester over its rate, retryable after `retry_after` (rounded up to
+/// whole seconds).
+pub fn rate_limited_response(per_minute: u32, retry_after: Duration) -> Response {
+    let body = ApiErrorBody::for_status(
+        StatusCode::TOO_MANY_REQUESTS.as_u16(),
+        format!("Rate limit of {per_minute} requests per minute exceeded"),
+    );
+    let mut response = (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response();
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(secs.max(1)));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_allows_a_burst_then_refills_at_the_rate() {
+        let limiter = RateLimiter::new(3);
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.acquire("alice", start).is_ok());
+        }
+        assert_eq!(
+            limiter.acquire("alice", start),
+            Err(Duration::from_secs(20))
+        );
+        // Other requesters have their own bucket
+        assert!(limiter.acquire("bob", start).is_ok());
+
+        // One token every 20 seconds
+        let almost = start + Duration::from_secs(19);
+        assert!(limiter.acquire("alice", almost).is_err());
+        let refilled = start + Duration::from_secs(20);
+        assert!(limiter.acquire("alice", refilled).is_ok());
+        assert!(limiter.acquire("alice", refilled).is_err());
+    }
+
+    #[test]
+    fn test_idle_buckets_are_evicted() {
+        let limiter = RateLimiter::new(1);
+        let start = Instant::now();
+        limiter.acquire("alice", start).unwrap();
+        limiter
+            .acquire("bob", start + Duration::from_secs(30))
+            .unwrap();
+
+        assert_eq!(limiter.evict_idle(start + Duration::from_secs(59)), 0);
+        assert_eq!(limiter.evict_idle(start + Duration::from_secs(60)), 1);
+        // Evicting a bucket loses nothing: it was full again
+        assert!(limiter
+            .acquire("alice", start + Duration::from_secs(60))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_rate_limited_response_rounds_retry_after_up() {
+        let response = rate_limited_response(3, Duration::from_millis(1500));
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "2");
+    }
+}